@@ -0,0 +1,234 @@
+use crate::catalog;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+///
+/// Suggested ways of reducing size of files to copy.
+///
+/// Every non-empty list is enough on its own to free
+/// requested number of bytes.
+///
+#[derive(Default)]
+pub struct Advice {
+    /// Directories that could be excluded, with their sizes.
+    pub directories: Vec<(PathBuf, u64)>,
+    /// Biggest files that could be skipped, with their sizes.
+    pub largest_files: Vec<(PathBuf, u64)>,
+    /// Least recently modified files that could be skipped, with their sizes.
+    pub oldest_files: Vec<(PathBuf, u64)>,
+}
+
+///
+/// Compute which directories or files should be left out
+/// so at least bytes_to_free bytes are not copied.
+///
+/// Directories are chosen greedily starting from the biggest one.
+/// src_directory itself is never suggested and neither are
+/// directories nested inside already suggested ones.
+///
+/// When there's problem with reading file metadata, error is logged
+/// and file is ignored.
+///
+pub fn advise(src_directory: &Path, files_paths: &[PathBuf], bytes_to_free: u64) -> Advice {
+    if bytes_to_free == 0 {
+        return Advice::default();
+    }
+
+    let files = files_paths
+        .iter()
+        .filter_map(|path| match fs::metadata(path) {
+            Ok(metadata) => {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((path, metadata.len(), modified))
+            }
+            Err(err) => {
+                log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut directories_sizes: HashMap<&Path, u64> = HashMap::new();
+    for (path, size, _) in files.iter() {
+        let directories = path
+            .ancestors()
+            .skip(1)
+            .take_while(|directory| *directory != src_directory);
+        for directory in directories {
+            *directories_sizes.entry(directory).or_default() += size;
+        }
+    }
+    let mut directories = directories_sizes.into_iter().collect::<Vec<_>>();
+    directories.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let mut chosen_directories: Vec<(PathBuf, u64)> = Vec::new();
+    let mut freed = 0;
+    for (directory, size) in directories {
+        if freed >= bytes_to_free {
+            break;
+        }
+        let overlaps = chosen_directories
+            .iter()
+            .any(|(chosen, _)| directory.starts_with(chosen) || chosen.starts_with(directory));
+        if !overlaps {
+            chosen_directories.push((directory.to_path_buf(), size));
+            freed += size;
+        }
+    }
+    if freed < bytes_to_free {
+        chosen_directories.clear();
+    }
+
+    let mut by_size = files
+        .iter()
+        .map(|(path, size, modified)| (path.to_path_buf(), *size, *modified))
+        .collect::<Vec<_>>();
+    by_size.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let largest_files = take_until_freed(by_size, bytes_to_free);
+
+    let mut by_age = files
+        .iter()
+        .map(|(path, size, modified)| (path.to_path_buf(), *size, *modified))
+        .collect::<Vec<_>>();
+    by_age.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+    let oldest_files = take_until_freed(by_age, bytes_to_free);
+
+    Advice {
+        directories: chosen_directories,
+        largest_files,
+        oldest_files,
+    }
+}
+
+fn take_until_freed(
+    files: Vec<(PathBuf, u64, SystemTime)>,
+    bytes_to_free: u64,
+) -> Vec<(PathBuf, u64)> {
+    let mut freed = 0;
+    let chosen = files
+        .into_iter()
+        .take_while(|(_, size, _)| {
+            let take = freed < bytes_to_free;
+            freed += size;
+            take
+        })
+        .map(|(path, size, _)| (path, size))
+        .collect::<Vec<_>>();
+
+    if chosen.iter().map(|(_, size)| size).sum::<u64>() < bytes_to_free {
+        return Vec::new();
+    }
+
+    chosen
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    ///
+    /// Creates directory tree with files of given sizes.
+    ///
+    /// ```not-rust
+    /// root
+    ///  |- a.bin: 10
+    ///  |- big
+    ///      |- b.bin: 100
+    ///      |- nested
+    ///          |- c.bin: 50
+    ///  |- small
+    ///      |- d.bin: 20
+    /// ```
+    fn create_sized_tree() -> (TempDir, Vec<PathBuf>) {
+        let root = TempDir::new().unwrap();
+        let files = [
+            ("a.bin", 10),
+            ("big/b.bin", 100),
+            ("big/nested/c.bin", 50),
+            ("small/d.bin", 20),
+        ];
+        let paths = files
+            .iter()
+            .map(|(relative, size)| {
+                let path = root.path().join(relative);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, vec![0u8; *size]).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        (root, paths)
+    }
+
+    #[test]
+    fn advise_nothing_to_free() {
+        let (root, paths) = create_sized_tree();
+
+        let advice = advise(root.path(), &paths, 0);
+
+        assert!(advice.directories.is_empty());
+        assert!(advice.largest_files.is_empty());
+        assert!(advice.oldest_files.is_empty());
+    }
+
+    #[test]
+    fn advise_biggest_directory() {
+        let (root, paths) = create_sized_tree();
+
+        let advice = advise(root.path(), &paths, 120);
+
+        assert_eq!(advice.directories, vec![(root.path().join("big"), 150)]);
+    }
+
+    #[test]
+    fn advise_multiple_directories_without_nesting() {
+        let (root, paths) = create_sized_tree();
+
+        let advice = advise(root.path(), &paths, 160);
+
+        assert_eq!(
+            advice.directories,
+            vec![
+                (root.path().join("big"), 150),
+                (root.path().join("small"), 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn advise_directories_not_enough() {
+        let (root, paths) = create_sized_tree();
+
+        let advice = advise(root.path(), &paths, 175);
+
+        assert!(advice.directories.is_empty());
+        assert_eq!(advice.largest_files.len(), 4);
+    }
+
+    #[test]
+    fn advise_largest_files() {
+        let (root, paths) = create_sized_tree();
+
+        let advice = advise(root.path(), &paths, 120);
+
+        assert_eq!(
+            advice.largest_files,
+            vec![(paths[1].clone(), 100), (paths[2].clone(), 50)]
+        );
+    }
+
+    #[test]
+    fn advise_more_than_available() {
+        let (root, paths) = create_sized_tree();
+
+        let advice = advise(root.path(), &paths, 1000);
+
+        assert!(advice.directories.is_empty());
+        assert!(advice.largest_files.is_empty());
+        assert!(advice.oldest_files.is_empty());
+    }
+}
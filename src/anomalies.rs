@@ -0,0 +1,221 @@
+use crate::{catalog, file_types::detect_extension};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Files modified within this period are considered recently modified.
+const RECENT_MODIFICATION_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Minimum number of recently modified files sharing the same
+/// modification time that is considered suspicious.
+const SHARED_MODIFICATION_TIME_THRESHOLD: usize = 100;
+
+/// Extensions that describe the same content as detected extension.
+const EXTENSION_ALIASES: [(&str, &[&str]); 6] = [
+    ("jpg", &["jpeg", "jpe", "jfif"]),
+    ("tif", &["tiff"]),
+    ("mpg", &["mpeg"]),
+    ("gz", &["tgz"]),
+    ("mp4", &["m4v"]),
+    (
+        "zip",
+        &["jar", "apk", "odt", "ods", "odp", "xpi", "whl", "nupkg"],
+    ),
+];
+
+///
+/// Suspicious property of files found during the scan.
+///
+#[derive(Debug, PartialEq, Eq)]
+pub enum Anomaly {
+    /// File's extension does not match its content.
+    ExtensionMismatch {
+        path: PathBuf,
+        detected_extension: &'static str,
+    },
+    /// File is empty even though it matched requested suffixes.
+    EmptyFile { path: PathBuf },
+    /// Many recently modified files share exactly the same
+    /// modification time (e.g. files encrypted by ransomware).
+    SharedModificationTime {
+        /// Seconds since UNIX epoch.
+        modified: u64,
+        files: usize,
+    },
+}
+
+///
+/// Find anomalies in files that are going to be copied.
+///
+/// Empty files are reported only when check_empty_files is set
+/// because without suffixes filter they are usually harmless.
+///
+/// When there's problem with reading file, error is logged
+/// and file is ignored.
+///
+pub fn find_anomalies(files_paths: &[PathBuf], check_empty_files: bool) -> Vec<Anomaly> {
+    let now = SystemTime::now();
+    let files = files_paths
+        .par_iter()
+        .filter_map(|path| match fs::metadata(path) {
+            Ok(metadata) => Some((path, metadata)),
+            Err(err) => {
+                log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+                None
+            }
+        })
+        .map(|(path, metadata)| {
+            let detected_extension = if metadata.len() > 0 {
+                detect_extension(path).unwrap_or_else(|err| {
+                    log::warn!(
+                        target: catalog::TYPE_DETECTION_FAILED,
+                        "Failed to detect type of {}; {err}",
+                        path.to_string_lossy()
+                    );
+                    None
+                })
+            } else {
+                None
+            };
+            (path, metadata, detected_extension)
+        })
+        .collect::<Vec<_>>();
+
+    let mut anomalies = Vec::new();
+    let mut modification_times: HashMap<u64, usize> = HashMap::new();
+    for (path, metadata, detected_extension) in files {
+        if let Some(detected_extension) = detected_extension {
+            if !extension_matches(path, detected_extension) {
+                anomalies.push(Anomaly::ExtensionMismatch {
+                    path: path.clone(),
+                    detected_extension,
+                });
+            }
+        }
+
+        if check_empty_files && metadata.len() == 0 {
+            anomalies.push(Anomaly::EmptyFile { path: path.clone() });
+        }
+
+        let modified = metadata.modified().ok().filter(|modified| {
+            now.duration_since(*modified)
+                .is_ok_and(|age| age <= RECENT_MODIFICATION_PERIOD)
+        });
+        if let Some(modified) = modified {
+            let seconds = modified.duration_since(UNIX_EPOCH).unwrap().as_secs();
+            *modification_times.entry(seconds).or_default() += 1;
+        }
+    }
+
+    let mut shared_modification_times = modification_times
+        .into_iter()
+        .filter(|(_, files)| *files >= SHARED_MODIFICATION_TIME_THRESHOLD)
+        .collect::<Vec<_>>();
+    shared_modification_times.sort();
+    anomalies.extend(
+        shared_modification_times
+            .into_iter()
+            .map(|(modified, files)| Anomaly::SharedModificationTime { modified, files }),
+    );
+
+    anomalies
+}
+
+fn extension_matches(path: &Path, detected_extension: &str) -> bool {
+    let Some(extension) = path.extension() else {
+        return true;
+    };
+    let extension = extension.to_string_lossy().to_lowercase();
+    if extension == detected_extension {
+        return true;
+    }
+
+    EXTENSION_ALIASES.iter().any(|(detected, aliases)| {
+        *detected == detected_extension && aliases.contains(&extension.as_str())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    const PNG_BYTES: [u8; 10] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0];
+
+    #[test]
+    fn find_anomalies_extension_mismatch() {
+        let root = TempDir::new().unwrap();
+        let disguised = root.path().join("document.pdf");
+        let correct = root.path().join("image.PNG");
+        fs::write(&disguised, PNG_BYTES).unwrap();
+        fs::write(&correct, PNG_BYTES).unwrap();
+
+        let anomalies = find_anomalies(&[disguised.clone(), correct], false);
+
+        assert_eq!(
+            anomalies,
+            vec![Anomaly::ExtensionMismatch {
+                path: disguised,
+                detected_extension: "png"
+            }]
+        );
+    }
+
+    #[test]
+    fn find_anomalies_extension_alias() {
+        let root = TempDir::new().unwrap();
+        let jpeg = root.path().join("photo.jpeg");
+        fs::write(&jpeg, [0xFF, 0xD8, 0xFF, 0xE0, 0, 0]).unwrap();
+
+        let anomalies = find_anomalies(&[jpeg], false);
+
+        assert!(anomalies.is_empty());
+    }
+
+    #[test]
+    fn find_anomalies_empty_files() {
+        let root = TempDir::new().unwrap();
+        let empty = root.path().join("empty.txt");
+        fs::write(&empty, "").unwrap();
+        let paths = vec![empty.clone()];
+
+        assert!(find_anomalies(&paths, false).is_empty());
+        assert_eq!(
+            find_anomalies(&paths, true),
+            vec![Anomaly::EmptyFile { path: empty }]
+        );
+    }
+
+    #[test]
+    fn find_anomalies_shared_modification_time() {
+        let root = TempDir::new().unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(60);
+        let paths = (0..SHARED_MODIFICATION_TIME_THRESHOLD)
+            .map(|i| {
+                let path = root.path().join(format!("{i}.txt"));
+                fs::write(&path, "text").unwrap();
+                fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        let anomalies = find_anomalies(&paths, false);
+
+        assert_eq!(
+            anomalies,
+            vec![Anomaly::SharedModificationTime {
+                modified: modified.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                files: SHARED_MODIFICATION_TIME_THRESHOLD
+            }]
+        );
+    }
+}
@@ -0,0 +1,827 @@
+use crate::{
+    catalog,
+    encryption::Encryption,
+    files::{temp_file_name, SymlinkPolicy},
+    volumes::{write_volumes_manifest, VolumeWriter},
+};
+use anyhow::{anyhow, Result};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    cell::Cell,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Seek, Write},
+    ops::RangeInclusive,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zip::{write::FullFileOptions, CompressionMethod, ZipWriter};
+
+/// Format of tar archives, e.g. 'tar:/backups/documents.tar'.
+const TAR_FORMAT: &str = "tar";
+
+/// Format of tar archives compressed with gzip.
+const TAR_GZ_FORMAT: &str = "tar.gz";
+
+/// Format of tar archives compressed with zstd.
+const TAR_ZST_FORMAT: &str = "tar.zst";
+
+/// Format of zip archives, e.g. 'zip:/backups/documents.zip'.
+const ZIP_FORMAT: &str = "zip";
+
+/// Path of archive written to standard output.
+const STDOUT_PATH: &str = "-";
+
+/// Compression level of zip and gzip archives when it's not set.
+const DEFAULT_COMPRESSION: u8 = 6;
+
+/// Compression level of zstd archives when it's not set.
+const DEFAULT_ZSTD_COMPRESSION: u8 = 3;
+
+/// Files of this size and bigger are stored with ZIP64 extensions.
+const ZIP64_SIZE: u64 = u32::MAX as u64;
+
+/// Zip extra field with Unix times in seconds (Info-ZIP 'UT').
+const EXTENDED_TIMESTAMP_FIELD: u16 = 0x5455;
+
+/// Zip extra field with Windows times in 100 ns intervals.
+const NTFS_FIELD: u16 = 0x000a;
+
+/// Seconds between 1601-01-01, where Windows times start, and Unix epoch.
+const WINDOWS_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+///
+/// Archive into which selected files are written instead of dst_directory.
+/// Archive is written to standard output when its path is '-'.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Archive {
+    Tar(PathBuf),
+    TarGz(PathBuf),
+    TarZst(PathBuf),
+    Zip(PathBuf),
+}
+
+impl Archive {
+    pub fn path(&self) -> &Path {
+        match self {
+            Archive::Tar(path)
+            | Archive::TarGz(path)
+            | Archive::TarZst(path)
+            | Archive::Zip(path) => path,
+        }
+    }
+
+    pub fn is_stdout(&self) -> bool {
+        self.path().as_os_str() == STDOUT_PATH
+    }
+
+    ///
+    /// Range of compression levels supported by the archive
+    /// or None when it's not compressed.
+    ///
+    pub fn compression_levels(&self) -> Option<RangeInclusive<u8>> {
+        match self {
+            Archive::Tar(_) => None,
+            Archive::TarGz(_) | Archive::Zip(_) => Some(0..=9),
+            Archive::TarZst(_) => Some(1..=22),
+        }
+    }
+}
+
+impl fmt::Display for Archive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = match self {
+            Archive::Tar(_) => TAR_FORMAT,
+            Archive::TarGz(_) => TAR_GZ_FORMAT,
+            Archive::TarZst(_) => TAR_ZST_FORMAT,
+            Archive::Zip(_) => ZIP_FORMAT,
+        };
+        write!(f, "{format}:{}", self.path().to_string_lossy())
+    }
+}
+
+///
+/// Summary of archiving.
+///
+#[derive(Debug, Default)]
+pub struct ArchiveSummary {
+    pub archived_files: usize,
+    pub archived_bytes: u64,
+    pub failed_files: Vec<PathBuf>,
+    /// Relative paths of archived files and byte ranges of their entries
+    /// in the archive. Recorded only when archive is split into volumes.
+    pub files_ranges: Vec<(PathBuf, u64, u64)>,
+}
+
+///
+/// Parse archive written as 'tar:PATH', 'tar.gz:PATH',
+/// 'tar.zst:PATH' or 'zip:PATH'.
+///
+/// #### Errors
+/// This function returns error when format of the archive
+/// is not supported or path is missing.
+///
+pub fn parse_archive(value: &str) -> Result<Archive> {
+    let archive = match value.split_once(':') {
+        Some((_, "")) | None => None,
+        Some((TAR_FORMAT, path)) => Some(Archive::Tar(PathBuf::from(path))),
+        Some((TAR_GZ_FORMAT, path)) => Some(Archive::TarGz(PathBuf::from(path))),
+        Some((TAR_ZST_FORMAT, path)) => Some(Archive::TarZst(PathBuf::from(path))),
+        Some((ZIP_FORMAT, path)) => Some(Archive::Zip(PathBuf::from(path))),
+        Some(_) => None,
+    };
+
+    archive.ok_or_else(|| {
+        anyhow!(
+            "invalid archive '{value}'; use 'tar:PATH', 'tar.gz:PATH', \
+             'tar.zst:PATH' or 'zip:PATH'"
+        )
+    })
+}
+
+///
+/// Write files into archive with their paths relative to src_directory.
+/// Compressed archives use given compression level or the default one
+/// of their format. Files are compressed as they're read, so nothing
+/// is staged besides the archive.
+///
+/// Tar archives can be split into volumes of volume_size, described
+/// by manifest written next to them. Archive is encrypted when encryption
+/// is given, so it never hits disk unencrypted. Encrypted archives can't be
+/// split, because their encrypted chunks don't end with files.
+///
+/// Archive is written to temporary file and renamed when it's complete,
+/// so interrupted run never leaves truncated archive. Files that can't
+/// be read are logged with WARN level and left out of the archive.
+/// Symbolic links are stored as links with [SymlinkPolicy::CopyLink].
+///
+/// #### Errors
+/// This function returns error when archive can't be written.
+///
+pub fn archive_files(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    archive: &Archive,
+    compression: Option<u8>,
+    volume_size: Option<u64>,
+    encryption: Option<&Encryption>,
+    symlinks: SymlinkPolicy,
+) -> Result<ArchiveSummary> {
+    let path = archive.path();
+    if let Some(volume_size) = volume_size {
+        if encryption.is_some() {
+            return Err(anyhow!("encrypted archive can't be split into volumes"));
+        }
+        return write_volumes(
+            src_directory,
+            files_paths,
+            archive,
+            compression,
+            volume_size,
+            symlinks,
+        )
+        .map_err(|err| anyhow!("failed to write '{}'; {err}", path.to_string_lossy()));
+    }
+    if archive.is_stdout() {
+        let stdout = io::stdout().lock();
+        return write_archive(
+            src_directory,
+            files_paths,
+            archive,
+            compression,
+            encryption,
+            stdout,
+            symlinks,
+        )
+        .and_then(|(summary, mut stdout)| {
+            stdout.flush()?;
+            Ok(summary)
+        });
+    }
+
+    let temp_path = path.with_file_name(temp_file_name());
+    let result = File::create(&temp_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| match (archive, encryption) {
+            // Zip archive written directly to file can be seeked
+            // to store sizes in headers of its entries
+            (Archive::Zip(_), None) => write_zip(
+                src_directory,
+                files_paths,
+                ZipWriter::new(BufWriter::new(file)),
+                compression.unwrap_or(DEFAULT_COMPRESSION),
+                symlinks,
+            ),
+            (archive, encryption) => write_archive(
+                src_directory,
+                files_paths,
+                archive,
+                compression,
+                encryption,
+                BufWriter::new(file),
+                symlinks,
+            ),
+        })
+        .and_then(|(summary, mut writer)| {
+            writer.flush()?;
+            Ok(summary)
+        })
+        .and_then(|summary| {
+            fs::rename(&temp_path, path)?;
+            Ok(summary)
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result.map_err(|err| anyhow!("failed to write '{}'; {err}", path.to_string_lossy()))
+}
+
+///
+/// Write archive, encrypted when encryption is given, as stream
+/// and return writer the archive was written to.
+///
+fn write_archive<W: Write>(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    archive: &Archive,
+    compression: Option<u8>,
+    encryption: Option<&Encryption>,
+    writer: W,
+    symlinks: SymlinkPolicy,
+) -> Result<(ArchiveSummary, W)> {
+    let Some(encryption) = encryption else {
+        return write_stream(
+            src_directory,
+            files_paths,
+            archive,
+            compression,
+            writer,
+            symlinks,
+        );
+    };
+    let writer = encryption.encrypt(writer)?;
+    let (summary, writer) = write_stream(
+        src_directory,
+        files_paths,
+        archive,
+        compression,
+        writer,
+        symlinks,
+    )?;
+
+    Ok((summary, writer.finish()?))
+}
+
+///
+/// Write archive of any format to writer that can't be seeked.
+///
+fn write_stream<W: Write>(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    archive: &Archive,
+    compression: Option<u8>,
+    writer: W,
+    symlinks: SymlinkPolicy,
+) -> Result<(ArchiveSummary, W)> {
+    match archive {
+        Archive::Zip(_) => write_zip(
+            src_directory,
+            files_paths,
+            ZipWriter::new_stream(writer),
+            compression.unwrap_or(DEFAULT_COMPRESSION),
+            symlinks,
+        )
+        .map(|(summary, writer)| (summary, writer.into_inner())),
+        archive => write_compressed_tar(
+            src_directory,
+            files_paths,
+            archive,
+            compression,
+            writer,
+            None,
+            symlinks,
+        ),
+    }
+}
+
+///
+/// Write tar archive split into volumes and manifest describing them.
+///
+fn write_volumes(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    archive: &Archive,
+    compression: Option<u8>,
+    volume_size: u64,
+    symlinks: SymlinkPolicy,
+) -> Result<ArchiveSummary> {
+    let writer = VolumeWriter::new(archive.path(), volume_size);
+    let position = writer.position();
+    let (summary, writer) = write_compressed_tar(
+        src_directory,
+        files_paths,
+        archive,
+        compression,
+        writer,
+        Some(&position),
+        symlinks,
+    )?;
+    let volumes_paths = writer.finish()?;
+    let manifest_path = write_volumes_manifest(
+        archive.path(),
+        volume_size,
+        &volumes_paths,
+        &summary.files_ranges,
+    )?;
+    log::info!(
+        "Archive is split into {} volumes described in {}",
+        volumes_paths.len(),
+        manifest_path.to_string_lossy()
+    );
+
+    Ok(summary)
+}
+
+///
+/// Write tar archive compressed according to its format
+/// and return writer the compressed archive was written to.
+///
+fn write_compressed_tar<W: Write>(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    archive: &Archive,
+    compression: Option<u8>,
+    writer: W,
+    position: Option<&Cell<u64>>,
+    symlinks: SymlinkPolicy,
+) -> Result<(ArchiveSummary, W)> {
+    let result = match archive {
+        Archive::TarGz(_) => {
+            let level = compression.unwrap_or(DEFAULT_COMPRESSION);
+            let encoder = GzEncoder::new(writer, Compression::new(level.into()));
+            let (summary, encoder) =
+                write_tar(src_directory, files_paths, encoder, position, symlinks)?;
+            (summary, encoder.finish()?)
+        }
+        Archive::TarZst(_) => {
+            let level = compression.unwrap_or(DEFAULT_ZSTD_COMPRESSION);
+            let encoder = zstd::Encoder::new(writer, level.into())?;
+            let (summary, encoder) =
+                write_tar(src_directory, files_paths, encoder, position, symlinks)?;
+            (summary, encoder.finish()?)
+        }
+        _ => write_tar(src_directory, files_paths, writer, position, symlinks)?,
+    };
+
+    Ok(result)
+}
+
+///
+/// Write tar archive. When position of the underlying writer is given,
+/// every entry is flushed, so byte ranges of files can be recorded.
+/// Flushing gzip or zstd encoder ends its block, so compressed archive
+/// of many small files split into volumes is somewhat bigger.
+///
+fn write_tar<W: Write>(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    writer: W,
+    position: Option<&Cell<u64>>,
+    symlinks: SymlinkPolicy,
+) -> Result<(ArchiveSummary, W)> {
+    let mut builder = tar::Builder::new(writer);
+    let mut summary = ArchiveSummary::default();
+    for file_path in files_paths {
+        let name = file_path.strip_prefix(src_directory).unwrap_or(file_path);
+        if symlinks == SymlinkPolicy::CopyLink && file_path.is_symlink() {
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&fs::symlink_metadata(file_path)?);
+            header.set_size(0);
+            builder.append_link(&mut header, name, fs::read_link(file_path)?)?;
+            log::info!("Archived {}", file_path.to_string_lossy());
+            summary.archived_files += 1;
+            continue;
+        }
+        let Some(mut file) = open_file(file_path, &mut summary) else {
+            continue;
+        };
+        let start = position.map(Cell::get);
+        // Entry that fails midway can't be removed from the stream
+        builder.append_file(name, &mut file)?;
+        if let Some((position, start)) = position.zip(start) {
+            builder.get_mut().flush()?;
+            summary
+                .files_ranges
+                .push((name.to_path_buf(), start, position.get()));
+        }
+        log::info!("Archived {}", file_path.to_string_lossy());
+        summary.archived_files += 1;
+        summary.archived_bytes += file.metadata()?.len();
+    }
+    let writer = builder.into_inner()?;
+
+    Ok((summary, writer))
+}
+
+///
+/// Write zip archive and return writer it was written to.
+///
+/// Besides DOS times with 2 s precision entries have extended timestamp
+/// and NTFS extra fields, so extracted files get exact modification times.
+/// Symbolic links are stored as links when links are kept.
+///
+fn write_zip<W: Write + Seek>(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    mut writer: ZipWriter<W>,
+    compression: u8,
+    symlinks: SymlinkPolicy,
+) -> Result<(ArchiveSummary, W)> {
+    let options = match compression {
+        0 => FullFileOptions::default().compression_method(CompressionMethod::Stored),
+        level => FullFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(level.into())),
+    };
+    let mut summary = ArchiveSummary::default();
+    for file_path in files_paths {
+        let name = file_path.strip_prefix(src_directory).unwrap_or(file_path);
+        if symlinks == SymlinkPolicy::CopyLink && file_path.is_symlink() {
+            let metadata = fs::symlink_metadata(file_path)?;
+            let target = fs::read_link(file_path)?;
+            writer.add_symlink(
+                zip_name(name),
+                target.to_string_lossy(),
+                zip_file_options(&options, &metadata)?,
+            )?;
+            log::info!("Archived {}", file_path.to_string_lossy());
+            summary.archived_files += 1;
+            continue;
+        }
+        let Some(mut file) = open_file(file_path, &mut summary) else {
+            continue;
+        };
+        let metadata = file.metadata()?;
+        let file_options =
+            zip_file_options(&options, &metadata)?.large_file(metadata.len() >= ZIP64_SIZE);
+
+        writer.start_file(zip_name(name), file_options)?;
+        io::copy(&mut file, &mut writer)?;
+        log::info!("Archived {}", file_path.to_string_lossy());
+        summary.archived_files += 1;
+        summary.archived_bytes += metadata.len();
+    }
+    let writer = writer.finish()?;
+
+    Ok((summary, writer))
+}
+
+///
+/// Open file that's archived. Failure is logged with WARN level
+/// and the file is recorded as failed.
+///
+fn open_file(file_path: &Path, summary: &mut ArchiveSummary) -> Option<File> {
+    match File::open(file_path) {
+        Ok(file) => Some(file),
+        Err(err) => {
+            log::warn!(
+                target: catalog::COPY_FAILED,
+                "Failed to archive {}; {err}",
+                file_path.to_string_lossy()
+            );
+            summary.failed_files.push(file_path.to_path_buf());
+            None
+        }
+    }
+}
+
+///
+/// Options of zip entry with times and permissions from metadata.
+///
+fn zip_file_options<'a>(
+    options: &FullFileOptions<'a, 'a>,
+    metadata: &fs::Metadata,
+) -> Result<FullFileOptions<'a, 'a>> {
+    let mut file_options = options.clone();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file_options = file_options.unix_permissions(metadata.permissions().mode());
+    }
+    let Ok(modified) = metadata.modified() else {
+        return Ok(file_options);
+    };
+    if let Some(date_time) = zip_date_time(modified) {
+        file_options = file_options.last_modified_time(date_time);
+    }
+
+    if let Some(seconds) = modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|since_epoch| i32::try_from(since_epoch.as_secs()).ok())
+    {
+        let mut timestamp = vec![1];
+        timestamp.extend_from_slice(&seconds.to_le_bytes());
+        file_options.add_extra_field(EXTENDED_TIMESTAMP_FIELD, timestamp, false)?;
+    }
+    let accessed = metadata.accessed().unwrap_or(modified);
+    let created = metadata.created().unwrap_or(modified);
+    if let Some(times) = [modified, accessed, created]
+        .into_iter()
+        .map(windows_file_time)
+        .collect::<Option<Vec<_>>>()
+    {
+        let mut ntfs = vec![0, 0, 0, 0, 1, 0, 24, 0];
+        for time in times {
+            ntfs.extend_from_slice(&time.to_le_bytes());
+        }
+        file_options.add_extra_field(NTFS_FIELD, ntfs, false)?;
+    }
+
+    Ok(file_options)
+}
+
+///
+/// Convert time to number of 100 ns intervals since 1601-01-01.
+/// Returns None for times before Unix epoch.
+///
+fn windows_file_time(time: SystemTime) -> Option<u64> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+    let seconds = since_epoch.as_secs().checked_add(WINDOWS_EPOCH_OFFSET)?;
+    seconds
+        .checked_mul(10_000_000)?
+        .checked_add(u64::from(since_epoch.subsec_nanos() / 100))
+}
+
+///
+/// Name of the file in zip archive, which always uses '/' as separator.
+///
+fn zip_name(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+///
+/// Convert time to local time stored in zip archives.
+/// Returns None for times zip can't store (before 1980).
+///
+fn zip_date_time(time: SystemTime) -> Option<zip::DateTime> {
+    let local_time = jiff::Zoned::try_from(time).ok()?;
+    zip::DateTime::try_from(local_time.datetime()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_archive_tar() {
+        assert_eq!(
+            parse_archive("tar:/backups/docs.tar").unwrap(),
+            Archive::Tar(PathBuf::from("/backups/docs.tar"))
+        );
+        assert_eq!(
+            parse_archive("zip:docs.zip").unwrap(),
+            Archive::Zip(PathBuf::from("docs.zip"))
+        );
+        assert_eq!(
+            parse_archive("tar.zst:-").unwrap(),
+            Archive::TarZst(PathBuf::from("-"))
+        );
+        assert!(parse_archive("tar:").is_err());
+        assert!(parse_archive("7z:/backups/docs.7z").is_err());
+    }
+
+    #[test]
+    fn archive_files_preserves_relative_paths() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        fs::create_dir(src_dir.path().join("docs")).unwrap();
+        let files_paths = [
+            src_dir.path().join("docs").join("notes.txt"),
+            src_dir.path().join("photo.jpg"),
+            src_dir.path().join("missing.txt"),
+        ];
+        fs::write(&files_paths[0], "notes").unwrap();
+        fs::write(&files_paths[1], "photo").unwrap();
+        let archive_path = dst_dir.path().join("backup.tar");
+
+        let summary = archive_files(
+            src_dir.path(),
+            &files_paths,
+            &Archive::Tar(archive_path.clone()),
+            None,
+            None,
+            None,
+            SymlinkPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(summary.archived_files, 2);
+        assert_eq!(summary.archived_bytes, 10);
+        assert_eq!(summary.failed_files, vec![files_paths[2].clone()]);
+        let mut archive = tar::Archive::new(File::open(archive_path).unwrap());
+        let names = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                Path::new("docs").join("notes.txt"),
+                PathBuf::from("photo.jpg")
+            ]
+        );
+        assert_eq!(fs::read_dir(dst_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn archive_files_compressed_tar() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let file_path = src_dir.path().join("notes.txt");
+        fs::write(&file_path, "notes ".repeat(1000)).unwrap();
+        let gz_path = dst_dir.path().join("backup.tar.gz");
+        let zst_path = dst_dir.path().join("backup.tar.zst");
+        let files_paths = [file_path];
+
+        archive_files(
+            src_dir.path(),
+            &files_paths,
+            &Archive::TarGz(gz_path.clone()),
+            None,
+            None,
+            None,
+            SymlinkPolicy::Skip,
+        )
+        .unwrap();
+        archive_files(
+            src_dir.path(),
+            &files_paths,
+            &Archive::TarZst(zst_path.clone()),
+            Some(19),
+            None,
+            None,
+            SymlinkPolicy::Skip,
+        )
+        .unwrap();
+
+        let gz_file = File::open(gz_path).unwrap();
+        let zst_file = File::open(zst_path).unwrap();
+        assert!(gz_file.metadata().unwrap().len() < 1000);
+        assert!(zst_file.metadata().unwrap().len() < 1000);
+        let decoders: [Box<dyn io::Read>; 2] = [
+            Box::new(flate2::read::GzDecoder::new(gz_file)),
+            Box::new(zstd::Decoder::new(zst_file).unwrap()),
+        ];
+        for decoder in decoders {
+            let mut archive = tar::Archive::new(decoder);
+            let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+            assert_eq!(entry.path().unwrap(), Path::new("notes.txt"));
+            let mut notes = String::new();
+            io::Read::read_to_string(&mut entry, &mut notes).unwrap();
+            assert_eq!(notes, "notes ".repeat(1000));
+        }
+    }
+
+    #[test]
+    fn archive_files_zip_preserves_directory_structure() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        fs::create_dir(src_dir.path().join("docs")).unwrap();
+        let files_paths = [
+            src_dir.path().join("docs").join("notes.txt"),
+            src_dir.path().join("photo.jpg"),
+        ];
+        fs::write(&files_paths[0], "notes ".repeat(100)).unwrap();
+        fs::write(&files_paths[1], "photo").unwrap();
+        let archive_path = dst_dir.path().join("backup.zip");
+
+        for compression in [0, 9] {
+            let summary = archive_files(
+                src_dir.path(),
+                &files_paths,
+                &Archive::Zip(archive_path.clone()),
+                Some(compression),
+                None,
+                None,
+                SymlinkPolicy::Skip,
+            )
+            .unwrap();
+
+            assert_eq!(summary.archived_files, 2);
+            let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+            let mut notes = String::new();
+            io::Read::read_to_string(&mut archive.by_name("docs/notes.txt").unwrap(), &mut notes)
+                .unwrap();
+            assert_eq!(notes, "notes ".repeat(100));
+            assert_eq!(archive.by_name("photo.jpg").unwrap().size(), 5);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archive_files_zip_stores_exact_times_and_links() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let files_paths = [
+            src_dir.path().join("notes.txt"),
+            src_dir.path().join("link.txt"),
+        ];
+        fs::write(&files_paths[0], "notes").unwrap();
+        symlink("notes.txt", &files_paths[1]).unwrap();
+        let modified = UNIX_EPOCH + std::time::Duration::new(1_700_000_001, 123_456_700);
+        File::options()
+            .write(true)
+            .open(&files_paths[0])
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::set_permissions(&files_paths[0], fs::Permissions::from_mode(0o640)).unwrap();
+        let archive_path = dst_dir.path().join("backup.zip");
+
+        archive_files(
+            src_dir.path(),
+            &files_paths,
+            &Archive::Zip(archive_path.clone()),
+            None,
+            None,
+            None,
+            SymlinkPolicy::CopyLink,
+        )
+        .unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let notes = archive.by_name("notes.txt").unwrap();
+        assert_eq!(notes.unix_mode().unwrap() & 0o777, 0o640);
+        let mut times = (None, None);
+        for field in notes.extra_data_fields() {
+            match field {
+                zip::extra_fields::ExtraField::ExtendedTimestamp(timestamp) => {
+                    times.0 = timestamp.mod_time()
+                }
+                zip::extra_fields::ExtraField::Ntfs(ntfs) => times.1 = Some(ntfs.mtime()),
+                _ => {}
+            }
+        }
+        assert_eq!(times.0, Some(1_700_000_001));
+        assert_eq!(times.1, windows_file_time(modified));
+        drop(notes);
+        let mut link = archive.by_name("link.txt").unwrap();
+        assert!(link.is_symlink());
+        let mut target = String::new();
+        io::Read::read_to_string(&mut link, &mut target).unwrap();
+        assert_eq!(target, "notes.txt");
+    }
+
+    #[test]
+    fn archive_files_encrypted_zip() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let files_paths = [src_dir.path().join("tax_return.pdf")];
+        fs::write(&files_paths[0], "tax return").unwrap();
+        let archive_path = dst_dir.path().join("taxes.zip");
+        let identity = age::x25519::Identity::generate();
+        let encryption = Encryption::Age(identity.to_public());
+
+        archive_files(
+            src_dir.path(),
+            &files_paths,
+            &Archive::Zip(archive_path.clone()),
+            None,
+            None,
+            Some(&encryption),
+            SymlinkPolicy::Skip,
+        )
+        .unwrap();
+
+        let encrypted = fs::read(&archive_path).unwrap();
+        assert!(!encrypted.windows(10).any(|window| window == b"tax return"));
+        let mut reader = age::Decryptor::new(encrypted.as_slice())
+            .unwrap()
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap();
+        let mut decrypted = Vec::new();
+        io::Read::read_to_end(&mut reader, &mut decrypted).unwrap();
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(decrypted)).unwrap();
+        let mut tax_return = String::new();
+        io::Read::read_to_string(
+            &mut archive.by_name("tax_return.pdf").unwrap(),
+            &mut tax_return,
+        )
+        .unwrap();
+        assert_eq!(tax_return, "tax return");
+    }
+}
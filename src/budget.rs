@@ -0,0 +1,125 @@
+use crate::catalog;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+///
+/// Files selected to fit into the size budget.
+///
+#[derive(Default)]
+pub struct BudgetSelection {
+    /// Files that fit into the budget.
+    pub selected_files: Vec<PathBuf>,
+    /// Sum of sizes of selected files.
+    pub selected_size: u64,
+    /// Files left out because they didn't fit.
+    pub dropped_files: Vec<PathBuf>,
+    /// Sum of sizes of dropped files.
+    pub dropped_size: u64,
+}
+
+///
+/// Select files whose total size doesn't exceed max_total_size.
+///
+/// Files are taken in order of their priority (lower number is more
+/// important) and, with equal priority, in their original order.
+/// File that doesn't fit is dropped but less important smaller files
+/// can still fill the remaining space. Selected files keep their
+/// original order.
+/// When there's problem with reading file metadata, error is logged
+/// and file is dropped.
+///
+pub fn select_within_budget(
+    files_paths: Vec<PathBuf>,
+    max_total_size: u64,
+    priority: impl Fn(&Path) -> usize,
+) -> BudgetSelection {
+    let mut files = files_paths
+        .into_iter()
+        .enumerate()
+        .map(|(position, path)| {
+            let size = match fs::metadata(&path) {
+                Ok(metadata) => Some(metadata.len()),
+                Err(err) => {
+                    log::warn!(
+                        target: catalog::UNREADABLE_ENTRY,
+                        "{}; {err}",
+                        path.to_string_lossy()
+                    );
+                    None
+                }
+            };
+            (priority(&path), position, path, size)
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|(priority, position, _, _)| (*priority, *position));
+
+    let mut selection = BudgetSelection::default();
+    let mut selected = Vec::new();
+    for (_, position, path, size) in files {
+        match size {
+            Some(size) if selection.selected_size + size <= max_total_size => {
+                selection.selected_size += size;
+                selected.push((position, path));
+            }
+            size => {
+                selection.dropped_size += size.unwrap_or_default();
+                selection.dropped_files.push(path);
+            }
+        }
+    }
+    selected.sort_by_key(|(position, _)| *position);
+    selection.selected_files = selected.into_iter().map(|(_, path)| path).collect();
+
+    selection
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn select_within_budget_prefers_priority() {
+        let dir = TempDir::new().unwrap();
+        let paths = ["photo.jpg", "report.docx", "notes.txt"]
+            .into_iter()
+            .map(|name| dir.path().join(name))
+            .collect::<Vec<_>>();
+        fs::write(&paths[0], vec![0u8; 60]).unwrap();
+        fs::write(&paths[1], vec![0u8; 50]).unwrap();
+        fs::write(&paths[2], vec![0u8; 40]).unwrap();
+        let priority = |path: &Path| match path.extension().unwrap().to_str().unwrap() {
+            "docx" => 0,
+            "txt" => 1,
+            _ => 2,
+        };
+
+        let selection = select_within_budget(paths.clone(), 100, priority);
+
+        assert_eq!(
+            selection.selected_files,
+            vec![paths[1].clone(), paths[2].clone()]
+        );
+        assert_eq!(selection.selected_size, 90);
+        assert_eq!(selection.dropped_files, vec![paths[0].clone()]);
+        assert_eq!(selection.dropped_size, 60);
+    }
+
+    #[test]
+    fn select_within_budget_smaller_files_fill_space() {
+        let dir = TempDir::new().unwrap();
+        let paths = ["big", "small"]
+            .into_iter()
+            .map(|name| dir.path().join(name))
+            .collect::<Vec<_>>();
+        fs::write(&paths[0], vec![0u8; 200]).unwrap();
+        fs::write(&paths[1], vec![0u8; 10]).unwrap();
+
+        let selection = select_within_budget(paths.clone(), 100, |_| 0);
+
+        assert_eq!(selection.selected_files, vec![paths[1].clone()]);
+        assert_eq!(selection.dropped_files, vec![paths[0].clone()]);
+    }
+}
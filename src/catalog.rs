@@ -0,0 +1,484 @@
+use crate::exit_code::ExitCode;
+
+///
+/// Documentation of warning or error with stable identifier.
+///
+/// Identifiers are used as targets of log records, so they're
+/// written in every log line and in JSON logs. RUST_LOG filters
+/// these records by identifier (e.g. 'SMF-E009=off') instead of module.
+///
+pub struct CatalogEntry {
+    pub id: &'static str,
+    pub summary: &'static str,
+    pub cause: &'static str,
+    pub remediation: &'static str,
+}
+
+pub const UNREADABLE_ENTRY: &str = "SMF-E001";
+pub const TYPE_DETECTION_FAILED: &str = "SMF-E002";
+pub const HASH_FAILED: &str = "SMF-E003";
+pub const INVALID_EXCLUSION: &str = "SMF-E004";
+pub const REDUNDANT_EXCLUSION: &str = "SMF-E005";
+pub const INTERRUPTED: &str = "SMF-E006";
+pub const SPECIAL_FILE: &str = "SMF-E007";
+pub const CLOUD_PLACEHOLDER: &str = "SMF-E008";
+pub const LOCKED_FILE: &str = "SMF-E009";
+pub const SEAL_FAILED: &str = "SMF-E010";
+pub const HARD_LINK_FAILED: &str = "SMF-E011";
+pub const DATABASE_NOT_CONSISTENT: &str = "SMF-E012";
+pub const OUT_OF_SPACE: &str = "SMF-E013";
+pub const COPY_FAILED: &str = "SMF-E014";
+pub const TIMESTAMPS_NOT_PRESERVED: &str = "SMF-E015";
+pub const SYMLINK_FAILED: &str = "SMF-E016";
+pub const INVALID_LISTED_FILE: &str = "SMF-E017";
+pub const INVALID_INCLUDE_PATH: &str = "SMF-E018";
+pub const INTERRUPT_HANDLER_FAILED: &str = "SMF-E019";
+pub const OVER_BUDGET: &str = "SMF-E020";
+pub const UNREADABLE_FILE: &str = "SMF-E021";
+pub const SPACE_CHECK_IGNORED: &str = "SMF-E022";
+pub const TEMP_DIR_ON_OTHER_FILESYSTEM: &str = "SMF-E023";
+pub const NAMES_SHORTENED: &str = "SMF-E024";
+pub const MANIFEST_NOT_WRITTEN: &str = "SMF-E025";
+pub const PARTIAL_COPY: &str = "SMF-E026";
+pub const INVALID_OVERRIDES: &str = "SMF-E027";
+pub const STREAMS_NOT_COPIED: &str = "SMF-E028";
+pub const INVALID_SUFFIX: &str = "SMF-E029";
+pub const REDUNDANT_SUFFIX: &str = "SMF-E030";
+pub const COPY_DIFFERS: &str = "SMF-E031";
+pub const RUN_FAILED: &str = "SMF-E032";
+pub const INVALID_ARGUMENTS: &str = "SMF-E033";
+pub const NOT_ENOUGH_SPACE: &str = "SMF-E034";
+pub const VERIFICATION_FAILED: &str = "SMF-E035";
+pub const DRIVE_TOO_HOT: &str = "SMF-E036";
+pub const TEMPERATURE_UNAVAILABLE: &str = "SMF-E037";
+pub const OS_BACKUP_INTEGRATION_FAILED: &str = "SMF-E038";
+pub const ACCESS_TIMES_NOT_RECORDED: &str = "SMF-E039";
+pub const BACKUP_PRIVILEGES_UNAVAILABLE: &str = "SMF-E040";
+pub const MIRROR_DELETE_FAILED: &str = "SMF-E041";
+pub const MIRROR_NOT_CONFIRMED: &str = "SMF-E042";
+pub const CHANGED_SINCE_SCAN: &str = "SMF-E043";
+pub const CHANGED_WHILE_COPYING: &str = "SMF-E044";
+pub const PERMISSIONS_NOT_PRESERVED: &str = "SMF-E045";
+pub const HTML_REPORT_NOT_WRITTEN: &str = "SMF-E046";
+pub const IMPORTED_FILE_MISSING: &str = "SMF-E047";
+pub const FILTER_NOT_EXPORTABLE: &str = "SMF-E048";
+pub const XATTRS_NOT_PRESERVED: &str = "SMF-E049";
+pub const SOURCE_NOT_REMOVED: &str = "SMF-E050";
+pub const STATE_NOT_WRITTEN: &str = "SMF-E051";
+pub const ACL_NOT_PRESERVED: &str = "SMF-E052";
+pub const METADATA_NOT_PRESERVED: &str = "SMF-E053";
+
+/// Every warning and error the application reports.
+pub const CATALOG: [CatalogEntry; 53] = [
+    CatalogEntry {
+        id: UNREADABLE_ENTRY,
+        summary: "Entry of src_directory can't be read",
+        cause: "Directory can't be listed or metadata of file can't be read, \
+                usually because of missing permissions or a broken link.",
+        remediation: "Check permissions of the path or exclude it.",
+    },
+    CatalogEntry {
+        id: TYPE_DETECTION_FAILED,
+        summary: "Type of file content can't be detected",
+        cause: "File can't be opened or read while its type is checked.",
+        remediation: "Check permissions of the file or don't filter by file type.",
+    },
+    CatalogEntry {
+        id: HASH_FAILED,
+        summary: "File can't be hashed",
+        cause: "File can't be read while looking for duplicates.",
+        remediation: "Check permissions of the file. It's treated as unique.",
+    },
+    CatalogEntry {
+        id: INVALID_EXCLUSION,
+        summary: "Exclusion is ignored",
+        cause: "Exclusion is invalid glob, relative path outside of src_directory \
+                or path that doesn't exist.",
+        remediation: "Fix or remove the exclusion.",
+    },
+    CatalogEntry {
+        id: REDUNDANT_EXCLUSION,
+        summary: "Exclusion has no effect",
+        cause: "Exclusion is duplicated or inside other excluded directory.",
+        remediation: "Remove the exclusion.",
+    },
+    CatalogEntry {
+        id: INTERRUPTED,
+        summary: "Run is interrupted",
+        cause: "Ctrl+C was pressed. Files being copied are finished \
+                and remaining files are skipped.",
+        remediation: "Run again to copy remaining files. \
+                      Press Ctrl+C again to stop immediately.",
+    },
+    CatalogEntry {
+        id: SPECIAL_FILE,
+        summary: "Special file is skipped",
+        cause: "FIFOs, sockets and devices can't be copied.",
+        remediation: "Nothing to do. Use '--special-files error' \
+                      to stop when such files are found.",
+    },
+    CatalogEntry {
+        id: CLOUD_PLACEHOLDER,
+        summary: "Cloud placeholder is skipped",
+        cause: "File is available online only and reading it would download it.",
+        remediation: "Make the file available offline or run without '--skip-placeholders'.",
+    },
+    CatalogEntry {
+        id: LOCKED_FILE,
+        summary: "Locked file is skipped",
+        cause: "File is locked or opened without sharing by other process.",
+        remediation: "Close application that uses the file \
+                      or run without '--skip-locked'.",
+    },
+    CatalogEntry {
+        id: SEAL_FAILED,
+        summary: "dst_directory isn't sealed",
+        cause: "Some files failed to copy or entries can't be made read-only.",
+        remediation: "Fix failed copies and check permissions of dst_directory, then run again.",
+    },
+    CatalogEntry {
+        id: HARD_LINK_FAILED,
+        summary: "Hard link can't be recreated",
+        cause: "Filesystem of dst_directory doesn't support hard links \
+                or link can't be created.",
+        remediation: "Nothing to do when file was copied instead. \
+                      Otherwise check permissions of dst_directory.",
+    },
+    CatalogEntry {
+        id: DATABASE_NOT_CONSISTENT,
+        summary: "Database can't be copied consistently",
+        cause: "SQLite backup failed or database server has its files open.",
+        remediation: "Stop application or server using the database and run again.",
+    },
+    CatalogEntry {
+        id: OUT_OF_SPACE,
+        summary: "dst_directory ran out of space while copying",
+        cause: "Files need more space than dst_directory has left.",
+        remediation: "Free some space in dst_directory and continue \
+                      or select fewer files.",
+    },
+    CatalogEntry {
+        id: COPY_FAILED,
+        summary: "File can't be copied",
+        cause: "File can't be read or destination can't be written, usually because \
+                of permissions, invalid names or damaged media.",
+        remediation: "Check the error message, fix the cause and run again.",
+    },
+    CatalogEntry {
+        id: TIMESTAMPS_NOT_PRESERVED,
+        summary: "Timestamps of copy aren't preserved",
+        cause: "Filesystem of dst_directory doesn't allow setting timestamps.",
+        remediation: "Nothing to do. Copy has time of copying as its timestamps.",
+    },
+    CatalogEntry {
+        id: SYMLINK_FAILED,
+        summary: "Symbolic link can't be recreated",
+        cause: "Link can't be read or existing destination can't be replaced.",
+        remediation: "Check permissions of the link and dst_directory.",
+    },
+    CatalogEntry {
+        id: INVALID_LISTED_FILE,
+        summary: "Listed file is skipped",
+        cause: "Path in 'files_from' doesn't exist, isn't a file \
+                or is outside of src_directory.",
+        remediation: "Fix the list of files.",
+    },
+    CatalogEntry {
+        id: INVALID_INCLUDE_PATH,
+        summary: "Included path is skipped",
+        cause: "Path in 'include_paths_file' doesn't exist or is outside of src_directory.",
+        remediation: "Fix the included paths file.",
+    },
+    CatalogEntry {
+        id: INTERRUPT_HANDLER_FAILED,
+        summary: "Ctrl+C can't be handled",
+        cause: "Handler of Ctrl+C can't be installed.",
+        remediation: "Nothing to do. Ctrl+C stops the run immediately.",
+    },
+    CatalogEntry {
+        id: OVER_BUDGET,
+        summary: "Files don't fit into max_total_size",
+        cause: "Selected files are bigger than '--max-total-size'.",
+        remediation: "Increase the size or move important suffixes earlier.",
+    },
+    CatalogEntry {
+        id: UNREADABLE_FILE,
+        summary: "File is unreadable",
+        cause: "File can't be read, possibly because of damaged media.",
+        remediation: "Try to recover the file with specialized tools or exclude it.",
+    },
+    CatalogEntry {
+        id: SPACE_CHECK_IGNORED,
+        summary: "Space check is ignored",
+        cause: "Files don't fit into dst_directory but '--ignore-space-check' is present.",
+        remediation: "Nothing to do. Copying fails when space runs out.",
+    },
+    CatalogEntry {
+        id: TEMP_DIR_ON_OTHER_FILESYSTEM,
+        summary: "temp_dir isn't used",
+        cause: "temp_dir is on different filesystem than dst_directory, \
+                so files can't be renamed into place.",
+        remediation: "Use temp_dir on the same filesystem as dst_directory.",
+    },
+    CatalogEntry {
+        id: NAMES_SHORTENED,
+        summary: "Names of files are shortened or sanitized",
+        cause: "Names are longer than dst_directory filesystem allows \
+                or contain characters it doesn't allow ('sanitize_names').",
+        remediation: "Original names are recorded at dst_directory \
+                      and can be used to rename files back.",
+    },
+    CatalogEntry {
+        id: MANIFEST_NOT_WRITTEN,
+        summary: "Manifest isn't written",
+        cause: "Manifest file can't be written to dst_directory.",
+        remediation: "Check permissions and free space of dst_directory.",
+    },
+    CatalogEntry {
+        id: PARTIAL_COPY,
+        summary: "Some files failed to copy",
+        cause: "Copying of some files failed. Each of them is reported separately.",
+        remediation: "Fix causes of reported failures and run again.",
+    },
+    CatalogEntry {
+        id: INVALID_OVERRIDES,
+        summary: "Directory rules are ignored",
+        cause: "Rules file of directory can't be read or contains invalid line.",
+        remediation: "Fix the rules file.",
+    },
+    CatalogEntry {
+        id: STREAMS_NOT_COPIED,
+        summary: "Alternate data streams aren't copied",
+        cause: "Filesystem of dst_directory doesn't support NTFS streams \
+                or streams can't be listed.",
+        remediation: "Copy to NTFS drive when streams are important.",
+    },
+    CatalogEntry {
+        id: INVALID_SUFFIX,
+        summary: "Suffix is ignored",
+        cause: "Line of suffixes file or scope is invalid.",
+        remediation: "Fix the suffixes file.",
+    },
+    CatalogEntry {
+        id: REDUNDANT_SUFFIX,
+        summary: "Suffix has no effect",
+        cause: "Suffix is duplicated or covered by other suffix.",
+        remediation: "Remove the suffix.",
+    },
+    CatalogEntry {
+        id: COPY_DIFFERS,
+        summary: "Copy differs from its source",
+        cause: "Content of copied file doesn't match source or can't be read, \
+                possibly because of failing destination drive.",
+        remediation: "Check destination drive and copy files again.",
+    },
+    CatalogEntry {
+        id: RUN_FAILED,
+        summary: "Run failed",
+        cause: "Unexpected error stopped the run.",
+        remediation: "Check the error message and run again.",
+    },
+    CatalogEntry {
+        id: INVALID_ARGUMENTS,
+        summary: "Arguments or rules files are invalid",
+        cause: "Some argument or rules file can't be used.",
+        remediation: "Fix the reported argument or file.",
+    },
+    CatalogEntry {
+        id: NOT_ENOUGH_SPACE,
+        summary: "There's not enough space in dst_directory",
+        cause: "Selected files need more space than dst_directory has available.",
+        remediation: "Free some space, follow the logged advice or select fewer files.",
+    },
+    CatalogEntry {
+        id: VERIFICATION_FAILED,
+        summary: "Verification of copied files failed",
+        cause: "Some copies differ from their sources.",
+        remediation: "Check destination drive and copy files again.",
+    },
+    CatalogEntry {
+        id: DRIVE_TOO_HOT,
+        summary: "Copying is paused because destination drive is too hot",
+        cause: "Temperature of destination drive exceeds '--max-drive-temperature'.",
+        remediation: "Nothing to do. Copying resumes when drive cools down. \
+                      Improve cooling of the drive to avoid pauses.",
+    },
+    CatalogEntry {
+        id: TEMPERATURE_UNAVAILABLE,
+        summary: "Temperature of destination drive can't be read",
+        cause: "Drive or operating system doesn't expose temperature sensor. \
+                It's read only on Linux from hwmon sensors.",
+        remediation: "On Linux load 'drivetemp' module for SATA drives. \
+                      Copying isn't throttled otherwise.",
+    },
+    CatalogEntry {
+        id: OS_BACKUP_INTEGRATION_FAILED,
+        summary: "Integration with backup facilities of the operating system failed",
+        cause: "On macOS 'tmutil' couldn't exclude destination from Time Machine. \
+                On Windows run couldn't be written to Application event log.",
+        remediation: "Check permissions to run 'tmutil' or to write event log. \
+                      Files are copied regardless.",
+    },
+    CatalogEntry {
+        id: ACCESS_TIMES_NOT_RECORDED,
+        summary: "Access times of source files aren't updated",
+        cause: "'--accessed-within' is used but src_directory is on filesystem \
+                mounted with 'noatime', so access times show when files were created.",
+        remediation: "Remount filesystem with 'relatime' or 'strictatime' \
+                      and wait until files are used before relying on the filter.",
+    },
+    CatalogEntry {
+        id: BACKUP_PRIVILEGES_UNAVAILABLE,
+        summary: "Backup privileges can't be enabled",
+        cause: "'--backup-privileges' is used but process isn't elevated on Windows \
+                or lacks CAP_DAC_READ_SEARCH capability on Linux.",
+        remediation: "Run as administrator on Windows, as root or with capability granted \
+                      by 'setcap cap_dac_read_search+ep' on Linux. Files are read \
+                      with normal permissions otherwise.",
+    },
+    CatalogEntry {
+        id: MIRROR_DELETE_FAILED,
+        summary: "File that's no longer selected can't be deleted from destination",
+        cause: "File in dst_directory is read-only, in use or not accessible \
+                (e.g. dst_directory was sealed) or drive has no trash ('use_trash').",
+        remediation: "Delete the file manually or fix its permissions and run again.",
+    },
+    CatalogEntry {
+        id: MIRROR_NOT_CONFIRMED,
+        summary: "Files are not deleted because deletion wasn't confirmed",
+        cause: "'--mirror' previews files to delete and requires confirmation. \
+                It was declined or standard input isn't a terminal.",
+        remediation: "Review the listed files and confirm deletion, or pass '--yes' \
+                      to delete without asking in unattended runs.",
+    },
+    CatalogEntry {
+        id: CHANGED_SINCE_SCAN,
+        summary: "Source file changed after it was found",
+        cause: "Size or modification time of the file differs from the moment \
+                it was found, so space checks and listings may be outdated.",
+        remediation: "Nothing to do with '--on-change recopy' or 'warn', \
+                      current content is copied. With 'skip' file isn't copied.",
+    },
+    CatalogEntry {
+        id: CHANGED_WHILE_COPYING,
+        summary: "Source file changed while it was copied",
+        cause: "File was written by another program during copying, \
+                so its copy may mix old and new content.",
+        remediation: "Check copies marked with 'changed_while_copying' in the manifest. \
+                      Close programs writing the files or use '--on-change recopy'.",
+    },
+    CatalogEntry {
+        id: PERMISSIONS_NOT_PRESERVED,
+        summary: "Permissions of copied file or directory can't be preserved",
+        cause: "dst_directory is on filesystem without Unix permissions (e.g. FAT, exFAT) \
+                or the user doesn't own the copy.",
+        remediation: "Use filesystem that stores permissions for dst_directory. \
+                      Files are copied regardless.",
+    },
+    CatalogEntry {
+        id: HTML_REPORT_NOT_WRITTEN,
+        summary: "HTML report isn't written",
+        cause: "File given with 'report_html' can't be written.",
+        remediation: "Check that its directory exists and is writable.",
+    },
+    CatalogEntry {
+        id: IMPORTED_FILE_MISSING,
+        summary: "File listed in imported log is missing from dst_directory",
+        cause: "File was deleted or moved after the backup was made, or the log \
+                belongs to different backup.",
+        remediation: "Check that dst_directory is the backup the log was written for. \
+                      Missing files are left out of the manifest.",
+    },
+    CatalogEntry {
+        id: FILTER_NOT_EXPORTABLE,
+        summary: "Rule can't be exported as filter of other tool",
+        cause: "The tool has no equivalent of the rule (e.g. regular expressions, \
+                scopes or ordered exclusions in robocopy).",
+        remediation: "Check files selected by the tool or simplify the rule. \
+                      Exported filters select more or fewer files than this application.",
+    },
+    CatalogEntry {
+        id: XATTRS_NOT_PRESERVED,
+        summary: "Extended attributes of copied file can't be preserved",
+        cause: "dst_directory is on filesystem without extended attributes (e.g. FAT, exFAT, \
+                some network shares) or attribute is too large for it.",
+        remediation: "Use filesystem that stores extended attributes for dst_directory. \
+                      File content is copied regardless.",
+    },
+    CatalogEntry {
+        id: SOURCE_NOT_REMOVED,
+        summary: "Moved file can't be removed from source",
+        cause: "File is read-only, in use by other program or the user \
+                can't modify its directory.",
+        remediation: "File is already copied, so remove it manually \
+                      or run again after closing programs that use it.",
+    },
+    CatalogEntry {
+        id: STATE_NOT_WRITTEN,
+        summary: "Progress of the run can't be recorded",
+        cause: "dst_directory is read-only or out of space, or state file \
+                is used by other program.",
+        remediation: "Files are copied regardless, but interrupted run \
+                      can't be resumed and has to be started again.",
+    },
+    CatalogEntry {
+        id: ACL_NOT_PRESERVED,
+        summary: "Access control list of copied file can't be preserved",
+        cause: "Source ACL can't be read or dst_directory is on filesystem \
+                without ACLs (e.g. FAT, exFAT, some network shares).",
+        remediation: "Use NTFS for dst_directory and run as administrator \
+                      with 'backup_privileges'. File content is copied regardless.",
+    },
+    CatalogEntry {
+        id: METADATA_NOT_PRESERVED,
+        summary: "macOS metadata of copied file can't be preserved",
+        cause: "dst_directory is on filesystem without extended attributes \
+                or creation dates (e.g. FAT, exFAT, some network shares).",
+        remediation: "Use APFS or HFS+ for dst_directory. File content is copied regardless.",
+    },
+];
+
+///
+/// Find entry with identifier ignoring case.
+///
+pub fn find_entry(id: &str) -> Option<&'static CatalogEntry> {
+    CATALOG
+        .iter()
+        .find(|entry| entry.id.eq_ignore_ascii_case(id))
+}
+
+///
+/// Identifier of the error that ended the run with code.
+///
+pub fn run_error_id(code: ExitCode) -> &'static str {
+    match code {
+        ExitCode::InvalidArguments => INVALID_ARGUMENTS,
+        ExitCode::NotEnoughSpace => NOT_ENOUGH_SPACE,
+        ExitCode::VerificationFailure => VERIFICATION_FAILED,
+        ExitCode::PartialCopyFailure => PARTIAL_COPY,
+        ExitCode::Interrupted => INTERRUPTED,
+        ExitCode::Success | ExitCode::Failure => RUN_FAILED,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn catalog_ids_are_unique_and_numbered() {
+        let ids = CATALOG.iter().map(|entry| entry.id).collect::<HashSet<_>>();
+
+        assert_eq!(ids.len(), CATALOG.len());
+        for (index, entry) in CATALOG.iter().enumerate() {
+            assert_eq!(entry.id, format!("SMF-E{:03}", index + 1));
+        }
+    }
+
+    #[test]
+    fn find_entry_ignores_case() {
+        assert_eq!(find_entry("smf-e012").unwrap().id, DATABASE_NOT_CONSISTENT);
+        assert!(find_entry("SMF-E999").is_none());
+    }
+}
@@ -0,0 +1,84 @@
+use clap::ValueEnum;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+///
+/// What to do with files that change while the run copies them.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ChangePolicy {
+    /// Copy files again until they stop changing.
+    Recopy,
+    /// Leave out files that changed.
+    Skip,
+    /// Keep copies and report files that changed.
+    Warn,
+}
+
+///
+/// Size and modification time of file used to detect its changes.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileState {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+///
+/// How changes of source files are detected and handled.
+///
+pub struct ChangeDetection {
+    pub policy: ChangePolicy,
+    /// States of files when they were found.
+    pub scanned: HashMap<PathBuf, FileState>,
+}
+
+impl ChangeDetection {
+    ///
+    /// Record states of files found to be copied.
+    ///
+    /// Files whose metadata can't be read are not recorded.
+    ///
+    pub fn new(policy: ChangePolicy, paths: &[PathBuf]) -> Self {
+        let scanned = paths
+            .par_iter()
+            .filter_map(|path| Some((path.clone(), file_state(path)?)))
+            .collect();
+        Self { policy, scanned }
+    }
+}
+
+///
+/// Read current state of the file.
+///
+pub fn file_state(path: &Path) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(FileState {
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn change_detection_records_file_states() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(&file, "text").unwrap();
+        let paths = [file.path().to_path_buf()];
+
+        let detection = ChangeDetection::new(ChangePolicy::Warn, &paths);
+        fs::write(&file, "longer text").unwrap();
+
+        assert_eq!(detection.scanned[&paths[0]].size, 4);
+        assert_ne!(Some(detection.scanned[&paths[0]]), file_state(&paths[0]));
+    }
+}
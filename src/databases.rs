@@ -0,0 +1,121 @@
+use anyhow::Result;
+use rusqlite::{Connection, OpenFlags, MAIN_DB};
+use std::{ffi::OsStr, fs::File, io::Read, path::Path, time::Duration};
+
+/// Every SQLite database file starts with this header.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Endings of files SQLite keeps next to database while it's used.
+const SQLITE_SIDECAR_SUFFIXES: [&str; 3] = ["-wal", "-shm", "-journal"];
+
+/// Extensions of database files that can't be copied consistently
+/// while their database server is running.
+const SERVER_DATABASE_EXTENSIONS: [&str; 5] = ["mdf", "ldf", "ibd", "accdb", "mdb"];
+
+/// How long backup waits for other connections to release the database.
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+///
+/// Check whether file is SQLite database by its header.
+///
+pub fn is_sqlite_database(path: &Path) -> bool {
+    let mut header = [0; SQLITE_HEADER.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| &header == SQLITE_HEADER)
+}
+
+///
+/// Check whether file is journal, WAL or shared memory file
+/// of SQLite database next to it.
+///
+pub fn is_sqlite_sidecar(path: &Path) -> bool {
+    let Some(filename) = path.file_name().and_then(OsStr::to_str) else {
+        return false;
+    };
+
+    SQLITE_SIDECAR_SUFFIXES
+        .iter()
+        .filter_map(|suffix| filename.strip_suffix(suffix))
+        .any(|database| is_sqlite_database(&path.with_file_name(database)))
+}
+
+///
+/// Check whether file belongs to database server (SQL Server,
+/// MySQL, Access) judging by its extension.
+///
+pub fn is_server_database(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            SERVER_DATABASE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+///
+/// Copy SQLite database using its backup API so the copy
+/// is consistent even when database is being written to.
+///
+/// Content of WAL file is included in the copy.
+///
+/// #### Errors
+/// This function returns error when database can't be opened
+/// or is locked for longer than [SQLITE_BUSY_TIMEOUT].
+///
+pub fn backup_sqlite_database(src: &Path, dst: &Path) -> Result<()> {
+    let connection = Connection::open_with_flags(
+        src,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    connection.busy_timeout(SQLITE_BUSY_TIMEOUT)?;
+    connection.backup(MAIN_DB, dst, None)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn backup_sqlite_database_with_wal() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("app.db");
+        let dst = dir.path().join("copy.db");
+        let connection = Connection::open(&src).unwrap();
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .unwrap();
+        connection
+            .execute_batch("CREATE TABLE notes (text TEXT); INSERT INTO notes VALUES ('saved');")
+            .unwrap();
+
+        assert!(is_sqlite_database(&src));
+        assert!(is_sqlite_sidecar(&dir.path().join("app.db-wal")));
+        backup_sqlite_database(&src, &dst).unwrap();
+
+        let copy = Connection::open(&dst).unwrap();
+        let text: String = copy
+            .query_row("SELECT text FROM notes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(text, "saved");
+    }
+
+    #[test]
+    fn is_sqlite_database_other_files() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("fake.db");
+        fs::write(&path, "not a database").unwrap();
+
+        assert!(!is_sqlite_database(&path));
+        assert!(!is_sqlite_sidecar(&dir.path().join("fake.db-wal")));
+    }
+
+    #[test]
+    fn is_server_database_extensions() {
+        assert!(is_server_database(Path::new("data/Sales.MDF")));
+        assert!(!is_server_database(Path::new("data/notes.txt")));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{privileges::open_for_reading, rate_limit::RateLimiter};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Size of blocks of existing copy that are looked for in src.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Size of chunks in which src is read.
+const READ_SIZE: usize = 16 * BLOCK_SIZE;
+
+/// Block of existing copy of src.
+struct Block {
+    offset: u64,
+    length: usize,
+    hash: blake3::Hash,
+}
+
+///
+/// Weak checksum of window of bytes used by rsync. Window can be moved
+/// by one byte without reading all of its bytes again, so blocks of copy
+/// are found in src at any offset. Blocks with equal checksums are
+/// compared by their hashes.
+///
+#[derive(Clone, Copy)]
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    length: u32,
+}
+
+impl RollingChecksum {
+    fn new(window: &[u8]) -> Self {
+        let length = window.len() as u32;
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for (index, &byte) in window.iter().enumerate() {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add((length - index as u32).wrapping_mul(byte as u32));
+        }
+
+        Self { a, b, length }
+    }
+
+    ///
+    /// Move window by one byte, removing its first byte and adding the next one.
+    ///
+    fn roll(&mut self, removed: u8, added: u8) {
+        self.a = self
+            .a
+            .wrapping_sub(removed as u32)
+            .wrapping_add(added as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.length.wrapping_mul(removed as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn value(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+///
+/// Write new copy of src from blocks of its existing copy dst like rsync
+/// does and return number of bytes taken from src.
+///
+/// Blocks of the copy are found anywhere in src with rolling checksums,
+/// so data inserted into src or removed from it doesn't make the rest
+/// of src differ. New copy is assembled in new_copy (usually temporary
+/// file next to dst that replaces it later) from blocks of dst and parts
+/// of src that aren't in it, dst isn't changed. Permissions are set
+/// to those of src.
+///
+/// #### Errors
+/// This function returns error when files can't be read or new_copy can't be written.
+///
+pub fn copy_delta(
+    src: &Path,
+    dst: &Path,
+    new_copy: &Path,
+    rate_limiter: Option<&RateLimiter>,
+) -> io::Result<u64> {
+    let mut src_file = open_for_reading(src)?;
+    let mut dst_file = File::open(dst)?;
+    let blocks = read_blocks(&mut dst_file)?;
+    let mut new_file = File::create(new_copy)?;
+
+    let mut buffer = Vec::with_capacity(READ_SIZE + BLOCK_SIZE);
+    let mut block_buffer = vec![0; BLOCK_SIZE];
+    // Bytes of buffer before position that weren't found in dst start at literal_start
+    let mut literal_start = 0;
+    let mut position = 0;
+    let mut checksum: Option<RollingChecksum> = None;
+    let mut end_of_src = false;
+    let mut bytes_taken = 0;
+    loop {
+        if !end_of_src && buffer.len() - position <= BLOCK_SIZE {
+            bytes_taken += write_literal(
+                &buffer[literal_start..position],
+                &mut new_file,
+                rate_limiter,
+            )?;
+            buffer.drain(..position);
+            literal_start = 0;
+            position = 0;
+            let length = buffer.len();
+            buffer.resize(length + READ_SIZE, 0);
+            let read_bytes = read_block(&mut src_file, &mut buffer[length..])?;
+            buffer.truncate(length + read_bytes);
+            end_of_src = read_bytes < READ_SIZE;
+        }
+
+        let window_end = buffer.len().min(position + BLOCK_SIZE);
+        if position == window_end {
+            break;
+        }
+        let window = &buffer[position..window_end];
+        let current = *checksum.get_or_insert_with(|| RollingChecksum::new(window));
+        if let Some(block) = find_block(&blocks, current, window) {
+            bytes_taken += write_literal(
+                &buffer[literal_start..position],
+                &mut new_file,
+                rate_limiter,
+            )?;
+            if let Some(rate_limiter) = rate_limiter {
+                rate_limiter.acquire(block.length as u64);
+            }
+            dst_file.seek(SeekFrom::Start(block.offset))?;
+            dst_file.read_exact(&mut block_buffer[..block.length])?;
+            new_file.write_all(&block_buffer[..block.length])?;
+            position = window_end;
+            literal_start = position;
+            checksum = None;
+            continue;
+        }
+        // Window reached the end of src
+        if window_end == buffer.len() {
+            break;
+        }
+        if let Some(checksum) = checksum.as_mut() {
+            checksum.roll(buffer[position], buffer[window_end]);
+        }
+        position += 1;
+    }
+    bytes_taken += write_literal(&buffer[literal_start..], &mut new_file, rate_limiter)?;
+    new_file.set_permissions(src_file.metadata()?.permissions())?;
+
+    Ok(bytes_taken)
+}
+
+///
+/// Split file into blocks and index them by their rolling checksums.
+///
+fn read_blocks(file: &mut File) -> io::Result<HashMap<u32, Vec<Block>>> {
+    let mut blocks: HashMap<u32, Vec<Block>> = HashMap::new();
+    let mut buffer = vec![0; BLOCK_SIZE];
+    let mut offset = 0;
+    loop {
+        let length = read_block(file, &mut buffer)?;
+        if length == 0 {
+            break;
+        }
+        let block = &buffer[..length];
+        blocks
+            .entry(RollingChecksum::new(block).value())
+            .or_default()
+            .push(Block {
+                offset,
+                length,
+                hash: blake3::hash(block),
+            });
+        offset += length as u64;
+    }
+
+    Ok(blocks)
+}
+
+///
+/// Find block of copy with the same content as window.
+///
+fn find_block<'a>(
+    blocks: &'a HashMap<u32, Vec<Block>>,
+    checksum: RollingChecksum,
+    window: &[u8],
+) -> Option<&'a Block> {
+    let candidates = blocks.get(&checksum.value())?;
+    let hash = blake3::hash(window);
+    candidates
+        .iter()
+        .find(|block| block.length == window.len() && block.hash == hash)
+}
+
+///
+/// Write part of src that isn't in copy and return its length.
+///
+fn write_literal(
+    literal: &[u8],
+    file: &mut File,
+    rate_limiter: Option<&RateLimiter>,
+) -> io::Result<u64> {
+    if let Some(rate_limiter) = rate_limiter {
+        rate_limiter.acquire(literal.len() as u64);
+    }
+    file.write_all(literal)?;
+
+    Ok(literal.len() as u64)
+}
+
+///
+/// Fill buffer with content of file unless its end is reached
+/// and return number of read bytes.
+///
+fn read_block(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut length = 0;
+    while length < buffer.len() {
+        match file.read(&mut buffer[length..]) {
+            Ok(0) => break,
+            Ok(read_bytes) => length += read_bytes,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(length)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn copy_delta_rewrites_changed_blocks() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("disk.img");
+        let dst = dir.path().join("copy.img");
+        let new_copy = dir.path().join("new.img");
+        let mut content = vec![1; BLOCK_SIZE * 4];
+        fs::write(&dst, &content).unwrap();
+        content[BLOCK_SIZE * 2 + 10] = 2;
+        content.extend_from_slice(&[3; 100]);
+        fs::write(&src, &content).unwrap();
+
+        let bytes_taken = copy_delta(&src, &dst, &new_copy, None).unwrap();
+
+        assert_eq!(bytes_taken, BLOCK_SIZE as u64 + 100);
+        assert_eq!(fs::read(&new_copy).unwrap(), content);
+    }
+
+    #[test]
+    fn copy_delta_truncates_copy() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("mail.mbox");
+        let dst = dir.path().join("copy.mbox");
+        let new_copy = dir.path().join("new.mbox");
+        fs::write(&src, vec![5; BLOCK_SIZE + 7]).unwrap();
+        fs::write(&dst, vec![5; BLOCK_SIZE * 3]).unwrap();
+
+        let bytes_taken = copy_delta(&src, &dst, &new_copy, None).unwrap();
+
+        assert_eq!(bytes_taken, 7);
+        assert_eq!(fs::read(&new_copy).unwrap(), vec![5; BLOCK_SIZE + 7]);
+    }
+
+    #[test]
+    fn copy_delta_finds_shifted_blocks() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("inbox.mbox");
+        let dst = dir.path().join("copy.mbox");
+        let new_copy = dir.path().join("new.mbox");
+        let old_content: Vec<u8> = (0..BLOCK_SIZE * 5 + 300)
+            .map(|index| (index * 7 % 251) as u8)
+            .collect();
+        fs::write(&dst, &old_content).unwrap();
+        let mut content = old_content.clone();
+        content.splice(BLOCK_SIZE + 123..BLOCK_SIZE + 123, [9; 1000]);
+        content.drain(BLOCK_SIZE * 4..BLOCK_SIZE * 4 + 50);
+        fs::write(&src, &content).unwrap();
+
+        let bytes_taken = copy_delta(&src, &dst, &new_copy, None).unwrap();
+
+        // Only blocks with inserted and removed bytes are taken from src
+        assert!(bytes_taken < 2 * BLOCK_SIZE as u64 + 1000);
+        assert_eq!(fs::read(&new_copy).unwrap(), content);
+        assert_eq!(fs::read(&dst).unwrap(), old_content);
+    }
+
+    #[test]
+    fn rolling_checksum_rolls_to_checksum_of_next_window() {
+        let content: Vec<u8> = (0..=255).collect();
+        let mut checksum = RollingChecksum::new(&content[..100]);
+
+        for start in 1..=156 {
+            checksum.roll(content[start - 1], content[start + 99]);
+            assert_eq!(
+                checksum.value(),
+                RollingChecksum::new(&content[start..start + 100]).value()
+            );
+        }
+    }
+}
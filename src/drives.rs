@@ -0,0 +1,79 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Value of src_directory that stands for all fixed drives.
+pub const ALL_FIXED_DRIVES: &str = "all-fixed-drives";
+
+///
+/// List root directories of all fixed (not removable,
+/// not network) drives, e.g. 'C:\' and 'D:\'.
+///
+/// #### Errors
+/// This function returns error when drives can't be listed
+/// or when platform is not Windows.
+///
+#[cfg(windows)]
+pub fn list_fixed_drives() -> Result<Vec<PathBuf>> {
+    use std::{ffi::OsStr, io, iter, os::windows::ffi::OsStrExt};
+    use windows_sys::Win32::{
+        Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives},
+        System::WindowsProgramming::DRIVE_FIXED,
+    };
+
+    // SAFETY: function has no arguments
+    let drives_mask = unsafe { GetLogicalDrives() };
+    if drives_mask == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let drives = (0..26u8)
+        .filter(|index| drives_mask & (1 << index) != 0)
+        .map(|index| format!("{}:\\", (b'A' + index) as char))
+        .filter(|root| {
+            let root = OsStr::new(root)
+                .encode_wide()
+                .chain(iter::once(0))
+                .collect::<Vec<_>>();
+            // SAFETY: root is NUL terminated wide string
+            unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_FIXED }
+        })
+        .map(PathBuf::from)
+        .collect();
+
+    Ok(drives)
+}
+
+#[cfg(not(windows))]
+pub fn list_fixed_drives() -> Result<Vec<PathBuf>> {
+    Err(anyhow::anyhow!(
+        "'{ALL_FIXED_DRIVES}' is supported only on Windows"
+    ))
+}
+
+///
+/// Name of the directory in dst_directory where files
+/// from drive are copied (e.g. 'C' for 'C:\').
+///
+pub fn drive_directory_name(drive: &Path) -> String {
+    drive
+        .to_string_lossy()
+        .trim_end_matches(['\\', '/', ':'])
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drive_directory_name_letter() {
+        assert_eq!(drive_directory_name(Path::new("C:\\")), "C");
+        assert_eq!(drive_directory_name(Path::new("D:")), "D");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn list_fixed_drives_not_supported() {
+        assert!(list_fixed_drives().is_err());
+    }
+}
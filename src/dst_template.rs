@@ -0,0 +1,287 @@
+use anyhow::{anyhow, Result};
+use std::{
+    ffi::{OsStr, OsString},
+    fmt,
+    path::{is_separator, Component, Path, PathBuf},
+};
+
+/// Names of tokens that can be used in template.
+const TOKENS: [&str; 6] = ["date", "time", "hostname", "rel_dir", "rel_path", "name"];
+
+///
+/// Values of tokens that are the same for every file of the run.
+///
+#[derive(Clone, Debug)]
+pub struct RunValues {
+    /// Local date of the run as 'YYYY-MM-DD'.
+    pub date: String,
+    /// Local time of the run as 'HH-MM-SS'.
+    pub time: String,
+    pub hostname: String,
+}
+
+impl RunValues {
+    ///
+    /// Read values of the run that starts now.
+    ///
+    pub fn current() -> Self {
+        let now = jiff::Zoned::now();
+        let hostname = gethostname::gethostname()
+            .to_string_lossy()
+            .replace(is_separator, "_");
+
+        Self {
+            date: now.strftime("%Y-%m-%d").to_string(),
+            time: now.strftime("%H-%M-%S").to_string(),
+            hostname,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Token(&'static str),
+}
+
+///
+/// Layout of copies in dst_directory, e.g. '{date}/{rel_dir}/{name}'.
+///
+#[derive(Clone, Debug)]
+pub struct DstTemplate {
+    template: String,
+    parts: Vec<Part>,
+    run_values: RunValues,
+}
+
+impl DstTemplate {
+    ///
+    /// Parse template with values of tokens of the run.
+    ///
+    /// Template is a path relative to dst_directory with tokens
+    /// in braces. Path components that are empty after
+    /// tokens are replaced (e.g. '{rel_dir}' of files directly
+    /// in src_directory) are left out.
+    ///
+    /// Template has to contain '{name}' or '{rel_path}', so copies
+    /// of files with different names don't overwrite each other.
+    /// Only '{rel_path}' (or '{rel_dir}' with '{name}') keeps copies
+    /// of files with the same names in different directories apart,
+    /// e.g. '{date}/{name}' gives them the same path.
+    ///
+    /// #### Errors
+    /// This function returns error when template contains unknown
+    /// or unclosed token, doesn't contain '{name}' or '{rel_path}'
+    /// or points outside of dst_directory.
+    ///
+    pub fn new(template: &str, run_values: RunValues) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return Err(anyhow!("unexpected '}}' in template '{template}'"));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow!("unclosed '{{' in template '{template}'"))?;
+            let name = &rest[start + 1..end];
+            let token = TOKENS
+                .into_iter()
+                .find(|token| *token == name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "unknown token '{{{name}}}' in template '{template}'; known tokens are {}",
+                        TOKENS.map(|token| format!("{{{token}}}")).join(", ")
+                    )
+                })?;
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            parts.push(Part::Token(token));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        if !parts
+            .iter()
+            .any(|part| matches!(part, Part::Token("name" | "rel_path")))
+        {
+            return Err(anyhow!(
+                "template '{template}' has to contain {{name}} or {{rel_path}}"
+            ));
+        }
+        if template.starts_with(is_separator) || Path::new(template).has_root() {
+            return Err(anyhow!(
+                "template '{template}' has to be relative to dst_directory"
+            ));
+        }
+        let has_parent_directory = parts.iter().any(|part| match part {
+            Part::Literal(text) => text.split(is_separator).any(|component| component == ".."),
+            Part::Token(_) => false,
+        });
+        if has_parent_directory {
+            return Err(anyhow!(
+                "template '{template}' can't point outside of dst_directory"
+            ));
+        }
+
+        Ok(Self {
+            template: template.to_string(),
+            parts,
+            run_values,
+        })
+    }
+
+    ///
+    /// Path of copy relative to dst_directory of file
+    /// at path relative to src_directory.
+    ///
+    pub fn render(&self, relative: &Path) -> PathBuf {
+        let relative_components = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let (name, directories) = relative_components
+            .split_last()
+            .map_or((OsStr::new(""), &[][..]), |(name, directories)| {
+                (*name, directories)
+            });
+
+        let mut rendered = PathBuf::new();
+        let mut component = OsString::new();
+        for part in &self.parts {
+            let segments = match part {
+                Part::Literal(text) => text.split(is_separator).map(OsStr::new).collect(),
+                Part::Token("date") => vec![OsStr::new(&self.run_values.date)],
+                Part::Token("time") => vec![OsStr::new(&self.run_values.time)],
+                Part::Token("hostname") => vec![OsStr::new(&self.run_values.hostname)],
+                Part::Token("rel_dir") => match directories.is_empty() {
+                    true => vec![OsStr::new("")],
+                    false => directories.to_vec(),
+                },
+                Part::Token("rel_path") => relative_components.clone(),
+                Part::Token(_) => vec![name],
+            };
+            // Every segment after the first starts new component
+            for (index, segment) in segments.into_iter().enumerate() {
+                if index > 0 {
+                    push_component(&mut rendered, &mut component);
+                }
+                component.push(segment);
+            }
+        }
+        push_component(&mut rendered, &mut component);
+
+        rendered
+    }
+}
+
+impl DstTemplate {
+    ///
+    /// Check whether rendered paths end with directories of files
+    /// relative to src_directory, so directories of copies
+    /// correspond to source directories.
+    ///
+    pub fn keeps_directories(&self) -> bool {
+        match self.parts.as_slice() {
+            [.., Part::Token("rel_path")] => true,
+            [.., Part::Token("rel_dir"), Part::Literal(separator), Part::Token("name")] => {
+                !separator.is_empty() && separator.chars().all(is_separator)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DstTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+fn push_component(path: &mut PathBuf, component: &mut OsString) {
+    let component = std::mem::take(component);
+    if !component.is_empty() && component != "." {
+        path.push(component);
+    }
+}
+
+///
+/// Parse template of destination paths for the run that starts now.
+///
+/// #### Errors
+/// This function returns error when template is not valid,
+/// see [DstTemplate::new].
+///
+pub fn parse_dst_template(value: &str) -> Result<DstTemplate> {
+    DstTemplate::new(value, RunValues::current())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn template(template: &str) -> Result<DstTemplate> {
+        DstTemplate::new(
+            template,
+            RunValues {
+                date: "2024-05-06".to_string(),
+                time: "07-08-09".to_string(),
+                hostname: "laptop".to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn render_replaces_tokens() {
+        let template = template("{hostname}/{date}/{rel_dir}/{time} {name}").unwrap();
+
+        assert_eq!(
+            template.render(Path::new("docs/2024/report.pdf")),
+            Path::new("laptop/2024-05-06/docs/2024/07-08-09 report.pdf")
+        );
+    }
+
+    #[test]
+    fn keeps_directories_when_paths_end_with_source_directories() {
+        let keeps_directories = |text| template(text).unwrap().keeps_directories();
+
+        assert!(keeps_directories("{hostname}/{date}/{rel_dir}/{name}"));
+        assert!(keeps_directories("{date}/{rel_path}"));
+        assert!(!keeps_directories("{date}/{name}"));
+        assert!(!keeps_directories("{rel_dir}/{date}/{name}"));
+        assert!(!keeps_directories("{rel_dir}/{time} {name}"));
+    }
+
+    #[test]
+    fn render_leaves_out_empty_components() {
+        let template = template("{date}/{rel_dir}/{name}").unwrap();
+
+        assert_eq!(
+            template.render(Path::new("report.pdf")),
+            Path::new("2024-05-06/report.pdf")
+        );
+        assert_eq!(
+            self::template("backup/{rel_path}")
+                .unwrap()
+                .render(Path::new("a/b.txt")),
+            Path::new("backup/a/b.txt")
+        );
+    }
+
+    #[test]
+    fn new_rejects_invalid_templates() {
+        assert!(template("{date}/{unknown}/{name}").is_err());
+        assert!(template("{date}/{name").is_err());
+        assert!(template("{date}}/{name}").is_err());
+        assert!(template("{date}/{rel_dir}").is_err());
+        assert!(template("/{date}/{name}").is_err());
+        assert!(template("../{name}").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{
+    catalog,
+    files::{hard_link_id, temp_file_name},
+    hash::hash_file,
+    xattrs::have_same_xattrs,
+};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+///
+/// Group of files with identical content.
+///
+pub struct DuplicateCluster {
+    /// Size of a single file in the cluster.
+    pub file_size: u64,
+    /// Paths of all files in the cluster.
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateCluster {
+    ///
+    /// Number of bytes that would be saved if only
+    /// one file from the cluster was kept.
+    ///
+    pub fn wasted_bytes(&self) -> u64 {
+        self.file_size * (self.paths.len() as u64 - 1)
+    }
+}
+
+///
+/// Find files with identical content.
+///
+/// Files are grouped by size first so only files that
+/// could be duplicates are hashed.
+/// Clusters are sorted by wasted bytes in descending order.
+///
+/// When there's problem with reading file, error is logged
+/// and file is ignored.
+///
+pub fn find_duplicates(files_paths: &[PathBuf]) -> Vec<DuplicateCluster> {
+    let mut by_size: HashMap<u64, Vec<&Path>> = HashMap::new();
+    for file_path in files_paths {
+        match fs::metadata(file_path) {
+            Ok(metadata) => by_size.entry(metadata.len()).or_default().push(file_path),
+            Err(err) => log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}"),
+        }
+    }
+
+    let mut clusters = by_size
+        .into_par_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map_iter(|(file_size, paths)| {
+            let mut by_hash: HashMap<blake3::Hash, Vec<PathBuf>> = HashMap::new();
+            for path in paths {
+                match hash_file(path) {
+                    Ok(hash) => by_hash.entry(hash).or_default().push(path.to_path_buf()),
+                    Err(err) => log::warn!(
+                        target: catalog::HASH_FAILED,
+                        "Failed to hash {}; {err}",
+                        path.to_string_lossy()
+                    ),
+                }
+            }
+
+            by_hash
+                .into_values()
+                .filter(|paths| paths.len() > 1)
+                .map(move |mut paths| {
+                    paths.sort();
+                    DuplicateCluster { file_size, paths }
+                })
+        })
+        .collect::<Vec<_>>();
+
+    clusters.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    clusters
+}
+
+///
+/// Remove all but the first file of every cluster from files_paths.
+///
+/// Returns number of removed files.
+///
+pub fn remove_duplicates(files_paths: &mut Vec<PathBuf>, clusters: &[DuplicateCluster]) -> usize {
+    let duplicates = clusters
+        .iter()
+        .flat_map(|cluster| cluster.paths.iter().skip(1))
+        .collect::<HashSet<_>>();
+    let files_count = files_paths.len();
+    files_paths.retain(|path| !duplicates.contains(path));
+
+    files_count - files_paths.len()
+}
+
+///
+/// Replace all but the first file of every cluster with hard links to it.
+///
+/// Only files whose metadata preserved in copies (modification time,
+/// permissions, owner and extended attributes) is the same as metadata
+/// of the first file are linked, because linked files share it. Every link is created under temporary name and renamed into place,
+/// so file is never lost when it can't be linked. Files that can't be
+/// linked are logged with WARN level and kept.
+/// Returns paths of linked files and files they're linked to.
+///
+pub fn link_duplicates(clusters: &[DuplicateCluster]) -> HashMap<PathBuf, PathBuf> {
+    let mut linked_files = HashMap::new();
+    for cluster in clusters {
+        let original = &cluster.paths[0];
+        for duplicate in &cluster.paths[1..] {
+            // Linked by previous run
+            let is_linked = hard_link_id(original)
+                .is_some_and(|original_id| hard_link_id(duplicate) == Some(original_id));
+            if !is_linked {
+                if !have_same_metadata(original, duplicate) {
+                    log::debug!(
+                        "Not linking {} to identical {}; their metadata differs",
+                        duplicate.to_string_lossy(),
+                        original.to_string_lossy()
+                    );
+                    continue;
+                }
+                if let Err(err) = replace_with_link(original, duplicate) {
+                    log::warn!(
+                        target: catalog::HARD_LINK_FAILED,
+                        "Failed to replace {} with link to identical {}; {err}",
+                        duplicate.to_string_lossy(),
+                        original.to_string_lossy()
+                    );
+                    continue;
+                }
+            }
+            linked_files.insert(duplicate.clone(), original.clone());
+        }
+    }
+
+    linked_files
+}
+
+fn have_same_metadata(first: &Path, second: &Path) -> bool {
+    let (Ok(first_metadata), Ok(second_metadata)) = (fs::metadata(first), fs::metadata(second))
+    else {
+        return false;
+    };
+
+    first_metadata.modified().ok() == second_metadata.modified().ok()
+        && first_metadata.permissions() == second_metadata.permissions()
+        && owner(&first_metadata) == owner(&second_metadata)
+        && have_same_xattrs(first, second)
+}
+
+#[cfg(unix)]
+fn owner(metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.uid(), metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner(_metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+fn replace_with_link(original: &Path, duplicate: &Path) -> io::Result<()> {
+    let temp_path = duplicate.with_file_name(temp_file_name());
+    let result =
+        fs::hard_link(original, &temp_path).and_then(|()| fs::rename(&temp_path, duplicate));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn find_duplicates_groups_identical_files() {
+        let files = [
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+        ];
+        fs::write(&files[0], "duplicated text").unwrap();
+        fs::write(&files[1], "duplicated text").unwrap();
+        fs::write(&files[2], "different text!").unwrap();
+        let files_paths = files
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .collect::<Vec<_>>();
+
+        let clusters = find_duplicates(&files_paths);
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].paths.len(), 2);
+        assert!(clusters[0].paths.contains(&files_paths[0]));
+        assert!(clusters[0].paths.contains(&files_paths[1]));
+        assert_eq!(clusters[0].wasted_bytes(), "duplicated text".len() as u64);
+    }
+
+    #[test]
+    fn find_duplicates_no_duplicates() {
+        let files = [NamedTempFile::new().unwrap(), NamedTempFile::new().unwrap()];
+        fs::write(&files[0], "some text").unwrap();
+        fs::write(&files[1], "more text").unwrap();
+        let files_paths = files
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .collect::<Vec<_>>();
+
+        let clusters = find_duplicates(&files_paths);
+
+        assert!(clusters.is_empty());
+    }
+
+    #[test]
+    fn remove_duplicates_keeps_first_file() {
+        let files = [
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+        ];
+        for file in files.iter() {
+            fs::write(file, "duplicated text").unwrap();
+        }
+        let mut files_paths = files
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .collect::<Vec<_>>();
+        let clusters = find_duplicates(&files_paths);
+
+        let removed = remove_duplicates(&mut files_paths, &clusters);
+
+        assert_eq!(removed, 2);
+        assert_eq!(files_paths, vec![clusters[0].paths[0].clone()]);
+    }
+
+    #[test]
+    fn link_duplicates_replaces_duplicates_with_links() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let files_paths = ["a.txt", "b.txt", "c.txt"].map(|name| dir.path().join(name));
+        fs::write(&files_paths[0], "duplicated text").unwrap();
+        fs::write(&files_paths[1], "duplicated text").unwrap();
+        fs::write(&files_paths[2], "different text!").unwrap();
+        // Copies of duplicates get the same modification time
+        let modified = fs::metadata(&files_paths[0]).unwrap().modified().unwrap();
+        set_modified(&files_paths[1], modified);
+        let clusters = find_duplicates(&files_paths);
+
+        let linked_files = link_duplicates(&clusters);
+
+        let original = &clusters[0].paths[0];
+        let duplicate = &clusters[0].paths[1];
+        assert_eq!(
+            linked_files,
+            HashMap::from([(duplicate.clone(), original.clone())])
+        );
+        assert_eq!(hard_link_id(duplicate), hard_link_id(original));
+        assert_ne!(hard_link_id(&files_paths[2]), hard_link_id(original));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+        // Links of previous run are recorded again
+        assert_eq!(link_duplicates(&clusters), linked_files);
+    }
+
+    #[test]
+    fn link_duplicates_keeps_duplicates_with_different_metadata() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let files_paths = ["a.txt", "b.txt"].map(|name| dir.path().join(name));
+        fs::write(&files_paths[0], "duplicated text").unwrap();
+        fs::write(&files_paths[1], "duplicated text").unwrap();
+        let modified = fs::metadata(&files_paths[0]).unwrap().modified().unwrap();
+        set_modified(&files_paths[1], modified - Duration::from_secs(3600));
+        let clusters = find_duplicates(&files_paths);
+
+        let linked_files = link_duplicates(&clusters);
+
+        assert!(linked_files.is_empty());
+        assert!(hard_link_id(&files_paths[0]).is_none());
+        assert!(hard_link_id(&files_paths[1]).is_none());
+    }
+
+    fn set_modified(path: &Path, modified: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use age::{secrecy::SecretString, stream::StreamWriter, x25519, Encryptor};
+use anyhow::{anyhow, Result};
+use std::{env, fmt, io::Write, iter};
+
+/// Encryption with age public key, e.g. 'age:age1ql3z7hjy54pw3hyww5ay...'.
+const AGE_ENCRYPTION: &str = "age";
+
+/// Encryption with passphrase read from PASSPHRASE_VARIABLE.
+const PASSPHRASE_ENCRYPTION: &str = "passphrase";
+
+/// Environment variable with passphrase of archives encrypted with passphrase.
+pub const PASSPHRASE_VARIABLE: &str = "SAVE_ME_FILES_PASSPHRASE";
+
+///
+/// Encryption of archives with age, which can be decrypted
+/// with 'age --decrypt' or rage.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Encryption {
+    Age(x25519::Recipient),
+    Passphrase,
+}
+
+impl Encryption {
+    ///
+    /// Wrap writer, so everything written into it is encrypted.
+    /// Returned writer has to be finished to write the last chunk.
+    ///
+    /// #### Errors
+    /// This function returns error when passphrase is missing
+    /// or age header can't be written.
+    ///
+    pub fn encrypt<W: Write>(&self, writer: W) -> Result<StreamWriter<W>> {
+        let encryptor = match self {
+            Encryption::Age(recipient) => {
+                Encryptor::with_recipients(iter::once(recipient as &dyn age::Recipient))?
+            }
+            Encryption::Passphrase => Encryptor::with_user_passphrase(passphrase()?),
+        };
+
+        Ok(encryptor.wrap_output(writer)?)
+    }
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encryption::Age(recipient) => write!(f, "{AGE_ENCRYPTION}:{recipient}"),
+            Encryption::Passphrase => write!(f, "{PASSPHRASE_ENCRYPTION}"),
+        }
+    }
+}
+
+///
+/// Parse encryption written as 'age:RECIPIENT' or 'passphrase'.
+///
+/// #### Errors
+/// This function returns error when encryption is not supported
+/// or recipient is not valid age public key.
+///
+pub fn parse_encryption(value: &str) -> Result<Encryption> {
+    if value == PASSPHRASE_ENCRYPTION {
+        return Ok(Encryption::Passphrase);
+    }
+    match value.split_once(':') {
+        Some((AGE_ENCRYPTION, recipient)) => recipient
+            .parse()
+            .map(Encryption::Age)
+            .map_err(|err| anyhow!("invalid age recipient '{recipient}'; {err}")),
+        _ => Err(anyhow!(
+            "invalid encryption '{value}'; use 'age:RECIPIENT' or 'passphrase'"
+        )),
+    }
+}
+
+///
+/// Read passphrase of encrypted archives from PASSPHRASE_VARIABLE,
+/// so it doesn't show up in shell history and list of processes.
+///
+/// #### Errors
+/// This function returns error when the variable is not set or empty.
+///
+pub fn passphrase() -> Result<SecretString> {
+    match env::var(PASSPHRASE_VARIABLE) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(SecretString::from(passphrase)),
+        _ => Err(anyhow!(
+            "passphrase encryption requires {PASSPHRASE_VARIABLE} to be set"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn parse_encryption_age() {
+        let identity = x25519::Identity::generate();
+        let recipient = identity.to_public();
+
+        let encryption = parse_encryption(&format!("age:{recipient}")).unwrap();
+
+        assert_eq!(encryption, Encryption::Age(recipient.clone()));
+        assert_eq!(encryption.to_string(), format!("age:{recipient}"));
+        assert_eq!(
+            parse_encryption("passphrase").unwrap(),
+            Encryption::Passphrase
+        );
+        assert!(parse_encryption("age:age1invalid").is_err());
+        assert!(parse_encryption("gpg:alice@example.com").is_err());
+    }
+
+    #[test]
+    fn encrypt_age() {
+        let identity = x25519::Identity::generate();
+        let encryption = Encryption::Age(identity.to_public());
+
+        let mut writer = encryption.encrypt(Vec::new()).unwrap();
+        writer.write_all(b"tax return 2025").unwrap();
+        let encrypted = writer.finish().unwrap();
+
+        let decryptor = age::Decryptor::new(encrypted.as_slice()).unwrap();
+        let mut reader = decryptor
+            .decrypt(iter::once(&identity as &dyn age::Identity))
+            .unwrap();
+        let mut decrypted = String::new();
+        reader.read_to_string(&mut decrypted).unwrap();
+        assert_eq!(decrypted, "tax return 2025");
+    }
+}
@@ -1,19 +1,70 @@
-use crate::COMMENT_LINE_PREFIX;
+use crate::{
+    catalog,
+    ignore_patterns::{IgnorePattern, IgnorePatterns, NEGATION_PREFIX},
+    COMMENT_LINE_PREFIX,
+};
 use anyhow::Result;
 use std::{
+    collections::HashSet,
+    ffi::OsString,
     fs::File,
     io::{BufRead, BufReader},
     path::{Path, PathBuf},
 };
 
+/// Characters that make exclusion a pattern even when such path exists.
+const GLOB_CHARACTERS: [char; 4] = ['*', '?', '[', '{'];
+
 ///
-/// Read exclusions from file to the vector.
+/// Paths and glob patterns that should not be copied.
+///
+#[derive(Default)]
+pub struct Exclusions {
+    /// Absolute paths of excluded directories.
+    pub paths: Vec<PathBuf>,
+    /// Patterns with gitignore semantics matched against paths
+    /// relative to src_directory.
+    pub patterns: IgnorePatterns,
+    /// Names of directories excluded at any depth.
+    pub directory_names: HashSet<OsString>,
+}
+
+impl Exclusions {
+    ///
+    /// Check whether entry at path should be skipped.
+    ///
+    /// Excluded paths and directory names only apply to directories
+    /// and always exclude them. Patterns apply to both files and
+    /// directories (unless they end with slash) and are matched
+    /// against path relative to src_directory.
+    ///
+    pub fn is_excluded(&self, src_directory: &Path, path: &Path, is_dir: bool) -> bool {
+        if is_dir && self.paths.iter().any(|excluded| path.starts_with(excluded)) {
+            return true;
+        }
+
+        let is_excluded_name = path
+            .file_name()
+            .is_some_and(|name| self.directory_names.contains(name));
+        if is_dir && is_excluded_name && path != src_directory {
+            return true;
+        }
+
+        match path.strip_prefix(src_directory) {
+            Ok(relative) => {
+                !relative.as_os_str().is_empty() && self.patterns.is_excluded(relative, is_dir)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+///
+/// Read exclusions from file.
 ///
 /// Exclusions are trimmed so they don't contain leading and following
-/// whitespaces.
-/// Exclusion is valid when it is an absolute path to existing directory.
-/// Every invalid exclusion is logged with WARN level unless
-/// it starts with [COMMENT_LINE_PREFIX].
+/// whitespaces. Empty lines and lines starting with
+/// [COMMENT_LINE_PREFIX] are ignored.
 ///
 /// #### Errors
 /// This function returns error when there's a problem with
@@ -23,34 +74,143 @@ use std::{
 /// This function panics when input file contains not valid
 /// UTF-8 characters.
 ///
-pub fn read_exclusions(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+pub fn read_exclusion_lines(path: impl AsRef<Path>) -> Result<Vec<String>> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
 
-    let exclusions = reader
+    let lines = reader
         .lines()
-        .map(|line| PathBuf::from(line.unwrap().trim()))
-        .filter(|path| {
-            let path_str = path.to_string_lossy();
-            if path_str.is_empty() || path_str.starts_with(COMMENT_LINE_PREFIX) {
-                return false;
-            }
+        .map(|line| line.unwrap().trim().to_owned())
+        .filter(|line| !line.is_empty() && !line.starts_with(COMMENT_LINE_PREFIX))
+        .collect();
 
-            if !path.is_absolute() {
-                log::warn!("Exclusion directory is not an absolute path: {}", path_str);
-                return false;
+    Ok(lines)
+}
+
+///
+/// Parse exclusions.
+///
+/// Exclusion that is an absolute path to existing directory excludes
+/// that directory. Any other exclusion is a pattern with gitignore semantics
+/// (e.g. '*.log', '!important.log', '/build', 'cache/' or '**/node_modules'),
+/// see [IgnorePattern::new].
+/// Absolute paths that don't lead to existing directory are patterns too,
+/// they're logged with WARN level when they look like paths rather than
+/// patterns anchored to src_directory.
+/// Every invalid pattern is logged with WARN level. Duplicated
+/// patterns and paths inside other excluded paths are logged
+/// with WARN level and ignored.
+///
+pub fn parse_exclusions(exclusions: Vec<String>) -> Exclusions {
+    let mut patterns = IgnorePatterns::default();
+    let mut known_patterns = HashSet::new();
+    let paths = exclusions
+        .into_iter()
+        .filter(|exclusion| {
+            if is_directory_path(exclusion) {
+                return true;
+            }
+            if looks_like_path(exclusion) {
+                log::warn!(
+                    target: catalog::INVALID_EXCLUSION,
+                    "Exclusion {} is not an existing directory; it's matched as glob relative to src_directory",
+                    exclusion
+                );
             }
 
-            if !path.is_dir() {
-                log::warn!("Exclusion directory not exist: {}", path_str);
+            if !known_patterns.insert(exclusion.clone()) {
+                log::warn!(
+                    target: catalog::REDUNDANT_EXCLUSION,
+                    "Duplicated exclusion glob: {}",
+                    exclusion
+                );
                 return false;
             }
-
-            return true;
+            match IgnorePattern::new(exclusion) {
+                Ok(pattern) => {
+                    // Pattern repeated after negation excludes entries again
+                    if pattern.negated {
+                        known_patterns.clear();
+                    }
+                    patterns.push(pattern);
+                }
+                Err(err) => log::warn!(
+                    target: catalog::INVALID_EXCLUSION,
+                    "Invalid exclusion glob: {}; {err}",
+                    exclusion
+                ),
+            }
+            false
         })
+        .map(PathBuf::from)
         .collect();
 
-    Ok(exclusions)
+    Exclusions {
+        paths: remove_redundant_paths(paths),
+        patterns,
+        ..Default::default()
+    }
+}
+
+///
+/// Check whether exclusion uses glob syntax, so it can't be a path.
+///
+pub fn is_glob(exclusion: &str) -> bool {
+    exclusion.contains(GLOB_CHARACTERS) || exclusion.starts_with(NEGATION_PREFIX)
+}
+
+///
+/// Check whether exclusion is an absolute path to existing directory.
+///
+pub fn is_directory_path(exclusion: &str) -> bool {
+    let path = Path::new(exclusion);
+    !is_glob(exclusion) && path.is_absolute() && path.is_dir()
+}
+
+///
+/// Check whether absolute exclusion that isn't directory path was meant
+/// to be one, because its first directory exists (e.g. '/home/user/old'
+/// rather than '/build').
+///
+fn looks_like_path(exclusion: &str) -> bool {
+    let path = Path::new(exclusion);
+    !is_glob(exclusion)
+        && path.is_absolute()
+        && path.components().take(2).collect::<PathBuf>().exists()
+}
+
+fn remove_redundant_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut effective_paths: Vec<PathBuf> = Vec::with_capacity(paths.len());
+    for path in paths {
+        if let Some(excluded) = effective_paths
+            .iter()
+            .find(|excluded| path.starts_with(excluded))
+        {
+            log::warn!(
+                target: catalog::REDUNDANT_EXCLUSION,
+                "Exclusion {} has no effect; it's inside {}",
+                path.to_string_lossy(),
+                excluded.to_string_lossy()
+            );
+            continue;
+        }
+
+        effective_paths.retain(|excluded| {
+            let is_inside = excluded.starts_with(&path);
+            if is_inside {
+                log::warn!(
+                    target: catalog::REDUNDANT_EXCLUSION,
+                    "Exclusion {} has no effect; it's inside {}",
+                    excluded.to_string_lossy(),
+                    path.to_string_lossy()
+                );
+            }
+            !is_inside
+        });
+        effective_paths.push(path);
+    }
+
+    effective_paths
 }
 
 #[cfg(test)]
@@ -59,6 +219,10 @@ mod test {
     use std::fs;
     use tempfile::{NamedTempFile, TempDir};
 
+    fn read_exclusions(path: &Path) -> Result<Exclusions> {
+        Ok(parse_exclusions(read_exclusion_lines(path)?))
+    }
+
     #[test]
     fn read_exclusions_all_exclusions() {
         let file = NamedTempFile::new().unwrap();
@@ -84,7 +248,7 @@ mod test {
         exclusions
             .into_iter()
             .map(|exclusion_directory| exclusion_directory.path().to_path_buf())
-            .for_each(|path| assert!(read_exclusions.contains(&path)));
+            .for_each(|path| assert!(read_exclusions.paths.contains(&path)));
     }
 
     #[test]
@@ -112,7 +276,7 @@ mod test {
         exclusions
             .into_iter()
             .map(|exclusion_directory| exclusion_directory.path().to_path_buf())
-            .for_each(|path| assert!(read_exclusions.contains(&path)));
+            .for_each(|path| assert!(read_exclusions.paths.contains(&path)));
     }
 
     #[test]
@@ -130,45 +294,158 @@ mod test {
 
         let read_exclusions = read_exclusions(file.path()).unwrap();
 
-        assert!(read_exclusions.is_empty());
+        assert!(read_exclusions.paths.is_empty());
+        assert!(read_exclusions.patterns.is_empty());
     }
 
     #[test]
-    fn read_exclusions_ignore_non_existent_directories() {
+    fn read_exclusions_non_existent_directories_are_patterns() {
         let file = NamedTempFile::new().unwrap();
-        let exclusions = [
-            "save-me-files.test.noexistent.file1",
-            "save-me-files.test.noexistent.file2",
-            "save-me-files.test.noexistent.file3",
-        ];
+        let exclusion = TempDir::new().unwrap().path().join("missing");
 
-        fs::write(
-            file.path(),
-            format!("{}\n{}\n{}", exclusions[0], exclusions[1], exclusions[2]),
-        )
-        .unwrap();
+        fs::write(file.path(), exclusion.to_string_lossy().as_bytes()).unwrap();
+
+        let read_exclusions = read_exclusions(file.path()).unwrap();
+
+        assert!(read_exclusions.paths.is_empty());
+        assert_eq!(read_exclusions.patterns.len(), 1);
+    }
+
+    #[test]
+    fn looks_like_path_missing_directories() {
+        let missing = TempDir::new().unwrap().path().join("missing");
+
+        assert!(looks_like_path(&missing.to_string_lossy()));
+        assert!(!looks_like_path("/save-me-files-build/cache"));
+        assert!(!looks_like_path("cache/"));
+    }
+
+    #[test]
+    fn read_exclusions_gitignore_lines_without_wildcards() {
+        let file = NamedTempFile::new().unwrap();
+        let src_directory = Path::new("/src");
+
+        fs::write(file.path(), "/build\ncache/\ndoc/notes.txt\n\\!name\n").unwrap();
+
+        let read_exclusions = read_exclusions(file.path()).unwrap();
+
+        assert!(read_exclusions.paths.is_empty());
+        assert_eq!(read_exclusions.patterns.len(), 4);
+        let is_excluded = |path: &str, is_dir| {
+            read_exclusions.is_excluded(src_directory, Path::new(path), is_dir)
+        };
+        assert!(is_excluded("/src/build", true));
+        assert!(!is_excluded("/src/a/build", true));
+        assert!(is_excluded("/src/a/cache", true));
+        assert!(!is_excluded("/src/a/cache", false));
+        assert!(is_excluded("/src/doc/notes.txt", false));
+        assert!(!is_excluded("/src/a/doc/notes.txt", false));
+        assert!(is_excluded("/src/a/!name", false));
+    }
+
+    #[test]
+    fn read_exclusions_globs() {
+        let file = NamedTempFile::new().unwrap();
+        let src_directory = Path::new("/src");
+
+        fs::write(file.path(), "**/node_modules\n*/target/*\n").unwrap();
 
         let read_exclusions = read_exclusions(file.path()).unwrap();
 
-        assert!(read_exclusions.is_empty());
+        assert!(read_exclusions.paths.is_empty());
+        assert_eq!(read_exclusions.patterns.len(), 2);
+        assert!(read_exclusions.is_excluded(
+            src_directory,
+            Path::new("/src/a/b/node_modules"),
+            true
+        ));
+        assert!(read_exclusions.is_excluded(
+            src_directory,
+            Path::new("/src/project/target/debug"),
+            true
+        ));
+        assert!(!read_exclusions.is_excluded(
+            src_directory,
+            Path::new("/src/a/project/target/debug"),
+            true
+        ));
+        assert!(!read_exclusions.is_excluded(src_directory, Path::new("/src/a/b"), true));
     }
 
     #[test]
-    fn read_exclusions_ignore_relative_paths() {
+    fn read_exclusions_ignore_invalid_globs() {
         let file = NamedTempFile::new().unwrap();
-        let exclusion = TempDir::new().unwrap();
+
+        fs::write(file.path(), "[invalid").unwrap();
+
+        let read_exclusions = read_exclusions(file.path()).unwrap();
+
+        assert!(read_exclusions.paths.is_empty());
+        assert!(read_exclusions.patterns.is_empty());
+    }
+
+    #[test]
+    fn read_exclusions_ignore_redundant() {
+        let file = NamedTempFile::new().unwrap();
+        let parent = TempDir::new().unwrap();
+        let child = TempDir::new_in(parent.path()).unwrap();
 
         fs::write(
             file.path(),
             format!(
-                "{}",
-                exclusion.path().file_name().unwrap().to_string_lossy()
+                "{}\n{}\n{}\n**/target\n**/target\n",
+                child.path().to_string_lossy(),
+                parent.path().to_string_lossy(),
+                parent.path().to_string_lossy()
             ),
         )
         .unwrap();
 
         let read_exclusions = read_exclusions(file.path()).unwrap();
 
-        assert!(read_exclusions.is_empty());
+        assert_eq!(read_exclusions.paths, vec![parent.path().to_path_buf()]);
+        assert_eq!(read_exclusions.patterns.len(), 1);
+    }
+
+    #[test]
+    fn read_exclusions_keep_pattern_repeated_after_negation() {
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(file.path(), "*.log\n!keep.log\n*.log\n").unwrap();
+
+        let read_exclusions = read_exclusions(file.path()).unwrap();
+
+        assert_eq!(read_exclusions.patterns.len(), 3);
+        assert!(read_exclusions.is_excluded(
+            Path::new("/src"),
+            Path::new("/src/a/keep.log"),
+            false
+        ));
+    }
+
+    #[test]
+    fn is_excluded_paths_only_exclude_directories() {
+        let excluded = TempDir::new().unwrap();
+        let exclusions = Exclusions {
+            paths: vec![excluded.path().to_path_buf()],
+            ..Default::default()
+        };
+
+        assert!(exclusions.is_excluded(Path::new("/"), excluded.path(), true));
+        assert!(!exclusions.is_excluded(Path::new("/"), excluded.path(), false));
+    }
+
+    #[test]
+    fn is_excluded_directory_names_at_any_depth() {
+        let exclusions = Exclusions {
+            directory_names: HashSet::from(["node_modules".into()]),
+            ..Default::default()
+        };
+        let src_directory = Path::new("/src");
+
+        assert!(exclusions.is_excluded(src_directory, Path::new("/src/node_modules"), true));
+        assert!(exclusions.is_excluded(src_directory, Path::new("/src/a/b/node_modules"), true));
+        assert!(!exclusions.is_excluded(src_directory, Path::new("/src/a/node_modules"), false));
+        assert!(!exclusions.is_excluded(src_directory, Path::new("/src/a/node_modules2"), true));
     }
 }
@@ -0,0 +1,132 @@
+use crate::catalog;
+use std::{
+    fmt::{self, Display},
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+///
+/// Code returned by the application when it exits.
+///
+/// Codes are documented so scripts and schedulers
+/// can branch on the outcome of the run.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Everything went fine.
+    Success = 0,
+    /// Unexpected error not covered by other codes.
+    Failure = 1,
+    /// Some files failed to copy.
+    PartialCopyFailure = 2,
+    /// There's not enough space at dst_directory.
+    NotEnoughSpace = 3,
+    /// Arguments or rules files are invalid.
+    InvalidArguments = 4,
+    /// Copied files don't match source files.
+    VerificationFailure = 5,
+    /// Run was interrupted with Ctrl+C.
+    Interrupted = 130,
+}
+
+impl From<ExitCode> for process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        process::ExitCode::from(code as u8)
+    }
+}
+
+///
+/// Error that ends the run together with the code
+/// application should exit with.
+///
+#[derive(Debug)]
+pub struct RunError {
+    pub code: ExitCode,
+    pub error: anyhow::Error,
+}
+
+impl RunError {
+    pub fn new(code: ExitCode, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            code,
+            error: error.into(),
+        }
+    }
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl From<anyhow::Error> for RunError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(ExitCode::Failure, error)
+    }
+}
+
+impl From<std::io::Error> for RunError {
+    fn from(error: std::io::Error) -> Self {
+        Self::new(ExitCode::Failure, error)
+    }
+}
+
+///
+/// Install Ctrl+C handler that marks the run as interrupted
+/// instead of killing the application, so it can stop
+/// between files and exit with [ExitCode::Interrupted].
+///
+/// #### Errors
+/// This function returns error when handler can't be installed.
+///
+pub fn install_interrupt_handler() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // Second Ctrl+C stops the application immediately
+            process::exit(ExitCode::Interrupted as i32);
+        }
+        log::warn!(
+            target: catalog::INTERRUPTED,
+            "Interrupted; finishing files being copied. Press Ctrl+C again to stop now"
+        );
+    })
+}
+
+///
+/// Check whether the run was interrupted with Ctrl+C.
+///
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_documented_values() {
+        let codes = [
+            (ExitCode::Success, 0),
+            (ExitCode::Failure, 1),
+            (ExitCode::PartialCopyFailure, 2),
+            (ExitCode::NotEnoughSpace, 3),
+            (ExitCode::InvalidArguments, 4),
+            (ExitCode::VerificationFailure, 5),
+            (ExitCode::Interrupted, 130),
+        ];
+
+        for (code, value) in codes {
+            assert_eq!(code as u8, value);
+        }
+    }
+
+    #[test]
+    fn run_error_from_anyhow_is_failure() {
+        let error = RunError::from(anyhow::anyhow!("error"));
+
+        assert_eq!(error.code, ExitCode::Failure);
+    }
+}
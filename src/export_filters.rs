@@ -0,0 +1,429 @@
+use crate::{
+    catalog,
+    ignore_patterns::{IgnorePattern, IgnorePatterns},
+    suffixes::{Matcher, Rule, Suffixes},
+};
+use clap::ValueEnum;
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+};
+
+/// Characters with special meaning in rsync patterns.
+const RSYNC_WILDCARDS: [char; 3] = ['*', '?', '['];
+
+///
+/// Tool whose filter files can be produced from rules.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FilterTool {
+    /// Filter rules for rsync '--filter=merge FILE'.
+    Rsync,
+    /// Robocopy job file for '/JOB:FILE'.
+    Robocopy,
+}
+
+///
+/// Rules that select files to copy.
+///
+pub struct SelectionRules<'a> {
+    pub src_directory: &'a Path,
+    pub suffixes: &'a Suffixes,
+    pub case_insensitive: bool,
+    /// Exclusion patterns relative to src_directory.
+    pub exclusion_patterns: &'a IgnorePatterns,
+    /// Absolute paths of excluded directories.
+    pub excluded_paths: &'a [PathBuf],
+    pub excluded_directory_names: BTreeSet<&'a OsString>,
+    /// Absolute paths of files and directories that are always copied.
+    pub include_paths: &'a [PathBuf],
+}
+
+///
+/// Produce content of filter file for tool that selects
+/// the same files as rules.
+///
+/// Rules that can't be expressed by the tool are logged
+/// with WARN level and left out.
+///
+pub fn export_filters(tool: FilterTool, rules: &SelectionRules) -> String {
+    match tool {
+        FilterTool::Rsync => export_rsync_filters(rules),
+        FilterTool::Robocopy => export_robocopy_job(rules),
+    }
+}
+
+///
+/// Rsync uses the first matching rule, so rules are written in order:
+/// always copied paths, exclusions, every directory (so scopes and
+/// suffixes decide about files only), scopes and top level rules
+/// from the last to the first and exclusion of all other files.
+///
+fn export_rsync_filters(rules: &SelectionRules) -> String {
+    let mut filters = String::from(
+        "# rsync filter rules exported by save-me-files\n\
+         # Use with: rsync -a --prune-empty-dirs --filter='merge FILE' SRC/ DST/\n",
+    );
+    if rules.case_insensitive {
+        warn_not_exportable("case insensitive matching", "rsync matches names with case");
+    }
+
+    let mut included_directories = BTreeSet::new();
+    for path in rules.include_paths {
+        let Ok(relative) = path.strip_prefix(rules.src_directory) else {
+            continue;
+        };
+        let components = relative_components(relative);
+        for depth in 1..components.len() {
+            let parent = components[..depth].join("/");
+            if included_directories.insert(parent.clone()) {
+                let _ = writeln!(filters, "+ /{}/", rsync_literal(&parent, false));
+            }
+        }
+        let relative = components.join("/");
+        match path.is_dir() {
+            true => {
+                let _ = writeln!(filters, "+ /{}/***", rsync_literal(&relative, true));
+            }
+            false => {
+                let _ = writeln!(filters, "+ /{}", rsync_literal(&relative, false));
+            }
+        }
+    }
+
+    for path in rules.excluded_paths {
+        if let Ok(relative) = path.strip_prefix(rules.src_directory) {
+            let relative = relative_components(relative).join("/");
+            let _ = writeln!(filters, "- /{}/", rsync_literal(&relative, false));
+        }
+    }
+    for name in &rules.excluded_directory_names {
+        let _ = writeln!(
+            filters,
+            "- {}/",
+            rsync_literal(&name.to_string_lossy(), false)
+        );
+    }
+    // Later patterns take precedence
+    for pattern in rules.exclusion_patterns.patterns().iter().rev() {
+        if pattern.negated {
+            warn_not_exportable(
+                &pattern_text(pattern),
+                "rsync filters can't include entries excluded by later rules",
+            );
+            continue;
+        }
+        if pattern.glob.contains('{') {
+            warn_not_exportable(
+                &pattern_text(pattern),
+                "rsync doesn't support alternatives in braces",
+            );
+            continue;
+        }
+        let _ = writeln!(filters, "- {}", pattern_text(pattern));
+    }
+    filters.push_str("+ */\n");
+
+    for scope in rules.suffixes.scopes.iter().rev() {
+        let Some(directory) = scope.pattern.strip_suffix("/**") else {
+            warn_not_exportable(
+                &scope.pattern,
+                "only scopes like 'directory/**' can be exported",
+            );
+            continue;
+        };
+        for rule in scope.rules.iter().rev() {
+            let Some(name) = rsync_name_pattern(rule) else {
+                continue;
+            };
+            let action = rsync_action(rule);
+            let _ = writeln!(filters, "{action} /{directory}/{name}");
+            let _ = writeln!(filters, "{action} /{directory}/**/{name}");
+        }
+        let _ = writeln!(filters, "- /{directory}/**");
+    }
+    for rule in rules.suffixes.rules.iter().rev() {
+        if let Some(name) = rsync_name_pattern(rule) {
+            let _ = writeln!(filters, "{} {name}", rsync_action(rule));
+        }
+    }
+    filters.push_str("- *\n");
+
+    filters
+}
+
+fn rsync_action(rule: &Rule) -> char {
+    match rule.negated {
+        true => '-',
+        false => '+',
+    }
+}
+
+fn rsync_name_pattern(rule: &Rule) -> Option<String> {
+    match &rule.matcher {
+        Matcher::Suffix(suffix) => Some(format!("*{}", rsync_literal(suffix, true))),
+        Matcher::Prefix(prefix) => Some(format!("{}*", rsync_literal(prefix, true))),
+        Matcher::Regex(_) => {
+            warn_not_exportable(
+                &rule.to_string(),
+                "rsync doesn't support regular expressions",
+            );
+            None
+        }
+    }
+}
+
+///
+/// Escape text matched literally in rsync pattern.
+///
+/// Backslash escapes only in patterns with wildcards, so it's
+/// escaped when pattern has them.
+///
+fn rsync_literal(text: &str, has_wildcards: bool) -> String {
+    let has_wildcards = has_wildcards || text.contains(RSYNC_WILDCARDS);
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if RSYNC_WILDCARDS.contains(&character) || (has_wildcards && character == '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+///
+/// Robocopy excludes files matching any '/XF' name regardless of order
+/// and matches names with wildcards, so ordered rules, scopes, always
+/// copied paths and path globs can't be exported.
+///
+fn export_robocopy_job(rules: &SelectionRules) -> String {
+    let mut included_files = Vec::new();
+    let mut excluded_files = Vec::new();
+    let mut excluded_directories = Vec::new();
+
+    let mut has_negated_rule = false;
+    for rule in &rules.suffixes.rules {
+        let name = match &rule.matcher {
+            Matcher::Suffix(suffix) => format!("*{suffix}"),
+            Matcher::Prefix(prefix) => format!("{prefix}*"),
+            Matcher::Regex(_) => {
+                warn_not_exportable(
+                    &rule.to_string(),
+                    "robocopy doesn't support regular expressions",
+                );
+                continue;
+            }
+        };
+        match rule.negated {
+            true => {
+                has_negated_rule = true;
+                excluded_files.push(name);
+            }
+            false => {
+                if has_negated_rule {
+                    warn_not_exportable(
+                        &rule.to_string(),
+                        "robocopy exclusions can't be overridden by later rules",
+                    );
+                }
+                included_files.push(name);
+            }
+        }
+    }
+    for scope in &rules.suffixes.scopes {
+        warn_not_exportable(&scope.pattern, "robocopy doesn't support scopes");
+    }
+    for path in rules.include_paths {
+        warn_not_exportable(
+            &path.to_string_lossy(),
+            "robocopy doesn't support always copied paths",
+        );
+    }
+
+    for path in rules.excluded_paths {
+        excluded_directories.push(path.to_string_lossy().into_owned());
+    }
+    for name in &rules.excluded_directory_names {
+        excluded_directories.push(name.to_string_lossy().into_owned());
+    }
+    for pattern in rules.exclusion_patterns.patterns() {
+        if pattern.negated {
+            warn_not_exportable(
+                &pattern_text(pattern),
+                "robocopy exclusions can't be overridden by later rules",
+            );
+            continue;
+        }
+        let is_name = !pattern.anchored && !pattern.glob.contains(['/', '[', '{', '\\']);
+        if !is_name {
+            warn_not_exportable(
+                &pattern_text(pattern),
+                "robocopy matches only names, so only patterns without slash can be exported",
+            );
+            continue;
+        }
+        excluded_directories.push(pattern.glob.clone());
+        if !pattern.directory_only {
+            excluded_files.push(pattern.glob.clone());
+        }
+    }
+
+    let mut job = String::from(
+        "::\n:: Robocopy job exported by save-me-files\n\
+         :: Use with: robocopy SRC DST /S /JOB:FILE\n::\n",
+    );
+    let sections = [
+        (
+            "Include These Files",
+            "/IF\t\t:: Include Files matching these names",
+            included_files,
+        ),
+        (
+            "Exclude These Directories",
+            "/XD\t\t:: eXclude Directories matching these names",
+            excluded_directories,
+        ),
+        (
+            "Exclude These Files",
+            "/XF\t\t:: eXclude Files matching these names",
+            excluded_files,
+        ),
+    ];
+    for (title, switch, names) in sections {
+        if names.is_empty() {
+            continue;
+        }
+        let _ = writeln!(job, "\n::\n:: {title} :\n::\n\t{switch}");
+        for name in names {
+            let _ = writeln!(job, "\t\t{name}");
+        }
+    }
+
+    job
+}
+
+///
+/// Write pattern back in gitignore syntax.
+///
+fn pattern_text(pattern: &IgnorePattern) -> String {
+    format!(
+        "{}{}{}{}",
+        if pattern.negated { "!" } else { "" },
+        if pattern.anchored { "/" } else { "" },
+        pattern.glob,
+        if pattern.directory_only { "/" } else { "" }
+    )
+}
+
+fn relative_components(relative: &Path) -> Vec<String> {
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn warn_not_exportable(rule: &str, reason: &str) {
+    log::warn!(
+        target: catalog::FILTER_NOT_EXPORTABLE,
+        "Rule '{rule}' is not exported; {reason}"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::suffixes::Scope;
+
+    fn rule(matcher: Matcher, negated: bool) -> Rule {
+        Rule { matcher, negated }
+    }
+
+    #[test]
+    fn export_rsync_filters_keeps_rule_precedence() {
+        let suffixes = Suffixes {
+            rules: vec![
+                rule(Matcher::Suffix(".png".to_string()), false),
+                rule(Matcher::Suffix(".thumb.png".to_string()), true),
+            ],
+            scopes: vec![Scope::new(
+                "Projects/**",
+                vec![rule(Matcher::Suffix(".rs".to_string()), false)],
+            )
+            .unwrap()],
+        };
+        let names = [OsString::from("node_modules")];
+        let src_directory = Path::new("/src");
+        let rules = SelectionRules {
+            src_directory,
+            suffixes: &suffixes,
+            case_insensitive: false,
+            exclusion_patterns: &IgnorePatterns::from_iter(["*.tmp", "/build/", "!keep.tmp"]),
+            excluded_paths: &[src_directory.join("old")],
+            excluded_directory_names: names.iter().collect(),
+            include_paths: &[src_directory.join("notes").join("todo[1].txt")],
+        };
+
+        let filters = export_filters(FilterTool::Rsync, &rules);
+
+        let lines = filters
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "+ /notes/",
+                "+ /notes/todo\\[1].txt",
+                "- /old/",
+                "- node_modules/",
+                "- /build/",
+                "- *.tmp",
+                "+ */",
+                "+ /Projects/*.rs",
+                "+ /Projects/**/*.rs",
+                "- /Projects/**",
+                "- *.thumb.png",
+                "+ *.png",
+                "- *",
+            ]
+        );
+    }
+
+    #[test]
+    fn export_robocopy_job_lists_names() {
+        let suffixes = Suffixes {
+            rules: vec![
+                rule(Matcher::Suffix(".png".to_string()), false),
+                rule(Matcher::Prefix("IMG_".to_string()), false),
+                rule(Matcher::Suffix(".thumb.png".to_string()), true),
+            ],
+            scopes: Vec::new(),
+        };
+        let rules = SelectionRules {
+            src_directory: Path::new("/src"),
+            suffixes: &suffixes,
+            case_insensitive: true,
+            exclusion_patterns: &IgnorePatterns::from_iter(["**/.cache", "logs/"]),
+            excluded_paths: &[],
+            excluded_directory_names: BTreeSet::new(),
+            include_paths: &[],
+        };
+
+        let job = export_filters(FilterTool::Robocopy, &rules);
+
+        assert!(
+            job.contains("/IF\t\t:: Include Files matching these names\n\t\t*.png\n\t\tIMG_*\n")
+        );
+        assert!(job.contains(
+            "/XD\t\t:: eXclude Directories matching these names\n\t\t.cache\n\t\tlogs\n"
+        ));
+        assert!(job.contains(
+            "/XF\t\t:: eXclude Files matching these names\n\t\t*.thumb.png\n\t\t.cache\n"
+        ));
+    }
+}
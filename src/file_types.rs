@@ -0,0 +1,125 @@
+use clap::ValueEnum;
+use infer::MatcherType;
+use std::{io, path::Path};
+
+/// MIME types that are classified as archives by magic bytes
+/// detection but are documents from user's perspective.
+const DOCUMENT_MIME_TYPES: [&str; 3] = [
+    "application/pdf",
+    "application/rtf",
+    "application/postscript",
+];
+
+///
+/// Kind of file content detected from its first bytes.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FileType {
+    Image,
+    Video,
+    Audio,
+    /// Office documents, PDFs and similar.
+    Document,
+    /// Electronic books.
+    Book,
+    /// Compressed files and archives.
+    Archive,
+    Font,
+    /// Executables and libraries.
+    Application,
+}
+
+///
+/// Detect type of the file by reading its magic bytes.
+///
+/// Returns None when file content is not recognized.
+///
+/// #### Errors
+/// This function returns error when there's a problem with
+/// opening or reading the file.
+///
+pub fn detect_file_type(path: &Path) -> io::Result<Option<FileType>> {
+    let file_type = infer::get_from_path(path)?.and_then(|kind| match kind.matcher_type() {
+        _ if DOCUMENT_MIME_TYPES.contains(&kind.mime_type()) => Some(FileType::Document),
+        MatcherType::Image => Some(FileType::Image),
+        MatcherType::Video => Some(FileType::Video),
+        MatcherType::Audio => Some(FileType::Audio),
+        MatcherType::Doc => Some(FileType::Document),
+        MatcherType::Book => Some(FileType::Book),
+        MatcherType::Archive => Some(FileType::Archive),
+        MatcherType::Font => Some(FileType::Font),
+        MatcherType::App => Some(FileType::Application),
+        MatcherType::Text | MatcherType::Custom => None,
+    });
+
+    Ok(file_type)
+}
+
+///
+/// Detect extension that matches the file content by reading
+/// its magic bytes (e.g. 'png' or 'pdf').
+///
+/// Returns None when file content is not recognized.
+///
+/// #### Errors
+/// This function returns error when there's a problem with
+/// opening or reading the file.
+///
+pub fn detect_extension(path: &Path) -> io::Result<Option<&'static str>> {
+    Ok(infer::get_from_path(path)?.map(|kind| kind.extension()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn detect_file_type_image() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(
+            &file,
+            [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0],
+        )
+        .unwrap();
+
+        let file_type = detect_file_type(file.path()).unwrap();
+
+        assert_eq!(file_type, Some(FileType::Image));
+    }
+
+    #[test]
+    fn detect_file_type_document() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(&file, b"%PDF-1.7\n").unwrap();
+
+        let file_type = detect_file_type(file.path()).unwrap();
+
+        assert_eq!(file_type, Some(FileType::Document));
+    }
+
+    #[test]
+    fn detect_file_type_unknown() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(&file, "plain text without any magic").unwrap();
+
+        let file_type = detect_file_type(file.path()).unwrap();
+
+        assert_eq!(file_type, None);
+    }
+
+    #[test]
+    fn detect_extension_image() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(
+            &file,
+            [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0],
+        )
+        .unwrap();
+
+        let extension = detect_extension(file.path()).unwrap();
+
+        assert_eq!(extension, Some("png"));
+    }
+}
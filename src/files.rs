@@ -1,300 +1,4378 @@
+mod acl;
+
+use self::acl::{copy_acl, copy_attributes};
+use crate::{
+    catalog,
+    changes::{file_state, ChangeDetection, ChangePolicy},
+    databases::{
+        backup_sqlite_database, is_server_database, is_sqlite_database, is_sqlite_sidecar,
+    },
+    delta::copy_delta,
+    dst_template::DstTemplate,
+    exclusions::Exclusions,
+    exit_code::is_interrupted,
+    file_types::{detect_file_type, FileType},
+    filter::Filter,
+    hash::hash_file,
+    long_names::{extended_length_path, shorten_path},
+    mac_metadata::copy_mac_metadata,
+    overrides::Overrides,
+    privileges::{backup_privileges_enabled, open_for_reading},
+    rate_limit::RateLimiter,
+    sanitize::SanitizedNames,
+    state::RunState,
+    streams::{copy_streams, warn_about_lost_streams},
+    suffixes::Suffixes,
+    temperature::TemperatureThrottle,
+    versions::keep_previous_version,
+    xattrs::copy_xattrs,
+};
+use anyhow::{anyhow, Result};
 use byte_unit::Byte;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use clap::ValueEnum;
+use globset::GlobSet;
+use rayon::{
+    iter::ParallelBridge,
+    prelude::{IntoParallelIterator, ParallelIterator},
+};
 use std::{
-    fs,
-    path::{Path, PathBuf},
+    borrow::Cow,
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs::{self, File, FileTimes, TryLockError},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant, SystemTime},
 };
 use walkdir::{DirEntry, WalkDir};
 
+/// Size of the blocks read when checking if file is readable.
+const PRECHECK_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// How many times file that changes while it's copied is copied with [ChangePolicy::Recopy].
+const RECOPY_ATTEMPTS: usize = 3;
+
+/// Size of the blocks compared when checking if copy is beginning of append-only file.
+const APPEND_CHECK_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// File that marks directory as cache directory.
+/// See https://bford.info/cachedir/
+const CACHEDIR_TAG_NAME: &str = "CACHEDIR.TAG";
+
+/// Content CACHEDIR.TAG file has to start with.
+const CACHEDIR_TAG_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// Size of chunks in which files are read during IO simulation.
+const SIMULATION_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Maximum difference of modification times of up to date copy and its source.
+const MODIFICATION_TIME_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Immutable flag of inode (FS_IMMUTABLE_FL), see 'man ioctl_iflags'.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const IMMUTABLE_FLAG: nix::libc::c_int = 0x10;
+
+/// Makes names of temporary files unique within the run.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+///
+/// What to do with symbolic links found in src_directory.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Ignore symbolic links.
+    #[default]
+    Skip,
+    /// Follow symbolic links and copy files they point to.
+    Follow,
+    /// Recreate symbolic links in dst_directory.
+    CopyLink,
+}
+
+///
+/// Whether copies share content with their sources on filesystems
+/// with copy-on-write support (Btrfs, XFS, APFS, ReFS).
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReflinkPolicy {
+    /// Clone files when filesystem supports it, copy them otherwise.
+    Auto,
+    /// Clone files and fail when they can't be cloned.
+    Always,
+    /// Always copy content of files.
+    #[default]
+    Never,
+}
+
+///
+/// Order in which files are copied.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CopyOrder {
+    /// Order in which files were found.
+    #[default]
+    Discovered,
+    /// Many small files are done first.
+    SmallestFirst,
+    /// Big files are streamed early.
+    LargestFirst,
+    /// Files of the same directory are copied together,
+    /// directories in alphabetical order.
+    ByDirectory,
+}
+
+///
+/// Which size of files is used to estimate space they need.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SizeMode {
+    /// Size of file content, holes of sparse files included.
+    #[default]
+    Apparent,
+    /// Size of blocks allocated for file content. Sparse files
+    /// are copied without holes, so they need only allocated size.
+    /// Apparent size is used on Windows.
+    Allocated,
+}
+
+///
+/// Where in dst_directory files are copied.
+///
+#[derive(Default)]
+pub enum DestinationLayout {
+    /// Directory structure of src_directory is recreated.
+    #[default]
+    Tree,
+    /// Files are copied directly into dst_directory under these names.
+    Flat(HashMap<PathBuf, PathBuf>),
+    /// Paths of copies are rendered from template.
+    Template(DstTemplate),
+}
+
+impl DestinationLayout {
+    ///
+    /// Check whether directories of copies correspond
+    /// to directories of their sources.
+    ///
+    fn keeps_directories(&self) -> bool {
+        match self {
+            DestinationLayout::Tree => true,
+            DestinationLayout::Flat(_) => false,
+            DestinationLayout::Template(template) => template.keeps_directories(),
+        }
+    }
+}
+
+///
+/// What to do with special files (FIFOs, sockets, devices)
+/// found in src_directory.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SpecialFilePolicy {
+    /// Log and ignore special files.
+    #[default]
+    Skip,
+    /// Stop when any special file is found.
+    Error,
+}
+
+///
+/// How copies from previous runs are compared with their sources
+/// to decide whether they're up to date.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CompareMethod {
+    /// Compare size and modification time.
+    #[default]
+    Metadata,
+    /// Compare size and hash of content. Slower, but works
+    /// when modification times are rewritten by other tools.
+    Checksum,
+}
+
+///
+/// What to do when file already exists at the path where
+/// its source is copied.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OverwritePolicy {
+    /// Always replace existing files.
+    #[default]
+    Always,
+    /// Never replace existing files.
+    Never,
+    /// Replace existing files older than their sources.
+    IfNewer,
+    /// Replace existing files that differ from their sources.
+    IfDifferent,
+}
+
+///
+/// What to do when different file already exists at the path
+/// where its source is copied.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CollisionPolicy {
+    /// Replace or keep existing file according to [OverwritePolicy].
+    #[default]
+    Overwrite,
+    /// Copy file next to existing one as 'name (1).ext', 'name (2).ext'...
+    Rename,
+}
+
+///
+/// Options that control how source directory is traversed.
+///
+#[derive(Default)]
+pub struct SearchOptions {
+    /// Maximum depth of traversal relative to src_directory.
+    /// Files directly inside src_directory have depth 1.
+    /// When not set whole tree is traversed.
+    pub max_depth: Option<usize>,
+    /// Whether symbolic links are ignored, followed or kept as links.
+    pub symlinks: SymlinkPolicy,
+    /// Whether special files are skipped or stop the search.
+    pub special_files: SpecialFilePolicy,
+    /// Compare filenames with suffixes ignoring case.
+    /// Suffixes need to be lowercase already.
+    pub case_insensitive: bool,
+    /// Types of content that files need to have.
+    /// When empty content of files is not checked.
+    pub file_types: Vec<FileType>,
+    /// Additional condition that files need to satisfy.
+    pub filter: Option<Filter>,
+    /// Uid of the user that has to own files.
+    pub owner: Option<u32>,
+    /// Gid of the group that has to own files.
+    pub group: Option<u32>,
+    /// Skip directories marked with valid CACHEDIR.TAG file.
+    pub skip_caches: bool,
+    /// Name of the file that excludes directory containing it.
+    pub marker_file: Option<String>,
+    /// Don't descend into directories on other filesystems
+    /// than src_directory (e.g. mount points).
+    pub one_file_system: bool,
+    /// Leave out files locked by other processes.
+    pub skip_locked: bool,
+    /// Leave out cloud files whose content isn't stored locally,
+    /// so reading them doesn't download them.
+    pub skip_placeholders: bool,
+    /// Time after which files need to be accessed.
+    /// When not set access time is not checked.
+    pub accessed_after: Option<SystemTime>,
+}
+
+///
+/// Options that control how files are copied.
+///
+#[derive(Default)]
+pub struct CopyOptions {
+    /// Whether symbolic links are recreated instead of copied.
+    pub symlinks: SymlinkPolicy,
+    /// Directory where files are written before they are renamed
+    /// into place. When it's not set or it's on different filesystem
+    /// than copied file, temporary file is created next to copied file.
+    pub temp_directory: Option<PathBuf>,
+    /// Whether access, modification and (where supported)
+    /// creation times of copied files are preserved.
+    pub preserve_timestamps: bool,
+    /// Whether database files are copied in consistent state
+    /// instead of byte by byte.
+    pub consistent_databases: bool,
+    /// Pauses copying when destination drive is too hot.
+    pub temperature_throttle: Option<TemperatureThrottle>,
+    /// Limits rate at which content of files is copied.
+    pub rate_limiter: Option<RateLimiter>,
+    /// Skip files whose copies have the same size and modification time.
+    /// Modification time of copies is set to that of sources so later
+    /// runs can compare them.
+    pub update: bool,
+    /// How copies are compared with sources when [CopyOptions::update] is set
+    /// or [OverwritePolicy::IfDifferent] is used.
+    pub compare: CompareMethod,
+    /// What to do with files that already exist in dst_directory.
+    pub overwrite: OverwritePolicy,
+    /// Whether files that differ from existing ones are copied under new names.
+    pub on_collision: CollisionPolicy,
+    /// Names of files that only grow by appending (e.g. logs).
+    /// In [CopyOptions::update] mode only their appended part is copied
+    /// when copy from previous run is still their beginning.
+    pub append_only: Option<GlobSet>,
+    /// Detects files that changed after they were found or while they
+    /// were copied. When not set changes are not detected.
+    pub change_detection: Option<ChangeDetection>,
+    /// Whether permissions (mode bits on Unix, read-only attribute
+    /// on Windows) of copied files are set to those of their sources.
+    pub preserve_permissions: bool,
+    /// Whether extended attributes of copied files are copied with them.
+    pub preserve_xattrs: bool,
+    /// Whether access control lists of copied files are copied
+    /// with them (Windows only).
+    pub preserve_acl: bool,
+    /// Whether NTFS alternate data streams of copied files are copied
+    /// with them also when their content isn't copied by the system.
+    /// Content copied by the system (CopyFileEx) has them either way.
+    pub preserve_ads: bool,
+    /// Whether macOS metadata (creation dates, Finder tags, quarantine
+    /// flags and other extended attributes) of copied files is copied.
+    pub preserve_metadata: bool,
+    /// Whether copies are hard links to sources instead of new files.
+    /// Files that can't be linked (e.g. they're on other filesystem
+    /// than dst_directory) are copied.
+    pub link: bool,
+    /// Whether copies are clones sharing content with sources
+    /// until either of them is changed.
+    pub reflink: ReflinkPolicy,
+    /// Whether existing copies are updated with only blocks
+    /// that differ from sources.
+    pub delta: bool,
+    /// Where in dst_directory files are copied.
+    pub layout: DestinationLayout,
+    /// Order in which files are copied.
+    pub order: CopyOrder,
+    /// Names in which characters that FAT, exFAT and NTFS don't allow
+    /// are replaced, when they're replaced.
+    pub sanitize_names: Option<SanitizedNames>,
+    /// Number of previous versions kept when existing copy is
+    /// overwritten with different content. Zero keeps none.
+    pub backup_versions: usize,
+    /// Records finished files, so interrupted run can be resumed.
+    pub state: Option<RunState>,
+}
+
 ///
 /// Find files that should be copied and return their paths.
 ///
-/// Files to copy need to end with one of the suffixes. Furthermore
-/// File's path can not start with any of the exclusions
+/// Files to copy need to match suffixes. Furthermore
+/// File's path can not start with any of the excluded paths
+/// nor match any of the excluded globs
+/// and, when file types are specified, file's content has to be
+/// of one of these types. When filter is specified file has to
+/// satisfy it too. On Unix files can be limited to those
+/// owned by user or group.
+/// Search stops early when the run is interrupted.
+/// Rules from [crate::overrides::OVERRIDES_FILE_NAME] files apply to subtrees
+/// of directories containing them.
+/// Every physical directory is traversed once and, when symbolic
+/// links are followed, every file reached through them is found once.
+/// Special files (FIFOs, sockets, devices) are never copied.
+///
+/// #### Errors
+/// This function returns error when special files are found
+/// and [SpecialFilePolicy::Error] is used.
 ///
 pub fn find_files_to_copy(
     src_directory: &Path,
-    suffixes: &[String],
-    exclusions: &[PathBuf],
-) -> Vec<PathBuf> {
-    WalkDir::new(src_directory)
+    suffixes: &Suffixes,
+    exclusions: &Exclusions,
+    options: &SearchOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut walker = WalkDir::new(src_directory)
         .min_depth(0)
+        .follow_links(options.symlinks == SymlinkPolicy::Follow)
+        .same_file_system(options.one_file_system);
+    if let Some(max_depth) = options.max_depth {
+        walker = walker.max_depth(max_depth);
+    }
+
+    let mut special_files = Vec::new();
+    let mut visited_directories = HashSet::new();
+    let overrides = RefCell::new(Overrides::new(options.case_insensitive));
+    let files = walker
         .into_iter()
-        .filter_entry(|entry| should_keep_entry(entry, exclusions))
+        .filter_entry(|entry| {
+            let mut overrides = overrides.borrow_mut();
+            overrides.enter(entry.path());
+            if overrides.is_excluded(entry.path()) {
+                return false;
+            }
+
+            let keep = should_keep_entry(
+                entry,
+                src_directory,
+                exclusions,
+                options,
+                &mut visited_directories,
+            );
+            if keep && entry.file_type().is_dir() {
+                overrides.push_directory(entry.path());
+            }
+            keep
+        })
+        .take_while(|_| !is_interrupted())
         .filter_map(|entry| match entry {
             Ok(entry) => Some(entry),
             Err(err) => {
-                log::warn!("{err}");
+                log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
                 None
             }
         })
-        .filter(|entry| entry.file_type().is_file())
-        .filter(|entry| should_copy_file(&entry, suffixes))
-        .map(|entry| entry.path().to_path_buf())
+        .filter(|entry| match special_file_kind(entry) {
+            Some(kind) => {
+                if options.special_files == SpecialFilePolicy::Skip {
+                    log::warn!(
+                        target: catalog::SPECIAL_FILE,
+                        "Skipping {kind} {}",
+                        entry.path().to_string_lossy()
+                    );
+                }
+                special_files.push((kind, entry.path().to_path_buf()));
+                false
+            }
+            None => true,
+        })
+        .filter(|entry| is_file_to_copy(entry, options.symlinks))
+        .filter(|entry| {
+            // Checked before anything reads content of the file
+            if options.skip_placeholders && is_placeholder(entry) {
+                log::warn!(
+                    target: catalog::CLOUD_PLACEHOLDER,
+                    "Skipping cloud placeholder {}",
+                    entry.path().to_string_lossy()
+                );
+                return false;
+            }
+            true
+        })
+        .filter(|entry| {
+            let matches =
+                should_copy_file(entry, src_directory, suffixes, options.case_insensitive);
+            overrides.borrow().matches(entry.path(), matches)
+        })
+        .filter(|entry| has_owner(entry, options.owner, options.group))
+        .filter(|entry| was_accessed_after(entry, options.accessed_after))
+        .filter(|entry| has_file_type(entry, &options.file_types))
+        .filter(|entry| {
+            if options.skip_locked && is_locked(entry.path()) {
+                log::warn!(
+                    target: catalog::LOCKED_FILE,
+                    "Skipping locked file {}",
+                    entry.path().to_string_lossy()
+                );
+                return false;
+            }
+            true
+        })
+        .filter(|entry| match &options.filter {
+            Some(filter) => {
+                let relative_path = entry.path().strip_prefix(src_directory).unwrap();
+                filter.matches(entry.path(), relative_path)
+            }
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    let files = match options.symlinks {
+        SymlinkPolicy::Follow => remove_linked_duplicates(files),
+        _ => files,
+    };
+    let files = files
+        .into_iter()
+        .map(|entry| entry.into_path())
+        .collect::<Vec<_>>();
+
+    if options.special_files == SpecialFilePolicy::Error && !special_files.is_empty() {
+        let special_files = special_files
+            .iter()
+            .map(|(kind, path)| format!("{kind} {}", path.to_string_lossy()))
+            .collect::<Vec<_>>();
+        return Err(anyhow!(
+            "Found {} special files:\n{}",
+            special_files.len(),
+            special_files.join("\n")
+        ));
+    }
+
+    Ok(files)
+}
+
+///
+/// Find all files at include paths and add them to files_paths
+/// unless they are already there.
+///
+/// Include paths pointing to directories are searched recursively.
+/// Suffixes, exclusions and file types are not checked because
+/// included paths always have to be copied.
+///
+pub fn merge_included_files(
+    files_paths: &mut Vec<PathBuf>,
+    include_paths: &[PathBuf],
+    options: &SearchOptions,
+) {
+    let mut known_paths = files_paths.iter().cloned().collect::<HashSet<_>>();
+    let included_files = include_paths.iter().flat_map(|include_path| {
+        WalkDir::new(include_path)
+            .follow_links(options.symlinks == SymlinkPolicy::Follow)
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+                    None
+                }
+            })
+            .filter(|entry| is_file_to_copy(entry, options.symlinks))
+            .map(|entry| entry.into_path())
+    });
+
+    for included_file in included_files {
+        if known_paths.insert(included_file.clone()) {
+            files_paths.push(included_file);
+        }
+    }
+}
+
+///
+/// Set permissions of directories containing copied files
+/// to those of their source directories.
+///
+/// It's done after all files are copied, because directories
+/// without write permission would stop copying into them.
+/// Every directory whose permissions can't be preserved is logged
+/// with WARN level. Returns number of such directories.
+///
+pub fn preserve_directory_permissions(
+    src_directory: &Path,
+    dst_directory: &Path,
+    copied_files: &[(PathBuf, PathBuf)],
+    layout: &DestinationLayout,
+) -> usize {
+    let mut failures = 0;
+    for (src_directory, dst_directory) in
+        copied_directories(src_directory, dst_directory, copied_files, layout)
+    {
+        let result = fs::metadata(src_directory)
+            .and_then(|metadata| fs::set_permissions(dst_directory, metadata.permissions()));
+        if let Err(err) = result {
+            log::warn!(
+                target: catalog::PERMISSIONS_NOT_PRESERVED,
+                "Failed to preserve permissions of {}; {err}",
+                dst_directory.to_string_lossy()
+            );
+            failures += 1;
+        }
+    }
+
+    failures
+}
+
+///
+/// Set modification times of directories containing copied files
+/// to those of their source directories (e.g. for photo managers
+/// that rely on folder dates).
+///
+/// It's done after all files are copied, because every file written
+/// into directory changes its modification time.
+/// Every directory whose time can't be preserved is logged
+/// with WARN level. Returns number of such directories.
+///
+pub fn preserve_directory_times(
+    src_directory: &Path,
+    dst_directory: &Path,
+    copied_files: &[(PathBuf, PathBuf)],
+    layout: &DestinationLayout,
+) -> usize {
+    let mut failures = 0;
+    for (src_directory, dst_directory) in
+        copied_directories(src_directory, dst_directory, copied_files, layout)
+    {
+        let result = fs::metadata(src_directory)
+            .and_then(|metadata| metadata.modified())
+            .and_then(|modified| {
+                open_directory(dst_directory)?.set_times(FileTimes::new().set_modified(modified))
+            });
+        if let Err(err) = result {
+            log::warn!(
+                target: catalog::TIMESTAMPS_NOT_PRESERVED,
+                "Failed to preserve modification time of {}; {err}",
+                dst_directory.to_string_lossy()
+            );
+            failures += 1;
+        }
+    }
+
+    failures
+}
+
+///
+/// Source directories of copied files below src_directory
+/// and directories of their copies below dst_directory.
+///
+/// Directories are paired only when layout keeps directories
+/// of sources, so directories made by layout (e.g. '{date}'
+/// of template) don't get times or permissions of unrelated ones.
+///
+fn copied_directories<'a>(
+    src_directory: &Path,
+    dst_directory: &Path,
+    copied_files: &'a [(PathBuf, PathBuf)],
+    layout: &DestinationLayout,
+) -> HashMap<&'a Path, &'a Path> {
+    if !layout.keeps_directories() {
+        return HashMap::new();
+    }
+
+    copied_files
+        .iter()
+        .flat_map(|(src_path, dst_path)| {
+            src_path
+                .ancestors()
+                .zip(dst_path.ancestors())
+                .skip(1)
+                .take_while(|(src_ancestor, dst_ancestor)| {
+                    *src_ancestor != src_directory && *dst_ancestor != dst_directory
+                })
+        })
         .collect()
 }
 
+///
+/// Open directory so its times can be set.
+///
+#[cfg(windows)]
+fn open_directory(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_BACKUP_SEMANTICS;
+
+    File::options()
+        .write(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+fn open_directory(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+///
+/// Open copy so its times can be set. Copy isn't opened for writing,
+/// because it's already read-only when it got permissions of read-only src.
+///
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::FILE_WRITE_ATTRIBUTES;
+
+    File::options()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .open(path)
+}
+
+///
+/// Open copy so its times can be set. Owner of the file can set them
+/// through file opened for reading, so read-only copies can be opened too.
+///
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+///
+/// Make directory and everything inside it read-only so finished
+/// backup can't be modified by accident.
+///
+/// Entries are also made immutable where it's supported: with immutable
+/// flag on Linux (like 'chattr +i', requires CAP_LINUX_IMMUTABLE)
+/// and with entry denying writes to everyone in ACL on Windows.
+/// When the first entry can't be made immutable, the rest is only read-only,
+/// which owner of files can revert.
+///
+/// Every entry that can't be sealed is logged with WARN level.
+/// Returns number of such entries.
+///
+pub fn seal_directory(directory: &Path) -> usize {
+    let mut failures = 0;
+    let mut immutable = true;
+    for entry in WalkDir::new(directory).contents_first(true) {
+        let result = entry
+            .map_err(io::Error::from)
+            .and_then(|entry| seal_entry(&entry, &mut immutable));
+        if let Err(err) = result {
+            log::warn!(target: catalog::SEAL_FAILED, "Failed to seal; {err}");
+            failures += 1;
+        }
+    }
+
+    failures
+}
+
+fn seal_entry(entry: &DirEntry, immutable: &mut bool) -> io::Result<()> {
+    if entry.path_is_symlink() {
+        return Ok(());
+    }
+
+    let mut permissions = entry.metadata()?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(entry.path(), permissions)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}; {err}", entry.path().display())))?;
+    if *immutable {
+        if let Err(err) = make_immutable(entry.path()) {
+            log::info!(
+                "Sealed entries are only read-only, because they can't be made immutable; {err}"
+            );
+            *immutable = false;
+        }
+    }
+
+    Ok(())
+}
+
+// FS_IOC_GETFLAGS and FS_IOC_SETFLAGS are declared with long,
+// but kernel reads and writes int
+#[cfg(any(target_os = "linux", target_os = "android"))]
+nix::ioctl_read_bad!(
+    get_inode_flags,
+    nix::request_code_read!(b'f', 1, std::mem::size_of::<nix::libc::c_long>()),
+    nix::libc::c_int
+);
+#[cfg(any(target_os = "linux", target_os = "android"))]
+nix::ioctl_write_ptr_bad!(
+    set_inode_flags,
+    nix::request_code_write!(b'f', 2, std::mem::size_of::<nix::libc::c_long>()),
+    nix::libc::c_int
+);
+
+///
+/// Set or clear immutable flag of file or directory, like 'chattr +i'
+/// or 'chattr -i'. Immutable entries can't be modified, removed
+/// or renamed, not even by root, until the flag is cleared.
+///
+/// #### Errors
+/// This function returns error when entry can't be opened, process lacks
+/// CAP_LINUX_IMMUTABLE or filesystem doesn't support the flag.
+///
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_immutable(path: &Path, immutable: bool) -> io::Result<()> {
+    use std::os::{fd::AsRawFd, unix::fs::OpenOptionsExt};
+
+    let file = File::options()
+        .read(true)
+        .custom_flags(nix::libc::O_NONBLOCK | nix::libc::O_NOFOLLOW)
+        .open(path)?;
+    let mut flags = 0;
+    // SAFETY: descriptor is open and flags outlive both calls
+    unsafe {
+        get_inode_flags(file.as_raw_fd(), &mut flags)?;
+        flags = match immutable {
+            true => flags | IMMUTABLE_FLAG,
+            false => flags & !IMMUTABLE_FLAG,
+        };
+        set_inode_flags(file.as_raw_fd(), &flags)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn make_immutable(path: &Path) -> io::Result<()> {
+    set_immutable(path, true)
+}
+
+#[cfg(windows)]
+fn make_immutable(path: &Path) -> io::Result<()> {
+    acl::deny_writes(path)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn make_immutable(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "immutable files aren't supported on this platform",
+    ))
+}
+
 ///
 /// Calculate sum of files sizes.
 ///
 /// When there's problem with reading file metadata, error is logged
 /// and file size is ignored.
 ///
-pub fn calculate_files_size(files_paths: &Vec<PathBuf>) -> u64 {
-    files_paths
-        .iter()
-        .map(|file_path| std::fs::metadata(file_path))
+pub fn calculate_files_size(files_paths: &[PathBuf], size_mode: SizeMode) -> u64 {
+    let (originals, _) = split_hard_links(files_paths);
+    originals
+        .into_iter()
+        .map(std::fs::metadata)
         .filter_map(|metadata| match metadata {
-            Ok(metadata) => Some(metadata.len()),
+            Ok(metadata) => Some(file_size(&metadata, size_mode)),
             Err(err) => {
-                log::warn!("{err}");
+                log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
                 None
             }
         })
         .sum::<u64>()
 }
 
-///
-/// Copy files at paths by replacing src_directory
-/// prefix with dst_directory.
-///
-pub fn copy_files(src_directory: &Path, dst_directory: &Path, paths: &[PathBuf]) {
-    paths
-        .into_par_iter()
-        .map(|path| {
-            let stripped = path.strip_prefix(src_directory).unwrap();
-            let src_path = path;
-            let dst_path = dst_directory.join(stripped);
+#[cfg(unix)]
+fn file_size(metadata: &fs::Metadata, size_mode: SizeMode) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    match size_mode {
+        SizeMode::Apparent => metadata.len(),
+        // Blocks are always counted in 512 byte units
+        SizeMode::Allocated => metadata.blocks() * 512,
+    }
+}
+
+#[cfg(not(unix))]
+fn file_size(metadata: &fs::Metadata, _size_mode: SizeMode) -> u64 {
+    metadata.len()
+}
+
+///
+/// Find files that can not be read.
+///
+/// Only the first and the last block of each file are read
+/// so the check is fast even for big files.
+/// Files are checked in parallel.
+///
+pub fn find_unreadable_files(files_paths: &[PathBuf]) -> Vec<(PathBuf, io::Error)> {
+    files_paths
+        .into_par_iter()
+        .filter_map(|path| match check_file_readable(path) {
+            Ok(()) => None,
+            Err(err) => Some((path.clone(), err)),
+        })
+        .collect()
+}
+
+///
+/// Result of reading files without copying them.
+///
+#[derive(Default)]
+pub struct ReadSimulation {
+    /// Number of bytes that were read.
+    pub read_bytes: u64,
+    /// Files that failed to read with the reason.
+    pub unreadable_files: Vec<(PathBuf, io::Error)>,
+    /// Time spent reading files.
+    pub elapsed: Duration,
+}
+
+///
+/// Read files the same way they are read when copying
+/// but without writing them anywhere.
+///
+/// Whole files are read in chunks and in parallel so
+/// [ReadSimulation] shows both which files are unreadable
+/// and read throughput that copying can achieve.
+/// Files that are not read yet are skipped when the run
+/// is interrupted.
+///
+pub fn simulate_reads(paths: &[PathBuf]) -> ReadSimulation {
+    let start = Instant::now();
+    let read_bytes = AtomicU64::new(0);
+    let unreadable_files = paths
+        .into_par_iter()
+        .filter(|_| !is_interrupted())
+        .filter_map(|path| match read_whole_file(path) {
+            Ok(bytes) => {
+                read_bytes.fetch_add(bytes, Ordering::Relaxed);
+                None
+            }
+            Err(err) => Some((path.clone(), err)),
+        })
+        .collect();
+
+    ReadSimulation {
+        read_bytes: read_bytes.into_inner(),
+        unreadable_files,
+        elapsed: start.elapsed(),
+    }
+}
+
+fn read_whole_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; SIMULATION_CHUNK_SIZE];
+    let mut read_bytes = 0;
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return Ok(read_bytes),
+            Ok(size) => read_bytes += size as u64,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+///
+/// Result of copying files.
+///
+#[derive(Default)]
+pub struct CopySummary {
+    /// Files that failed to copy.
+    pub failed_files: Vec<PathBuf>,
+    /// Files that were not copied because dst_directory ran out of space.
+    /// Copying them can be retried after space is freed.
+    pub out_of_space_files: Vec<PathBuf>,
+    /// Copies whose names were too long for dst_directory or were sanitized
+    /// and original paths of their sources relative to src_directory.
+    pub changed_names: Vec<(PathBuf, PathBuf)>,
+    /// Paths of copied files and their copies.
+    pub copied_files: Vec<(PathBuf, PathBuf)>,
+    /// Number of files skipped because their copies are up to date.
+    pub up_to_date_files: usize,
+    /// Number of existing files in dst_directory that were replaced.
+    pub overwritten_files: usize,
+    /// Number of existing files in dst_directory that were kept
+    /// because of [OverwritePolicy].
+    pub kept_files: usize,
+    /// Number of files copied under new names because different
+    /// files already existed in dst_directory.
+    pub renamed_files: usize,
+    /// Copies of append-only files to which only appended part of
+    /// their sources was copied, with their sizes before appending.
+    pub appended_files: Vec<(PathBuf, u64)>,
+    /// Copies of files that changed while they were copied.
+    pub changed_files: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+enum CopyOutcome {
+    Copied,
+    /// Copied in a way that doesn't preserve exact content
+    /// (e.g. database backup), so it can't be verified.
+    BackedUp,
+    Failed,
+    OutOfSpace,
+    Skipped,
+    /// Copy from previous run has the same size and modification time.
+    UpToDate,
+    /// Existing file wasn't replaced because of [OverwritePolicy].
+    Kept,
+    /// Only part appended to the source after the given offset was copied.
+    Appended(u64),
+    /// Source changed while it was copied, so copy may be inconsistent.
+    CopiedWhileChanging,
+}
+
+///
+/// Copy files at paths by replacing src_directory
+/// prefix with dst_directory.
+///
+/// Symbolic links are recreated instead of copied when
+/// [SymlinkPolicy::CopyLink] is used.
+/// Files that are not copied yet are skipped when the run
+/// is interrupted.
+/// When dst_directory runs out of space no more files are copied
+/// and all files that are not copied are returned in
+/// [CopySummary::out_of_space_files], so errors are not logged
+/// for every one of them.
+/// Path components longer than [crate::long_names::MAX_NAME_LENGTH] are shortened
+/// (and sanitized with [CopyOptions::sanitize_names]) and reported
+/// in [CopySummary::changed_names].
+/// Files that are hard links to the same file are copied once
+/// and the links are recreated in dst_directory (or copied when
+/// dst_directory doesn't support hard links).
+/// Files that already exist in dst_directory are replaced or kept
+/// according to [CopyOptions::overwrite] or copied under new names
+/// when [CollisionPolicy::Rename] is used.
+/// With [CopyOptions::link] files are hard linked instead of copied
+/// when they're on the same filesystem as dst_directory.
+/// Paths of copies in dst_directory depend on [CopyOptions::layout].
+///
+pub fn copy_files(
+    src_directory: &Path,
+    dst_directory: &Path,
+    paths: &[PathBuf],
+    options: &CopyOptions,
+) -> CopySummary {
+    let out_of_space = AtomicBool::new(false);
+    let overwritten_files = AtomicUsize::new(0);
+    let renamed_files = AtomicUsize::new(0);
+    let (mut originals, hard_links) = split_hard_links(paths);
+    order_files(&mut originals, options.order);
+    // Bridged iterator hands files to threads in order of the plan
+    let mut outcomes = originals
+        .into_iter()
+        .enumerate()
+        .par_bridge()
+        .map(|(index, src_path)| {
+            let (dst_path, shortened) = destination_path(
+                src_directory,
+                dst_directory,
+                src_path,
+                &options.layout,
+                options.sanitize_names.as_ref(),
+            );
+            if is_interrupted() {
+                return (index, src_path, dst_path, shortened, CopyOutcome::Skipped);
+            }
+            if out_of_space.load(Ordering::Relaxed) {
+                return (
+                    index,
+                    src_path,
+                    dst_path,
+                    shortened,
+                    CopyOutcome::OutOfSpace,
+                );
+            }
+            if let Some(throttle) = &options.temperature_throttle {
+                throttle.wait_until_cool();
+            }
+
+            create_directories(dst_directory, &dst_path);
+            let is_symlink = src_path
+                .symlink_metadata()
+                .is_ok_and(|metadata| metadata.file_type().is_symlink());
+            let copy_link = is_symlink && options.symlinks == SymlinkPolicy::CopyLink;
+            if !copy_link && options.update && is_up_to_date(src_path, &dst_path, options.compare) {
+                return (index, src_path, dst_path, shortened, CopyOutcome::UpToDate);
+            }
+
+            let exists = dst_path.symlink_metadata().is_ok();
+            let rename = exists && options.on_collision == CollisionPolicy::Rename;
+            let dst_path = match rename {
+                true => match collision_free_path(src_path, &dst_path) {
+                    Some(renamed_path) => renamed_path,
+                    None => return (index, src_path, dst_path, shortened, CopyOutcome::UpToDate),
+                },
+                false => dst_path,
+            };
+            if exists && !rename && !should_overwrite(src_path, &dst_path, options) {
+                return (index, src_path, dst_path, shortened, CopyOutcome::Kept);
+            }
+
+            let outcome = if copy_link {
+                match copy_symlink(src_directory, dst_directory, src_path, &dst_path, options) {
+                    true => CopyOutcome::Copied,
+                    false => CopyOutcome::Failed,
+                }
+            } else if let Some(outcome) = options
+                .consistent_databases
+                .then(|| copy_database(src_path, &dst_path, options))
+                .flatten()
+            {
+                outcome
+            } else if let Some(detection) = &options.change_detection {
+                copy_file_detecting_changes(src_path, &dst_path, detection, options, &out_of_space)
+            } else {
+                copy_file_outcome(src_path, &dst_path, options, &out_of_space)
+            };
+            if exists
+                && matches!(
+                    outcome,
+                    CopyOutcome::Copied | CopyOutcome::BackedUp | CopyOutcome::CopiedWhileChanging
+                )
+            {
+                match rename {
+                    true => renamed_files.fetch_add(1, Ordering::Relaxed),
+                    false => overwritten_files.fetch_add(1, Ordering::Relaxed),
+                };
+            }
+            (index, src_path, dst_path, shortened, outcome)
+        })
+        .inspect(|(_, src_path, dst_path, _, outcome)| {
+            record_finished(options, src_path, dst_path, *outcome)
+        })
+        .collect::<Vec<_>>();
+    outcomes.sort_by_key(|(index, ..)| *index);
+    let outcomes = outcomes
+        .into_iter()
+        .map(|(_, src_path, dst_path, shortened, outcome)| (src_path, dst_path, shortened, outcome))
+        .collect::<Vec<_>>();
+
+    let original_outcomes = outcomes
+        .iter()
+        .map(|(path, dst_path, _, outcome)| (*path, (dst_path.clone(), *outcome)))
+        .collect::<HashMap<_, _>>();
+    let link_outcomes = hard_links
+        .into_iter()
+        .map(|(src_path, original)| {
+            let (dst_path, shortened) = destination_path(
+                src_directory,
+                dst_directory,
+                src_path,
+                &options.layout,
+                options.sanitize_names.as_ref(),
+            );
+            let (original_dst_path, original_outcome) = &original_outcomes[original];
+            let needs_link = match original_outcome {
+                CopyOutcome::Copied
+                | CopyOutcome::BackedUp
+                | CopyOutcome::Appended(_)
+                | CopyOutcome::CopiedWhileChanging => true,
+                // Link created after the original was copied by earlier run has no copy yet
+                CopyOutcome::UpToDate | CopyOutcome::Kept => dst_path.symlink_metadata().is_err(),
+                _ => false,
+            };
+            let outcome = match needs_link {
+                true if !is_interrupted() => {
+                    create_directories(dst_directory, &dst_path);
+                    let rename = options.on_collision == CollisionPolicy::Rename
+                        && dst_path.symlink_metadata().is_ok();
+                    let dst_path = match rename {
+                        true => match collision_free_path(src_path, &dst_path) {
+                            Some(renamed_path) => renamed_path,
+                            None => return (src_path, dst_path, shortened, CopyOutcome::UpToDate),
+                        },
+                        false => dst_path,
+                    };
+                    let outcome = match link_file(original_dst_path, &dst_path) {
+                        Ok(()) => CopyOutcome::Copied,
+                        Err(err) => {
+                            log::warn!(
+                                target: catalog::HARD_LINK_FAILED,
+                                "Failed to recreate hard link {}; copying it instead; {err}",
+                                dst_path.to_string_lossy()
+                            );
+                            copy_file_outcome(src_path, &dst_path, options, &out_of_space)
+                        }
+                    };
+                    if rename && matches!(outcome, CopyOutcome::Copied) {
+                        renamed_files.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return (src_path, dst_path, shortened, outcome);
+                }
+                true => CopyOutcome::Skipped,
+                false => *original_outcome,
+            };
+            (src_path, dst_path, shortened, outcome)
+        })
+        .inspect(|(src_path, dst_path, _, outcome)| {
+            record_finished(options, src_path, dst_path, *outcome)
+        })
+        .collect::<Vec<_>>();
+
+    let mut summary = CopySummary {
+        overwritten_files: overwritten_files.into_inner(),
+        renamed_files: renamed_files.into_inner(),
+        ..Default::default()
+    };
+    for (path, dst_path, shortened, outcome) in outcomes.into_iter().chain(link_outcomes) {
+        match (outcome, shortened) {
+            (CopyOutcome::Failed, _) => summary.failed_files.push(path.clone()),
+            (CopyOutcome::OutOfSpace, _) => summary.out_of_space_files.push(path.clone()),
+            (
+                outcome @ (CopyOutcome::Copied
+                | CopyOutcome::BackedUp
+                | CopyOutcome::CopiedWhileChanging),
+                shortened,
+            ) => {
+                if matches!(outcome, CopyOutcome::CopiedWhileChanging) {
+                    summary.changed_files.push(dst_path.clone());
+                }
+                if shortened.is_some() {
+                    let original = path.strip_prefix(src_directory).unwrap().to_path_buf();
+                    summary.changed_names.push((dst_path.clone(), original));
+                }
+                if !matches!(outcome, CopyOutcome::BackedUp) {
+                    summary.copied_files.push((path.clone(), dst_path));
+                }
+            }
+            (CopyOutcome::Appended(offset), shortened) => {
+                if shortened.is_some() {
+                    let original = path.strip_prefix(src_directory).unwrap().to_path_buf();
+                    summary.changed_names.push((dst_path.clone(), original));
+                }
+                summary.appended_files.push((dst_path.clone(), offset));
+                summary.copied_files.push((path.clone(), dst_path));
+            }
+            (CopyOutcome::UpToDate, _) => summary.up_to_date_files += 1,
+            (CopyOutcome::Kept, _) => summary.kept_files += 1,
+            (CopyOutcome::Skipped, _) => {}
+        }
+    }
+
+    summary
+}
+
+///
+/// Sort files in order in which they're copied.
+///
+/// Files whose size can't be read are treated as empty.
+///
+fn order_files(files_paths: &mut [&PathBuf], order: CopyOrder) {
+    let size = |path: &&PathBuf| fs::metadata(path).map_or(0, |metadata| metadata.len());
+    match order {
+        CopyOrder::Discovered => {}
+        CopyOrder::SmallestFirst => files_paths.sort_by_cached_key(size),
+        CopyOrder::LargestFirst => files_paths.sort_by_cached_key(|path| Reverse(size(path))),
+        CopyOrder::ByDirectory => {
+            files_paths.sort_by(|a, b| a.parent().cmp(&b.parent()).then_with(|| a.cmp(b)))
+        }
+    }
+}
+
+///
+/// Record file that doesn't have to be copied again when the run
+/// is resumed.
+///
+fn record_finished(options: &CopyOptions, src_path: &Path, dst_path: &Path, outcome: CopyOutcome) {
+    let Some(state) = &options.state else {
+        return;
+    };
+    match outcome {
+        CopyOutcome::Copied
+        | CopyOutcome::BackedUp
+        | CopyOutcome::Appended(_)
+        | CopyOutcome::CopiedWhileChanging => state.record_finished(src_path, Some(dst_path)),
+        CopyOutcome::UpToDate | CopyOutcome::Kept => state.record_finished(src_path, None),
+        // Files skipped because of interruption have to be copied
+        CopyOutcome::Failed | CopyOutcome::OutOfSpace | CopyOutcome::Skipped => {}
+    }
+}
+
+///
+/// Find files whose copies in dst_directory are missing or differ
+/// from them, so they would be written in [CopyOptions::update] mode.
+///
+/// Copies are compared by size and modification time even when
+/// [CompareMethod::Checksum] is used, so reading files doesn't
+/// double. Files are checked in parallel.
+///
+pub fn find_outdated_files(
+    src_directory: &Path,
+    dst_directory: &Path,
+    files_paths: &[PathBuf],
+    layout: &DestinationLayout,
+    sanitized_names: Option<&SanitizedNames>,
+) -> Vec<PathBuf> {
+    files_paths
+        .into_par_iter()
+        .filter(|src_path| {
+            let (dst_path, _) = destination_path(
+                src_directory,
+                dst_directory,
+                src_path,
+                layout,
+                sanitized_names,
+            );
+            !is_up_to_date(src_path, &dst_path, CompareMethod::Metadata)
+        })
+        .cloned()
+        .collect()
+}
+
+///
+/// Sanitize names of copies of files at paths, so copies
+/// of different files don't get the same sanitized names.
+///
+pub fn sanitized_names(
+    src_directory: &Path,
+    paths: &[PathBuf],
+    layout: &DestinationLayout,
+) -> SanitizedNames {
+    let relative_paths = paths
+        .iter()
+        .map(|src_path| layout_path(src_directory, src_path, layout))
+        .collect::<Vec<_>>();
+    SanitizedNames::new(relative_paths.iter().map(Cow::as_ref))
+}
+
+///
+/// Path in dst_directory where file at src_path is copied
+/// and changed path relative to dst_directory when names
+/// had to be shortened or sanitized.
+///
+pub fn destination_path(
+    src_directory: &Path,
+    dst_directory: &Path,
+    src_path: &Path,
+    layout: &DestinationLayout,
+    sanitized_names: Option<&SanitizedNames>,
+) -> (PathBuf, Option<PathBuf>) {
+    let stripped = layout_path(src_directory, src_path, layout);
+    let sanitized = sanitized_names.and_then(|names| names.sanitize(&stripped));
+    let stripped = sanitized.as_deref().unwrap_or(&stripped);
+    let shortened = shorten_path(stripped).or_else(|| sanitized.clone());
+    let dst_path = dst_directory.join(shortened.as_deref().unwrap_or(stripped));
+
+    (dst_path, shortened)
+}
+
+///
+/// Path of copy of file at src_path relative to dst_directory
+/// before its names are sanitized or shortened.
+///
+fn layout_path<'a>(
+    src_directory: &Path,
+    src_path: &'a Path,
+    layout: &'a DestinationLayout,
+) -> Cow<'a, Path> {
+    let relative = src_path.strip_prefix(src_directory).unwrap();
+    match layout {
+        DestinationLayout::Tree => Cow::Borrowed(relative),
+        DestinationLayout::Flat(names) => {
+            Cow::Borrowed(names.get(src_path).map_or(relative, PathBuf::as_path))
+        }
+        DestinationLayout::Template(template) => Cow::Owned(template.render(relative)),
+    }
+}
+
+///
+/// Copy database files so copies are consistent.
+///
+/// SQLite databases are copied with backup API and their journal,
+/// WAL and shared memory files are skipped because backup includes
+/// their content. Database server files that are in use are skipped.
+/// Returns None when file is not a database.
+///
+fn copy_database(src_path: &Path, dst_path: &Path, options: &CopyOptions) -> Option<CopyOutcome> {
+    if is_sqlite_sidecar(src_path) {
+        log::info!(
+            "Skipping {}; it's included in backup of its database",
+            src_path.to_string_lossy()
+        );
+        return Some(CopyOutcome::Skipped);
+    }
+
+    if is_sqlite_database(src_path) {
+        // Backup writes into dst in place, which would change files linked to it
+        let linked = hard_link_id(dst_path).is_some();
+        let backup_path = match linked {
+            true => dst_path.with_file_name(temp_file_name()),
+            false => dst_path.to_path_buf(),
+        };
+        let result = backup_sqlite_database(src_path, &backup_path).and_then(|()| match linked {
+            true => Ok(fs::rename(&backup_path, dst_path)?),
+            false => Ok(()),
+        });
+        if linked && result.is_err() {
+            let _ = fs::remove_file(&backup_path);
+        }
+        let outcome = match result {
+            Ok(()) => {
+                log::info!(
+                    "Backed up database {} to {}",
+                    src_path.to_string_lossy(),
+                    dst_path.to_string_lossy()
+                );
+                copy_timestamps(src_path, dst_path, options);
+                if options.preserve_xattrs {
+                    copy_xattrs(src_path, dst_path);
+                }
+                copy_permissions(src_path, dst_path, options);
+                CopyOutcome::BackedUp
+            }
+            Err(err) => {
+                log::warn!(
+                    target: catalog::DATABASE_NOT_CONSISTENT,
+                    "Failed to back up database {}; {err}",
+                    src_path.to_string_lossy()
+                );
+                CopyOutcome::Failed
+            }
+        };
+        return Some(outcome);
+    }
+
+    if is_server_database(src_path) && is_locked(src_path) {
+        log::warn!(
+            target: catalog::DATABASE_NOT_CONSISTENT,
+            "Skipping database {}; it's in use and its copy wouldn't be consistent",
+            src_path.to_string_lossy()
+        );
+        return Some(CopyOutcome::Skipped);
+    }
+
+    None
+}
+
+///
+/// Copy file and handle its changes according to [ChangePolicy].
+///
+/// Files that changed after they were found are reported
+/// (and skipped with [ChangePolicy::Skip]). Every attempt is written
+/// under temporary name and replaces existing copy only when src didn't
+/// change meanwhile. Files that change while they're copied are copied
+/// again up to [RECOPY_ATTEMPTS] times with [ChangePolicy::Recopy],
+/// their copies are thrown away with [ChangePolicy::Skip], so existing
+/// copies stay as they were, and they're reported otherwise.
+///
+/// Append-only files are appended to their copies in place, so with
+/// [ChangePolicy::Skip] they're copied whole instead.
+///
+fn copy_file_detecting_changes(
+    src_path: &Path,
+    dst_path: &Path,
+    detection: &ChangeDetection,
+    options: &CopyOptions,
+    out_of_space: &AtomicBool,
+) -> CopyOutcome {
+    let mut state = file_state(src_path);
+    if detection
+        .scanned
+        .get(src_path)
+        .is_some_and(|scanned| state.as_ref() != Some(scanned))
+    {
+        log::warn!(
+            target: catalog::CHANGED_SINCE_SCAN,
+            "{} changed after it was found",
+            src_path.to_string_lossy()
+        );
+        if detection.policy == ChangePolicy::Skip {
+            return CopyOutcome::Skipped;
+        }
+    }
+
+    let attempts = match detection.policy {
+        ChangePolicy::Recopy => RECOPY_ATTEMPTS,
+        ChangePolicy::Skip | ChangePolicy::Warn => 1,
+    };
+    let append = detection.policy != ChangePolicy::Skip;
+    let mut attempt = 1;
+    loop {
+        let staged = match stage_copy(src_path, dst_path, options, append) {
+            Ok(staged) => staged,
+            Err(err) => return copy_error_outcome(src_path, err, out_of_space),
+        };
+        let copied_state = file_state(src_path);
+        let changed = copied_state != state;
+        state = copied_state;
+        if changed && attempt < attempts {
+            discard_copy(staged);
+            log::info!(
+                "{} changed while it was copied; copying it again",
+                src_path.to_string_lossy()
+            );
+            attempt += 1;
+            continue;
+        }
+        if changed && detection.policy == ChangePolicy::Skip {
+            discard_copy(staged);
+            log::warn!(
+                target: catalog::CHANGED_WHILE_COPYING,
+                "Skipping {}; it changed while it was copied",
+                src_path.to_string_lossy()
+            );
+            return CopyOutcome::Skipped;
+        }
+
+        let outcome = match commit_copy(staged, dst_path, options) {
+            Ok(None) => CopyOutcome::Copied,
+            Ok(Some(offset)) => CopyOutcome::Appended(offset),
+            Err(err) => copy_error_outcome(src_path, err, out_of_space),
+        };
+        if changed && matches!(outcome, CopyOutcome::Copied | CopyOutcome::Appended(_)) {
+            log::warn!(
+                target: catalog::CHANGED_WHILE_COPYING,
+                "{} changed while it was copied; its copy may be inconsistent",
+                src_path.to_string_lossy()
+            );
+            return CopyOutcome::CopiedWhileChanging;
+        }
+        return outcome;
+    }
+}
+
+fn copy_file_outcome(
+    src_path: &Path,
+    dst_path: &Path,
+    options: &CopyOptions,
+    out_of_space: &AtomicBool,
+) -> CopyOutcome {
+    match copy_file(src_path, dst_path, options) {
+        Ok(None) => CopyOutcome::Copied,
+        Ok(Some(offset)) => CopyOutcome::Appended(offset),
+        Err(err) => copy_error_outcome(src_path, err, out_of_space),
+    }
+}
+
+///
+/// Log error of copying file and return its outcome.
+///
+fn copy_error_outcome(src_path: &Path, err: io::Error, out_of_space: &AtomicBool) -> CopyOutcome {
+    if err.kind() == io::ErrorKind::StorageFull {
+        if !out_of_space.swap(true, Ordering::Relaxed) {
+            log::error!(
+                target: catalog::OUT_OF_SPACE,
+                "dst_directory ran out of space while copying {}",
+                src_path.to_string_lossy()
+            );
+        }
+        return CopyOutcome::OutOfSpace;
+    }
+    log::warn!(
+        target: catalog::COPY_FAILED,
+        "Failed to copy {}; {err}",
+        src_path.to_string_lossy()
+    );
+    CopyOutcome::Failed
+}
+
+///
+/// Split paths into files that have to be copied and hard links
+/// to one of these files paired with the file they link to.
+///
+fn split_hard_links(paths: &[PathBuf]) -> (Vec<&PathBuf>, Vec<(&PathBuf, &PathBuf)>) {
+    let mut originals = Vec::with_capacity(paths.len());
+    let mut hard_links = Vec::new();
+    let mut known_ids = HashMap::new();
+    for path in paths {
+        match hard_link_id(path) {
+            Some(id) => match known_ids.entry(id) {
+                Entry::Occupied(original) => hard_links.push((path, *original.get())),
+                Entry::Vacant(entry) => {
+                    entry.insert(path);
+                    originals.push(path);
+                }
+            },
+            None => originals.push(path),
+        }
+    }
+
+    (originals, hard_links)
+}
+
+///
+/// Identifier of the file shared by all hard links to it (device
+/// and inode on Unix, volume serial number and file index on Windows).
+/// Returns None when file has no other hard links.
+///
+#[cfg(unix)]
+pub fn hard_link_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = path.symlink_metadata().ok()?;
+    (metadata.is_file() && metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(windows)]
+pub fn hard_link_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+
+    if !path.symlink_metadata().ok()?.is_file() {
+        return None;
+    }
+    let file = File::open(path).ok()?;
+    let mut information = BY_HANDLE_FILE_INFORMATION::default();
+    // SAFETY: handle is valid until file is dropped and information lives until the call ends
+    let result = unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut information) };
+    let file_index =
+        (u64::from(information.nFileIndexHigh) << 32) | u64::from(information.nFileIndexLow);
+
+    (result != 0 && information.nNumberOfLinks > 1)
+        .then(|| (u64::from(information.dwVolumeSerialNumber), file_index))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn hard_link_id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+///
+/// Hard link original at link path. Link is created under temporary
+/// name and renamed over existing file, so existing file is kept
+/// when link can't be created.
+///
+fn link_file(original: &Path, link: &Path) -> io::Result<()> {
+    let temp_path = link.with_file_name(temp_file_name());
+    let result = fs::hard_link(original, &temp_path).and_then(|()| fs::rename(&temp_path, link));
+    // Renaming link over the same file does nothing and leaves it behind
+    let _ = fs::remove_file(&temp_path);
+    result?;
+    log::info!(
+        "Linked {} to {}",
+        link.to_string_lossy(),
+        original.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+fn should_keep_entry(
+    entry: &DirEntry,
+    src_directory: &Path,
+    exclusions: &Exclusions,
+    options: &SearchOptions,
+    visited_directories: &mut HashSet<(u64, u64)>,
+) -> bool {
+    let is_dir = entry.file_type().is_dir();
+    if exclusions.is_excluded(src_directory, entry.path(), is_dir) {
+        return false;
+    }
+
+    if is_dir && options.skip_caches && is_cache_directory(entry.path()) {
+        log::info!(
+            "Skipping cache directory {}",
+            entry.path().to_string_lossy()
+        );
+        return false;
+    }
+
+    if let Some(marker_file) = options.marker_file.as_ref().filter(|_| is_dir) {
+        if entry.path().join(marker_file).exists() {
+            log::info!(
+                "Skipping directory {} marked with {marker_file}",
+                entry.path().to_string_lossy()
+            );
+            return false;
+        }
+    }
+
+    // Directories reached more than once (bind mounts, symbolic links) are traversed once
+    if is_dir && entry_id(entry).is_some_and(|id| !visited_directories.insert(id)) {
+        log::info!(
+            "Skipping {}; the same directory was already found",
+            entry.path().to_string_lossy()
+        );
+        return false;
+    }
+
+    true
+}
+
+///
+/// Leave out selected files that are the same physical file
+/// as other selected file and were reached through symbolic link.
+///
+/// File selected at its own path is kept over symbolic links to it,
+/// otherwise the first symbolic link is kept. Separate hard links
+/// to the same file are kept, so they can be recreated in dst_directory.
+///
+fn remove_linked_duplicates(entries: Vec<DirEntry>) -> Vec<DirEntry> {
+    let entries = entries
+        .into_iter()
+        .map(|entry| (entry_id(&entry), entry))
+        .collect::<Vec<_>>();
+    let mut found_directly = HashMap::new();
+    for (id, entry) in &entries {
+        if let Some(id) = id {
+            *found_directly.entry(*id).or_insert(false) |= !entry.path_is_symlink();
+        }
+    }
+
+    let mut found_links = HashSet::new();
+    entries
+        .into_iter()
+        .filter(|(id, entry)| {
+            let Some(id) = id else {
+                return true;
+            };
+            let keep = match found_directly[id] {
+                true => !entry.path_is_symlink(),
+                false => found_links.insert(*id),
+            };
+            if !keep {
+                log::info!(
+                    "Skipping {}; the same file was already found",
+                    entry.path().to_string_lossy()
+                );
+            }
+            keep
+        })
+        .map(|(_, entry)| entry)
+        .collect()
+}
+
+#[cfg(unix)]
+fn entry_id(entry: &DirEntry) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = entry.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn entry_id(_entry: &DirEntry) -> Option<(u64, u64)> {
+    None
+}
+
+///
+/// Check whether directory contains CACHEDIR.TAG file
+/// starting with the signature.
+///
+fn is_cache_directory(path: &Path) -> bool {
+    let mut signature = [0; CACHEDIR_TAG_SIGNATURE.len()];
+    File::open(path.join(CACHEDIR_TAG_NAME))
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|_| signature == CACHEDIR_TAG_SIGNATURE)
+}
+
+///
+/// Name of the kind of special file or None
+/// when entry is regular file, directory or symbolic link.
+///
+#[cfg(unix)]
+fn special_file_kind(entry: &DirEntry) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = entry.file_type();
+    if file_type.is_fifo() {
+        Some("FIFO")
+    } else if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_block_device() {
+        Some("block device")
+    } else if file_type.is_char_device() {
+        Some("character device")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn special_file_kind(_entry: &DirEntry) -> Option<&'static str> {
+    None
+}
+
+fn is_file_to_copy(entry: &DirEntry, symlinks: SymlinkPolicy) -> bool {
+    entry.file_type().is_file()
+        || (entry.file_type().is_symlink() && symlinks == SymlinkPolicy::CopyLink)
+}
+
+fn should_copy_file(
+    entry: &DirEntry,
+    src_directory: &Path,
+    suffixes: &Suffixes,
+    case_insensitive: bool,
+) -> bool {
+    let relative_path = entry.path().strip_prefix(src_directory).unwrap();
+    if case_insensitive {
+        let relative_path = relative_path.to_string_lossy().to_lowercase();
+        return suffixes.matches(relative_path.as_str());
+    }
+    suffixes.matches(relative_path)
+}
+
+///
+/// Check whether file is locked by another process so it can't be
+/// read consistently. On Windows file is also locked when another
+/// process opened it without sharing it for reading.
+///
+fn is_locked(path: &Path) -> bool {
+    /// Windows errors returned when file is used by another process.
+    const SHARING_VIOLATION_ERRORS: [i32; 2] = [32, 33];
+
+    match File::open(path) {
+        Ok(file) => matches!(file.try_lock_shared(), Err(TryLockError::WouldBlock)),
+        Err(err) => {
+            cfg!(windows)
+                && err
+                    .raw_os_error()
+                    .is_some_and(|code| SHARING_VIOLATION_ERRORS.contains(&code))
+        }
+    }
+}
+
+///
+/// Check whether file is a cloud placeholder (e.g. OneDrive or Dropbox
+/// file available online only) whose content would be downloaded
+/// when it's read. Only metadata of the entry is used.
+///
+#[cfg(windows)]
+fn is_placeholder(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, FILE_ATTRIBUTE_RECALL_ON_OPEN,
+    };
+
+    let placeholder_attributes = FILE_ATTRIBUTE_OFFLINE
+        | FILE_ATTRIBUTE_RECALL_ON_OPEN
+        | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS;
+    entry
+        .metadata()
+        .is_ok_and(|metadata| metadata.file_attributes() & placeholder_attributes != 0)
+}
+
+#[cfg(target_os = "macos")]
+fn is_placeholder(entry: &DirEntry) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    /// Flag of files whose content is managed by file provider (e.g. iCloud Drive).
+    const SF_DATALESS: u32 = 0x40000000;
+
+    entry
+        .metadata()
+        .is_ok_and(|metadata| metadata.st_flags() & SF_DATALESS != 0)
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn is_placeholder(_entry: &DirEntry) -> bool {
+    false
+}
+
+fn check_file_readable(path: &Path) -> io::Result<()> {
+    let mut file = open_for_reading(path)?;
+    let file_size = file.metadata()?.len();
+    let mut buffer = vec![0; PRECHECK_BLOCK_SIZE as usize];
+
+    let first_block_size = file_size.min(PRECHECK_BLOCK_SIZE) as usize;
+    file.read_exact(&mut buffer[..first_block_size])?;
+
+    if file_size > PRECHECK_BLOCK_SIZE {
+        file.seek(SeekFrom::Start(file_size - PRECHECK_BLOCK_SIZE))?;
+        file.read_exact(&mut buffer)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn has_owner(entry: &DirEntry, owner: Option<u32>, group: Option<u32>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    if owner.is_none() && group.is_none() {
+        return true;
+    }
+
+    match entry.metadata() {
+        Ok(metadata) => {
+            owner.is_none_or(|uid| metadata.uid() == uid)
+                && group.is_none_or(|gid| metadata.gid() == gid)
+        }
+        Err(err) => {
+            log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+            false
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn has_owner(_entry: &DirEntry, _owner: Option<u32>, _group: Option<u32>) -> bool {
+    true
+}
+
+fn was_accessed_after(entry: &DirEntry, time: Option<SystemTime>) -> bool {
+    let Some(time) = time else {
+        return true;
+    };
+
+    match entry.metadata().map(|metadata| metadata.accessed()) {
+        Ok(Ok(accessed)) => accessed >= time,
+        // Filesystem doesn't store access times
+        Ok(Err(_)) => true,
+        Err(err) => {
+            log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+            false
+        }
+    }
+}
+
+fn has_file_type(entry: &DirEntry, file_types: &[FileType]) -> bool {
+    if file_types.is_empty() {
+        return true;
+    }
+
+    match detect_file_type(entry.path()) {
+        Ok(Some(file_type)) => file_types.contains(&file_type),
+        Ok(None) => false,
+        Err(err) => {
+            log::warn!(
+                target: catalog::TYPE_DETECTION_FAILED,
+                "Failed to detect type of {}; {err}",
+                entry.path().to_string_lossy()
+            );
+            false
+        }
+    }
+}
+
+fn create_directories(dst_root: &Path, dst: &Path) {
+    let mut dir_path = dst_root.to_path_buf();
+    let dst_components = dst
+        .parent()
+        .unwrap()
+        .strip_prefix(dst_root)
+        .unwrap()
+        .components();
+    for component in dst_components {
+        dir_path.push(component);
+        if dir_path.is_dir() {
+            continue;
+        }
+
+        if let Err(err) = fs::create_dir(extended_length_path(&dir_path)) {
+            log::warn!(
+                target: catalog::COPY_FAILED,
+                "Failed to create parent directories for {}; {err}",
+                dst.to_string_lossy()
+            );
+            break;
+        }
+    }
+}
+
+///
+/// Copy of src written under temporary name, that replaces dst
+/// when it's committed, or part of src already appended to dst.
+///
+enum StagedCopy {
+    Temp(PathBuf),
+    Appended(u64),
+}
+
+///
+/// Copy file and return offset from which it was copied
+/// when only part appended to append-only file was copied.
+///
+fn copy_file(src: &Path, dst: &Path, options: &CopyOptions) -> io::Result<Option<u64>> {
+    let staged = stage_copy(src, dst, options, true)?;
+    commit_copy(staged, dst, options)
+}
+
+///
+/// Write copy of src with its metadata under temporary name next to dst
+/// (linked, cloned, updated with delta or copied, depending on options).
+///
+/// When appending is allowed, part appended to append-only file is
+/// appended to dst instead. Otherwise dst isn't changed.
+///
+fn stage_copy(
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+    append: bool,
+) -> io::Result<StagedCopy> {
+    // Paths of deeply nested files can be longer than Windows allows without prefix
+    let (src, dst) = (&*extended_length_path(src), &*extended_length_path(dst));
+    // Appending in place would change files linked to dst too
+    let appended_from = match append && is_append_only(src, options) && hard_link_id(dst).is_none()
+    {
+        true => appended_offset(src, dst),
+        false => None,
+    };
+    if let Some(offset) = appended_from {
+        let bytes_appended = append_tail(src, dst, offset)?;
+        log_copy("Appended", bytes_appended, src, dst);
+        copy_metadata(src, dst, options);
+        return Ok(StagedCopy::Appended(offset));
+    }
+
+    let temp_path = dst.with_file_name(temp_file_name());
+    if options.link {
+        match fs::hard_link(src, &temp_path) {
+            Ok(()) => {
+                log::info!(
+                    "Linked {} to {}",
+                    dst.to_string_lossy(),
+                    src.to_string_lossy()
+                );
+                return Ok(StagedCopy::Temp(temp_path));
+            }
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                log::debug!(
+                    "{} is on other filesystem; copying it",
+                    src.to_string_lossy()
+                );
+            }
+            Err(err) => log::warn!(
+                target: catalog::HARD_LINK_FAILED,
+                "Failed to link {} to {}; copying it instead; {err}",
+                dst.to_string_lossy(),
+                src.to_string_lossy()
+            ),
+        }
+    }
+    let (temp_path, kind, bytes) =
+        if let Some(bytes_cloned) = try_clone_file(src, &temp_path, options.reflink)? {
+            (temp_path, "Cloned", bytes_cloned)
+        } else if let Some(bytes_rewritten) = options
+            .delta
+            .then(|| try_copy_delta(src, dst, &temp_path, options))
+            .flatten()
+        {
+            (temp_path, "Rewrote", bytes_rewritten)
+        } else {
+            let (temp_path, bytes_copied) = copy_to_temp_file(src, dst, options)?;
+            (temp_path, "Copied", bytes_copied)
+        };
+    log_copy(kind, bytes, src, dst);
+    copy_metadata(src, &temp_path, options);
+    warn_about_lost_streams(src, &temp_path);
+
+    Ok(StagedCopy::Temp(temp_path))
+}
+
+///
+/// Replace dst with staged copy and return offset from which src
+/// was copied when it was appended.
+///
+/// Existing dst that differs from the copy is kept as previous version
+/// first. Appended copy still contains previous version.
+///
+/// #### Errors
+/// This function returns error when previous version can't be kept
+/// or staged copy can't be renamed, it's removed then.
+///
+fn commit_copy(staged: StagedCopy, dst: &Path, options: &CopyOptions) -> io::Result<Option<u64>> {
+    let temp_path = match staged {
+        StagedCopy::Temp(temp_path) => temp_path,
+        StagedCopy::Appended(offset) => return Ok(Some(offset)),
+    };
+    let dst = extended_length_path(dst);
+    let result = match options.backup_versions > 0
+        && dst.symlink_metadata().is_ok()
+        && !is_up_to_date(&temp_path, &dst, CompareMethod::Checksum)
+    {
+        true => keep_previous_version(&dst, options.backup_versions),
+        false => Ok(()),
+    }
+    .and_then(|()| fs::rename(&temp_path, &dst));
+    // Renaming link over the same file does nothing and leaves it behind
+    let _ = fs::remove_file(&temp_path);
+
+    result.map(|()| None)
+}
+
+///
+/// Remove staged copy, so dst stays as it was. Appended part stays in dst.
+///
+fn discard_copy(staged: StagedCopy) {
+    if let StagedCopy::Temp(temp_path) = staged {
+        let _ = fs::remove_file(temp_path);
+    }
+}
+
+fn log_copy(kind: &str, bytes: u64, src: &Path, dst: &Path) {
+    log::info!(
+        "{kind} {} from {} to {}",
+        Byte::from_bytes(bytes as u128).get_appropriate_unit(true),
+        src.to_string_lossy(),
+        dst.to_string_lossy()
+    );
+}
+
+///
+/// Copy metadata of src to its copy according to options.
+///
+fn copy_metadata(src: &Path, dst: &Path, options: &CopyOptions) {
+    if options.preserve_ads {
+        copy_streams(src, dst);
+    }
+    copy_timestamps(src, dst, options);
+    if options.preserve_xattrs {
+        copy_xattrs(src, dst);
+    }
+    copy_attributes(src, dst);
+    if options.preserve_acl {
+        copy_acl(src, dst);
+    }
+    if options.preserve_metadata {
+        copy_mac_metadata(src, dst);
+    }
+    copy_permissions(src, dst, options);
+}
+
+///
+/// Replace copy of src with its new full copy, e.g. when copy
+/// turned out to differ from src.
+///
+/// Metadata is preserved the same way as when file is copied.
+///
+/// #### Errors
+/// This function returns error when file can't be copied.
+///
+pub fn recopy_file(src: &Path, dst: &Path, options: &CopyOptions) -> io::Result<()> {
+    let bytes_copied = copy_through_temp_file(src, dst, options)?;
+    log_copy("Copied again", bytes_copied, src, dst);
+    copy_metadata(src, dst, options);
+
+    Ok(())
+}
+
+///
+/// Write new copy of src into temp_path from blocks of its existing copy
+/// that are in src and return number of bytes taken from src or None
+/// when file has to be copied.
+///
+/// Copy that fails to be updated is copied again.
+///
+fn try_copy_delta(src: &Path, dst: &Path, temp_path: &Path, options: &CopyOptions) -> Option<u64> {
+    let metadata = dst.symlink_metadata().ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    match copy_delta(src, dst, temp_path, options.rate_limiter.as_ref()) {
+        Ok(bytes_rewritten) => Some(bytes_rewritten),
+        Err(err) => {
+            let _ = fs::remove_file(temp_path);
+            log::debug!(
+                "{} can't be updated with delta; copying it; {err}",
+                dst.to_string_lossy()
+            );
+            None
+        }
+    }
+}
+
+///
+/// Set permissions of dst to those of src when they're preserved.
+///
+/// Copied content usually has permissions of src already. They're set
+/// last again, because backed up databases, copies updated with delta
+/// and copies made with backup privileges get them only here.
+///
+fn copy_permissions(src: &Path, dst: &Path, options: &CopyOptions) {
+    if !options.preserve_permissions {
+        return;
+    }
+    let result =
+        fs::metadata(src).and_then(|metadata| fs::set_permissions(dst, metadata.permissions()));
+    if let Err(err) = result {
+        log::warn!(
+            target: catalog::PERMISSIONS_NOT_PRESERVED,
+            "Failed to preserve permissions of {}; {err}",
+            dst.to_string_lossy()
+        );
+    }
+}
+
+///
+/// Set timestamps of dst to those of src when they're preserved
+/// or when [CopyOptions::update] needs modification time to compare copies.
+///
+fn copy_timestamps(src: &Path, dst: &Path, options: &CopyOptions) {
+    let result = match (options.preserve_timestamps, options.update) {
+        (true, _) => preserve_timestamps(src, dst),
+        (false, true) => preserve_modification_time(src, dst),
+        (false, false) => Ok(()),
+    };
+    if let Err(err) = result {
+        log::warn!(
+            target: catalog::TIMESTAMPS_NOT_PRESERVED,
+            "Failed to preserve timestamps of {}; {err}",
+            dst.to_string_lossy()
+        );
+    }
+}
+
+fn is_append_only(src: &Path, options: &CopyOptions) -> bool {
+    options.update
+        && options
+            .append_only
+            .as_ref()
+            .zip(src.file_name())
+            .is_some_and(|(globs, name)| globs.is_match(name))
+}
+
+///
+/// Return size of dst when dst is beginning of bigger src, so only
+/// the rest of src has to be appended to it.
+///
+/// First and last [APPEND_CHECK_BLOCK_SIZE] bytes of dst are compared
+/// with the same parts of src, which is enough to tell apart files
+/// that were rotated or rewritten.
+///
+fn appended_offset(src: &Path, dst: &Path) -> Option<u64> {
+    let mut src_file = open_for_reading(src).ok()?;
+    let mut dst_file = File::open(dst).ok()?;
+    let src_size = src_file.metadata().ok()?.len();
+    let dst_size = dst_file.metadata().ok()?.len();
+    if dst_size == 0 || dst_size >= src_size {
+        return None;
+    }
+
+    let block_size = dst_size.min(APPEND_CHECK_BLOCK_SIZE);
+    let mut src_block = vec![0; block_size as usize];
+    let mut dst_block = vec![0; block_size as usize];
+    for offset in [0, dst_size - block_size] {
+        src_file.seek(SeekFrom::Start(offset)).ok()?;
+        dst_file.seek(SeekFrom::Start(offset)).ok()?;
+        src_file.read_exact(&mut src_block).ok()?;
+        dst_file.read_exact(&mut dst_block).ok()?;
+        if src_block != dst_block {
+            return None;
+        }
+    }
+
+    Some(dst_size)
+}
+
+///
+/// Append part of src after offset to dst and return number of appended bytes.
+///
+fn append_tail(src: &Path, dst: &Path, offset: u64) -> io::Result<u64> {
+    let mut src_file = open_for_reading(src)?;
+    src_file.seek(SeekFrom::Start(offset))?;
+    let mut dst_file = File::options().append(true).open(dst)?;
+    io::copy(&mut src_file, &mut dst_file)
+}
+
+///
+/// Set modification time of dst to that of src.
+///
+fn preserve_modification_time(src: &Path, dst: &Path) -> io::Result<()> {
+    let modified = fs::metadata(src)?.modified()?;
+    open_for_times(dst)?.set_times(FileTimes::new().set_modified(modified))
+}
+
+///
+/// Check whether dst has the same size as src and the same
+/// modification time or content, depending on compare method.
+///
+/// Modification times can differ by [MODIFICATION_TIME_TOLERANCE]
+/// because some filesystems (e.g. FAT) store them with low precision.
+///
+fn is_up_to_date(src: &Path, dst: &Path, compare: CompareMethod) -> bool {
+    let (Ok(src_metadata), Ok(dst_metadata)) = (fs::metadata(src), fs::metadata(dst)) else {
+        return false;
+    };
+    if src_metadata.len() != dst_metadata.len() {
+        return false;
+    }
+    if compare == CompareMethod::Checksum {
+        return match (hash_file(src), hash_file(dst)) {
+            (Ok(src_hash), Ok(dst_hash)) => src_hash == dst_hash,
+            _ => false,
+        };
+    }
+    let (Ok(src_modified), Ok(dst_modified)) = (src_metadata.modified(), dst_metadata.modified())
+    else {
+        return false;
+    };
+    let difference = src_modified
+        .duration_since(dst_modified)
+        .or_else(|_| dst_modified.duration_since(src_modified))
+        .unwrap_or_default();
+
+    difference <= MODIFICATION_TIME_TOLERANCE
+}
+
+///
+/// Decide whether existing file at dst is replaced with src
+/// according to [CopyOptions::overwrite] and log the decision.
+///
+/// Files whose metadata can't be read are replaced.
+///
+fn should_overwrite(src: &Path, dst: &Path, options: &CopyOptions) -> bool {
+    let (overwrite, reason) = match options.overwrite {
+        OverwritePolicy::Always => return true,
+        OverwritePolicy::Never => (false, "overwriting is disabled"),
+        OverwritePolicy::IfNewer => match is_newer(src, dst) {
+            true => (true, "source is newer"),
+            false => (false, "source is not newer"),
+        },
+        OverwritePolicy::IfDifferent => match is_up_to_date(src, dst, options.compare) {
+            true => (false, "it's the same as source"),
+            false => (true, "it differs from source"),
+        },
+    };
+    match overwrite {
+        true => log::info!("Overwriting {}; {reason}", dst.to_string_lossy()),
+        false => log::info!("Keeping existing {}; {reason}", dst.to_string_lossy()),
+    }
+
+    overwrite
+}
+
+///
+/// Find path where src can be copied without replacing different
+/// file at dst, by adding ' (N)' to the name of the file.
+///
+/// Returns None when dst or one of its renamed copies has the same
+/// content as src, so there's nothing to copy.
+///
+fn collision_free_path(src: &Path, dst: &Path) -> Option<PathBuf> {
+    let mut candidate = dst.to_path_buf();
+    let mut number = 0;
+    while candidate.symlink_metadata().is_ok() {
+        if is_up_to_date(src, &candidate, CompareMethod::Checksum) {
+            log::info!(
+                "Skipping {}; its copy {} already exists",
+                src.to_string_lossy(),
+                candidate.to_string_lossy()
+            );
+            return None;
+        }
+        number += 1;
+        candidate = numbered_path(dst, number);
+    }
+    log::info!(
+        "{} already exists and differs; copying {} as {}",
+        dst.to_string_lossy(),
+        src.to_string_lossy(),
+        candidate.to_string_lossy()
+    );
+
+    Some(candidate)
+}
+
+///
+/// Add ' (number)' between stem and extension of the file name.
+///
+pub fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(" ({number})"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+
+    path.with_file_name(name)
+}
+
+///
+/// Check whether src was modified later than dst.
+///
+/// Modification times within [MODIFICATION_TIME_TOLERANCE]
+/// are considered equal.
+///
+fn is_newer(src: &Path, dst: &Path) -> bool {
+    let src_modified = fs::metadata(src).and_then(|metadata| metadata.modified());
+    let dst_modified = fs::symlink_metadata(dst).and_then(|metadata| metadata.modified());
+    match (src_modified, dst_modified) {
+        (Ok(src_modified), Ok(dst_modified)) => src_modified
+            .duration_since(dst_modified)
+            .is_ok_and(|difference| difference > MODIFICATION_TIME_TOLERANCE),
+        _ => true,
+    }
+}
+
+///
+/// Set access and modification times of dst to those of src.
+///
+/// Creation time is preserved on Windows and macOS. Linux has no way
+/// to set birth time so it's left as the time of copying.
+///
+fn preserve_timestamps(src: &Path, dst: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(src)?;
+    #[allow(unused_mut)]
+    let mut times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTimesExt;
+        if let Ok(created) = metadata.created() {
+            times = times.set_created(created);
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::macos::fs::FileTimesExt;
+        if let Ok(created) = metadata.created() {
+            times = times.set_created(created);
+        }
+    }
+
+    open_for_times(dst)?.set_times(times)
+}
+
+///
+/// Copy src to temporary file and rename it to dst when it's complete,
+/// so interrupted copy never leaves truncated file that looks like
+/// a valid copy and existing dst is replaced only by complete one.
+///
+fn copy_through_temp_file(src: &Path, dst: &Path, options: &CopyOptions) -> io::Result<u64> {
+    let (temp_path, bytes_copied) = copy_to_temp_file(src, dst, options)?;
+    let result = fs::rename(&temp_path, dst);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result.map(|()| bytes_copied)
+}
+
+///
+/// Copy src to temporary file that can be renamed to dst and return
+/// its path and number of copied bytes. File is written to temp_directory
+/// when it's on the same filesystem as dst, or next to dst.
+///
+fn copy_to_temp_file(src: &Path, dst: &Path, options: &CopyOptions) -> io::Result<(PathBuf, u64)> {
+    let dst_parent = dst.parent().unwrap();
+    let staging_directory = options
+        .temp_directory
+        .as_deref()
+        .filter(|temp_directory| is_same_filesystem(temp_directory, dst_parent))
+        .unwrap_or(dst_parent);
+    let temp_path = staging_directory.join(temp_file_name());
+
+    match copy_contents(src, &temp_path, options.rate_limiter.as_ref()) {
+        Ok(bytes_copied) => Ok((temp_path, bytes_copied)),
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(err)
+        }
+    }
+}
+
+///
+/// Clone src to clone_path according to policy and return size
+/// of the clone or None when file has to be copied.
+///
+/// #### Errors
+/// This function returns error when file can't be cloned
+/// and [ReflinkPolicy::Always] is used.
+///
+fn try_clone_file(src: &Path, clone_path: &Path, policy: ReflinkPolicy) -> io::Result<Option<u64>> {
+    if policy == ReflinkPolicy::Never {
+        return Ok(None);
+    }
+    match clone_file(src, clone_path) {
+        Ok(bytes_cloned) => Ok(Some(bytes_cloned)),
+        Err(err) if policy == ReflinkPolicy::Always => Err(err),
+        Err(err) => {
+            log::debug!(
+                "{} can't be cloned; copying it; {err}",
+                src.to_string_lossy()
+            );
+            Ok(None)
+        }
+    }
+}
+
+///
+/// Clone file content (FICLONE on Linux, clonefile on macOS, block
+/// cloning on Windows) and permissions to clone_path.
+///
+/// Clone that fails midway is removed.
+///
+fn clone_file(src: &Path, clone_path: &Path) -> io::Result<u64> {
+    let result = reflink_copy::reflink(src, clone_path)
+        .and_then(|()| fs::metadata(src))
+        .and_then(|metadata| {
+            fs::set_permissions(clone_path, metadata.permissions())?;
+            Ok(metadata.len())
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(clone_path);
+    }
+
+    result
+}
+
+///
+/// Copy file content and permissions. When file can't be read because
+/// of its permissions and backup privileges are enabled, its content
+/// is copied through file opened with them instead.
+/// Holes of sparse files are preserved on Linux.
+///
+fn copy_contents(src: &Path, dst: &Path, rate_limiter: Option<&RateLimiter>) -> io::Result<u64> {
+    if is_sparse(src) {
+        return copy_sparse_file(src, dst, rate_limiter);
+    }
+    if let Some(rate_limiter) = rate_limiter {
+        return copy_limited_file(src, dst, rate_limiter);
+    }
+    match fs::copy(src, dst) {
+        Err(err)
+            if err.kind() == io::ErrorKind::PermissionDenied && backup_privileges_enabled() =>
+        {
+            let mut src_file = open_for_reading(src)?;
+            let mut dst_file = File::create(dst)?;
+            io::copy(&mut src_file, &mut dst_file)
+        }
+        result => result,
+    }
+}
+
+///
+/// Copy file content in chunks not faster than the rate limit,
+/// and its permissions.
+///
+fn copy_limited_file(src: &Path, dst: &Path, rate_limiter: &RateLimiter) -> io::Result<u64> {
+    let mut src_file = match File::open(src) {
+        Err(err)
+            if err.kind() == io::ErrorKind::PermissionDenied && backup_privileges_enabled() =>
+        {
+            open_for_reading(src)?
+        }
+        result => result?,
+    };
+    let mut dst_file = File::create(dst)?;
+    let bytes_copied = rate_limiter.copy(&mut src_file, &mut dst_file)?;
+    fs::set_permissions(dst, src_file.metadata()?.permissions())?;
+
+    Ok(bytes_copied)
+}
+
+#[cfg(target_os = "linux")]
+fn is_sparse(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| {
+        file_size(&metadata, SizeMode::Allocated) < file_size(&metadata, SizeMode::Apparent)
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_sparse(_path: &Path) -> bool {
+    false
+}
+
+///
+/// Copy only data of sparse src (found with SEEK_DATA and SEEK_HOLE),
+/// so holes are left unallocated in dst too. Permissions are copied too.
+///
+#[cfg(target_os = "linux")]
+fn copy_sparse_file(src: &Path, dst: &Path, rate_limiter: Option<&RateLimiter>) -> io::Result<u64> {
+    use nix::{
+        errno::Errno,
+        unistd::{lseek, Whence},
+    };
+    use std::io::Read;
+
+    let src_file = File::open(src)?;
+    let metadata = src_file.metadata()?;
+    let mut dst_file = File::create(dst)?;
+    let mut offset = 0;
+    loop {
+        let data_start = match lseek(&src_file, offset, Whence::SeekData) {
+            Ok(data_start) => data_start,
+            // There's no data after offset
+            Err(Errno::ENXIO) => break,
+            Err(errno) => return Err(errno.into()),
+        };
+        let data_end = lseek(&src_file, data_start, Whence::SeekHole)?;
+        (&src_file).seek(SeekFrom::Start(data_start as u64))?;
+        dst_file.seek(SeekFrom::Start(data_start as u64))?;
+        let mut data = (&src_file).take((data_end - data_start) as u64);
+        match rate_limiter {
+            Some(rate_limiter) => rate_limiter.copy(&mut data, &mut dst_file)?,
+            None => io::copy(&mut data, &mut dst_file)?,
+        };
+        offset = data_end;
+    }
+    // Trailing hole
+    dst_file.set_len(metadata.len())?;
+    fs::set_permissions(dst, metadata.permissions())?;
+
+    Ok(metadata.len())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_sparse_file(src: &Path, dst: &Path, rate_limiter: Option<&RateLimiter>) -> io::Result<u64> {
+    match rate_limiter {
+        Some(rate_limiter) => copy_limited_file(src, dst, rate_limiter),
+        None => fs::copy(src, dst),
+    }
+}
+
+///
+/// Unique name of temporary file.
+///
+/// Name doesn't contain name of the file it replaces, so it doesn't
+/// exceed name length limit when name of that file is long.
+///
+pub fn temp_file_name() -> String {
+    let counter = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!(".smf-{}-{}.part", process::id(), counter)
+}
+
+///
+/// Check whether filesystem storing path updates access times of files.
+///
+/// Filesystems mounted with 'noatime' don't, so access times
+/// tell nothing about use of files. It's checked only on Linux,
+/// other platforms are assumed to update access times.
+///
+#[cfg(target_os = "linux")]
+pub fn records_access_times(path: &Path) -> bool {
+    match fs::read_to_string("/proc/self/mountinfo") {
+        Ok(mountinfo) => mount_options(&mountinfo, path)
+            .is_none_or(|options| !options.split(',').any(|option| option == "noatime")),
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn records_access_times(_path: &Path) -> bool {
+    true
+}
+
+///
+/// Find options of the mount that contains path in content
+/// of '/proc/self/mountinfo'.
+///
+/// Path needs to be canonical.
+///
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn mount_options(mountinfo: &str, path: &Path) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ').skip(4);
+            let mount_point = unescape_mount_field(fields.next()?);
+            let options = fields.next()?;
+            path.starts_with(&mount_point)
+                .then(|| (mount_point, options.to_string()))
+        })
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, options)| options)
+}
+
+///
+/// Replace octal escapes of whitespace and backslash used in mountinfo fields.
+///
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn unescape_mount_field(field: &str) -> PathBuf {
+    let unescaped = field
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\");
+    PathBuf::from(unescaped)
+}
+
+///
+/// Check whether both paths are on the same filesystem
+/// so files can be renamed between them.
+///
+/// Paths that can't be read are treated as being
+/// on different filesystems.
+///
+#[cfg(unix)]
+pub fn is_same_filesystem(first: &Path, second: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (first.metadata(), second.metadata()) {
+        (Ok(first), Ok(second)) => first.dev() == second.dev(),
+        _ => false,
+    }
+}
+
+///
+/// Check whether both paths are on the same filesystem
+/// so files can be renamed between them.
+///
+/// Paths need to be canonical because their drives are compared.
+///
+#[cfg(not(unix))]
+pub fn is_same_filesystem(first: &Path, second: &Path) -> bool {
+    first.components().next() == second.components().next()
+}
+
+fn copy_symlink(
+    src_directory: &Path,
+    dst_directory: &Path,
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+) -> bool {
+    let target = match fs::read_link(src) {
+        Ok(target) => target,
+        Err(err) => {
+            log::warn!(
+                target: catalog::SYMLINK_FAILED,
+                "Failed to read link {}; {err}",
+                src.to_string_lossy()
+            );
+            return false;
+        }
+    };
+    let target = rewrite_link_target(src_directory, dst_directory, src, dst, &target, options);
+
+    if dst.symlink_metadata().is_ok() {
+        if let Err(err) = fs::remove_file(dst) {
+            log::warn!(
+                target: catalog::SYMLINK_FAILED,
+                "Failed to replace {}; {err}",
+                dst.to_string_lossy()
+            );
+            return false;
+        }
+    }
+
+    match create_symlink(src, &target, dst) {
+        Ok(()) => {
+            log::info!(
+                "Linked {} to {}",
+                dst.to_string_lossy(),
+                target.to_string_lossy()
+            );
+            true
+        }
+        Err(err) => {
+            log::warn!(
+                target: catalog::SYMLINK_FAILED,
+                "Failed to link {}; {err}",
+                dst.to_string_lossy()
+            );
+            false
+        }
+    }
+}
+
+///
+/// Target of the copy at dst of the link at src.
+///
+/// Targets inside src_directory are rewritten to point to their
+/// copies relative to dst, so links stay valid inside dst_directory
+/// (and when it's moved) whatever layout is used. Relative targets
+/// outside of src_directory become absolute and absolute ones are kept.
+///
+fn rewrite_link_target(
+    src_directory: &Path,
+    dst_directory: &Path,
+    src: &Path,
+    dst: &Path,
+    target: &Path,
+    options: &CopyOptions,
+) -> PathBuf {
+    let resolved = normalize_path(&src.parent().unwrap().join(target));
+    if !resolved.starts_with(src_directory) {
+        return match target.is_absolute() {
+            true => target.to_path_buf(),
+            false => resolved,
+        };
+    }
+
+    let (target_copy, _) = destination_path(
+        src_directory,
+        dst_directory,
+        &resolved,
+        &options.layout,
+        options.sanitize_names.as_ref(),
+    );
+    relative_path(dst.parent().unwrap(), &target_copy)
+}
+
+///
+/// Remove '.' and '..' components without resolving links.
+///
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+///
+/// Path to 'to' relative to directory 'from'.
+///
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(from, to)| from == to)
+        .count();
+    let mut relative = from
+        .components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .collect::<PathBuf>();
+    relative.extend(to.components().skip(common));
+    if relative.as_os_str().is_empty() {
+        relative.push(Component::CurDir);
+    }
+
+    relative
+}
+
+#[cfg(unix)]
+fn create_symlink(_src: &Path, target: &Path, dst: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, dst)
+}
+
+#[cfg(windows)]
+fn create_symlink(src: &Path, target: &Path, dst: &Path) -> std::io::Result<()> {
+    if src.is_dir() {
+        std::os::windows::fs::symlink_dir(target, dst)
+    } else {
+        std::os::windows::fs::symlink_file(target, dst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        dst_template::RunValues, ignore_patterns::IgnorePatterns, overrides::OVERRIDES_FILE_NAME,
+    };
+    use std::{fs, time::SystemTime};
+    use tempfile::{NamedTempFile, TempDir};
+
+    #[test]
+    fn find_files_to_copy_skip_caches() {
+        let src_dir = TempDir::new().unwrap();
+        let cache_dir = src_dir.path().join("cache");
+        let fake_cache_dir = src_dir.path().join("fake_cache");
+        fs::create_dir(&cache_dir).unwrap();
+        fs::create_dir(&fake_cache_dir).unwrap();
+        fs::write(
+            cache_dir.join(CACHEDIR_TAG_NAME),
+            [CACHEDIR_TAG_SIGNATURE, b"\n# comment"].concat(),
+        )
+        .unwrap();
+        fs::write(fake_cache_dir.join(CACHEDIR_TAG_NAME), "no signature").unwrap();
+        fs::write(cache_dir.join("cached.txt"), "").unwrap();
+        fs::write(fake_cache_dir.join("kept.txt"), "").unwrap();
+        let search_options = SearchOptions {
+            skip_caches: true,
+            ..Default::default()
+        };
+
+        let files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::from_iter([".txt".to_string()]),
+            &Exclusions::default(),
+            &search_options,
+        )
+        .unwrap();
+
+        assert_eq!(files, vec![fake_cache_dir.join("kept.txt")]);
+    }
+
+    #[test]
+    fn find_files_to_copy_skip_marked_directories() {
+        let src_dir = TempDir::new().unwrap();
+        let marked_dir = src_dir.path().join("marked");
+        fs::create_dir_all(marked_dir.join("child")).unwrap();
+        fs::write(marked_dir.join(".nobackup"), "").unwrap();
+        fs::write(marked_dir.join("child").join("ignored.txt"), "").unwrap();
+        fs::write(src_dir.path().join("kept.txt"), "").unwrap();
+        let search_options = SearchOptions {
+            marker_file: Some(".nobackup".to_string()),
+            ..Default::default()
+        };
+
+        let files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::from_iter([".txt".to_string()]),
+            &Exclusions::default(),
+            &search_options,
+        )
+        .unwrap();
+
+        assert_eq!(files, vec![src_dir.path().join("kept.txt")]);
+    }
+
+    #[test]
+    fn find_files_to_copy_one_file_system() {
+        let (dirs, files) = create_temp_dir_tree();
+        let search_options = SearchOptions {
+            one_file_system: true,
+            ..Default::default()
+        };
+
+        let found = find_files_to_copy(
+            dirs[0].path(),
+            &Suffixes::any(),
+            &Exclusions::default(),
+            &search_options,
+        )
+        .unwrap();
+
+        assert_eq!(found.len(), files.len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_files_to_copy_special_files() {
+        let src_dir = TempDir::new().unwrap();
+        let _socket =
+            std::os::unix::net::UnixListener::bind(src_dir.path().join("socket.txt")).unwrap();
+        fs::write(src_dir.path().join("file.txt"), "").unwrap();
+        let skip = SearchOptions::default();
+        let error = SearchOptions {
+            special_files: SpecialFilePolicy::Error,
+            ..Default::default()
+        };
+
+        let files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::any(),
+            &Exclusions::default(),
+            &skip,
+        )
+        .unwrap();
+        let result = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::any(),
+            &Exclusions::default(),
+            &error,
+        );
+
+        assert_eq!(files, vec![src_dir.path().join("file.txt")]);
+        assert!(result.unwrap_err().to_string().contains("socket"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_files_to_copy_follow_symlinks_visits_once() {
+        let src_dir = TempDir::new().unwrap();
+        let real_dir = src_dir.path().join("real");
+        fs::create_dir(&real_dir).unwrap();
+        fs::write(real_dir.join("file.txt"), "").unwrap();
+        std::os::unix::fs::symlink(&real_dir, src_dir.path().join("link_dir")).unwrap();
+        std::os::unix::fs::symlink(real_dir.join("file.txt"), src_dir.path().join("link.txt"))
+            .unwrap();
+        std::os::unix::fs::symlink(src_dir.path(), real_dir.join("loop")).unwrap();
+        let search_options = SearchOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+
+        let files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::any(),
+            &Exclusions::default(),
+            &search_options,
+        )
+        .unwrap();
+
+        assert_eq!(files.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_files_to_copy_follow_symlinks_prefers_file_over_link() {
+        let src_dir = TempDir::new().unwrap();
+        let real_dir = src_dir.path().join("d");
+        fs::create_dir(&real_dir).unwrap();
+        fs::write(real_dir.join("a.txt"), "").unwrap();
+        for link in ["0", "l", "z"] {
+            std::os::unix::fs::symlink(real_dir.join("a.txt"), src_dir.path().join(link)).unwrap();
+        }
+        let search_options = SearchOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+
+        let files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::from_iter([".txt".to_string()]),
+            &Exclusions::default(),
+            &search_options,
+        )
+        .unwrap();
+
+        assert_eq!(files, vec![real_dir.join("a.txt")]);
+    }
+
+    #[test]
+    fn find_files_to_copy_directory_overrides() {
+        let src_dir = TempDir::new().unwrap();
+        let logs_dir = src_dir.path().join("logs");
+        fs::create_dir_all(logs_dir.join("old")).unwrap();
+        fs::write(
+            logs_dir.join(OVERRIDES_FILE_NAME),
+            ".log\n!.txt\nexclude: old\n",
+        )
+        .unwrap();
+        fs::write(logs_dir.join("app.log"), "").unwrap();
+        fs::write(logs_dir.join("notes.txt"), "").unwrap();
+        fs::write(logs_dir.join("old").join("old.log"), "").unwrap();
+        fs::write(src_dir.path().join("root.log"), "").unwrap();
+        fs::write(src_dir.path().join("root.txt"), "").unwrap();
+
+        let mut files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::from_iter([".txt".to_string()]),
+            &Exclusions::default(),
+            &SearchOptions::default(),
+        )
+        .unwrap();
+        files.sort();
+
+        assert_eq!(
+            files,
+            vec![logs_dir.join("app.log"), src_dir.path().join("root.txt")]
+        );
+    }
+
+    #[test]
+    fn find_files_to_copy_skip_locked() {
+        let src_dir = TempDir::new().unwrap();
+        let locked_path = src_dir.path().join("locked.txt");
+        fs::write(&locked_path, "").unwrap();
+        fs::write(src_dir.path().join("free.txt"), "").unwrap();
+        let locked = File::open(&locked_path).unwrap();
+        locked.lock().unwrap();
+        let search_options = SearchOptions {
+            skip_locked: true,
+            ..Default::default()
+        };
+
+        let files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::any(),
+            &Exclusions::default(),
+            &search_options,
+        )
+        .unwrap();
+
+        assert_eq!(files, vec![src_dir.path().join("free.txt")]);
+    }
+
+    #[test]
+    fn find_files_to_copy_all_files() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let suffixes = files
+            .iter()
+            .map(|file| {
+                file.path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Suffixes>();
+        let exclusions = Exclusions::default();
+
+        let found_files =
+            find_files_to_copy(root_dir, &suffixes, &exclusions, &SearchOptions::default())
+                .unwrap();
+        assert_eq!(found_files.len(), files.len());
+
+        files
+            .iter()
+            .for_each(|file| assert!(found_files.contains(&file.path().to_path_buf())));
+    }
+
+    #[test]
+    fn find_files_to_copy_some_files() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let some_files = [&files[2], &files[4]];
+        let suffixes = some_files
+            .iter()
+            .map(|file| {
+                file.path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Suffixes>();
+        let exclusions = Exclusions::default();
+
+        let found_files =
+            find_files_to_copy(root_dir, &suffixes, &exclusions, &SearchOptions::default())
+                .unwrap();
+        assert_eq!(found_files.len(), some_files.len());
+
+        some_files
+            .iter()
+            .for_each(|file| assert!(found_files.contains(&file.path().to_path_buf())));
+    }
+
+    #[test]
+    fn find_files_to_copy_exclude_all() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let suffixes = files
+            .iter()
+            .map(|file| {
+                file.path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Suffixes>();
+        let exclusions = Exclusions {
+            paths: vec![dirs[0].path().to_path_buf()],
+            ..Default::default()
+        };
+
+        let found_files =
+            find_files_to_copy(root_dir, &suffixes, &exclusions, &SearchOptions::default())
+                .unwrap();
+        assert!(found_files.is_empty());
+    }
+
+    #[test]
+    fn find_files_to_copy_exclude_some() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let remaining_files = [&files[0], &files[1]];
+        let suffixes = files
+            .iter()
+            .map(|file| {
+                file.path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Suffixes>();
+        let exclusions = Exclusions {
+            paths: [&dirs[1], &dirs[3]]
+                .iter()
+                .map(|dir| dir.path().to_path_buf())
+                .collect::<Vec<_>>(),
+            ..Default::default()
+        };
+
+        let found_files =
+            find_files_to_copy(root_dir, &suffixes, &exclusions, &SearchOptions::default())
+                .unwrap();
+        assert_eq!(found_files.len(), remaining_files.len());
+
+        remaining_files
+            .iter()
+            .for_each(|file| assert!(found_files.contains(&file.path().to_path_buf())))
+    }
+
+    #[test]
+    fn find_files_to_copy_exclude_path_above_src() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let suffixes = files
+            .iter()
+            .map(|file| {
+                file.path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Suffixes>();
+        let exclusions = Exclusions {
+            paths: vec![dirs[0].path().parent().unwrap().to_path_buf()],
+            ..Default::default()
+        };
+
+        let found_files =
+            find_files_to_copy(root_dir, &suffixes, &exclusions, &SearchOptions::default())
+                .unwrap();
+        assert!(found_files.is_empty());
+    }
+
+    #[test]
+    fn find_files_to_copy_exclude_glob() {
+        let root_dir = TempDir::new().unwrap();
+        let kept = root_dir.path().join("project/src/main.rs");
+        let excluded = root_dir.path().join("project/node_modules/lib/index.js");
+        for path in [&kept, &excluded] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let suffixes = Suffixes::any();
+        let exclusions = Exclusions {
+            patterns: IgnorePatterns::from_iter(["**/node_modules"]),
+            ..Default::default()
+        };
+
+        let found_files = find_files_to_copy(
+            root_dir.path(),
+            &suffixes,
+            &exclusions,
+            &SearchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(found_files, vec![kept]);
+    }
+
+    #[test]
+    fn find_files_to_copy_max_depth() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let shallow_files = [&files[0], &files[1], &files[2], &files[3]];
+        let suffixes = files
+            .iter()
+            .map(|file| {
+                file.path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Suffixes>();
+        let exclusions = Exclusions::default();
+        let options = SearchOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+
+        let found_files = find_files_to_copy(root_dir, &suffixes, &exclusions, &options).unwrap();
+        assert_eq!(found_files.len(), shallow_files.len());
+
+        shallow_files
+            .iter()
+            .for_each(|file| assert!(found_files.contains(&file.path().to_path_buf())));
+    }
+
+    #[test]
+    fn merge_included_files_without_duplicates() {
+        let (dirs, files) = create_temp_dir_tree();
+        let mut files_paths = vec![files[0].path().to_path_buf(), files[2].path().to_path_buf()];
+        let include_paths = vec![dirs[1].path().to_path_buf(), files[0].path().to_path_buf()];
+
+        merge_included_files(&mut files_paths, &include_paths, &SearchOptions::default());
+
+        assert_eq!(files_paths.len(), 3);
+        assert_eq!(files_paths[0], files[0].path());
+        assert_eq!(files_paths[1], files[2].path());
+        assert!(files_paths.contains(&files[3].path().to_path_buf()));
+    }
+
+    #[test]
+    fn calculate_files_size_correct_sum() {
+        let files = [
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+        ];
+        fs::write(&files[0], "some unimportant text").unwrap();
+        fs::write(&files[1], "event more text").unwrap();
+        fs::write(&files[2], "That's the last one").unwrap();
+        let files_paths = files
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .collect::<Vec<_>>();
+        let files_size = files
+            .iter()
+            .map(|file| fs::metadata(file).unwrap().len())
+            .sum::<u64>();
+
+        let calculated_size = calculate_files_size(&files_paths, SizeMode::Apparent);
+
+        assert_eq!(files_size, calculated_size);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn copy_files_preserves_holes_of_sparse_files() {
+        use std::io::Write;
+
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let src_path = src_dir.path().join("disk.img");
+        let mut file = File::create(&src_path).unwrap();
+        file.set_len(8 * 1024 * 1024).unwrap();
+        file.seek(SeekFrom::Start(4 * 1024 * 1024)).unwrap();
+        file.write_all(b"data in the middle").unwrap();
+        drop(file);
+        let paths = [src_path.clone()];
+        let allocated_size = calculate_files_size(&paths, SizeMode::Allocated);
+        assert!(allocated_size < calculate_files_size(&paths, SizeMode::Apparent));
+
+        copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &paths,
+            &CopyOptions::default(),
+        );
+
+        let dst_path = dst_dir.path().join("disk.img");
+        assert_eq!(fs::read(&dst_path).unwrap(), fs::read(&src_path).unwrap());
+        assert_eq!(
+            calculate_files_size(&[dst_path], SizeMode::Allocated),
+            allocated_size
+        );
+    }
+
+    #[test]
+    fn seal_directory_makes_everything_readonly() {
+        let (dirs, files) = create_temp_dir_tree();
+
+        let failures = seal_directory(dirs[0].path());
+
+        assert_eq!(failures, 0);
+        for path in dirs
+            .iter()
+            .map(|dir| dir.path())
+            .chain(files.iter().map(|file| file.path()))
+        {
+            assert!(fs::metadata(path).unwrap().permissions().readonly());
+        }
+        // Immutable flag keeps even root from writing, when process could set it
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::os::fd::AsRawFd;
+
+            let file = File::open(files[0].path()).unwrap();
+            let mut flags = 0;
+            // SAFETY: descriptor is open and flags outlive the call
+            unsafe { get_inode_flags(file.as_raw_fd(), &mut flags).unwrap() };
+            if flags & IMMUTABLE_FLAG != 0 {
+                assert!(File::options().write(true).open(files[0].path()).is_err());
+            }
+        }
+
+        // Let TempDir clean up after the test
+        for entry in WalkDir::new(dirs[0].path()) {
+            let entry = entry.unwrap();
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            let _ = set_immutable(entry.path(), false);
+            let mut permissions = entry.metadata().unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(entry.path(), permissions).unwrap();
+        }
+    }
+
+    #[test]
+    fn find_unreadable_files_all_readable() {
+        let files = [NamedTempFile::new().unwrap(), NamedTempFile::new().unwrap()];
+        fs::write(&files[0], "short text").unwrap();
+        fs::write(&files[1], vec![7u8; 3 * PRECHECK_BLOCK_SIZE as usize]).unwrap();
+        let files_paths = files
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .collect::<Vec<_>>();
+
+        let unreadable = find_unreadable_files(&files_paths);
+
+        assert!(unreadable.is_empty());
+    }
+
+    #[test]
+    fn find_unreadable_files_missing_file() {
+        let file = NamedTempFile::new().unwrap();
+        let missing = PathBuf::from("save-me-files.test.noexistent.file");
+        let files_paths = vec![file.path().to_path_buf(), missing.clone()];
+
+        let unreadable = find_unreadable_files(&files_paths);
+
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].0, missing);
+    }
+
+    #[test]
+    fn simulate_reads_counts_bytes_and_unreadable_files() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(&file, vec![7u8; SIMULATION_CHUNK_SIZE + 10]).unwrap();
+        let missing = PathBuf::from("save-me-files.test.noexistent.file");
+        let files_paths = vec![file.path().to_path_buf(), missing.clone()];
+
+        let simulation = simulate_reads(&files_paths);
+
+        assert_eq!(simulation.read_bytes, SIMULATION_CHUNK_SIZE as u64 + 10);
+        assert_eq!(simulation.unreadable_files.len(), 1);
+        assert_eq!(simulation.unreadable_files[0].0, missing);
+    }
+
+    #[test]
+    fn copy_files_contents_are_preserved() {
+        let (dirs, files) = create_temp_dir_tree();
+        let src_dir = &dirs[1];
+        let dst_dir = TempDir::new().unwrap();
+        let src_paths = files[2..=3]
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .collect::<Vec<_>>();
+        let p1_src_text = "some boring text";
+        let p2_src_text = "another boring text";
+        fs::write(&src_paths[0], p1_src_text).unwrap();
+        fs::write(&src_paths[1], p2_src_text).unwrap();
+
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &src_paths,
+            &CopyOptions::default(),
+        );
+
+        assert!(summary.failed_files.is_empty());
+
+        let dst_paths = src_paths
+            .iter()
+            .map(|path| dst_dir.path().join(path.strip_prefix(src_dir).unwrap()))
+            .collect::<Vec<_>>();
+        assert!(dst_paths[0].is_file());
+        assert!(dst_paths[1].is_file());
+        let p1_dst_text = fs::read_to_string(&dst_paths[0]).unwrap();
+        let p2_dst_text = fs::read_to_string(&dst_paths[1]).unwrap();
+        assert_eq!(p1_dst_text, p1_src_text);
+        assert_eq!(p2_dst_text, p2_src_text);
+    }
+
+    #[test]
+    fn copy_files_names_of_maximum_length() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let name = format!("{}.txt", "a".repeat(250));
+        let src_path = src_dir.path().join(&name);
+        fs::write(&src_path, "long name").unwrap();
+
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[src_path],
+            &CopyOptions::default(),
+        );
+
+        assert!(summary.failed_files.is_empty());
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join(name)).unwrap(),
+            "long name"
+        );
+    }
+
+    #[test]
+    fn copy_files_preserve_timestamps() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let src_path = src_dir.path().join("old.txt");
+        fs::write(&src_path, "old text").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(&src_path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let copy_options = CopyOptions {
+            preserve_timestamps: true,
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &[src_path], &copy_options);
+
+        assert!(summary.failed_files.is_empty());
+        let dst_metadata = fs::metadata(dst_dir.path().join("old.txt")).unwrap();
+        assert_eq!(dst_metadata.modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn copy_files_preserve_timestamps_of_read_only_files() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let src_path = src_dir.path().join("contract.pdf");
+        fs::write(&src_path, "contract").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(&src_path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let mut permissions = fs::metadata(&src_path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&src_path, permissions).unwrap();
+        let copy_options = CopyOptions {
+            preserve_timestamps: true,
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &[src_path], &copy_options);
+
+        assert!(summary.failed_files.is_empty());
+        let dst_metadata = fs::metadata(dst_dir.path().join("contract.pdf")).unwrap();
+        assert!(dst_metadata.permissions().readonly());
+        assert_eq!(dst_metadata.modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn copy_files_update_skips_up_to_date_files() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let unchanged = src_dir.path().join("unchanged.txt");
+        let changed = src_dir.path().join("changed.txt");
+        fs::write(&unchanged, "unchanged").unwrap();
+        fs::write(&changed, "old").unwrap();
+        let paths = [unchanged, changed.clone()];
+        let copy_options = CopyOptions {
+            update: true,
+            ..Default::default()
+        };
+        copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+        fs::write(&changed, "new text").unwrap();
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        assert_eq!(summary.up_to_date_files, 1);
+        assert_eq!(summary.copied_files.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("changed.txt")).unwrap(),
+            "new text"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_files_sanitizes_names() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let src_path = src_dir.path().join("notes: draft?.txt");
+        fs::write(&src_path, "notes").unwrap();
+        let paths = [src_path];
+        let copy_options = CopyOptions {
+            sanitize_names: Some(sanitized_names(
+                src_dir.path(),
+                &paths,
+                &DestinationLayout::Tree,
+            )),
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        assert!(dst_dir.path().join("notes_ draft_.txt").exists());
+        assert_eq!(
+            summary.changed_names,
+            vec![(
+                dst_dir.path().join("notes_ draft_.txt"),
+                PathBuf::from("notes: draft?.txt")
+            )]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_files_sanitized_names_dont_collide() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let paths = [
+            src_dir.path().join("a:b.txt"),
+            src_dir.path().join("a?b.txt"),
+        ];
+        fs::write(&paths[0], "colon").unwrap();
+        fs::write(&paths[1], "question mark").unwrap();
+        let copy_options = CopyOptions {
+            sanitize_names: Some(sanitized_names(
+                src_dir.path(),
+                &paths,
+                &DestinationLayout::Tree,
+            )),
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        assert_eq!(summary.overwritten_files, 0);
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("a_b.txt")).unwrap(),
+            "colon"
+        );
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("a_b (1).txt")).unwrap(),
+            "question mark"
+        );
+        assert_eq!(summary.changed_names.len(), 2);
+    }
+
+    #[test]
+    fn order_files_sorts_by_order() {
+        let dir = TempDir::new().unwrap();
+        let big = dir.path().join("b/big.txt");
+        let small = dir.path().join("small.txt");
+        let medium = dir.path().join("a/medium.txt");
+        for (path, content) in [(&big, "big text"), (&small, "s"), (&medium, "text")] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let ordered = |order| {
+            let mut paths = vec![&big, &small, &medium];
+            order_files(&mut paths, order);
+            paths
+        };
+
+        assert_eq!(ordered(CopyOrder::Discovered), [&big, &small, &medium]);
+        assert_eq!(ordered(CopyOrder::SmallestFirst), [&small, &medium, &big]);
+        assert_eq!(ordered(CopyOrder::LargestFirst), [&big, &medium, &small]);
+        assert_eq!(ordered(CopyOrder::ByDirectory), [&small, &medium, &big]);
+    }
+
+    #[test]
+    fn find_outdated_files_skips_up_to_date_copies() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let unchanged = src_dir.path().join("unchanged.txt");
+        let changed = src_dir.path().join("changed.txt");
+        let new = src_dir.path().join("new.txt");
+        fs::write(&unchanged, "unchanged").unwrap();
+        fs::write(&changed, "old").unwrap();
+        let copy_options = CopyOptions {
+            update: true,
+            ..Default::default()
+        };
+        copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[unchanged.clone(), changed.clone()],
+            &copy_options,
+        );
+        fs::write(&changed, "new text").unwrap();
+        fs::write(&new, "new").unwrap();
+
+        let mut outdated_files = find_outdated_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[unchanged, changed.clone(), new.clone()],
+            &DestinationLayout::Tree,
+            None,
+        );
+
+        outdated_files.sort();
+        assert_eq!(outdated_files, vec![changed, new]);
+    }
+
+    #[test]
+    fn copy_files_update_compares_checksums() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let src_path = src_dir.path().join("touched.txt");
+        let dst_path = dst_dir.path().join("touched.txt");
+        fs::write(&src_path, "same text").unwrap();
+        fs::write(&dst_path, "same text").unwrap();
+        File::options()
+            .write(true)
+            .open(&dst_path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let copy_options = CopyOptions {
+            update: true,
+            compare: CompareMethod::Checksum,
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &[src_path], &copy_options);
+
+        assert_eq!(summary.up_to_date_files, 1);
+        assert!(summary.copied_files.is_empty());
+    }
+
+    #[test]
+    fn find_files_to_copy_accessed_after() {
+        let src_dir = TempDir::new().unwrap();
+        let recent = src_dir.path().join("recent.txt");
+        let old = src_dir.path().join("old.txt");
+        fs::write(&recent, "recent").unwrap();
+        fs::write(&old, "old").unwrap();
+        File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_times(FileTimes::new().set_accessed(SystemTime::UNIX_EPOCH))
+            .unwrap();
+        let options = SearchOptions {
+            accessed_after: Some(SystemTime::now() - Duration::from_secs(3600)),
+            ..Default::default()
+        };
+
+        let files = find_files_to_copy(
+            src_dir.path(),
+            &Suffixes::from_iter([".txt".to_string()]),
+            &Exclusions::default(),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(files, vec![recent]);
+    }
+
+    #[test]
+    fn mount_options_of_longest_mount_point() {
+        let mountinfo = "\
+            22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+            35 22 8:2 / /mnt/my\\040data rw,noatime shared:2 - ext4 /dev/sda2 rw\n\
+            36 22 8:3 / /mnt/my rw,relatime shared:3 - ext4 /dev/sda3 rw";
+
+        assert_eq!(
+            mount_options(mountinfo, Path::new("/mnt/my data/docs")).unwrap(),
+            "rw,noatime"
+        );
+        assert_eq!(
+            mount_options(mountinfo, Path::new("/home/user")).unwrap(),
+            "rw,relatime"
+        );
+    }
+
+    #[test]
+    fn copy_files_overwrite_never_keeps_existing_files() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let existing = src_dir.path().join("existing.txt");
+        let new = src_dir.path().join("new.txt");
+        fs::write(&existing, "source").unwrap();
+        fs::write(&new, "new").unwrap();
+        fs::write(dst_dir.path().join("existing.txt"), "copy").unwrap();
+        let copy_options = CopyOptions {
+            overwrite: OverwritePolicy::Never,
+            ..Default::default()
+        };
+
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[existing, new],
+            &copy_options,
+        );
+
+        assert_eq!(summary.kept_files, 1);
+        assert_eq!(summary.overwritten_files, 0);
+        assert_eq!(summary.copied_files.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("existing.txt")).unwrap(),
+            "copy"
+        );
+    }
+
+    #[test]
+    fn copy_files_update_appends_to_append_only_files() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let grown = src_dir.path().join("grown.log");
+        let rotated = src_dir.path().join("rotated.log");
+        fs::write(&grown, "line 1\nline 2\n").unwrap();
+        fs::write(&rotated, "new line 1\nnew line 2\n").unwrap();
+        fs::write(dst_dir.path().join("grown.log"), "line 1\n").unwrap();
+        fs::write(dst_dir.path().join("rotated.log"), "line 1\n").unwrap();
+        let mut globs = globset::GlobSetBuilder::new();
+        globs.add(globset::Glob::new("*.log").unwrap());
+        let copy_options = CopyOptions {
+            update: true,
+            append_only: Some(globs.build().unwrap()),
+            ..Default::default()
+        };
+
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[grown, rotated],
+            &copy_options,
+        );
+
+        assert_eq!(
+            summary.appended_files,
+            vec![(dst_dir.path().join("grown.log"), 7)]
+        );
+        assert_eq!(summary.copied_files.len(), 2);
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("grown.log")).unwrap(),
+            "line 1\nline 2\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("rotated.log")).unwrap(),
+            "new line 1\nnew line 2\n"
+        );
+    }
+
+    #[test]
+    fn copy_files_update_copies_linked_append_only_files_whole() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let grown = src_dir.path().join("grown.log");
+        fs::write(&grown, "line 1\nline 2\n").unwrap();
+        // Copy linked to identical copy of other file
+        let dst_linked = dst_dir.path().join("other.log");
+        fs::write(&dst_linked, "line 1\n").unwrap();
+        fs::hard_link(&dst_linked, dst_dir.path().join("grown.log")).unwrap();
+        let mut globs = globset::GlobSetBuilder::new();
+        globs.add(globset::Glob::new("*.log").unwrap());
+        let copy_options = CopyOptions {
+            update: true,
+            append_only: Some(globs.build().unwrap()),
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &[grown], &copy_options);
+
+        assert!(summary.appended_files.is_empty());
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("grown.log")).unwrap(),
+            "line 1\nline 2\n"
+        );
+        assert_eq!(fs::read_to_string(&dst_linked).unwrap(), "line 1\n");
+    }
+
+    #[test]
+    fn copy_files_skips_files_changed_since_scan() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let changed = src_dir.path().join("changed.txt");
+        let unchanged = src_dir.path().join("unchanged.txt");
+        fs::write(&changed, "old").unwrap();
+        fs::write(&unchanged, "unchanged").unwrap();
+        let paths = [changed.clone(), unchanged];
+        let copy_options = CopyOptions {
+            change_detection: Some(ChangeDetection::new(ChangePolicy::Skip, &paths)),
+            ..Default::default()
+        };
+        fs::write(&changed, "new text").unwrap();
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        assert_eq!(summary.copied_files.len(), 1);
+        assert!(summary.changed_files.is_empty());
+        assert!(!dst_dir.path().join("changed.txt").exists());
+    }
+
+    #[test]
+    fn copy_files_skip_keeps_previous_copy_of_file_changed_while_copying() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let mailbox = src_dir.path().join("inbox.mbox");
+        let copy = dst_dir.path().join("inbox.mbox");
+        fs::write(&mailbox, vec![1; 20_000]).unwrap();
+        fs::write(&copy, "older copy").unwrap();
+        let paths = [mailbox.clone()];
+        // Copy limited to 10 kB/s takes about a second, src changes meanwhile
+        let copy_options = CopyOptions {
+            change_detection: Some(ChangeDetection::new(ChangePolicy::Skip, &paths)),
+            rate_limiter: Some(RateLimiter::new(10_000)),
+            ..Default::default()
+        };
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            fs::write(&mailbox, vec![2; 30_000]).unwrap();
+        });
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+        writer.join().unwrap();
+
+        assert!(summary.copied_files.is_empty());
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "older copy");
+        assert_eq!(fs::read_dir(dst_dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_files_preserve_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let scripts = src_dir.path().join("scripts");
+        let script = scripts.join("run.sh");
+        fs::create_dir(&scripts).unwrap();
+        fs::write(&script, "#!/bin/sh").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o754)).unwrap();
+        fs::set_permissions(&scripts, fs::Permissions::from_mode(0o750)).unwrap();
+        let copy_options = CopyOptions {
+            preserve_permissions: true,
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &[script], &copy_options);
+        let failures = preserve_directory_permissions(
+            src_dir.path(),
+            dst_dir.path(),
+            &summary.copied_files,
+            &DestinationLayout::Tree,
+        );
+
+        assert_eq!(failures, 0);
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dst_dir.path().join("scripts").join("run.sh")), 0o754);
+        assert_eq!(mode(&dst_dir.path().join("scripts")), 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserve_directory_times_sets_modification_times() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let album = src_dir.path().join("2019").join("album");
+        let photo = album.join("photo.jpg");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(&photo, "photo").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_560_000_000);
+        for directory in [&album, &src_dir.path().join("2019")] {
+            File::open(directory)
+                .unwrap()
+                .set_times(FileTimes::new().set_modified(modified))
+                .unwrap();
+        }
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[photo],
+            &CopyOptions::default(),
+        );
+
+        let failures = preserve_directory_times(
+            src_dir.path(),
+            dst_dir.path(),
+            &summary.copied_files,
+            &DestinationLayout::Tree,
+        );
+
+        assert_eq!(failures, 0);
+        let modified_time = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified_time(&dst_dir.path().join("2019/album")), modified);
+        assert_eq!(modified_time(&dst_dir.path().join("2019")), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserve_directory_times_skips_directories_made_by_template() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let album = src_dir.path().join("album");
+        let photo = album.join("photo.jpg");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(&photo, "photo").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_560_000_000);
+        for directory in [&album, &src_dir.path().to_path_buf()] {
+            File::open(directory)
+                .unwrap()
+                .set_times(FileTimes::new().set_modified(modified))
+                .unwrap();
+        }
+        let modified_time = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        let paths = [photo];
+
+        for (template, album_copy) in [
+            ("{hostname}/{rel_dir}/{name}", Some("laptop/album")),
+            ("{hostname}/{name}", None),
+        ] {
+            let layout = DestinationLayout::Template(
+                DstTemplate::new(
+                    template,
+                    RunValues {
+                        date: "2019-06-08".to_string(),
+                        time: "13-20-00".to_string(),
+                        hostname: "laptop".to_string(),
+                    },
+                )
+                .unwrap(),
+            );
+            let copy_options = CopyOptions {
+                layout,
+                ..Default::default()
+            };
+            let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+            let failures = preserve_directory_times(
+                src_dir.path(),
+                dst_dir.path(),
+                &summary.copied_files,
+                &copy_options.layout,
+            );
+
+            assert_eq!(failures, 0);
+            assert_ne!(modified_time(&dst_dir.path().join("laptop")), modified);
+            if let Some(album_copy) = album_copy {
+                assert_eq!(modified_time(&dst_dir.path().join(album_copy)), modified);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn copy_files_preserve_xattrs() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let tagged = src_dir.path().join("tagged.txt");
+        fs::write(&tagged, "tagged").unwrap();
+        if xattr::set(&tagged, "user.xdg.tags", b"rescue").is_err() {
+            // Filesystem of temporary directory doesn't support user attributes
+            return;
+        }
+        let copy_options = CopyOptions {
+            preserve_xattrs: true,
+            ..Default::default()
+        };
+
+        copy_files(src_dir.path(), dst_dir.path(), &[tagged], &copy_options);
+
+        let copy = dst_dir.path().join("tagged.txt");
+        assert_eq!(
+            xattr::get(copy, "user.xdg.tags").unwrap(),
+            Some(b"rescue".to_vec())
+        );
+    }
+
+    #[test]
+    fn copy_files_on_collision_rename() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let same = src_dir.path().join("same.txt");
+        let different = src_dir.path().join("report.txt");
+        fs::write(&same, "same").unwrap();
+        fs::write(&different, "new report").unwrap();
+        fs::write(dst_dir.path().join("same.txt"), "same").unwrap();
+        fs::write(dst_dir.path().join("report.txt"), "first report").unwrap();
+        fs::write(dst_dir.path().join("report (1).txt"), "second report").unwrap();
+        let copy_options = CopyOptions {
+            on_collision: CollisionPolicy::Rename,
+            ..Default::default()
+        };
+
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[same, different],
+            &copy_options,
+        );
+
+        assert_eq!(summary.renamed_files, 1);
+        assert_eq!(summary.up_to_date_files, 1);
+        assert_eq!(
+            summary.copied_files[0].1,
+            dst_dir.path().join("report (2).txt")
+        );
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("report.txt")).unwrap(),
+            "first report"
+        );
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("report (2).txt")).unwrap(),
+            "new report"
+        );
+    }
+
+    #[test]
+    fn numbered_path_keeps_extension() {
+        assert_eq!(
+            numbered_path(Path::new("dir/photo.jpg"), 1),
+            Path::new("dir/photo (1).jpg")
+        );
+        assert_eq!(
+            numbered_path(Path::new("dir/.bashrc"), 2),
+            Path::new("dir/.bashrc (2)")
+        );
+    }
+
+    #[test]
+    fn copy_files_overwrite_if_newer() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let newer = src_dir.path().join("newer.txt");
+        let older = src_dir.path().join("older.txt");
+        fs::write(&newer, "newer source").unwrap();
+        fs::write(&older, "older source").unwrap();
+        fs::write(dst_dir.path().join("newer.txt"), "copy").unwrap();
+        fs::write(dst_dir.path().join("older.txt"), "copy").unwrap();
+        for (path, modified) in [
+            (dst_dir.path().join("newer.txt"), SystemTime::UNIX_EPOCH),
+            (older.clone(), SystemTime::UNIX_EPOCH),
+        ] {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        let copy_options = CopyOptions {
+            overwrite: OverwritePolicy::IfNewer,
+            ..Default::default()
+        };
+
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &[newer, older],
+            &copy_options,
+        );
+
+        assert_eq!(summary.overwritten_files, 1);
+        assert_eq!(summary.kept_files, 1);
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("newer.txt")).unwrap(),
+            "newer source"
+        );
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("older.txt")).unwrap(),
+            "copy"
+        );
+    }
+
+    #[test]
+    fn copy_files_hard_links_are_recreated() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let original = src_dir.path().join("original.txt");
+        let link = src_dir.path().join("link.txt");
+        fs::write(&original, "linked text").unwrap();
+        fs::hard_link(&original, &link).unwrap();
+        let paths = [original, link];
+
+        let size = calculate_files_size(&paths, SizeMode::Apparent);
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &paths,
+            &CopyOptions::default(),
+        );
+
+        assert_eq!(size, "linked text".len() as u64);
+        assert!(summary.failed_files.is_empty());
+        let dst_link = dst_dir.path().join("link.txt");
+        assert_eq!(fs::read_to_string(&dst_link).unwrap(), "linked text");
+        assert_eq!(
+            hard_link_id(&dst_link),
+            hard_link_id(&dst_dir.path().join("original.txt"))
+        );
+        assert!(hard_link_id(&dst_link).is_some());
+    }
 
-            (src_path, dst_path)
-        })
-        .for_each(|(src_path, dst_path)| {
-            create_directories(dst_directory, &dst_path);
-            copy_file(src_path, &dst_path);
-        });
-}
+    #[test]
+    fn copy_files_update_recreates_hard_link_created_after_copy() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let original = src_dir.path().join("original.txt");
+        let link = src_dir.path().join("link.txt");
+        fs::write(&original, "linked text").unwrap();
+        let options = CopyOptions {
+            update: true,
+            ..Default::default()
+        };
+        copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            std::slice::from_ref(&original),
+            &options,
+        );
+        fs::hard_link(&original, &link).unwrap();
 
-fn should_keep_entry(entry: &DirEntry, exclusions: &[PathBuf]) -> bool {
-    !entry.file_type().is_dir() || !exclusions.iter().any(|path| entry.path().starts_with(path))
-}
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &[original, link], &options);
 
-fn should_copy_file(entry: &DirEntry, suffixes: &[String]) -> bool {
-    let filename = entry.file_name().to_string_lossy();
-    suffixes.iter().any(|suffix| filename.ends_with(suffix))
-}
+        assert!(summary.failed_files.is_empty());
+        let dst_link = dst_dir.path().join("link.txt");
+        assert_eq!(fs::read_to_string(&dst_link).unwrap(), "linked text");
+        assert_eq!(
+            hard_link_id(&dst_link),
+            hard_link_id(&dst_dir.path().join("original.txt"))
+        );
+    }
 
-fn create_directories(dst_root: &Path, dst: &Path) {
-    let mut dir_path = dst_root.to_path_buf();
-    let dst_components = dst
-        .parent()
-        .unwrap()
-        .strip_prefix(dst_root)
-        .unwrap()
-        .components();
-    for component in dst_components {
-        dir_path.push(component);
-        if dir_path.is_dir() {
-            continue;
-        }
+    #[cfg(unix)]
+    #[test]
+    fn copy_files_link() {
+        use std::os::unix::fs::MetadataExt;
 
-        if let Err(err) = fs::create_dir(&dir_path) {
-            log::warn!(
-                "Failed to create parent directories for {}; {err}",
-                dst.to_string_lossy()
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let paths = [src_dir.path().join("staged.txt")];
+        fs::write(&paths[0], "staged").unwrap();
+        fs::write(dst_dir.path().join("staged.txt"), "old copy").unwrap();
+        let copy_options = CopyOptions {
+            link: true,
+            ..Default::default()
+        };
+
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        assert_eq!(summary.copied_files.len(), 1);
+        let copy = dst_dir.path().join("staged.txt");
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "staged");
+        if is_same_filesystem(src_dir.path(), dst_dir.path()) {
+            assert_eq!(
+                fs::metadata(copy).unwrap().ino(),
+                fs::metadata(&paths[0]).unwrap().ino()
             );
-            break;
         }
     }
-}
 
-fn copy_file(src: &Path, dst: &Path) {
-    match fs::copy(src, dst) {
-        Ok(bytes_copied) => {
-            let bytes = Byte::from_bytes(bytes_copied as u128).get_appropriate_unit(true);
-            log::info!(
-                "Copied {} from {} to {}",
-                bytes,
-                src.to_string_lossy(),
-                dst.to_string_lossy()
-            )
+    #[test]
+    fn link_file_keeps_existing_file_when_linking_fails() {
+        let dir = TempDir::new().unwrap();
+        let original = dir.path().join("original.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&link, "previous copy").unwrap();
+
+        assert!(link_file(&original, &link).is_err());
+        assert_eq!(fs::read_to_string(&link).unwrap(), "previous copy");
+
+        fs::write(&original, "original").unwrap();
+        link_file(&original, &link).unwrap();
+        link_file(&original, &link).unwrap();
+        assert_eq!(fs::read_to_string(&link).unwrap(), "original");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn copy_files_keeps_previous_versions() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let paths = [src_dir.path().join("notes.txt")];
+        let copy = dst_dir.path().join("notes.txt");
+        let copy_options = CopyOptions {
+            backup_versions: 1,
+            ..Default::default()
+        };
+
+        for content in ["first", "second", "second", "third"] {
+            fs::write(&paths[0], content).unwrap();
+            copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
         }
-        Err(err) => log::warn!("{err}"),
+
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "third");
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("notes.txt.~1~")).unwrap(),
+            "second"
+        );
+        assert!(!dst_dir.path().join("notes.txt.~2~").exists());
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use std::fs;
-    use tempfile::{NamedTempFile, TempDir};
+    #[test]
+    fn copy_files_keeps_copy_when_new_version_fails_to_copy() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let paths = [src_dir.path().join("notes.txt")];
+        let copy = dst_dir.path().join("notes.txt");
+        fs::write(&copy, "old copy").unwrap();
+        let copy_options = CopyOptions {
+            backup_versions: 1,
+            ..Default::default()
+        };
+
+        // Source that disappeared can't be copied
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        assert_eq!(summary.failed_files.len(), 1);
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "old copy");
+        assert_eq!(fs::read_dir(dst_dir.path()).unwrap().count(), 1);
+    }
 
     #[test]
-    fn find_files_to_copy_all_files() {
-        let (dirs, files) = create_temp_dir_tree();
-        let root_dir = dirs[0].path();
-        let suffixes = files
-            .iter()
-            .map(|file| {
-                file.path()
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
-            })
-            .collect::<Vec<_>>();
-        let exclusions = Vec::new();
+    fn copy_files_keeps_one_previous_version_when_file_is_recopied() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let mailbox = src_dir.path().join("inbox.mbox");
+        let copy = dst_dir.path().join("inbox.mbox");
+        fs::write(&mailbox, vec![1; 15_000]).unwrap();
+        fs::write(&copy, "older copy").unwrap();
+        let paths = [mailbox.clone()];
+        // Copy limited to 10 kB/s takes a while, src changes during the first attempt
+        let copy_options = CopyOptions {
+            change_detection: Some(ChangeDetection::new(ChangePolicy::Recopy, &paths)),
+            rate_limiter: Some(RateLimiter::new(10_000)),
+            backup_versions: 3,
+            ..Default::default()
+        };
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            fs::write(&mailbox, vec![2; 12_000]).unwrap();
+        });
 
-        let found_files = find_files_to_copy(&root_dir, &suffixes, &exclusions);
-        assert_eq!(found_files.len(), files.len());
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+        writer.join().unwrap();
 
-        files
-            .iter()
-            .for_each(|file| assert!(found_files.contains(&file.path().to_path_buf())));
+        assert_eq!(summary.copied_files.len(), 1);
+        assert_eq!(fs::read(&copy).unwrap(), vec![2; 12_000]);
+        assert_eq!(
+            fs::read_to_string(dst_dir.path().join("inbox.mbox.~1~")).unwrap(),
+            "older copy"
+        );
+        assert!(!dst_dir.path().join("inbox.mbox.~2~").exists());
     }
 
     #[test]
-    fn find_files_to_copy_some_files() {
-        let (dirs, files) = create_temp_dir_tree();
-        let root_dir = dirs[0].path();
-        let some_files = [&files[2], &files[4]];
-        let suffixes = some_files
-            .iter()
-            .map(|file| {
-                file.path()
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
-            })
-            .collect::<Vec<_>>();
-        let exclusions = Vec::new();
+    fn copy_files_reflink_auto() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let paths = [src_dir.path().join("photo.jpg")];
+        fs::write(&paths[0], "photo").unwrap();
+        fs::write(dst_dir.path().join("photo.jpg"), "old copy").unwrap();
+        let copy_options = CopyOptions {
+            reflink: ReflinkPolicy::Auto,
+            ..Default::default()
+        };
 
-        let found_files = find_files_to_copy(&root_dir, &suffixes, &exclusions);
-        assert_eq!(found_files.len(), some_files.len());
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
 
-        some_files
-            .iter()
-            .for_each(|file| assert!(found_files.contains(&file.path().to_path_buf())));
+        // Cloned or copied depending on filesystem of temporary directory
+        assert_eq!(summary.copied_files.len(), 1);
+        let copy = dst_dir.path().join("photo.jpg");
+        assert_eq!(fs::read_to_string(copy).unwrap(), "photo");
+        assert_eq!(fs::read_dir(dst_dir.path()).unwrap().count(), 1);
     }
 
     #[test]
-    fn find_files_to_copy_exclude_all() {
-        let (dirs, files) = create_temp_dir_tree();
-        let root_dir = dirs[0].path();
-        let suffixes = files
-            .iter()
-            .map(|file| {
-                file.path()
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
-            })
-            .collect::<Vec<_>>();
-        let exclusions = vec![dirs[0].path().to_path_buf()];
+    fn copy_files_consistent_databases() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let database = src_dir.path().join("app.db");
+        let connection = rusqlite::Connection::open(&database).unwrap();
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .unwrap();
+        connection
+            .execute_batch("CREATE TABLE notes (text TEXT);")
+            .unwrap();
+        let paths = [database, src_dir.path().join("app.db-wal")];
+        let copy_options = CopyOptions {
+            consistent_databases: true,
+            ..Default::default()
+        };
 
-        let found_files = find_files_to_copy(&root_dir, &suffixes, &exclusions);
-        assert!(found_files.is_empty());
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        assert!(summary.failed_files.is_empty());
+        assert!(summary.copied_files.is_empty());
+        assert!(dst_dir.path().join("app.db").is_file());
+        assert!(!dst_dir.path().join("app.db-wal").exists());
     }
 
     #[test]
-    fn find_files_to_copy_exclude_some() {
-        let (dirs, files) = create_temp_dir_tree();
-        let root_dir = dirs[0].path();
-        let remaining_files = [&files[0], &files[1]];
-        let suffixes = files
-            .iter()
-            .map(|file| {
-                file.path()
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
-            })
-            .collect::<Vec<_>>();
-        let exclusions = [&dirs[1], &dirs[3]]
-            .iter()
-            .map(|dir| dir.path().to_path_buf())
-            .collect::<Vec<_>>();
+    fn copy_files_consistent_databases_preserve_timestamps() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let database = src_dir.path().join("app.db");
+        rusqlite::Connection::open(&database)
+            .unwrap()
+            .execute_batch("CREATE TABLE notes (text TEXT);")
+            .unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(&database)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let copy_options = CopyOptions {
+            consistent_databases: true,
+            preserve_timestamps: true,
+            ..Default::default()
+        };
 
-        let found_files = find_files_to_copy(&root_dir, &suffixes, &exclusions);
-        assert_eq!(found_files.len(), remaining_files.len());
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &[database], &copy_options);
 
-        remaining_files
-            .iter()
-            .for_each(|file| assert!(found_files.contains(&file.path().to_path_buf())))
+        assert!(summary.failed_files.is_empty());
+        let dst_metadata = fs::metadata(dst_dir.path().join("app.db")).unwrap();
+        assert_eq!(dst_metadata.modified().unwrap(), modified);
     }
 
     #[test]
-    fn find_files_to_copy_exclude_path_above_src() {
+    fn copy_files_through_temp_directory() {
         let (dirs, files) = create_temp_dir_tree();
-        let root_dir = dirs[0].path();
-        let suffixes = files
-            .iter()
-            .map(|file| {
-                file.path()
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
-            })
-            .collect::<Vec<_>>();
-        let exclusions = vec![dirs[0].path().parent().unwrap().to_path_buf()];
+        let src_dir = &dirs[2];
+        let dst_dir = TempDir::new().unwrap();
+        let temp_dir = TempDir::new_in(dst_dir.path()).unwrap();
+        fs::write(&files[4], "staged text").unwrap();
+        let paths = [files[4].path().to_path_buf()];
+        let copy_options = CopyOptions {
+            temp_directory: Some(temp_dir.path().to_path_buf()),
+            ..Default::default()
+        };
 
-        let found_files = find_files_to_copy(&root_dir, &suffixes, &exclusions);
-        assert!(found_files.is_empty());
+        let summary = copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        let dst_path = dst_dir
+            .path()
+            .join(files[4].path().strip_prefix(src_dir).unwrap());
+        assert!(summary.failed_files.is_empty());
+        assert_eq!(fs::read_to_string(dst_path).unwrap(), "staged text");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
     }
 
     #[test]
-    fn calculate_files_size_correct_sum() {
-        let files = [
-            NamedTempFile::new().unwrap(),
-            NamedTempFile::new().unwrap(),
-            NamedTempFile::new().unwrap(),
-        ];
-        fs::write(&files[0], "some unimportant text").unwrap();
-        fs::write(&files[1], "event more text").unwrap();
-        fs::write(&files[2], "That's the last one").unwrap();
-        let files_paths = files
-            .iter()
-            .map(|file| file.path().to_path_buf())
-            .collect::<Vec<_>>();
-        let files_size = files
-            .iter()
-            .map(|file| fs::metadata(&file).unwrap().len())
-            .sum::<u64>();
+    fn copy_files_replaces_copies_by_rename() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let paths = [src_dir.path().join("notes.txt")];
+        fs::write(&paths[0], "new").unwrap();
+        let copy = dst_dir.path().join("notes.txt");
+        let other_link = dst_dir.path().join("other.txt");
+        fs::write(&copy, "old").unwrap();
+        fs::hard_link(&copy, &other_link).unwrap();
 
-        let calculated_size = calculate_files_size(&files_paths);
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &paths,
+            &CopyOptions::default(),
+        );
 
-        assert_eq!(files_size, calculated_size);
+        // Content written in place would show in other link too
+        assert!(summary.failed_files.is_empty());
+        assert_eq!(fs::read_to_string(copy).unwrap(), "new");
+        assert_eq!(fs::read_to_string(other_link).unwrap(), "old");
+        assert_eq!(fs::read_dir(dst_dir.path()).unwrap().count(), 2);
     }
 
     #[test]
-    fn copy_files_contents_are_preserved() {
+    fn copy_files_failures_are_counted() {
         let (dirs, files) = create_temp_dir_tree();
         let src_dir = &dirs[1];
         let dst_dir = TempDir::new().unwrap();
-        let src_paths = files[2..=3]
-            .into_iter()
-            .map(|file| file.path().to_path_buf())
-            .collect::<Vec<_>>();
-        let p1_src_text = "some boring text";
-        let p2_src_text = "another boring text";
-        fs::write(&src_paths[0], p1_src_text).unwrap();
-        fs::write(&src_paths[1], p2_src_text).unwrap();
+        let paths = [
+            files[2].path().to_path_buf(),
+            src_dir.path().join("save-me-files.test.noexistent.file"),
+        ];
 
-        copy_files(src_dir.path(), dst_dir.path(), &src_paths);
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &paths,
+            &CopyOptions::default(),
+        );
 
-        let dst_paths = src_paths
-            .iter()
-            .map(|path| dst_dir.path().join(path.strip_prefix(src_dir).unwrap()))
-            .collect::<Vec<_>>();
-        assert!(dst_paths[0].is_file());
-        assert!(dst_paths[1].is_file());
-        let p1_dst_text = fs::read_to_string(&dst_paths[0]).unwrap();
-        let p2_dst_text = fs::read_to_string(&dst_paths[1]).unwrap();
-        assert_eq!(p1_dst_text, p1_src_text);
-        assert_eq!(p2_dst_text, p2_src_text);
+        assert_eq!(summary.failed_files, vec![paths[1].clone()]);
     }
 
     #[test]
@@ -304,7 +4382,12 @@ mod test {
         let dst_dir = TempDir::new().unwrap();
         let paths = [files[4].path().to_path_buf()];
 
-        copy_files(src_dir.path(), dst_dir.path(), &paths);
+        copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            &paths,
+            &CopyOptions::default(),
+        );
 
         let td3_stripped = dirs[3].path().strip_prefix(src_dir).unwrap();
         let td3_dst = dst_dir.path().join(td3_stripped);
@@ -315,6 +4398,261 @@ mod test {
         assert!(ntf4_dst.is_file());
     }
 
+    #[test]
+    fn copy_files_paths_longer_than_max_path() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let nested = (0..6).map(|i| format!("{i}{}", "d".repeat(49)));
+        let src_path = src_dir
+            .path()
+            .join(nested.collect::<PathBuf>())
+            .join("file.txt");
+        assert!(src_path.as_os_str().len() > 260);
+        fs::create_dir_all(src_path.parent().unwrap()).unwrap();
+        fs::write(&src_path, "deep").unwrap();
+
+        let summary = copy_files(
+            src_dir.path(),
+            dst_dir.path(),
+            std::slice::from_ref(&src_path),
+            &CopyOptions::default(),
+        );
+
+        assert!(summary.failed_files.is_empty());
+        let dst_path = dst_dir
+            .path()
+            .join(src_path.strip_prefix(src_dir.path()).unwrap());
+        assert_eq!(fs::read_to_string(dst_path).unwrap(), "deep");
+    }
+
+    #[test]
+    fn find_files_to_copy_case_insensitive() {
+        let root_dir = TempDir::new().unwrap();
+        let upper = root_dir.path().join("PHOTO.JPG");
+        let lower = root_dir.path().join("photo2.jpg");
+        fs::write(&upper, "").unwrap();
+        fs::write(&lower, "").unwrap();
+        let suffixes = Suffixes::from_iter([".jpg".to_string()]);
+        let exclusions = Exclusions::default();
+        let options = SearchOptions {
+            case_insensitive: true,
+            ..Default::default()
+        };
+
+        let found_files =
+            find_files_to_copy(root_dir.path(), &suffixes, &exclusions, &options).unwrap();
+
+        assert_eq!(found_files.len(), 2);
+        assert!(found_files.contains(&upper));
+        assert!(found_files.contains(&lower));
+    }
+
+    #[test]
+    fn find_files_to_copy_case_sensitive() {
+        let root_dir = TempDir::new().unwrap();
+        let upper = root_dir.path().join("PHOTO.JPG");
+        let lower = root_dir.path().join("photo2.jpg");
+        fs::write(&upper, "").unwrap();
+        fs::write(&lower, "").unwrap();
+        let suffixes = Suffixes::from_iter([".jpg".to_string()]);
+        let exclusions = Exclusions::default();
+
+        let found_files = find_files_to_copy(
+            root_dir.path(),
+            &suffixes,
+            &exclusions,
+            &SearchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(found_files, vec![lower]);
+    }
+
+    #[test]
+    fn find_files_to_copy_file_types() {
+        let root_dir = TempDir::new().unwrap();
+        let image = root_dir.path().join("image.unknown");
+        let text = root_dir.path().join("text.unknown");
+        fs::write(
+            &image,
+            [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0],
+        )
+        .unwrap();
+        fs::write(&text, "just text").unwrap();
+        let suffixes = Suffixes::any();
+        let exclusions = Exclusions::default();
+        let options = SearchOptions {
+            file_types: vec![FileType::Image, FileType::Video],
+            ..Default::default()
+        };
+
+        let found_files =
+            find_files_to_copy(root_dir.path(), &suffixes, &exclusions, &options).unwrap();
+
+        assert_eq!(found_files, vec![image]);
+    }
+
+    #[test]
+    fn find_files_to_copy_filter() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let suffixes = Suffixes::any();
+        let exclusions = Exclusions::default();
+        let td2_name = dirs[2].path().file_name().unwrap().to_string_lossy();
+        let options = SearchOptions {
+            filter: Some(Filter::parse(&format!("path:{td2_name}/**")).unwrap()),
+            ..Default::default()
+        };
+
+        let found_files = find_files_to_copy(root_dir, &suffixes, &exclusions, &options).unwrap();
+
+        assert_eq!(found_files, vec![files[4].path().to_path_buf()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_files_to_copy_owner() {
+        use std::os::unix::fs::MetadataExt;
+
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let suffixes = Suffixes::any();
+        let exclusions = Exclusions::default();
+        let metadata = files[0].path().metadata().unwrap();
+        let owned = SearchOptions {
+            owner: Some(metadata.uid()),
+            group: Some(metadata.gid()),
+            ..Default::default()
+        };
+        let not_owned = SearchOptions {
+            owner: Some(metadata.uid().wrapping_add(1)),
+            ..Default::default()
+        };
+
+        let owned_files = find_files_to_copy(root_dir, &suffixes, &exclusions, &owned).unwrap();
+        let not_owned_files =
+            find_files_to_copy(root_dir, &suffixes, &exclusions, &not_owned).unwrap();
+
+        assert_eq!(owned_files.len(), files.len());
+        assert!(not_owned_files.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_files_to_copy_symlinks_skipped() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let link = root_dir.join("link");
+        std::os::unix::fs::symlink(files[4].path(), &link).unwrap();
+        let suffixes = Suffixes::any();
+        let exclusions = Exclusions::default();
+
+        let found_files =
+            find_files_to_copy(root_dir, &suffixes, &exclusions, &SearchOptions::default())
+                .unwrap();
+
+        assert_eq!(found_files.len(), files.len());
+        assert!(!found_files.contains(&link));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_files_to_copy_symlinks_followed() {
+        let (dirs, files) = create_temp_dir_tree();
+        let root_dir = dirs[0].path();
+        let linked_dir = TempDir::new().unwrap();
+        let linked_file = NamedTempFile::new_in(linked_dir.path()).unwrap();
+        let link = root_dir.join("link");
+        std::os::unix::fs::symlink(linked_dir.path(), &link).unwrap();
+        let suffixes = Suffixes::any();
+        let exclusions = Exclusions::default();
+        let options = SearchOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+
+        let found_files = find_files_to_copy(root_dir, &suffixes, &exclusions, &options).unwrap();
+
+        assert_eq!(found_files.len(), files.len() + 1);
+        assert!(found_files.contains(&link.join(linked_file.path().file_name().unwrap())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_files_symlinks_recreated() {
+        let (dirs, files) = create_temp_dir_tree();
+        let src_dir = dirs[0].path();
+        let dst_dir = TempDir::new().unwrap();
+        let link = src_dir.join("link");
+        std::os::unix::fs::symlink(files[4].path(), &link).unwrap();
+        let suffixes = Suffixes::from_iter(["link".to_string()]);
+        let exclusions = Exclusions::default();
+        let search_options = SearchOptions {
+            symlinks: SymlinkPolicy::CopyLink,
+            ..Default::default()
+        };
+        let copy_options = CopyOptions {
+            symlinks: SymlinkPolicy::CopyLink,
+            ..Default::default()
+        };
+
+        let paths = find_files_to_copy(src_dir, &suffixes, &exclusions, &search_options).unwrap();
+        copy_files(src_dir, dst_dir.path(), &paths, &copy_options);
+
+        let dst_link = dst_dir.path().join("link");
+        assert_eq!(paths, vec![link]);
+        assert!(dst_link
+            .symlink_metadata()
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let relative_target = files[4].path().strip_prefix(src_dir).unwrap();
+        assert_eq!(fs::read_link(dst_link).unwrap(), relative_target);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_files_symlink_targets_are_rewritten() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let outside_dir = TempDir::new().unwrap();
+        let docs = src_dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(docs.join("report.txt"), "report").unwrap();
+        let links = [
+            ("relative", PathBuf::from("../docs/./report.txt")),
+            ("absolute", docs.join("report.txt")),
+            ("outside_absolute", outside_dir.path().to_path_buf()),
+            ("outside_relative", relative_path(&docs, outside_dir.path())),
+        ];
+        let mut paths = Vec::new();
+        for (name, target) in &links {
+            let link = docs.join(name);
+            std::os::unix::fs::symlink(target, &link).unwrap();
+            paths.push(link);
+        }
+        let copy_options = CopyOptions {
+            symlinks: SymlinkPolicy::CopyLink,
+            layout: DestinationLayout::Flat(HashMap::from([(
+                docs.join("relative"),
+                PathBuf::from("relative"),
+            )])),
+            ..Default::default()
+        };
+
+        copy_files(src_dir.path(), dst_dir.path(), &paths, &copy_options);
+
+        let target = |path: &str| fs::read_link(dst_dir.path().join(path)).unwrap();
+        // Flattened link points into directory structure of its target
+        assert_eq!(target("relative"), Path::new("docs/report.txt"));
+        assert_eq!(target("docs/absolute"), Path::new("report.txt"));
+        assert_eq!(target("docs/outside_absolute"), outside_dir.path());
+        assert_eq!(
+            target("docs/outside_relative"),
+            normalize_path(outside_dir.path())
+        );
+    }
+
     ///
     /// Creates directory tree.
     /// Returns tuple with arrays of directories and files.
@@ -0,0 +1,275 @@
+#[cfg(windows)]
+use crate::catalog;
+use std::path::Path;
+
+///
+/// Copy access control list (DACL) of src to dst, with owner
+/// and group when the process is permitted to set them.
+///
+/// Inherited entries come from the parent directory of dst, unless
+/// src is protected from inheritance. Failure is logged with WARN level.
+/// Other platforms have no ACLs to copy this way.
+///
+#[cfg(windows)]
+pub fn copy_acl(src: &Path, dst: &Path) {
+    use std::ptr;
+    use windows_sys::Win32::{
+        Foundation::{LocalFree, ERROR_SUCCESS},
+        Security::{
+            Authorization::{GetNamedSecurityInfoW, SetNamedSecurityInfoW, SE_FILE_OBJECT},
+            GetSecurityDescriptorControl, ACL, DACL_SECURITY_INFORMATION,
+            GROUP_SECURITY_INFORMATION, OWNER_SECURITY_INFORMATION,
+            PROTECTED_DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, SE_DACL_PROTECTED,
+            UNPROTECTED_DACL_SECURITY_INFORMATION,
+        },
+    };
+
+    let src_path = wide_path(src);
+    let dst_path = wide_path(dst);
+    let mut owner = ptr::null_mut();
+    let mut group = ptr::null_mut();
+    let mut dacl: *mut ACL = ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+    // SAFETY: path is NUL terminated wide string and pointers live until the call ends
+    let result = unsafe {
+        GetNamedSecurityInfoW(
+            src_path.as_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION,
+            &mut owner,
+            &mut group,
+            &mut dacl,
+            ptr::null_mut(),
+            &mut descriptor,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        log::warn!(
+            target: catalog::ACL_NOT_PRESERVED,
+            "Failed to read ACL of {}; {}",
+            src.to_string_lossy(),
+            std::io::Error::from_raw_os_error(result as i32)
+        );
+        return;
+    }
+
+    let mut control = 0;
+    let mut revision = 0;
+    // SAFETY: descriptor was returned by GetNamedSecurityInfoW and is freed below
+    unsafe { GetSecurityDescriptorControl(descriptor, &mut control, &mut revision) };
+    let inheritance = match control & SE_DACL_PROTECTED != 0 {
+        true => PROTECTED_DACL_SECURITY_INFORMATION,
+        false => UNPROTECTED_DACL_SECURITY_INFORMATION,
+    };
+    let set_security = |information, owner, group| {
+        // SAFETY: path is NUL terminated wide string and owner, group and dacl
+        // point into descriptor that is freed after both calls
+        unsafe {
+            SetNamedSecurityInfoW(
+                dst_path.as_ptr(),
+                SE_FILE_OBJECT,
+                information | DACL_SECURITY_INFORMATION | inheritance,
+                owner,
+                group,
+                dacl,
+                ptr::null(),
+            )
+        }
+    };
+    let mut result = set_security(
+        OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION,
+        owner,
+        group,
+    );
+    // Only administrators (with restore privilege) can give files to other owners
+    if result != ERROR_SUCCESS {
+        log::debug!(
+            "Owner of {} isn't preserved; {}",
+            dst.to_string_lossy(),
+            std::io::Error::from_raw_os_error(result as i32)
+        );
+        result = set_security(0, ptr::null_mut(), ptr::null_mut());
+    }
+    // SAFETY: descriptor was allocated by GetNamedSecurityInfoW and is not used anymore
+    unsafe { LocalFree(descriptor) };
+
+    if result != ERROR_SUCCESS {
+        log::warn!(
+            target: catalog::ACL_NOT_PRESERVED,
+            "Failed to preserve ACL of {}; {}",
+            dst.to_string_lossy(),
+            std::io::Error::from_raw_os_error(result as i32)
+        );
+    }
+}
+
+#[cfg(not(windows))]
+pub fn copy_acl(_src: &Path, _dst: &Path) {}
+
+///
+/// Copy Hidden, System, Archive and NotContentIndexed attributes
+/// of src to dst. ReadOnly attribute is preserved with permissions.
+///
+/// Failure is logged with WARN level.
+/// Other platforms have no such attributes.
+///
+#[cfg(windows)]
+pub fn copy_attributes(src: &Path, dst: &Path) {
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileAttributesW, SetFileAttributesW, FILE_ATTRIBUTE_ARCHIVE, FILE_ATTRIBUTE_HIDDEN,
+        FILE_ATTRIBUTE_NOT_CONTENT_INDEXED, FILE_ATTRIBUTE_SYSTEM, INVALID_FILE_ATTRIBUTES,
+    };
+
+    /// Attributes that are copied.
+    const COPIED_ATTRIBUTES: u32 = FILE_ATTRIBUTE_HIDDEN
+        | FILE_ATTRIBUTE_SYSTEM
+        | FILE_ATTRIBUTE_ARCHIVE
+        | FILE_ATTRIBUTE_NOT_CONTENT_INDEXED;
+
+    let src_path = wide_path(src);
+    let dst_path = wide_path(dst);
+    // SAFETY: paths are NUL terminated wide strings
+    let result = unsafe {
+        let src_attributes = GetFileAttributesW(src_path.as_ptr());
+        let dst_attributes = GetFileAttributesW(dst_path.as_ptr());
+        src_attributes != INVALID_FILE_ATTRIBUTES
+            && dst_attributes != INVALID_FILE_ATTRIBUTES
+            && SetFileAttributesW(
+                dst_path.as_ptr(),
+                (dst_attributes & !COPIED_ATTRIBUTES) | (src_attributes & COPIED_ATTRIBUTES),
+            ) != 0
+    };
+    if !result {
+        log::warn!(
+            target: catalog::PERMISSIONS_NOT_PRESERVED,
+            "Failed to preserve attributes of {}; {}",
+            dst.to_string_lossy(),
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(windows))]
+pub fn copy_attributes(_src: &Path, _dst: &Path) {}
+
+///
+/// Add entry denying modification and removal to everyone at the beginning
+/// of access control list (DACL) of path. Owner can still remove the entry,
+/// so it guards against accidents, not against administrators.
+///
+/// #### Errors
+/// This function returns error when ACL can't be read or changed.
+///
+#[cfg(windows)]
+pub fn deny_writes(path: &Path) -> std::io::Result<()> {
+    use std::{io, ptr};
+    use windows_sys::Win32::{
+        Foundation::{LocalFree, ERROR_SUCCESS},
+        Security::{
+            Authorization::{
+                GetNamedSecurityInfoW, SetEntriesInAclW, SetNamedSecurityInfoW, DENY_ACCESS,
+                EXPLICIT_ACCESS_W, NO_MULTIPLE_TRUSTEE, SE_FILE_OBJECT, TRUSTEE_IS_SID,
+                TRUSTEE_IS_WELL_KNOWN_GROUP, TRUSTEE_W,
+            },
+            CreateWellKnownSid, WinWorldSid, ACL, DACL_SECURITY_INFORMATION, NO_INHERITANCE,
+            PSECURITY_DESCRIPTOR, SECURITY_MAX_SID_SIZE,
+        },
+        Storage::FileSystem::{
+            DELETE, FILE_APPEND_DATA, FILE_DELETE_CHILD, FILE_WRITE_ATTRIBUTES, FILE_WRITE_DATA,
+            FILE_WRITE_EA,
+        },
+    };
+
+    /// Rights that are denied, FILE_DELETE_CHILD protects contents of directories.
+    const DENIED_RIGHTS: u32 = FILE_WRITE_DATA
+        | FILE_APPEND_DATA
+        | FILE_WRITE_EA
+        | FILE_WRITE_ATTRIBUTES
+        | FILE_DELETE_CHILD
+        | DELETE;
+
+    let wide_path = wide_path(path);
+    let mut everyone = [0u8; SECURITY_MAX_SID_SIZE as usize];
+    let mut everyone_size = SECURITY_MAX_SID_SIZE;
+    // SAFETY: buffer has size passed to the function
+    let created = unsafe {
+        CreateWellKnownSid(
+            WinWorldSid,
+            ptr::null_mut(),
+            everyone.as_mut_ptr().cast(),
+            &mut everyone_size,
+        )
+    };
+    if created == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut dacl: *mut ACL = ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+    // SAFETY: path is NUL terminated wide string and pointers live until the call ends
+    let result = unsafe {
+        GetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut dacl,
+            ptr::null_mut(),
+            &mut descriptor,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return Err(io::Error::from_raw_os_error(result as i32));
+    }
+
+    let access = EXPLICIT_ACCESS_W {
+        grfAccessPermissions: DENIED_RIGHTS,
+        grfAccessMode: DENY_ACCESS,
+        grfInheritance: NO_INHERITANCE,
+        Trustee: TRUSTEE_W {
+            pMultipleTrustee: ptr::null_mut(),
+            MultipleTrusteeOperation: NO_MULTIPLE_TRUSTEE,
+            TrusteeForm: TRUSTEE_IS_SID,
+            TrusteeType: TRUSTEE_IS_WELL_KNOWN_GROUP,
+            ptstrName: everyone.as_mut_ptr().cast(),
+        },
+    };
+    let mut sealed_dacl: *mut ACL = ptr::null_mut();
+    // SAFETY: dacl points into descriptor that is freed after the calls
+    // and sealed_dacl is freed after it's set
+    let result = unsafe {
+        let mut result = SetEntriesInAclW(1, &access, dacl, &mut sealed_dacl);
+        if result == ERROR_SUCCESS {
+            result = SetNamedSecurityInfoW(
+                wide_path.as_ptr(),
+                SE_FILE_OBJECT,
+                DACL_SECURITY_INFORMATION,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                sealed_dacl,
+                ptr::null(),
+            );
+            LocalFree(sealed_dacl.cast());
+        }
+        LocalFree(descriptor);
+        result
+    };
+
+    match result {
+        ERROR_SUCCESS => Ok(()),
+        _ => Err(io::Error::from_raw_os_error(result as i32)),
+    }
+}
+
+#[cfg(windows)]
+fn wide_path(path: &Path) -> Vec<u16> {
+    use crate::long_names::extended_length_path;
+    use std::{iter, os::windows::ffi::OsStrExt};
+
+    extended_length_path(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(iter::once(0))
+        .collect()
+}
@@ -0,0 +1,143 @@
+use crate::catalog;
+use anyhow::Result;
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+///
+/// Read list of files to copy separated by separator
+/// (usually new line or NUL character).
+///
+/// Paths are resolved against current directory. Path is valid
+/// when it points to existing file inside src_directory.
+/// Every invalid path is logged with WARN level.
+/// Empty entries are ignored.
+///
+/// #### Errors
+/// This function returns error when there's a problem with
+/// reading from reader.
+///
+pub fn read_files_from(
+    reader: impl Read,
+    separator: u8,
+    src_directory: &Path,
+) -> Result<Vec<PathBuf>> {
+    let reader = BufReader::new(reader);
+
+    let mut files_paths = Vec::new();
+    for entry in reader.split(separator) {
+        let entry = entry?;
+        let entry = trim_line_ending(&entry, separator);
+        if entry.is_empty() {
+            continue;
+        }
+
+        let path = bytes_to_path(entry);
+        let Ok(canonical_path) = path.canonicalize() else {
+            log::warn!(
+                target: catalog::INVALID_LISTED_FILE,
+                "File not exist: {}",
+                path.to_string_lossy()
+            );
+            continue;
+        };
+
+        if !canonical_path.starts_with(src_directory) {
+            log::warn!(
+                target: catalog::INVALID_LISTED_FILE,
+                "File is outside of src_directory: {}",
+                path.to_string_lossy()
+            );
+            continue;
+        }
+
+        if !canonical_path.is_file() {
+            log::warn!(
+                target: catalog::INVALID_LISTED_FILE,
+                "Not a file: {}",
+                path.to_string_lossy()
+            );
+            continue;
+        }
+
+        files_paths.push(canonical_path);
+    }
+
+    Ok(files_paths)
+}
+
+fn trim_line_ending(entry: &[u8], separator: u8) -> &[u8] {
+    match entry {
+        [rest @ .., b'\r'] if separator == b'\n' => rest,
+        _ => entry,
+    }
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).as_ref())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::{NamedTempFile, TempDir};
+
+    #[test]
+    fn read_files_from_new_lines() {
+        let src_directory = TempDir::new().unwrap();
+        let src_path = src_directory.path().canonicalize().unwrap();
+        let files = [
+            NamedTempFile::new_in(&src_path).unwrap(),
+            NamedTempFile::new_in(&src_path).unwrap(),
+        ];
+        let input = format!(
+            "{}\r\n\n{}\n",
+            files[0].path().to_string_lossy(),
+            files[1].path().to_string_lossy()
+        );
+
+        let files_paths = read_files_from(input.as_bytes(), b'\n', &src_path).unwrap();
+
+        assert_eq!(
+            files_paths,
+            vec![files[0].path().to_path_buf(), files[1].path().to_path_buf()]
+        );
+    }
+
+    #[test]
+    fn read_files_from_nul_separated() {
+        let src_directory = TempDir::new().unwrap();
+        let src_path = src_directory.path().canonicalize().unwrap();
+        let file = NamedTempFile::new_in(&src_path).unwrap();
+        let input = format!("{}\0", file.path().to_string_lossy());
+
+        let files_paths = read_files_from(input.as_bytes(), b'\0', &src_path).unwrap();
+
+        assert_eq!(files_paths, vec![file.path().to_path_buf()]);
+    }
+
+    #[test]
+    fn read_files_from_ignore_invalid() {
+        let src_directory = TempDir::new().unwrap();
+        let src_path = src_directory.path().canonicalize().unwrap();
+        let outside = NamedTempFile::new().unwrap();
+        let directory = TempDir::new_in(&src_path).unwrap();
+        let input = format!(
+            "save-me-files.test.noexistent.file\n{}\n{}\n",
+            outside.path().to_string_lossy(),
+            directory.path().to_string_lossy()
+        );
+
+        let files_paths = read_files_from(input.as_bytes(), b'\n', &src_path).unwrap();
+
+        assert!(files_paths.is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    catalog,
+    file_types::{detect_file_type, FileType},
+};
+use anyhow::{anyhow, Result};
+use byte_unit::Byte;
+use clap::ValueEnum;
+use globset::{GlobBuilder, GlobMatcher};
+use std::{fs, path::Path};
+
+///
+/// Comparison operator used by size predicate.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+impl Comparison {
+    fn compare(self, left: u64, right: u64) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Equal => left == right,
+        }
+    }
+}
+
+///
+/// Tree of conditions that file needs to satisfy to be copied.
+///
+/// Filter is parsed from expression like
+/// `size<100MB && (suffix:.docx || suffix:.xlsx) && !path:**/Archive/**`.
+///
+/// Supported predicates:
+/// - `size<SIZE` (also `<=`, `>`, `>=`, `=`) compares file size
+/// - `suffix:VALUE` checks if filename ends with VALUE
+/// - `name:GLOB` matches filename against GLOB
+/// - `path:GLOB` matches path relative to src_directory against GLOB
+/// - `type:TYPE` checks type detected from file content
+///
+/// Predicates can be combined with `!`, `&&`, `||` and parentheses.
+/// `&&` binds stronger than `||`. Values containing spaces
+/// or parentheses can be surrounded with double quotes.
+///
+#[derive(Clone, Debug)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Size(Comparison, u64),
+    Suffix(String),
+    Name(GlobMatcher),
+    Path(GlobMatcher),
+    Type(FileType),
+}
+
+impl Filter {
+    ///
+    /// Parse filter expression.
+    ///
+    /// #### Errors
+    /// This function returns error when expression is not valid.
+    ///
+    pub fn parse(expression: &str) -> Result<Filter> {
+        let mut parser = Parser {
+            input: expression,
+            position: 0,
+        };
+        let filter = parser.parse_or()?;
+        parser.skip_whitespaces();
+        if !parser.is_end() {
+            return Err(parser.error("unexpected input"));
+        }
+
+        Ok(filter)
+    }
+
+    ///
+    /// Check whether file at path satisfies the filter.
+    ///
+    /// relative_path is path of the file relative to src_directory.
+    /// When there's problem with reading the file, error is logged
+    /// and predicate is not satisfied.
+    ///
+    pub fn matches(&self, path: &Path, relative_path: &Path) -> bool {
+        match self {
+            Filter::And(left, right) => {
+                left.matches(path, relative_path) && right.matches(path, relative_path)
+            }
+            Filter::Or(left, right) => {
+                left.matches(path, relative_path) || right.matches(path, relative_path)
+            }
+            Filter::Not(filter) => !filter.matches(path, relative_path),
+            Filter::Size(comparison, size) => match fs::metadata(path) {
+                Ok(metadata) => comparison.compare(metadata.len(), *size),
+                Err(err) => {
+                    log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+                    false
+                }
+            },
+            Filter::Suffix(suffix) => path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with(suffix.as_str())),
+            Filter::Name(glob) => path.file_name().is_some_and(|name| glob.is_match(name)),
+            Filter::Path(glob) => glob.is_match(relative_path),
+            Filter::Type(file_type) => match detect_file_type(path) {
+                Ok(detected) => detected == Some(*file_type),
+                Err(err) => {
+                    log::warn!(
+                        target: catalog::TYPE_DETECTION_FAILED,
+                        "Failed to detect type of {}; {err}",
+                        path.to_string_lossy()
+                    );
+                    false
+                }
+            },
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_or(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_and()?;
+        while self.consume("||") {
+            let right = self.parse_and()?;
+            filter = Filter::Or(Box::new(filter), Box::new(right));
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_unary()?;
+        while self.consume("&&") {
+            let right = self.parse_unary()?;
+            filter = Filter::And(Box::new(filter), Box::new(right));
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_unary(&mut self) -> Result<Filter> {
+        if self.consume("!") {
+            let filter = self.parse_unary()?;
+            return Ok(Filter::Not(Box::new(filter)));
+        }
+
+        if self.consume("(") {
+            let filter = self.parse_or()?;
+            if !self.consume(")") {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(filter);
+        }
+
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> Result<Filter> {
+        self.skip_whitespaces();
+        let start = self.position;
+        let name_length = self
+            .rest()
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(self.rest().len());
+        let name = &self.rest()[..name_length];
+        self.position += name_length;
+
+        let filter = match name {
+            "size" => {
+                let comparison = self.parse_comparison()?;
+                let value = self.parse_value()?;
+                let size = Byte::from_str(&value)
+                    .map_err(|err| self.error(&format!("invalid size '{value}'; {err}")))?;
+                Filter::Size(comparison, size.get_bytes() as u64)
+            }
+            "suffix" | "name" | "path" | "type" => {
+                if !self.consume(":") {
+                    return Err(self.error("expected ':'"));
+                }
+                let value = self.parse_value()?;
+                match name {
+                    "suffix" => Filter::Suffix(value),
+                    "name" => Filter::Name(self.parse_glob(&value)?),
+                    "path" => Filter::Path(self.parse_glob(&value)?),
+                    _ => Filter::Type(
+                        FileType::from_str(&value, true)
+                            .map_err(|_| self.error(&format!("unknown type '{value}'")))?,
+                    ),
+                }
+            }
+            "" => return Err(self.error("expected predicate")),
+            _ => {
+                self.position = start;
+                return Err(self.error(&format!("unknown predicate '{name}'")));
+            }
+        };
+
+        Ok(filter)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Comparison> {
+        let comparisons = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ];
+        for (operator, comparison) in comparisons {
+            if self.consume(operator) {
+                return Ok(comparison);
+            }
+        }
+
+        Err(self.error("expected comparison operator"))
+    }
+
+    fn parse_value(&mut self) -> Result<String> {
+        self.skip_whitespaces();
+        if let Some(quoted) = self.rest().strip_prefix('"') {
+            let Some(length) = quoted.find('"') else {
+                return Err(self.error("unterminated quote"));
+            };
+            let value = quoted[..length].to_string();
+            self.position += length + 2;
+            return Ok(value);
+        }
+
+        let length = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .unwrap_or(self.rest().len());
+        let value = self.rest()[..length].to_string();
+        if value.is_empty() {
+            return Err(self.error("expected value"));
+        }
+        self.position += length;
+
+        Ok(value)
+    }
+
+    fn parse_glob(&self, value: &str) -> Result<GlobMatcher> {
+        GlobBuilder::new(value)
+            .literal_separator(true)
+            .build()
+            .map(|glob| glob.compile_matcher())
+            .map_err(|err| self.error(&format!("invalid glob '{value}'; {err}")))
+    }
+
+    fn consume(&mut self, token: &str) -> bool {
+        self.skip_whitespaces();
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            return true;
+        }
+
+        false
+    }
+
+    fn skip_whitespaces(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn is_end(&self) -> bool {
+        self.position >= self.input.len()
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!(
+            "Invalid filter at position {}: {message}",
+            self.position + 1
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_precedence() {
+        let filter = Filter::parse("suffix:.a || suffix:.b && !suffix:.c").unwrap();
+
+        let Filter::Or(left, right) = filter else {
+            panic!("expected Or, got {filter:?}");
+        };
+        assert!(matches!(*left, Filter::Suffix(ref suffix) if suffix == ".a"));
+        let Filter::And(_, right) = *right else {
+            panic!("expected And");
+        };
+        assert!(matches!(*right, Filter::Not(_)));
+    }
+
+    #[test]
+    fn parse_size() {
+        let filter = Filter::parse("size<=1KiB").unwrap();
+
+        assert!(matches!(
+            filter,
+            Filter::Size(Comparison::LessOrEqual, 1024)
+        ));
+    }
+
+    #[test]
+    fn parse_quoted_value() {
+        let filter = Filter::parse("name:\"my file (1).txt\"").unwrap();
+
+        let Filter::Name(glob) = filter else {
+            panic!("expected Name");
+        };
+        assert!(glob.is_match("my file (1).txt"));
+    }
+
+    #[test]
+    fn parse_invalid_expressions() {
+        let expressions = [
+            "",
+            "size",
+            "size<lots",
+            "suffix",
+            "suffix:",
+            "unknown:.txt",
+            "type:spreadsheet",
+            "(suffix:.txt",
+            "suffix:.txt &&",
+            "suffix:.txt suffix:.md",
+            "name:\"unterminated",
+            "path:[invalid",
+        ];
+
+        for expression in expressions {
+            assert!(
+                Filter::parse(expression).is_err(),
+                "'{expression}' should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn matches_combined_expression() {
+        let root = TempDir::new().unwrap();
+        let files = [
+            ("report.docx", 10),
+            ("big.docx", 2000),
+            ("table.xlsx", 10),
+            ("Archive/old.docx", 10),
+            ("notes.txt", 10),
+        ];
+        for (relative, size) in files {
+            let path = root.path().join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0u8; size]).unwrap();
+        }
+        let filter =
+            Filter::parse("size<1KB && (suffix:.docx || suffix:.xlsx) && !path:**/Archive/**")
+                .unwrap();
+
+        let matching = files
+            .iter()
+            .map(|(relative, _)| *relative)
+            .filter(|relative| filter.matches(&root.path().join(relative), Path::new(relative)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(matching, vec!["report.docx", "table.xlsx"]);
+    }
+}
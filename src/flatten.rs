@@ -0,0 +1,123 @@
+use crate::files::numbered_path;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+};
+
+/// Joins components of relative path in names of flattened files.
+const FLAT_PATH_SEPARATOR: &str = "_";
+
+///
+/// Choose names of files copied directly into dst_directory.
+///
+/// Files keep their names when no other file has the same name.
+/// Files sharing name are named by their path relative to src_directory
+/// with components joined by [FLAT_PATH_SEPARATOR] (e.g. 'a/b/c.pdf'
+/// becomes 'a_b_c.pdf') and ' (N)' is added when that name is taken too.
+/// Names are compared ignoring case, because dst_directory can be
+/// on case-insensitive filesystem. Names depend only on paths of selected
+/// files, so runs with the same files give them the same names. File can
+/// get other name when file with the same name is selected later
+/// (e.g. 'report.pdf' becomes 'a_report.pdf').
+/// Returns names of files relative to dst_directory.
+///
+pub fn flat_names(src_directory: &Path, paths: &[PathBuf]) -> HashMap<PathBuf, PathBuf> {
+    let mut sorted_paths = paths.iter().collect::<Vec<_>>();
+    sorted_paths.sort();
+    sorted_paths.dedup();
+
+    let mut name_counts = HashMap::new();
+    for path in &sorted_paths {
+        *name_counts.entry(name_key(path)).or_insert(0) += 1;
+    }
+
+    let mut taken_names = HashSet::new();
+    let mut names = HashMap::with_capacity(sorted_paths.len());
+    let mut clashing_paths = Vec::new();
+    for path in sorted_paths {
+        let key = name_key(path);
+        match name_counts[&key] {
+            1 => {
+                taken_names.insert(key);
+                names.insert(
+                    path.clone(),
+                    PathBuf::from(path.file_name().unwrap_or_default()),
+                );
+            }
+            _ => clashing_paths.push(path),
+        }
+    }
+
+    for path in clashing_paths {
+        let relative = path.strip_prefix(src_directory).unwrap_or(path);
+        let name = PathBuf::from(joined_components(relative));
+        let mut candidate = name.clone();
+        let mut number = 0;
+        while !taken_names.insert(name_key(&candidate)) {
+            number += 1;
+            candidate = numbered_path(&name, number);
+        }
+        names.insert(path.clone(), candidate);
+    }
+
+    names
+}
+
+fn name_key(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase()
+}
+
+fn joined_components(relative: &Path) -> OsString {
+    let mut joined = OsString::new();
+    for component in relative.components() {
+        if let Component::Normal(name) = component {
+            if !joined.is_empty() {
+                joined.push(FLAT_PATH_SEPARATOR);
+            }
+            joined.push(name);
+        }
+    }
+    joined
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flat_names_rename_only_clashing_files() {
+        let src_directory = Path::new("/src");
+        let paths = [
+            src_directory.join("a").join("report.pdf"),
+            src_directory.join("b").join("Report.pdf"),
+            src_directory.join("a_report.pdf"),
+            src_directory.join("c").join("notes.pdf"),
+        ];
+
+        let names = flat_names(src_directory, &paths);
+
+        assert_eq!(names[&paths[0]], PathBuf::from("a_report (1).pdf"));
+        assert_eq!(names[&paths[1]], PathBuf::from("b_Report.pdf"));
+        assert_eq!(names[&paths[2]], PathBuf::from("a_report.pdf"));
+        assert_eq!(names[&paths[3]], PathBuf::from("notes.pdf"));
+    }
+
+    #[test]
+    fn flat_names_dont_depend_on_order() {
+        let src_directory = Path::new("/src");
+        let mut paths = vec![
+            src_directory.join("x").join("y").join("a.txt"),
+            src_directory.join("x_y").join("a.txt"),
+            src_directory.join("a.txt"),
+        ];
+
+        let names = flat_names(src_directory, &paths);
+        paths.reverse();
+
+        assert_eq!(flat_names(src_directory, &paths), names);
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Result};
+use git2::{Repository, Status, StatusOptions};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+///
+/// Which files of git repository are selected.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GitSelection {
+    /// Files in the index of repository.
+    Tracked,
+    /// Tracked files with changes that aren't committed
+    /// and untracked files that aren't ignored.
+    Modified,
+}
+
+///
+/// Find files selected from git repository that contains directory.
+///
+/// Files of submodules aren't selected. Paths of files are joined
+/// to canonical path of working directory, so they can be compared
+/// with paths of files found in canonical src_directory.
+///
+/// #### Errors
+/// This function returns error when directory isn't inside
+/// git repository with working directory or when repository
+/// can't be read.
+///
+pub fn find_git_files(directory: &Path, selection: GitSelection) -> Result<HashSet<PathBuf>> {
+    let repository = Repository::discover(directory).map_err(|err| {
+        anyhow!(
+            "'{}' is not inside git repository; {err}",
+            directory.to_string_lossy()
+        )
+    })?;
+    let workdir = repository
+        .workdir()
+        .ok_or_else(|| {
+            anyhow!(
+                "git repository of '{}' is bare",
+                directory.to_string_lossy()
+            )
+        })?
+        .canonicalize()?;
+
+    let files = match selection {
+        GitSelection::Tracked => repository
+            .index()?
+            .iter()
+            .map(|entry| join_git_path(&workdir, &entry.path))
+            .collect(),
+        GitSelection::Modified => {
+            let mut options = StatusOptions::new();
+            options
+                .include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .include_ignored(false)
+                .exclude_submodules(true);
+            repository
+                .statuses(Some(&mut options))?
+                .iter()
+                .filter(|entry| {
+                    !entry.status().is_empty()
+                        && !entry
+                            .status()
+                            .intersects(Status::WT_DELETED | Status::INDEX_DELETED)
+                })
+                .map(|entry| join_git_path(&workdir, entry.path_bytes()))
+                .collect()
+        }
+    };
+
+    Ok(files)
+}
+
+///
+/// Join path stored by git (always separated with '/') to working directory.
+///
+fn join_git_path(workdir: &Path, git_path: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    let relative_path = {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(git_path))
+    };
+    #[cfg(not(unix))]
+    let relative_path = String::from_utf8_lossy(git_path)
+        .split('/')
+        .collect::<PathBuf>();
+
+    workdir.join(relative_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use git2::Signature;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn create_repository(dir: &Path) {
+        let repository = Repository::init(dir).unwrap();
+        fs::create_dir(dir.join("docs")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join("docs/unchanged.txt"), "unchanged").unwrap();
+        fs::write(dir.join("changed.txt"), "committed").unwrap();
+
+        let mut index = repository.index().unwrap();
+        for path in [".gitignore", "docs/unchanged.txt", "changed.txt"] {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repository.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        repository
+            .commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        fs::write(dir.join("changed.txt"), "modified").unwrap();
+        fs::write(dir.join("new.txt"), "untracked").unwrap();
+        fs::write(dir.join("app.log"), "ignored").unwrap();
+    }
+
+    #[test]
+    fn find_git_files_tracked() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        create_repository(&root);
+
+        let files = find_git_files(&root.join("docs"), GitSelection::Tracked).unwrap();
+
+        assert_eq!(
+            files,
+            HashSet::from([
+                root.join(".gitignore"),
+                root.join("docs").join("unchanged.txt"),
+                root.join("changed.txt"),
+            ])
+        );
+    }
+
+    #[test]
+    fn find_git_files_modified() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        create_repository(&root);
+
+        let files = find_git_files(&root, GitSelection::Modified).unwrap();
+
+        assert_eq!(
+            files,
+            HashSet::from([root.join("changed.txt"), root.join("new.txt")])
+        );
+    }
+
+    #[test]
+    fn find_git_files_outside_repository() {
+        let dir = TempDir::new().unwrap();
+
+        assert!(find_git_files(dir.path(), GitSelection::Tracked).is_err());
+    }
+}
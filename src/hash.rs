@@ -0,0 +1,144 @@
+use crate::privileges::open_for_reading;
+use std::{fs::File, io, path::Path};
+
+/// Size of blocks read from files that bypass cache.
+#[cfg(windows)]
+const UNCACHED_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Alignment of buffer and blocks read from files that bypass cache.
+/// It's a multiple of sector sizes of disks.
+#[cfg(windows)]
+const UNCACHED_ALIGNMENT: usize = 4096;
+
+///
+/// Calculate hash of the file content.
+///
+/// File is read in chunks so it's safe to use with big files.
+///
+/// #### Errors
+/// This function returns error when there's a problem with
+/// opening or reading the file.
+///
+pub fn hash_file(path: &Path) -> io::Result<blake3::Hash> {
+    let file = open_for_reading(path)?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(file)?;
+
+    Ok(hasher.finalize())
+}
+
+///
+/// Calculate hash of the file content read from the device instead
+/// of from memory, so errors of writing the file (e.g. bit flips
+/// of USB sticks) are found right after it was written.
+///
+/// On Linux cached pages of the file are written and dropped before
+/// it's read. On Windows the file is read without buffering. Other
+/// platforms can't drop cached pages, so the file is only synced.
+///
+/// #### Errors
+/// This function returns error when there's a problem with
+/// opening, syncing or reading the file.
+///
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn hash_file_uncached(path: &Path) -> io::Result<blake3::Hash> {
+    use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+
+    let file = File::open(path)?;
+    // Only pages that are already written to the device are dropped
+    file.sync_data()?;
+    posix_fadvise(&file, 0, 0, PosixFadviseAdvice::POSIX_FADV_DONTNEED)?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(file)?;
+
+    Ok(hasher.finalize())
+}
+
+#[cfg(windows)]
+pub fn hash_file_uncached(path: &Path) -> io::Result<blake3::Hash> {
+    use std::{io::Read, os::windows::fs::OpenOptionsExt};
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_NO_BUFFERING;
+
+    let mut file = File::options()
+        .read(true)
+        .custom_flags(FILE_FLAG_NO_BUFFERING)
+        .open(path)?;
+    // Reads without buffering have to go to aligned buffer
+    let mut buffer = vec![0; UNCACHED_BLOCK_SIZE + UNCACHED_ALIGNMENT];
+    let offset = buffer.as_ptr().align_offset(UNCACHED_ALIGNMENT);
+    let block = &mut buffer[offset..offset + UNCACHED_BLOCK_SIZE];
+    let mut hasher = blake3::Hasher::new();
+    loop {
+        let read = match file.read(block) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        hasher.update(&block[..read]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    windows
+)))]
+pub fn hash_file_uncached(path: &Path) -> io::Result<blake3::Hash> {
+    File::open(path)?.sync_data()?;
+    hash_file(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn hash_file_same_content_same_hash() {
+        let files = [NamedTempFile::new().unwrap(), NamedTempFile::new().unwrap()];
+        fs::write(&files[0], "identical text").unwrap();
+        fs::write(&files[1], "identical text").unwrap();
+
+        let hashes = files
+            .iter()
+            .map(|file| hash_file(file.path()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(hashes[0], hashes[1]);
+    }
+
+    #[test]
+    fn hash_file_different_content_different_hash() {
+        let files = [NamedTempFile::new().unwrap(), NamedTempFile::new().unwrap()];
+        fs::write(&files[0], "some text").unwrap();
+        fs::write(&files[1], "another text").unwrap();
+
+        let hashes = files
+            .iter()
+            .map(|file| hash_file(file.path()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_ne!(hashes[0], hashes[1]);
+    }
+
+    #[test]
+    fn hash_file_uncached_same_hash() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(&file, "written just now").unwrap();
+
+        assert_eq!(
+            hash_file_uncached(file.path()).unwrap(),
+            hash_file(file.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn hash_file_not_exist() {
+        assert!(hash_file(Path::new("save-me-files.test.noexistent.file")).is_err());
+    }
+}
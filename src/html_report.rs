@@ -0,0 +1,294 @@
+use crate::statistics::extension_statistics;
+use anyhow::Result;
+use byte_unit::Byte;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Maximum number of copied files linked in the report.
+const MAX_LINKED_FILES: usize = 1000;
+
+/// Maximum number of extensions shown in the chart.
+const MAX_CHARTED_EXTENSIONS: usize = 20;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.5em; }
+h2 { font-size: 1.2em; margin-top: 2em; }
+table { border-collapse: collapse; }
+th, td { text-align: left; padding: 0.25em 1em 0.25em 0; vertical-align: top; }
+td.number { text-align: right; }
+.bar { background: #4a7ebb; height: 1em; min-width: 1px; }
+.failed { color: #b00020; }
+code { word-break: break-all; }
+";
+
+///
+/// Outcome of the run described by HTML report.
+///
+pub struct RunReport<'a> {
+    pub src_directory: &'a Path,
+    pub dst_directory: &'a Path,
+    pub fingerprint: &'a str,
+    /// Number of files selected to be copied.
+    pub selected_files: usize,
+    /// Paths of copied files and their copies.
+    pub copied_files: &'a [(PathBuf, PathBuf)],
+    /// Files that failed to copy.
+    pub failed_files: &'a [PathBuf],
+    /// Number of files skipped because their copies are up to date.
+    pub up_to_date_files: usize,
+    /// Copies whose names were shortened or sanitized and original
+    /// paths of their sources relative to src_directory.
+    pub renamed_files: &'a HashMap<PathBuf, PathBuf>,
+    /// Copies that differ from their sources after verification.
+    pub different_files: &'a [PathBuf],
+}
+
+///
+/// Write self-contained HTML page describing the run to path.
+///
+/// Page contains summary of the run, chart of sizes of copied files
+/// grouped by extension, lists of files that failed to copy or differ
+/// after verification, renamed copies and links to copies in dst_directory. It doesn't load any external resources,
+/// so it can be attached to a ticket or sent by e-mail.
+///
+/// #### Errors
+/// This function returns error when report can't be written.
+///
+pub fn write_html_report(path: &Path, report: &RunReport) -> Result<()> {
+    fs::write(path, render_html_report(report))?;
+    Ok(())
+}
+
+fn render_html_report(report: &RunReport) -> String {
+    let copies = report
+        .copied_files
+        .iter()
+        .map(|(_, dst_path)| dst_path.clone())
+        .collect::<Vec<_>>();
+    let statistics = extension_statistics(&copies);
+    let copied_bytes = statistics.iter().map(|entry| entry.bytes).sum::<u64>();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>save-me-files report</title>\n");
+    let _ = writeln!(html, "<style>\n{STYLE}</style>\n</head>\n<body>");
+    let _ = writeln!(
+        html,
+        "<h1>save-me-files report: {}</h1>",
+        escape(&report.src_directory.to_string_lossy())
+    );
+
+    html.push_str("<h2>Summary</h2>\n<table>\n");
+    let summary = [
+        ("Source", code_cell(report.src_directory)),
+        ("Destination", link_cell(report.dst_directory)),
+        ("Configuration fingerprint", escape(report.fingerprint)),
+        ("Selected files", report.selected_files.to_string()),
+        ("Copied files", report.copied_files.len().to_string()),
+        ("Copied size", format_size(copied_bytes)),
+        ("Up to date files", report.up_to_date_files.to_string()),
+        ("Failed files", report.failed_files.len().to_string()),
+        ("Different files", report.different_files.len().to_string()),
+    ];
+    for (name, value) in summary {
+        let _ = writeln!(html, "<tr><th>{name}</th><td>{value}</td></tr>");
+    }
+    html.push_str("</table>\n");
+
+    if !statistics.is_empty() {
+        html.push_str("<h2>Copied size by extension</h2>\n<table>\n");
+        let max_bytes = statistics[0].bytes.max(1);
+        for entry in statistics.iter().take(MAX_CHARTED_EXTENSIONS) {
+            let width = entry.bytes as f64 / max_bytes as f64 * 100.0;
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td>\
+                 <td style=\"width: 50%\"><div class=\"bar\" style=\"width: {width:.1}%\">\
+                 </div></td></tr>",
+                escape(&entry.extension),
+                entry.files,
+                format_size(entry.bytes)
+            );
+        }
+        if statistics.len() > MAX_CHARTED_EXTENSIONS {
+            let _ = writeln!(
+                html,
+                "<tr><td colspan=\"4\">and {} more extensions</td></tr>",
+                statistics.len() - MAX_CHARTED_EXTENSIONS
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    if !report.failed_files.is_empty() {
+        html.push_str("<h2 class=\"failed\">Failed files</h2>\n<ul>\n");
+        for path in report.failed_files {
+            let _ = writeln!(html, "<li>{}</li>", code_cell(path));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    if !report.different_files.is_empty() {
+        html.push_str("<h2 class=\"failed\">Copies different from sources</h2>\n<ul>\n");
+        for path in report.different_files {
+            let _ = writeln!(html, "<li>{}</li>", link_cell(path));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    if !report.renamed_files.is_empty() {
+        let mut renamed_files = report.renamed_files.iter().collect::<Vec<_>>();
+        renamed_files.sort();
+        html.push_str("<h2>Renamed files</h2>\n<table>\n");
+        html.push_str("<tr><th>Original path</th><th>Copy</th></tr>\n");
+        for (dst_path, original_path) in renamed_files {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                code_cell(original_path),
+                link_cell(dst_path)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    if !report.copied_files.is_empty() {
+        html.push_str("<h2>Copied files</h2>\n<table>\n");
+        html.push_str("<tr><th>Source</th><th>Copy</th></tr>\n");
+        for (src_path, dst_path) in report.copied_files.iter().take(MAX_LINKED_FILES) {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                code_cell(src_path),
+                link_cell(dst_path)
+            );
+        }
+        if report.copied_files.len() > MAX_LINKED_FILES {
+            let _ = writeln!(
+                html,
+                "<tr><td colspan=\"2\">and {} more files</td></tr>",
+                report.copied_files.len() - MAX_LINKED_FILES
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn code_cell(path: &Path) -> String {
+    format!("<code>{}</code>", escape(&path.to_string_lossy()))
+}
+
+fn link_cell(path: &Path) -> String {
+    format!(
+        "<a href=\"{}\">{}</a>",
+        escape(&file_url(path)),
+        code_cell(path)
+    )
+}
+
+fn format_size(bytes: u64) -> String {
+    Byte::from_bytes(bytes as u128)
+        .get_appropriate_unit(true)
+        .to_string()
+}
+
+///
+/// Escape characters that have special meaning in HTML.
+///
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+///
+/// Convert absolute path to 'file' URL.
+///
+/// Bytes other than unreserved characters and separators
+/// are percent-encoded.
+///
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        // Windows paths start with drive letter
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(byte as char)
+            }
+            byte => {
+                let _ = write!(url, "%{byte:02X}");
+            }
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn file_url_encodes_special_characters() {
+        assert_eq!(
+            file_url(Path::new("/backup/my files/a#1.txt")),
+            "file:///backup/my%20files/a%231.txt"
+        );
+    }
+
+    #[test]
+    fn write_html_report_lists_copied_and_failed_files() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let copy = dst_dir.path().join("notes.txt");
+        fs::write(&copy, "notes").unwrap();
+        let copied_files = [(src_dir.path().join("notes.txt"), copy)];
+        let failed_files = [src_dir.path().join("<locked>.txt")];
+        let renamed_files =
+            HashMap::from([(dst_dir.path().join("a_b.txt"), PathBuf::from("a:b.txt"))]);
+        let report_path = dst_dir.path().join("report.html");
+
+        write_html_report(
+            &report_path,
+            &RunReport {
+                src_directory: src_dir.path(),
+                dst_directory: dst_dir.path(),
+                fingerprint: "abc",
+                selected_files: 2,
+                copied_files: &copied_files,
+                failed_files: &failed_files,
+                up_to_date_files: 0,
+                renamed_files: &renamed_files,
+                different_files: &[],
+            },
+        )
+        .unwrap();
+
+        let html = fs::read_to_string(report_path).unwrap();
+        assert!(html.contains("&lt;locked&gt;.txt"));
+        assert!(html.contains("notes.txt</code></a>"));
+        assert!(html.contains("<td>txt</td>"));
+        assert!(html.contains("<code>a:b.txt</code>"));
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::Result;
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::Path;
+
+/// Prefix of the pattern that re-includes entries excluded by earlier patterns.
+pub const NEGATION_PREFIX: char = '!';
+
+/// Prefix of the pattern that matches path at any depth.
+const ANY_DEPTH_PREFIX: &str = "**/";
+
+///
+/// Single exclusion pattern with gitignore semantics.
+///
+#[derive(Clone, Debug)]
+pub struct IgnorePattern {
+    /// Glob without negation, anchoring and trailing slash.
+    pub glob: String,
+    /// Entries matched by pattern are included again.
+    pub negated: bool,
+    /// Pattern is matched against path relative to src_directory,
+    /// otherwise it's matched against path ending at any depth.
+    pub anchored: bool,
+    /// Pattern matches only directories.
+    pub directory_only: bool,
+    matcher: GlobMatcher,
+}
+
+impl IgnorePattern {
+    ///
+    /// Parse pattern written like line of '.gitignore'.
+    ///
+    /// Leading [NEGATION_PREFIX] negates the pattern ('\!' matches
+    /// names starting with it). Trailing slash makes it match only
+    /// directories. Pattern containing slash at the beginning or
+    /// in the middle is anchored to src_directory, pattern without
+    /// slash matches names at any depth. '*' doesn't match slash
+    /// and '**' matches any number of directories.
+    ///
+    /// #### Errors
+    /// This function returns error when pattern is not a valid glob.
+    ///
+    pub fn new(pattern: &str) -> Result<Self> {
+        let (negated, pattern) = match pattern.strip_prefix(NEGATION_PREFIX) {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let (directory_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let (anchored, glob) = match pattern.strip_prefix('/') {
+            Some(glob) => (true, glob),
+            None => match pattern.strip_prefix(ANY_DEPTH_PREFIX) {
+                Some(glob) => (false, glob),
+                None => (pattern.contains('/'), pattern),
+            },
+        };
+
+        let full_glob = match anchored {
+            true => glob.to_string(),
+            false => format!("{ANY_DEPTH_PREFIX}{glob}"),
+        };
+        let matcher = GlobBuilder::new(&full_glob)
+            .literal_separator(true)
+            .backslash_escape(true)
+            .build()?
+            .compile_matcher();
+
+        Ok(Self {
+            glob: glob.to_string(),
+            negated,
+            anchored,
+            directory_only,
+            matcher,
+        })
+    }
+
+    fn matches(&self, relative_path: &Path, is_dir: bool) -> bool {
+        (is_dir || !self.directory_only) && self.matcher.is_match(relative_path)
+    }
+}
+
+///
+/// Ordered exclusion patterns with gitignore semantics.
+///
+/// The last pattern matching the entry decides whether it's excluded,
+/// so `*.log` followed by `!important.log` excludes every log except
+/// 'important.log'. Entries inside excluded directories aren't visited,
+/// so negated patterns can't include them again.
+///
+#[derive(Clone, Debug, Default)]
+pub struct IgnorePatterns {
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnorePatterns {
+    ///
+    /// Add pattern after already added patterns.
+    ///
+    pub fn push(&mut self, pattern: IgnorePattern) {
+        self.patterns.push(pattern);
+    }
+
+    pub fn patterns(&self) -> &[IgnorePattern] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    ///
+    /// Check whether entry at path relative to src_directory is excluded.
+    ///
+    pub fn is_excluded(&self, relative_path: &Path, is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(relative_path, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+}
+
+impl<'a> FromIterator<&'a str> for IgnorePatterns {
+    ///
+    /// Create patterns from valid globs.
+    ///
+    /// #### Panics
+    /// This function panics when any pattern is not a valid glob.
+    ///
+    fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+        let patterns = iter
+            .into_iter()
+            .map(|pattern| IgnorePattern::new(pattern).expect("pattern is a valid glob"))
+            .collect();
+
+        Self { patterns }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_excluded(patterns: &[&str], path: &str, is_dir: bool) -> bool {
+        IgnorePatterns::from_iter(patterns.iter().copied()).is_excluded(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn pattern_without_slash_matches_at_any_depth() {
+        assert!(is_excluded(&["*.log"], "debug.log", false));
+        assert!(is_excluded(&["*.log"], "a/b/debug.log", false));
+        assert!(is_excluded(&["build"], "a/build", true));
+        assert!(!is_excluded(&["*.log"], "debug.log.txt", false));
+    }
+
+    #[test]
+    fn leading_slash_anchors_pattern() {
+        assert!(is_excluded(&["/*.log"], "debug.log", false));
+        assert!(!is_excluded(&["/*.log"], "a/debug.log", false));
+        assert!(is_excluded(&["/build"], "build", true));
+        assert!(!is_excluded(&["/build"], "a/build", true));
+    }
+
+    #[test]
+    fn slash_in_the_middle_anchors_pattern() {
+        assert!(is_excluded(&["doc/*.txt"], "doc/notes.txt", false));
+        assert!(!is_excluded(&["doc/*.txt"], "a/doc/notes.txt", false));
+        assert!(!is_excluded(&["doc/*.txt"], "doc/a/notes.txt", false));
+    }
+
+    #[test]
+    fn leading_double_star_matches_at_any_depth() {
+        assert!(is_excluded(&["**/foo"], "foo", true));
+        assert!(is_excluded(&["**/foo"], "a/b/foo", true));
+        assert!(is_excluded(&["**/foo/bar"], "foo/bar", false));
+        assert!(is_excluded(&["**/foo/bar"], "a/foo/bar", false));
+        assert!(!is_excluded(&["**/foo/bar"], "a/foo/x/bar", false));
+    }
+
+    #[test]
+    fn trailing_double_star_matches_everything_inside() {
+        assert!(is_excluded(&["abc/**"], "abc/x", false));
+        assert!(is_excluded(&["abc/**"], "abc/x/y", true));
+        assert!(!is_excluded(&["abc/**"], "x/abc/y", false));
+    }
+
+    #[test]
+    fn double_star_in_the_middle_matches_any_directories() {
+        assert!(is_excluded(&["a/**/b"], "a/b", true));
+        assert!(is_excluded(&["a/**/b"], "a/x/b", true));
+        assert!(is_excluded(&["a/**/b"], "a/x/y/b", true));
+        assert!(!is_excluded(&["a/**/b"], "x/a/b", true));
+    }
+
+    #[test]
+    fn star_doesnt_match_slash() {
+        assert!(!is_excluded(&["/a*b"], "a/b", false));
+        assert!(is_excluded(&["/a?c"], "abc", false));
+        assert!(!is_excluded(&["/a?c"], "a/c", false));
+    }
+
+    #[test]
+    fn trailing_slash_matches_only_directories() {
+        assert!(is_excluded(&["cache/"], "a/cache", true));
+        assert!(!is_excluded(&["cache/"], "a/cache", false));
+        assert!(is_excluded(&["/tmp*/"], "tmp1", true));
+        assert!(!is_excluded(&["/tmp*/"], "tmp1", false));
+    }
+
+    #[test]
+    fn last_matching_pattern_wins() {
+        let patterns = ["*.log", "!important.log"];
+
+        assert!(is_excluded(&patterns, "debug.log", false));
+        assert!(!is_excluded(&patterns, "important.log", false));
+        assert!(!is_excluded(&patterns, "a/important.log", false));
+        assert!(is_excluded(
+            &["*.log", "!important.log", "/important.log"],
+            "important.log",
+            false
+        ));
+    }
+
+    #[test]
+    fn negation_before_exclusion_has_no_effect() {
+        assert!(is_excluded(&["!keep.log", "*.log"], "keep.log", false));
+    }
+
+    #[test]
+    fn negation_alone_excludes_nothing() {
+        assert!(!is_excluded(&["!*.log"], "debug.log", false));
+        assert!(!is_excluded(&[], "debug.log", false));
+    }
+
+    #[test]
+    fn negated_directory_pattern_applies_only_to_directories() {
+        let patterns = ["/build*", "!/build*/"];
+
+        assert!(!is_excluded(&patterns, "build1", true));
+        assert!(is_excluded(&patterns, "build1", false));
+    }
+
+    #[test]
+    fn escaped_negation_prefix_is_literal() {
+        assert!(is_excluded(&["\\!important*"], "!important.txt", false));
+        assert!(!is_excluded(&["\\!important*"], "important.txt", false));
+    }
+
+    #[test]
+    fn pattern_parts_are_recorded() {
+        let pattern = IgnorePattern::new("!/build*/").unwrap();
+
+        assert_eq!(pattern.glob, "build*");
+        assert!(pattern.negated);
+        assert!(pattern.anchored);
+        assert!(pattern.directory_only);
+
+        let pattern = IgnorePattern::new("**/node_modules").unwrap();
+        assert_eq!(pattern.glob, "node_modules");
+        assert!(!pattern.anchored);
+    }
+
+    #[test]
+    fn invalid_pattern_is_error() {
+        assert!(IgnorePattern::new("[invalid").is_err());
+    }
+}
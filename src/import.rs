@@ -0,0 +1,285 @@
+use clap::ValueEnum;
+use regex::Regex;
+use std::path::PathBuf;
+
+/// Matches line of rsync '--itemize-changes' output describing regular file,
+/// optionally prefixed with date, time and process id written to '--log-file'.
+const RSYNC_FILE_REGEX: &str =
+    r"^(?:\d{4}/\d{2}/\d{2} \d{2}:\d{2}:\d{2} \[\d+\] )?([<>ch.])f.{9} (.+)$";
+
+/// Robocopy file classes of files that are present in destination
+/// after the run.
+const ROBOCOPY_FILE_CLASSES: [&str; 7] = [
+    "New File", "Newer", "Older", "Changed", "Tweaked", "Modified", "same",
+];
+
+///
+/// Format of log written by other backup tool.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BackupLogFormat {
+    /// Output of rsync run with '--itemize-changes' (or its '--log-file').
+    Rsync,
+    /// Log of robocopy run (its output or '/LOG' file).
+    Robocopy,
+}
+
+///
+/// Files of backup described by imported log.
+///
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportedLog {
+    /// Directory the files were copied from, when log contains it.
+    pub src_directory: Option<PathBuf>,
+    /// Paths of files relative to the directory they were copied to.
+    pub files: Vec<PathBuf>,
+}
+
+///
+/// Decode content of log, which is UTF-16 when it starts with
+/// byte order mark (robocopy '/UNILOG') and UTF-8 otherwise.
+///
+/// Invalid characters are replaced.
+///
+pub fn decode_log(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => {
+            let units = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        bytes => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+///
+/// Guess format of log from its content.
+///
+pub fn detect_log_format(content: &str) -> Option<BackupLogFormat> {
+    if content.contains("ROBOCOPY") {
+        return Some(BackupLogFormat::Robocopy);
+    }
+    let rsync_file_regex = Regex::new(RSYNC_FILE_REGEX).unwrap();
+    if content.lines().any(|line| rsync_file_regex.is_match(line)) {
+        return Some(BackupLogFormat::Rsync);
+    }
+    if content
+        .lines()
+        .any(|line| parse_robocopy_file(line).is_some())
+    {
+        return Some(BackupLogFormat::Robocopy);
+    }
+    None
+}
+
+///
+/// Find files that are in backup according to the log.
+///
+/// Deleted files, directories, links and entries that failed
+/// to copy are left out.
+///
+pub fn parse_log(content: &str, format: BackupLogFormat) -> ImportedLog {
+    match format {
+        BackupLogFormat::Rsync => parse_rsync_log(content),
+        BackupLogFormat::Robocopy => parse_robocopy_log(content),
+    }
+}
+
+fn parse_rsync_log(content: &str) -> ImportedLog {
+    let rsync_file_regex = Regex::new(RSYNC_FILE_REGEX).unwrap();
+    let files = content
+        .lines()
+        .filter_map(|line| rsync_file_regex.captures(line))
+        .map(|captures| {
+            let path = &captures[2];
+            // Hard links are written as 'link => target'
+            let path = match &captures[1] {
+                "h" => path.split_once(" => ").map_or(path, |(link, _)| link),
+                _ => path,
+            };
+            PathBuf::from(unescape_rsync_path(path))
+        })
+        .collect();
+
+    ImportedLog {
+        src_directory: None,
+        files,
+    }
+}
+
+///
+/// Replace '\#ooo' octal escapes that rsync uses for
+/// unprintable characters of names.
+///
+fn unescape_rsync_path(path: &str) -> String {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while !rest.is_empty() {
+        if let [b'\\', b'#', digits @ ..] = rest {
+            let code = digits
+                .get(..3)
+                .and_then(|digits| std::str::from_utf8(digits).ok())
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+            if let Some(code) = code {
+                bytes.push(code);
+                rest = &rest[5..];
+                continue;
+            }
+        }
+        bytes.push(rest[0]);
+        rest = &rest[1..];
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn parse_robocopy_log(content: &str) -> ImportedLog {
+    let header_source = content.lines().find_map(|line| {
+        let (name, value) = line.split_once(" : ")?;
+        (name.trim() == "Source").then(|| value.trim().to_string())
+    });
+
+    let mut root = header_source.clone();
+    let mut directory = None;
+    let mut files = Vec::new();
+    for line in content.lines() {
+        // Progress is written after carriage return
+        let line = line.split('\r').next().unwrap_or_default();
+        if let Some(path) = parse_robocopy_directory(line) {
+            root.get_or_insert_with(|| path.to_string());
+            directory = Some(path.to_string());
+            continue;
+        }
+        let (Some(name), Some(directory), Some(root)) =
+            (parse_robocopy_file(line), &directory, &root)
+        else {
+            continue;
+        };
+        let Some(relative_directory) = strip_windows_prefix(directory, root) else {
+            continue;
+        };
+        let mut path = windows_path_to_relative(relative_directory);
+        path.push(name);
+        files.push(path);
+    }
+
+    ImportedLog {
+        src_directory: header_source.map(PathBuf::from),
+        files,
+    }
+}
+
+///
+/// Read absolute path of directory from line that starts its files.
+///
+fn parse_robocopy_directory(line: &str) -> Option<&str> {
+    let path = line.rsplit('\t').next()?.trim();
+    let is_absolute = path.get(1..3) == Some(":\\") || path.starts_with("\\\\");
+    (is_absolute && path.ends_with('\\')).then_some(path)
+}
+
+///
+/// Read name of file that's in destination after the run.
+///
+fn parse_robocopy_file(line: &str) -> Option<&str> {
+    let fields = line
+        .split('\t')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .collect::<Vec<_>>();
+    match fields.as_slice() {
+        [class, _size, name] if ROBOCOPY_FILE_CLASSES.contains(class) => Some(name),
+        _ => None,
+    }
+}
+
+///
+/// Strip prefix from path ignoring case, like Windows does.
+///
+fn strip_windows_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = path.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &path[prefix.len()..])
+}
+
+fn windows_path_to_relative(path: &str) -> PathBuf {
+    path.split('\\')
+        .filter(|component| !component.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_rsync_log_keeps_files_in_backup() {
+        let content = "\
+sending incremental file list
+cd+++++++++ docs/
+>f+++++++++ docs/a b.txt
+.f..t...... notes.txt
+hf+++++++++ docs/link.txt => docs/a b.txt
+cL+++++++++ latest -> notes.txt
+*deleting   old.txt
+2024/01/02 03:04:05 [123] >f.st...... line\\#012break.txt
+";
+
+        let log = parse_log(content, BackupLogFormat::Rsync);
+
+        assert_eq!(detect_log_format(content), Some(BackupLogFormat::Rsync));
+        assert_eq!(
+            log.files,
+            vec![
+                PathBuf::from("docs/a b.txt"),
+                PathBuf::from("notes.txt"),
+                PathBuf::from("docs/link.txt"),
+                PathBuf::from("line\nbreak.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_robocopy_log_keeps_files_in_backup() {
+        let content = "\
+-------------------------------------------------------------------------------
+   ROBOCOPY     ::     Robust File Copy for Windows
+-------------------------------------------------------------------------------
+
+   Source : C:\\Users\\me\\Documents\\
+     Dest : E:\\Backup\\
+
+\t                   3\tC:\\Users\\me\\Documents\\
+\t    New File  \t\t     123\ta.txt\r  0%  \r100%
+\t    Newer     \t\t   1.2 m\tb.docx
+\t  *EXTRA File \t\t      12\told.txt
+\t  New Dir          1\tC:\\Users\\me\\Documents\\Sub\\
+\t    New File  \t\t     456\tc.txt
+";
+
+        let log = parse_log(content, BackupLogFormat::Robocopy);
+
+        assert_eq!(detect_log_format(content), Some(BackupLogFormat::Robocopy));
+        assert_eq!(
+            log.src_directory,
+            Some(PathBuf::from("C:\\Users\\me\\Documents\\"))
+        );
+        assert_eq!(
+            log.files,
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("b.docx"),
+                ["Sub", "c.txt"].iter().collect::<PathBuf>(),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_log_reads_utf16() {
+        let bytes = [0xFF, 0xFE, b'o', 0, b'k', 0];
+
+        assert_eq!(decode_log(&bytes), "ok");
+    }
+}
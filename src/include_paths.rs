@@ -0,0 +1,127 @@
+use crate::{catalog, COMMENT_LINE_PREFIX};
+use anyhow::Result;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+///
+/// Read paths that always have to be copied from file to the vector.
+///
+/// Paths are trimmed so they don't contain leading and following
+/// whitespaces. Relative paths are resolved against src_directory.
+/// Path is valid when it points to existing file or directory
+/// inside src_directory.
+/// Every invalid path is logged with WARN level unless
+/// it starts with [COMMENT_LINE_PREFIX].
+///
+/// #### Errors
+/// This function returns error when there's a problem with
+/// opening the file.
+///
+/// #### Panics
+/// This function panics when input file contains not valid
+/// UTF-8 characters.
+///
+pub fn read_include_paths(path: impl AsRef<Path>, src_directory: &Path) -> Result<Vec<PathBuf>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+
+    let include_paths = reader
+        .lines()
+        .map(|line| line.unwrap().trim().to_owned())
+        .filter(|line| !line.is_empty() && !line.starts_with(COMMENT_LINE_PREFIX))
+        .filter_map(|line| {
+            let path = src_directory.join(&line);
+            let Ok(path) = path.canonicalize() else {
+                log::warn!(
+                    target: catalog::INVALID_INCLUDE_PATH,
+                    "Included path not exist: {}",
+                    line
+                );
+                return None;
+            };
+
+            if !path.starts_with(src_directory) {
+                log::warn!(
+                    target: catalog::INVALID_INCLUDE_PATH,
+                    "Included path is outside of src_directory: {}",
+                    line
+                );
+                return None;
+            }
+
+            Some(path)
+        })
+        .collect();
+
+    Ok(include_paths)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::{NamedTempFile, TempDir};
+
+    #[test]
+    fn read_include_paths_relative_and_absolute() {
+        let src_directory = TempDir::new().unwrap();
+        let src_path = src_directory.path().canonicalize().unwrap();
+        let relative = NamedTempFile::new_in(&src_path).unwrap();
+        let absolute = TempDir::new_in(&src_path).unwrap();
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(
+            file.path(),
+            format!(
+                " {} \n{}\n",
+                relative.path().file_name().unwrap().to_string_lossy(),
+                absolute.path().to_string_lossy()
+            ),
+        )
+        .unwrap();
+
+        let include_paths = read_include_paths(file.path(), &src_path).unwrap();
+
+        assert_eq!(
+            include_paths,
+            vec![relative.path().to_path_buf(), absolute.path().to_path_buf()]
+        );
+    }
+
+    #[test]
+    fn read_include_paths_ignore_comments_and_non_existent() {
+        let src_directory = TempDir::new().unwrap();
+        let src_path = src_directory.path().canonicalize().unwrap();
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(
+            file.path(),
+            format!(
+                "{} comment\nsave-me-files.test.noexistent.file\n",
+                COMMENT_LINE_PREFIX
+            ),
+        )
+        .unwrap();
+
+        let include_paths = read_include_paths(file.path(), &src_path).unwrap();
+
+        assert!(include_paths.is_empty());
+    }
+
+    #[test]
+    fn read_include_paths_ignore_outside_src_directory() {
+        let src_directory = TempDir::new().unwrap();
+        let src_path = src_directory.path().canonicalize().unwrap();
+        let outside = NamedTempFile::new().unwrap();
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(file.path(), outside.path().to_string_lossy().as_bytes()).unwrap();
+
+        let include_paths = read_include_paths(file.path(), &src_path).unwrap();
+
+        assert!(include_paths.is_empty());
+    }
+}
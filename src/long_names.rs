@@ -0,0 +1,182 @@
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+/// Maximum length of a single path component in bytes
+/// supported by most filesystems.
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Extensions longer than this are not preserved in shortened names.
+const MAX_EXTENSION_LENGTH: usize = 16;
+
+/// Number of hash characters appended to shortened names.
+const HASH_LENGTH: usize = 16;
+
+///
+/// Shorten every component of relative path longer than [MAX_NAME_LENGTH].
+///
+/// Returns None when no component needs to be shortened.
+///
+pub fn shorten_path(relative_path: &Path) -> Option<PathBuf> {
+    let mut is_shortened = false;
+    let shortened_path = relative_path
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => match shorten_name(name) {
+                Some(shortened) => {
+                    is_shortened = true;
+                    shortened
+                }
+                None => name.to_os_string(),
+            },
+            component => component.as_os_str().to_os_string(),
+        })
+        .collect::<PathBuf>();
+
+    is_shortened.then_some(shortened_path)
+}
+
+///
+/// Shorten name longer than [MAX_NAME_LENGTH].
+///
+/// Shortened name consists of the beginning of the name, '~',
+/// hash of the whole name and the extension, so the same name
+/// is always shortened the same way and different names sharing
+/// the beginning don't collide.
+///
+/// Returns None when name is short enough.
+///
+fn shorten_name(name: &OsStr) -> Option<OsString> {
+    if name.len() <= MAX_NAME_LENGTH {
+        return None;
+    }
+
+    let hash = blake3::hash(name.as_encoded_bytes()).to_hex();
+    let extension = Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy())
+        .filter(|extension| extension.len() <= MAX_EXTENSION_LENGTH)
+        .map(|extension| format!(".{extension}"))
+        .unwrap_or_default();
+    let ending = format!("~{}{extension}", &hash[..HASH_LENGTH]);
+
+    let name = name.to_string_lossy();
+    let mut beginning_length = MAX_NAME_LENGTH - ending.len();
+    while !name.is_char_boundary(beginning_length) {
+        beginning_length -= 1;
+    }
+
+    Some(format!("{}{ending}", &name[..beginning_length]).into())
+}
+
+///
+/// Add extended-length prefix ('\\?\') to absolute path, so Windows
+/// APIs accept it when it's longer than MAX_PATH (260 characters).
+///
+/// Paths that already have the prefix, relative paths and paths
+/// with '..' (which extended-length paths don't resolve) are kept.
+/// Other platforms have no such limit.
+///
+#[cfg(windows)]
+pub fn extended_length_path(path: &Path) -> Cow<'_, Path> {
+    use std::path::Prefix;
+
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return Cow::Borrowed(path);
+    };
+    if path
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Cow::Borrowed(path);
+    }
+    let mut extended = match prefix.kind() {
+        Prefix::Disk(_) => {
+            let mut extended = OsString::from(r"\\?\");
+            extended.push(prefix.as_os_str());
+            extended
+        }
+        Prefix::UNC(server, share) => {
+            let mut extended = OsString::from(r"\\?\UNC\");
+            extended.push(server);
+            extended.push(r"\");
+            extended.push(share);
+            extended
+        }
+        _ => return Cow::Borrowed(path),
+    };
+    // Extended-length paths need backslashes as separators
+    let mut extended = PathBuf::from(extended);
+    for component in components {
+        match component {
+            Component::RootDir => extended.push(r"\"),
+            component => extended.push(component),
+        }
+    }
+
+    Cow::Owned(extended)
+}
+
+#[cfg(not(windows))]
+pub fn extended_length_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shorten_path_short_names() {
+        assert_eq!(shorten_path(Path::new("a/b/file.txt")), None);
+    }
+
+    #[test]
+    fn shorten_path_long_names() {
+        let long_name = format!("{}.txt", "ą".repeat(200));
+        let path = Path::new("dir").join(&long_name).join(&long_name);
+
+        let shortened = shorten_path(&path).unwrap();
+
+        let components = shortened.iter().collect::<Vec<_>>();
+        assert_eq!(components.len(), 3);
+        assert_eq!(components[0], "dir");
+        assert_eq!(components[1], components[2]);
+        assert!(components[1].len() <= MAX_NAME_LENGTH);
+        assert!(components[1].to_string_lossy().ends_with(".txt"));
+        assert_eq!(shorten_path(&path), Some(shortened));
+    }
+
+    #[test]
+    fn shorten_path_different_names_dont_collide() {
+        let common = "x".repeat(300);
+        let first = shorten_path(Path::new(&format!("{common}1"))).unwrap();
+        let second = shorten_path(Path::new(&format!("{common}2"))).unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extended_length_path_adds_prefix() {
+        assert_eq!(
+            extended_length_path(Path::new(r"C:\dir/file.txt")),
+            Path::new(r"\\?\C:\dir\file.txt")
+        );
+        assert_eq!(
+            extended_length_path(Path::new(r"\\server\share\dir\file.txt")),
+            Path::new(r"\\?\UNC\server\share\dir\file.txt")
+        );
+        assert_eq!(
+            extended_length_path(Path::new(r"\\?\C:\file.txt")),
+            Path::new(r"\\?\C:\file.txt")
+        );
+        assert_eq!(
+            extended_length_path(Path::new(r"dir\file.txt")),
+            Path::new(r"dir\file.txt")
+        );
+    }
+}
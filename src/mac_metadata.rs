@@ -0,0 +1,97 @@
+#[cfg(target_os = "macos")]
+use crate::catalog;
+use std::path::Path;
+
+///
+/// Copy metadata that Finder and Gatekeeper rely on from src to dst:
+/// creation date, flags, ACL and extended attributes (e.g. Finder tags,
+/// Finder info and quarantine flag).
+///
+/// Metadata is copied with copyfile, which also copies mode bits
+/// and modification time. Failure is logged with WARN level.
+/// Other platforms have no such metadata.
+///
+#[cfg(target_os = "macos")]
+pub fn copy_mac_metadata(src: &Path, dst: &Path) {
+    use std::{ffi::CString, fs, os::unix::ffi::OsStrExt, ptr};
+
+    // Creation date is set first, copied mode can make dst read-only
+    let result = fs::metadata(src).and_then(|metadata| set_created(dst, metadata.created()?));
+    if let Err(err) = result {
+        log::warn!(
+            target: catalog::METADATA_NOT_PRESERVED,
+            "Failed to preserve creation date of {}; {err}",
+            dst.to_string_lossy()
+        );
+    }
+
+    let (Ok(src_path), Ok(dst_path)) = (
+        CString::new(src.as_os_str().as_bytes()),
+        CString::new(dst.as_os_str().as_bytes()),
+    ) else {
+        return;
+    };
+    // SAFETY: paths are NUL terminated strings and no state is used
+    let result = unsafe {
+        libc::copyfile(
+            src_path.as_ptr(),
+            dst_path.as_ptr(),
+            ptr::null_mut(),
+            libc::COPYFILE_METADATA | libc::COPYFILE_NOFOLLOW,
+        )
+    };
+    if result != 0 {
+        log::warn!(
+            target: catalog::METADATA_NOT_PRESERVED,
+            "Failed to preserve metadata of {}; {}",
+            dst.to_string_lossy(),
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn copy_mac_metadata(_src: &Path, _dst: &Path) {}
+
+#[cfg(target_os = "macos")]
+fn set_created(dst: &Path, created: std::time::SystemTime) -> std::io::Result<()> {
+    use std::{
+        fs::{File, FileTimes},
+        os::macos::fs::FileTimesExt,
+    };
+
+    File::options()
+        .write(true)
+        .open(dst)?
+        .set_times(FileTimes::new().set_created(created))
+}
+
+#[cfg(all(test, target_os = "macos"))]
+mod test {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn copy_mac_metadata_copies_tags_and_quarantine() {
+        let src = NamedTempFile::new().unwrap();
+        let dst = NamedTempFile::new().unwrap();
+        let tags = b"bplist00 red tag";
+        xattr::set(src.path(), "com.apple.metadata:_kMDItemUserTags", tags).unwrap();
+        xattr::set(src.path(), "com.apple.quarantine", b"0081;00000000;Safari;").unwrap();
+
+        copy_mac_metadata(src.path(), dst.path());
+
+        assert_eq!(
+            xattr::get(dst.path(), "com.apple.metadata:_kMDItemUserTags").unwrap(),
+            Some(tags.to_vec())
+        );
+        assert_eq!(
+            xattr::get(dst.path(), "com.apple.quarantine").unwrap(),
+            Some(b"0081;00000000;Safari;".to_vec())
+        );
+        assert_eq!(
+            std::fs::metadata(dst.path()).unwrap().created().unwrap(),
+            std::fs::metadata(src.path()).unwrap().created().unwrap()
+        );
+    }
+}
@@ -1,31 +1,221 @@
+// Configuration of the run is a single large json! literal
+#![recursion_limit = "256"]
+
+mod advice;
+mod anomalies;
+mod archive;
+mod budget;
+mod catalog;
+mod changes;
+mod databases;
+mod delta;
+mod drives;
+mod dst_template;
+mod duplicates;
+mod encryption;
 mod exclusions;
+mod exit_code;
+mod export_filters;
+mod file_types;
 mod files;
+mod files_from;
+mod filter;
+mod flatten;
+mod git;
+mod hash;
+mod html_report;
+mod ignore_patterns;
+mod import;
+mod include_paths;
+mod long_names;
+mod mac_metadata;
+mod manifest;
+mod mirror;
+mod moving;
+mod os_backup;
+mod output;
+mod overrides;
+mod owners;
+mod privileges;
+mod rate_limit;
+mod report;
+mod sanitize;
+mod state;
+mod statistics;
+mod streams;
 mod suffixes;
+mod temperature;
+mod verify;
+mod versions;
+mod volumes;
+mod xattrs;
 
 use anyhow::{anyhow, Result};
+use archive::{archive_files, parse_archive, Archive};
+use budget::select_within_budget;
 use byte_unit::Byte;
-use clap::Parser;
-use exclusions::read_exclusions;
-use files::{calculate_files_size, copy_files, find_files_to_copy};
-use std::path::PathBuf;
-use suffixes::read_suffixes;
+use catalog::{find_entry, run_error_id, CATALOG};
+use changes::{ChangeDetection, ChangePolicy};
+use clap::{
+    builder::{OsStringValueParser, TypedValueParser},
+    ArgAction, ArgGroup, Parser, Subcommand, ValueEnum,
+};
+use drives::{drive_directory_name, list_fixed_drives, ALL_FIXED_DRIVES};
+use dst_template::{parse_dst_template, DstTemplate};
+use duplicates::{find_duplicates, link_duplicates, remove_duplicates};
+use encryption::{parse_encryption, Encryption};
+use exclusions::{is_directory_path, parse_exclusions, read_exclusion_lines, Exclusions};
+use exit_code::{install_interrupt_handler, is_interrupted, ExitCode, RunError};
+use export_filters::{export_filters, FilterTool, SelectionRules};
+use file_types::FileType;
+use files::{
+    calculate_files_size, copy_files, destination_path, find_files_to_copy, find_outdated_files,
+    find_unreadable_files, is_same_filesystem, merge_included_files,
+    preserve_directory_permissions, preserve_directory_times, records_access_times,
+    sanitized_names, seal_directory, simulate_reads, CollisionPolicy, CompareMethod, CopyOptions,
+    CopyOrder, DestinationLayout, OverwritePolicy, ReflinkPolicy, SearchOptions, SizeMode,
+    SpecialFilePolicy, SymlinkPolicy,
+};
+use files_from::read_files_from;
+use filter::Filter;
+use flatten::flat_names;
+use git::{find_git_files, GitSelection};
+use globset::{Glob, GlobSetBuilder};
+use html_report::{write_html_report, RunReport};
+use import::{decode_log, detect_log_format, parse_log, BackupLogFormat};
+use include_paths::read_include_paths;
+use manifest::{
+    diff_configurations, fingerprint, read_configuration, write_manifest, CopyDetails,
+    MANIFEST_FILE,
+};
+use mirror::{delete_files, plan_deletions};
+use moving::remove_sources;
+use output::OutputFormat;
+use owners::{parse_group, parse_user};
+use privileges::enable_backup_privileges;
+use rate_limit::{parse_rate, RateLimiter};
+use report::{
+    report_advice, report_anomalies, report_catalog_entries, report_configuration_differences,
+    report_duplicates, report_estimate, report_skipped_duplicates,
+};
+use serde_json::{json, Value};
+use state::{read_state, remove_state, RunState, STATE_FILE};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+use suffixes::{parse_suffix, read_suffixes, remove_redundant_rules, to_lowercase, Rule, Suffixes};
+use temperature::TemperatureThrottle;
+use verify::{parse_verify_mode, recopy_different_files, verify_copied_files, VerifyMode};
 
 const COMMENT_LINE_PREFIX: &str = "//";
 
+/// Path that stands for standard input.
+const STDIN_PATH: &str = "-";
+
 /// Simple application that finds all files with specified
 /// suffixes and copies them to dst_directory.
 /// src_directory structure is preserved in dst_directory.
-#[derive(Parser)]
+#[derive(Parser, Default, Clone)]
+#[command(subcommand_negates_reqs = true)]
+// Copies are compared with sources in 'update' mode and by 'overwrite'
+#[command(group(ArgGroup::new("comparing").args(["update", "overwrite"]).multiple(true)))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Source directory.
     /// Files will be copied starting from this place.
-    #[arg(short, long)]
-    src_directory: PathBuf,
+    /// On Windows 'all-fixed-drives' copies files from every fixed drive
+    /// into subdirectories of 'dst_directory' named after drive letters.
+    #[arg(
+        short,
+        long,
+        required = true,
+        value_parser = OsStringValueParser::new().map(PathBuf::from)
+    )]
+    src_directory: Option<PathBuf>,
 
     /// Destination directory.
     /// All copied files will be copied here.
+    /// Required unless one of the commands is used.
     #[arg(short, long)]
-    dst_directory: PathBuf,
+    dst_directory: Option<PathBuf>,
+
+    /// Write selected files into archive instead of 'dst_directory',
+    /// preserving their paths relative to 'src_directory'
+    /// (e.g. 'tar:/backups/documents.tar', 'tar.gz:...', 'tar.zst:...',
+    /// 'zip:/backups/documents.zip' or 'tar.zst:-' for standard output).
+    /// Options that work with copies in 'dst_directory' can't be used with it.
+    #[arg(
+        long,
+        value_name = "ARCHIVE",
+        value_parser = parse_archive,
+        conflicts_with_all = [
+            "dst_directory",
+            "preserve",
+            "preserve_permissions",
+            "preserve_directory_permissions",
+            "preserve_dir_times",
+            "preserve_xattrs",
+            "preserve_acl",
+            "preserve_ads",
+            "preserve_metadata",
+            "consistent_databases",
+            "update",
+            "on_change",
+            "resume",
+            "backup_versions",
+            "mirror",
+            "seal",
+            "link",
+            "delta",
+            "flatten",
+            "dst_template",
+            "sanitize_names",
+            "verify",
+            "move_files",
+            "report_html",
+            "temp_dir",
+            "dedup_dst",
+        ]
+    )]
+    archive: Option<Archive>,
+
+    /// Compression level of compressed 'archive', from 0 (no compression)
+    /// to 9 for zip and gzip [default: 6] and from 1 to 22 for zstd [default: 3].
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        value_parser = clap::value_parser!(u8).range(0..=22),
+        requires = "archive"
+    )]
+    compression: Option<u8>,
+
+    /// Split tar 'archive' into numbered volumes of this size
+    /// (e.g. '4GB' for FAT32 sticks or '4.7GB' for DVDs) described
+    /// by 'NAME.volumes.json' manifest written next to them.
+    /// Compression is flushed after every file so its volumes are known,
+    /// which makes compressed archives of many small files bigger.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "archive")]
+    volume_size: Option<u64>,
+
+    /// Encrypt 'archive' with age before it's written, either for age public key
+    /// (e.g. 'age:age1ql3z7hjy54pw3hyww5ay...') or with 'passphrase'
+    /// read from SAVE_ME_FILES_PASSPHRASE environment variable.
+    #[arg(
+        long,
+        value_name = "ENCRYPTION",
+        value_parser = parse_encryption,
+        requires = "archive",
+        conflicts_with = "volume_size"
+    )]
+    encrypt: Option<Encryption>,
 
     /// Path to file that stores all suffixes that should be copied
     /// (e.g. '.txt', '.drawio.png' '_backup.txt').
@@ -33,93 +223,1901 @@ struct Args {
     #[arg(short, long)]
     include_suffixes_file: Option<PathBuf>,
 
+    /// Suffix that should be copied (e.g. '--suffix .txt --suffix .md'
+    /// or '--suffix .txt,.md'). Written the same way as lines of
+    /// 'include_suffixes_file' and added after them.
+    #[arg(
+        long = "suffix",
+        value_name = "SUFFIX",
+        value_parser = parse_suffix,
+        value_delimiter = ','
+    )]
+    suffixes: Vec<Rule>,
+
     /// Path to file that stores all excluded paths.
     /// If filepath to copy starts with one of the paths file is ignored.
-    /// Paths can be relative to 'src_directory' or absolute.
+    /// Paths need to be absolute paths to existing directories.
+    /// Other lines are globs with '.gitignore' rules (e.g. '/build',
+    /// 'cache/' or '**/node_modules') matched against paths
+    /// relative to 'src_directory'.
     /// Each path should be written in new line.
     #[arg(short, long)]
     exclude_paths_file: Option<PathBuf>,
 
+    /// Excluded path or glob. Can be repeated.
+    /// Exclusions follow the same rules as lines of 'exclude_paths_file'
+    /// and are added after them.
+    #[arg(long = "exclude", value_name = "EXCLUSION")]
+    excludes: Vec<String>,
+
+    /// Name of directories that are skipped at any depth
+    /// (e.g. 'node_modules'). Can be repeated.
+    #[arg(long = "exclude-dir-name", value_name = "NAME")]
+    exclude_dir_names: Vec<OsString>,
+
+    /// Path to file that stores paths of files and directories
+    /// that are always copied, regardless of suffixes.
+    /// Paths can be relative to 'src_directory' or absolute.
+    /// Each path should be written in new line.
+    #[arg(long)]
+    include_paths_file: Option<PathBuf>,
+
+    /// Path to file that stores list of files to copy
+    /// ('-' reads list from standard input).
+    /// Each path should be written in new line.
+    /// Files are not searched for when this option is present.
+    #[arg(long)]
+    files_from: Option<PathBuf>,
+
+    /// Paths in 'files_from' are separated with NUL character
+    /// instead of new line (e.g. output of 'find -print0').
+    #[arg(long, default_value_t = false, requires = "files_from")]
+    from0: bool,
+
+    /// Maximum depth of directory traversal.
+    /// Files directly inside 'src_directory' are at depth 1.
+    /// When not present whole directory tree is searched.
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// Copy only files tracked by git repository
+    /// that contains 'src_directory'.
+    #[arg(long, default_value_t = false, conflicts_with = "git_modified_only")]
+    git_tracked_only: bool,
+
+    /// Copy only files of git repository that contains 'src_directory'
+    /// which have uncommitted changes or are untracked and not ignored.
+    #[arg(long, default_value_t = false)]
+    git_modified_only: bool,
+
+    /// Compare suffixes with filenames ignoring case.
+    /// Enabled by default on Windows.
+    /// Can be disabled with '--case-insensitive=false'.
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_value_t = cfg!(windows),
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    case_insensitive: bool,
+
+    /// Skip directories containing CACHEDIR.TAG file
+    /// (https://bford.info/cachedir/). Enabled by default,
+    /// use '--skip-caches=false' to copy caches too.
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_value_t = true,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    skip_caches: bool,
+
+    /// Name of the marker file (e.g. '.nobackup').
+    /// Directories containing file with this name are skipped
+    /// together with everything inside them.
+    #[arg(long, value_name = "NAME")]
+    marker_file: Option<String>,
+
+    /// Don't descend into directories on other filesystems than
+    /// 'src_directory' (e.g. network or bind mounts).
+    #[arg(long, default_value_t = false)]
+    one_file_system: bool,
+
+    /// Leave out files that are locked by other processes
+    /// (e.g. opened by other applications on Windows), so they're
+    /// not planned for copying nor counted in needed space.
+    #[arg(long, default_value_t = false)]
+    skip_locked: bool,
+
+    /// Leave out cloud placeholders (OneDrive, Dropbox, iCloud files
+    /// available online only), so they're neither downloaded
+    /// nor counted in needed space. Supported on Windows and macOS.
+    #[arg(long, default_value_t = false)]
+    skip_placeholders: bool,
+
+    /// Read files regardless of their permissions, like backup software
+    /// does. On Windows it needs elevated process, on Linux it needs
+    /// CAP_DAC_READ_SEARCH capability (root or granted with 'setcap').
+    #[arg(long, default_value_t = false)]
+    backup_privileges: bool,
+
+    /// Copy only files accessed within given time (e.g. '90d', '1y').
+    /// Units are 'h', 'd', 'w', 'mo' (30 days) and 'y' (365 days).
+    /// Access times aren't updated on filesystems mounted with 'noatime'.
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    accessed_within: Option<Duration>,
+
+    /// Types of files that should be copied
+    /// (e.g. 'image,video,document').
+    /// Type is detected from file content, not its name.
+    /// When combined with suffixes file needs to match both.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    include_types: Vec<FileType>,
+
+    /// Expression that files need to satisfy to be copied
+    /// (e.g. 'size<100MB && (suffix:.docx || suffix:.xlsx) && !path:**/Archive/**').
+    /// Supported predicates: 'size' with '<', '<=', '>', '>=', '=',
+    /// 'suffix:', 'name:' (glob), 'path:' (glob relative to 'src_directory')
+    /// and 'type:'. Predicates are combined with '!', '&&', '||' and parentheses.
+    #[arg(long, value_parser = parse_filter)]
+    filter: Option<ParsedArg<Filter>>,
+
+    /// Copy only files owned by user (name or uid).
+    /// Supported only on Unix.
+    #[arg(long, value_parser = parse_user)]
+    owner: Option<u32>,
+
+    /// Copy only files owned by group (name or gid).
+    /// Supported only on Unix.
+    #[arg(long, value_parser = parse_group)]
+    group: Option<u32>,
+
+    /// What to do with symbolic links.
+    #[arg(long, value_enum, default_value_t = SymlinkPolicy::Skip)]
+    symlinks: SymlinkPolicy,
+
+    /// What to do with special files (FIFOs, sockets, devices).
+    /// They're never copied.
+    #[arg(long, value_enum, default_value_t = SpecialFilePolicy::Skip)]
+    special_files: SpecialFilePolicy,
+
     /// Disable copying.
     /// If present makes sure application stops before copying files.
     /// It's useful when someone wants to check what files will be copied.
     #[arg(long, default_value_t = false)]
     no_copy: bool,
+
+    /// Read all files that would be copied without writing them.
+    /// Unreadable files and achievable read throughput are reported
+    /// so it's a more accurate dry run than 'no_copy' alone.
+    #[arg(long, default_value_t = false, requires = "no_copy")]
+    simulate_io: bool,
+
+    /// Format of the data (list of files to copy, reports).
+    /// Data is written to standard output unless it's logged.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Log)]
+    output: OutputFormat,
+
+    /// Where logs are written.
+    #[arg(long, value_enum, default_value_t = LogTarget::Stderr)]
+    log_target: LogTarget,
+
+    /// Format of log lines. Warnings and errors have identifiers
+    /// (e.g. 'SMF-E012') explained by 'explain-error' command,
+    /// which RUST_LOG filters them by instead of module paths.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Check that files are readable before copying.
+    /// First and last block of every file is read so damaged
+    /// files are reported before copying starts.
+    #[arg(long, default_value_t = false)]
+    precheck_readable: bool,
+
+    /// Copy files even when they don't fit into 'dst_directory'.
+    /// Useful when it's known that space check is too pessimistic.
+    #[arg(long, default_value_t = false)]
+    ignore_space_check: bool,
+
+    /// Preserve access, modification and creation times of copied files
+    /// (also of databases backed up with 'consistent_databases').
+    /// Creation time is preserved on Windows and macOS only.
+    #[arg(long, visible_alias = "preserve-times", default_value_t = false)]
+    preserve: bool,
+
+    /// Give copied files the same permissions as their sources
+    /// (mode bits on Unix, read-only attribute on Windows).
+    #[arg(long, default_value_t = false)]
+    preserve_permissions: bool,
+
+    /// Give directories containing copied files the same permissions
+    /// as their source directories. They're set after all files are copied.
+    #[arg(long, default_value_t = false)]
+    preserve_directory_permissions: bool,
+
+    /// Set modification times of directories containing copied files
+    /// to those of their source directories after copying
+    /// (e.g. for photo managers that rely on folder dates).
+    #[arg(long, default_value_t = false)]
+    preserve_dir_times: bool,
+
+    /// Copy extended attributes (e.g. tags and comments) with files.
+    /// User attributes are always copied, others (e.g. SELinux contexts)
+    /// only where the process is permitted to set them. Unix only.
+    #[arg(long, default_value_t = false)]
+    preserve_xattrs: bool,
+
+    /// Copy access control lists (permissions of users and groups)
+    /// with files. Owners are preserved only when running as
+    /// administrator with 'backup_privileges'. Windows only.
+    #[arg(long, default_value_t = false)]
+    preserve_acl: bool,
+
+    /// Copy NTFS alternate data streams (e.g. 'Zone.Identifier')
+    /// with files also when they're copied in chunks or cloned.
+    /// Streams are copied only between NTFS drives. Windows only.
+    /// Plain copies always get the streams from the system,
+    /// also without this option.
+    #[arg(long, default_value_t = false)]
+    preserve_ads: bool,
+
+    /// Copy macOS metadata (creation dates, Finder tags, quarantine
+    /// flags and other extended attributes) with files, so restored
+    /// files behave like the originals. macOS only.
+    #[arg(long, default_value_t = false)]
+    preserve_metadata: bool,
+
+    /// Copy SQLite databases with their backup API so copies are
+    /// consistent even when databases are in use. Files of other
+    /// databases (SQL Server, MySQL, Access) are skipped when in use.
+    #[arg(long, default_value_t = false)]
+    consistent_databases: bool,
+
+    /// Skip files whose copies in 'dst_directory' have the same size
+    /// and modification time, so repeated runs copy only changed files.
+    /// Modification time of copies is set to that of sources.
+    #[arg(long, default_value_t = false)]
+    update: bool,
+
+    /// Names of files that only grow by appending, like logs
+    /// (e.g. '*.log'). In 'update' mode only their appended part
+    /// is copied when copy from previous run is their beginning.
+    #[arg(long, value_name = "GLOB", value_parser = Glob::new, requires = "update")]
+    append_only: Vec<Glob>,
+
+    /// Detect files that change after they're found or while they're
+    /// copied (e.g. written by other programs). 'recopy' copies them again
+    /// until they stop changing, 'skip' leaves them out and 'warn' reports them.
+    /// Copies that may be inconsistent are marked in the manifest.
+    #[arg(long, value_enum, value_name = "POLICY")]
+    on_change: Option<ChangePolicy>,
+
+    /// How copies are compared with sources in 'update' mode
+    /// and with 'overwrite if-different'. 'checksum' reads both files,
+    /// so it's slower but works when other tools rewrite modification times.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = CompareMethod::Metadata,
+        requires = "comparing"
+    )]
+    compare: CompareMethod,
+
+    /// What to do with files that already exist in 'dst_directory'.
+    /// 'if-newer' compares modification times and 'if-different'
+    /// compares sizes and modification times (or checksums, see 'compare').
+    #[arg(long, value_enum, default_value_t = OverwritePolicy::Always)]
+    overwrite: OverwritePolicy,
+
+    /// Continue interrupted run with the same configuration. Files
+    /// planned by that run are copied, except those that were finished.
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+
+    /// Keep up to N previous versions of copies that are overwritten
+    /// with different content as 'name.ext.~1~' (the most recent),
+    /// 'name.ext.~2~'... instead of destroying them.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    backup_versions: usize,
+
+    /// Make 'dst_directory' an exact mirror of selected files by deleting
+    /// files that are no longer selected. Files to delete are listed
+    /// before copying (use with 'no_copy' to preview them) and deleted
+    /// after copying only when confirmed.
+    #[arg(long, default_value_t = false, conflicts_with = "on_collision")]
+    mirror: bool,
+
+    /// Delete files in 'mirror' mode without asking for confirmation.
+    #[arg(long, default_value_t = false, requires = "mirror")]
+    yes: bool,
+
+    /// Move files deleted in 'mirror' mode to trash (recycle bin)
+    /// of the system instead of deleting them permanently,
+    /// so files deleted because of bad rules can be restored.
+    #[arg(long, default_value_t = false, requires = "mirror")]
+    use_trash: bool,
+
+    /// What to do when file that differs from its source already exists
+    /// in 'dst_directory'. 'rename' copies it as 'name (1).ext', 'name (2).ext'...
+    /// so several sources can be consolidated into one 'dst_directory'.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = CollisionPolicy::Overwrite,
+        conflicts_with = "overwrite"
+    )]
+    on_collision: CollisionPolicy,
+
+    /// Pause copying while destination drive is hotter than this
+    /// temperature in degrees Celsius. Drive temperature is read
+    /// only on Linux (SATA drives need 'drivetemp' module).
+    #[arg(long, value_name = "CELSIUS")]
+    max_drive_temperature: Option<f64>,
+
+    /// Maximum rate at which all files together are copied
+    /// (e.g. '50MB/s'), so copying to network drive doesn't
+    /// saturate the network.
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    limit_rate: Option<u64>,
+
+    /// Cooperate with backup facilities of the operating system.
+    /// On macOS 'dst_directory' is excluded from Time Machine and Spotlight,
+    /// on Windows outcome of the run is written to Application event log.
+    #[arg(long, default_value_t = false)]
+    os_backup_integration: bool,
+
+    /// Make 'dst_directory' and everything inside it read-only
+    /// after all files are copied successfully. Entries are also made
+    /// immutable where supported (like 'chattr +i' on Linux when run
+    /// with CAP_LINUX_IMMUTABLE, entry denying writes in ACL on Windows).
+    #[arg(long, default_value_t = false)]
+    seal: bool,
+
+    /// Create hard links to files instead of copying them when they're
+    /// on the same filesystem as 'dst_directory', other files are copied.
+    /// Links share content with sources, so changes of sources show in them.
+    #[arg(long, default_value_t = false, conflicts_with = "seal")]
+    link: bool,
+
+    /// Clone files instead of copying their content on filesystems
+    /// with copy-on-write support (Btrfs, XFS, APFS, ReFS). Clones
+    /// are created instantly and share space with sources until
+    /// either of them is changed.
+    /// 'auto' copies files that can't be cloned, 'always' fails them.
+    #[arg(long, value_enum, default_value_t = ReflinkPolicy::Never)]
+    reflink: ReflinkPolicy,
+
+    /// Update existing copies with only blocks that differ from sources,
+    /// found with rolling checksums like rsync does (e.g. of VM disks and
+    /// mail stores where small part of big file changes). Updated copy
+    /// is written to temporary file that replaces the old copy.
+    #[arg(long, default_value_t = false, conflicts_with = "link")]
+    delta: bool,
+
+    /// Copy all files directly into 'dst_directory' without recreating
+    /// directory structure. Files whose names clash are named by their
+    /// path relative to 'src_directory' (e.g. 'a/b/c.pdf' is copied
+    /// as 'a_b_c.pdf') and ' (N)' is added when that name is taken too.
+    #[arg(long, default_value_t = false, conflicts_with = "dst_template")]
+    flatten: bool,
+
+    /// Layout of copies in 'dst_directory' with tokens {date}, {time},
+    /// {hostname}, {rel_dir} (directory relative to 'src_directory'),
+    /// {rel_path} and {name}, e.g. '{date}/{rel_dir}/{name}'.
+    /// Date and time are local time of the start of the run.
+    /// Copies of files with the same name in different directories
+    /// overwrite each other unless {rel_dir} or {rel_path} is used.
+    /// It can't be used with 'mirror', which would delete copies
+    /// of previous runs.
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = parse_dst_template,
+        conflicts_with = "mirror"
+    )]
+    dst_template: Option<DstTemplate>,
+
+    /// Order in which files are copied. 'smallest-first' gets many
+    /// small files done first, 'largest-first' streams big files early
+    /// and 'by-directory' copies files of each directory together.
+    #[arg(long, value_enum, default_value_t = CopyOrder::Discovered)]
+    order: CopyOrder,
+
+    /// Replace characters that FAT32, exFAT and NTFS don't allow
+    /// in names (e.g. ':', '?', '*', trailing dots) with '_',
+    /// e.g. when copying to USB sticks. Names that become the same
+    /// get ' (N)' added. Original names are recorded in manifest
+    /// in 'dst_directory'.
+    #[arg(long, default_value_t = false)]
+    sanitize_names: bool,
+
+    /// Compare copied files with their sources after copying.
+    /// 'full' checks every file, 'sample:N%' checks random N percent
+    /// of files and checks all of them when any sampled file differs.
+    #[arg(long, value_name = "MODE", value_parser = parse_verify)]
+    verify: Option<ParsedArg<VerifyMode>>,
+
+    /// How many times copies that differ from their sources
+    /// are copied and compared again when 'verify' is used.
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "verify")]
+    verify_retries: usize,
+
+    /// Remove source files after they're copied (and verified when
+    /// 'verify' is used), then remove directories that became empty.
+    /// Files that failed to copy, differ from their copies or changed
+    /// while they were copied are kept.
+    #[arg(long = "move", default_value_t = false)]
+    move_files: bool,
+
+    /// Path to HTML file to write report of the run to. Report
+    /// contains summary, chart of copied sizes by extension, files that
+    /// failed to copy and links to copies. It's self-contained,
+    /// so it can be attached to a ticket.
+    #[arg(long, value_name = "PATH")]
+    report_html: Option<PathBuf>,
+
+    /// Directory where files are written before they are renamed
+    /// into place in 'dst_directory'. It should be on the same
+    /// filesystem as 'dst_directory', otherwise (and when it's not set)
+    /// temporary files are created next to copied files.
+    #[arg(long)]
+    temp_dir: Option<PathBuf>,
+
+    /// Space that has to remain free in 'dst_directory'
+    /// after copying (e.g. '10GiB').
+    #[arg(long, value_parser = parse_size, default_value = "0")]
+    reserve: u64,
+
+    /// Size of files used to check whether they fit in 'dst_directory'.
+    /// Holes of sparse files (e.g. VM images) are preserved
+    /// on Linux, so 'allocated' estimates their space better.
+    #[arg(long, value_enum, default_value_t = SizeMode::Apparent)]
+    size_mode: SizeMode,
+
+    /// Maximum size of all copied files (e.g. '64GB').
+    /// When selected files are bigger, files matching suffixes
+    /// that come later in 'include_suffixes_file' are dropped first.
+    #[arg(long, value_parser = parse_size)]
+    max_total_size: Option<u64>,
+
+    /// Copy only one of the files with identical content.
+    /// Skipped duplicates are reported with 'output' format.
+    #[arg(long)]
+    dedup_source: bool,
+
+    /// Replace copies with identical content with hard links
+    /// to one of them after files are copied. Only copies with the same
+    /// metadata (e.g. modification time) are linked, because links share it.
+    /// Links are recorded in manifest.
+    #[arg(long)]
+    dedup_dst: bool,
+}
+
+///
+/// Value of argument together with text it was parsed from,
+/// so configuration of the run records arguments as they were written.
+///
+#[derive(Clone)]
+struct ParsedArg<T> {
+    text: String,
+    value: T,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum LogTarget {
+    #[default]
+    Stderr,
+    Stdout,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// JSON object per line with time, level, identifier and message.
+    Json,
+}
+
+#[derive(Clone, Subcommand)]
+enum Command {
+    /// Analyze files that would be copied instead of copying them.
+    Report {
+        #[arg(value_enum)]
+        kind: ReportKind,
+    },
+    /// Print cause and remediation of warning or error with identifier
+    /// (e.g. 'SMF-E012'). Lists all identifiers when it's not present.
+    /// Doesn't require 'src_directory'.
+    ExplainError { id: Option<String> },
+    /// Compare configurations stored in manifests of two runs.
+    /// Doesn't require 'src_directory'.
+    DiffConfig {
+        /// Manifest of the first run or dst_directory containing it.
+        first: PathBuf,
+        /// Manifest of the second run or dst_directory containing it.
+        second: PathBuf,
+    },
+    /// Write manifest to dst_directory for backup made by rsync or robocopy
+    /// from log of the run that made it, so the backup can be used like
+    /// one made by this application. Doesn't require 'src_directory'
+    /// when robocopy log contains it.
+    ImportManifest {
+        /// Log of rsync run with '--itemize-changes' or of robocopy run.
+        log: PathBuf,
+        /// Format of the log. It's detected from its content when not present.
+        #[arg(long, value_enum)]
+        format: Option<BackupLogFormat>,
+    },
+    /// Write rules selecting files (suffixes, exclusions and always copied
+    /// paths) as filter file of other backup tool, so both tools can use
+    /// the same rules. Rules the tool can't express are reported.
+    ExportFilters {
+        #[arg(value_enum)]
+        tool: FilterTool,
+        /// File to write filters to. They're written to standard output
+        /// when it's not present.
+        file: Option<PathBuf>,
+    },
+    /// Search for and select files like a regular run and report
+    /// their number and size without copying them.
+    /// Doesn't require 'dst_directory'.
+    Estimate,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportKind {
+    /// Group files by content and report duplicate clusters
+    /// with the number of wasted bytes.
+    Duplicates,
+    /// Suggest directories or files to leave out so that files
+    /// fit into dst_directory. Requires dst_directory.
+    Advise,
+    /// Report number and size of files per extension and flag
+    /// suspicious files: extensions not matching content, empty files
+    /// matching suffixes and many recent files sharing modification time.
+    Anomalies,
 }
 
-fn main() -> Result<()> {
-    env_logger::builder()
-        .target(env_logger::Target::Stdout)
+fn main() -> process::ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(err) => {
+            let _ = err.print();
+            let code = if err.use_stderr() {
+                ExitCode::InvalidArguments
+            } else {
+                // Help and version are not errors
+                ExitCode::Success
+            };
+            return code.into();
+        }
+    };
+
+    let log_target = match args.log_target {
+        LogTarget::Stderr => env_logger::Target::Stderr,
+        LogTarget::Stdout => env_logger::Target::Stdout,
+    };
+    let mut logger = env_logger::builder();
+    logger
+        .target(log_target)
         .filter_level(log::LevelFilter::Info)
-        .parse_default_env()
-        .init();
+        .parse_default_env();
+    if let LogFormat::Json = args.log_format {
+        logger.format(|buf, record| {
+            let id = find_entry(record.target()).map(|entry| entry.id);
+            let line = json!({
+                "time": buf.timestamp().to_string(),
+                "level": record.level().as_str(),
+                "id": id,
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{line}")
+        });
+    }
+    logger.init();
+
+    if let Err(err) = install_interrupt_handler() {
+        log::warn!(
+            target: catalog::INTERRUPT_HANDLER_FAILED,
+            "Failed to install Ctrl+C handler; {err}"
+        );
+    }
+
+    // Only runs that copy files are reported
+    let os_backup_integration = args.os_backup_integration && args.command.is_none();
+    let (code, message) = match run(args) {
+        Ok(code) => (code, format!("Run finished with exit code {}", code as u8)),
+        Err(err) => {
+            log::error!(target: run_error_id(err.code), "{err}");
+            (err.code, err.to_string())
+        }
+    };
+    if os_backup_integration {
+        if let Err(err) = os_backup::report_run_to_os(code, &message) {
+            log::warn!(
+                target: catalog::OS_BACKUP_INTEGRATION_FAILED,
+                "Failed to report run to the operating system; {err}"
+            );
+        }
+    }
+    code.into()
+}
+
+fn run(args: Args) -> Result<ExitCode, RunError> {
+    match &args.command {
+        Some(Command::DiffConfig { first, second }) => {
+            return run_diff_config(first, second, args.output);
+        }
+        Some(Command::ExplainError { id }) => return run_explain_error(id.as_deref(), args.output),
+        Some(Command::ImportManifest { log, format }) => {
+            return run_import_manifest(&args, log, *format);
+        }
+        _ => {}
+    }
+    if args
+        .src_directory
+        .as_ref()
+        .is_none_or(|src_directory| src_directory.as_os_str() != ALL_FIXED_DRIVES)
+    {
+        return run_source(args);
+    }
+
+    if args.files_from.is_some() {
+        return Err(RunError::new(
+            ExitCode::InvalidArguments,
+            anyhow!("files_from can't be used with '{ALL_FIXED_DRIVES}'"),
+        ));
+    }
+    if let Some(dst_directory) = args.dst_directory.as_ref().filter(|dst| !dst.is_dir()) {
+        return Err(RunError::new(
+            ExitCode::InvalidArguments,
+            anyhow!(
+                "dst_directory '{}' is not a directory",
+                dst_directory.to_string_lossy()
+            ),
+        ));
+    }
+    let drives =
+        list_fixed_drives().map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+
+    let mut code = ExitCode::Success;
+    for drive in drives {
+        log::info!("Processing drive {}", drive.to_string_lossy());
+        let mut drive_args = args.clone();
+        drive_args.src_directory = Some(drive.clone());
+        let result = match &args.dst_directory {
+            Some(dst_directory) => {
+                let drive_dst_directory = dst_directory.join(drive_directory_name(&drive));
+                fs::create_dir_all(&drive_dst_directory)
+                    .map_err(RunError::from)
+                    .and_then(|()| {
+                        drive_args.dst_directory = Some(drive_dst_directory);
+                        run_source(drive_args)
+                    })
+            }
+            None => run_source(drive_args),
+        };
+
+        // Failure of one drive doesn't stop backup of the other drives
+        match result {
+            Ok(ExitCode::Success) => {}
+            Ok(ExitCode::Interrupted) => return Ok(ExitCode::Interrupted),
+            Err(err) if err.code == ExitCode::Interrupted => return Err(err),
+            Ok(_) => code = ExitCode::PartialCopyFailure,
+            Err(err) => {
+                log::error!(
+                    target: run_error_id(err.code),
+                    "Failed to process drive {}; {err}",
+                    drive.to_string_lossy()
+                );
+                code = ExitCode::PartialCopyFailure;
+            }
+        }
+    }
+
+    Ok(code)
+}
 
-    let mut args = Args::parse();
-    args = canonicalize_args(args)?;
+fn run_source(args: Args) -> Result<ExitCode, RunError> {
+    let args =
+        canonicalize_args(args).map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+    // canonicalize_args checks that it's given
+    let src_directory = args.src_directory.clone().unwrap();
 
-    let suffixes = args
+    let has_suffixes = args.include_suffixes_file.is_some() || !args.suffixes.is_empty();
+    let mut suffixes = args
         .include_suffixes_file
+        .as_ref()
         .map(|path| {
             log::info!("Reading suffixes from {}", path.to_string_lossy());
             read_suffixes(path)
         })
-        .unwrap_or_else(|| Ok(vec!["".to_string()]))?;
-    let exclusions = args
+        .unwrap_or_else(|| Ok(Suffixes::default()))
+        .map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+    suffixes.rules.extend(args.suffixes.iter().cloned());
+    if !has_suffixes {
+        suffixes = Suffixes::any();
+    }
+    if args.case_insensitive {
+        suffixes = to_lowercase(suffixes);
+    }
+    let suffixes = remove_redundant_rules(suffixes);
+    if has_suffixes {
+        log::info!("Effective suffixes: {}", rules_to_string(&suffixes.rules));
+        for scope in suffixes.scopes.iter() {
+            log::info!(
+                "Effective suffixes in {}: {}",
+                scope.pattern,
+                rules_to_string(&scope.rules)
+            );
+        }
+    }
+    let mut exclusion_lines = args
         .exclude_paths_file
+        .as_ref()
         .map(|path| {
             log::info!("Reading exclusions from {}", path.to_string_lossy());
-            read_exclusions(path)
+            read_exclusion_lines(path)
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))
+        .map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+    exclusion_lines.extend(args.excludes.iter().cloned());
+    let mut exclusions = parse_exclusions(exclusion_lines.clone());
+    exclusions
+        .directory_names
+        .extend(args.exclude_dir_names.iter().cloned());
+    if !exclusions.paths.is_empty()
+        || !exclusions.patterns.is_empty()
+        || !exclusions.directory_names.is_empty()
+    {
+        log::info!(
+            "Effective exclusions: {} paths, {} patterns, {} directory names",
+            exclusions.paths.len(),
+            exclusions.patterns.len(),
+            exclusions.directory_names.len()
+        );
+    }
+    let include_paths = args
+        .include_paths_file
+        .as_ref()
+        .map(|path| {
+            log::info!("Reading included paths from {}", path.to_string_lossy());
+            read_include_paths(path, &src_directory)
         })
-        .unwrap_or_else(|| Ok(Vec::new()))?;
+        .unwrap_or_else(|| Ok(Vec::new()))
+        .map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+    if let Some(Command::ExportFilters { tool, file }) = &args.command {
+        let rules = SelectionRules {
+            src_directory: &src_directory,
+            suffixes: &suffixes,
+            case_insensitive: args.case_insensitive,
+            exclusion_patterns: &exclusions.patterns,
+            excluded_paths: &exclusions.paths,
+            excluded_directory_names: exclusions.directory_names.iter().collect(),
+            include_paths: &include_paths,
+        };
+        let filters = export_filters(*tool, &rules);
+        match file {
+            Some(file) => {
+                fs::write(file, filters).map_err(|err| {
+                    RunError::new(
+                        ExitCode::Failure,
+                        anyhow!("Failed to write '{}'; {err}", file.to_string_lossy()),
+                    )
+                })?;
+                log::info!("Filters written to {}", file.to_string_lossy());
+            }
+            None => io::stdout().write_all(filters.as_bytes())?,
+        }
+        return Ok(ExitCode::Success);
+    }
+    let configuration = run_configuration(&args, &suffixes, &exclusion_lines, &exclusions);
+    log::info!("Configuration fingerprint: {}", fingerprint(&configuration));
 
-    log::info!(
-        "Searching for files to copy starting at {}",
-        args.src_directory.to_string_lossy()
-    );
-    let files_to_copy = find_files_to_copy(&args.src_directory, &suffixes, &exclusions);
-    for file_path in files_to_copy.iter() {
-        log::info!("Will copy: {}", file_path.to_string_lossy());
+    if args.backup_privileges {
+        match enable_backup_privileges() {
+            Ok(()) => log::info!("Backup privileges enabled"),
+            Err(err) => log::warn!(
+                target: catalog::BACKUP_PRIVILEGES_UNAVAILABLE,
+                "Failed to enable backup privileges; {err}"
+            ),
+        }
+    }
+    if args.accessed_within.is_some() && !records_access_times(&src_directory) {
+        log::warn!(
+            target: catalog::ACCESS_TIMES_NOT_RECORDED,
+            "src_directory is on filesystem mounted with 'noatime'; \
+             access times don't show which files are used"
+        );
+    }
+    let search_options = SearchOptions {
+        max_depth: args.max_depth,
+        symlinks: args.symlinks,
+        special_files: args.special_files,
+        case_insensitive: args.case_insensitive,
+        file_types: args.include_types,
+        filter: args.filter.map(|filter| filter.value),
+        owner: args.owner,
+        group: args.group,
+        skip_caches: args.skip_caches,
+        marker_file: args.marker_file,
+        one_file_system: args.one_file_system,
+        skip_locked: args.skip_locked,
+        skip_placeholders: args.skip_placeholders,
+        accessed_after: args.accessed_within.map(|age| {
+            SystemTime::now()
+                .checked_sub(age)
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }),
+    };
+    let mut files_to_copy = match &args.files_from {
+        Some(files_from) => {
+            let separator = if args.from0 { b'\0' } else { b'\n' };
+            if files_from.as_os_str() == STDIN_PATH {
+                log::info!("Reading files to copy from standard input");
+                read_files_from(io::stdin(), separator, &src_directory)?
+            } else {
+                log::info!(
+                    "Reading files to copy from {}",
+                    files_from.to_string_lossy()
+                );
+                read_files_from(File::open(files_from)?, separator, &src_directory)?
+            }
+        }
+        None => {
+            log::info!(
+                "Searching for files to copy starting at {}",
+                src_directory.to_string_lossy()
+            );
+            find_files_to_copy(&src_directory, &suffixes, &exclusions, &search_options)?
+        }
+    };
+    let git_selection = match (args.git_tracked_only, args.git_modified_only) {
+        (true, _) => Some(GitSelection::Tracked),
+        (_, true) => Some(GitSelection::Modified),
+        _ => None,
+    };
+    if let Some(git_selection) = git_selection {
+        log::info!("Reading files selected from git repository");
+        let git_files = find_git_files(&src_directory, git_selection)
+            .map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+        files_to_copy.retain(|path| git_files.contains(path));
+    }
+    merge_included_files(&mut files_to_copy, &include_paths, &search_options);
+    if is_interrupted() {
+        return Err(RunError::new(
+            ExitCode::Interrupted,
+            anyhow!("Interrupted while searching for files"),
+        ));
+    }
+
+    if let Some(Command::Report { kind }) = args.command {
+        match kind {
+            ReportKind::Duplicates => report_duplicates(&files_to_copy, args.output),
+            ReportKind::Anomalies => report_anomalies(&files_to_copy, has_suffixes, args.output),
+            ReportKind::Advise => {
+                let dst_directory = args
+                    .dst_directory
+                    .expect("dst_directory is validated by canonicalize_args");
+                let needed_space = calculate_files_size(&files_to_copy, args.size_mode);
+                let available_space = read_usable_space(&dst_directory, args.reserve);
+                report_advice(
+                    &src_directory,
+                    &files_to_copy,
+                    needed_space,
+                    available_space,
+                    args.output,
+                );
+            }
+        }
+        return Ok(ExitCode::Success);
+    }
+
+    if args.dedup_source {
+        log::info!("Looking for files with identical content");
+        let clusters = find_duplicates(&files_to_copy);
+        let removed = remove_duplicates(&mut files_to_copy, &clusters);
+        if removed > 0 {
+            report_skipped_duplicates(&clusters, args.output);
+            let saved_bytes = clusters
+                .iter()
+                .map(|cluster| cluster.wasted_bytes())
+                .sum::<u64>();
+            log::info!(
+                "Skipping {removed} duplicated files ({})",
+                Byte::from_bytes(saved_bytes as u128).get_appropriate_unit(true)
+            );
+        }
+    }
+
+    if let Some(max_total_size) = args.max_total_size {
+        // Files that don't match any suffix are included paths which are always copied
+        let selection = select_within_budget(files_to_copy, max_total_size, |path| {
+            let relative_path = path.strip_prefix(&src_directory).unwrap();
+            let priority = match args.case_insensitive {
+                true => suffixes.priority(relative_path.to_string_lossy().to_lowercase()),
+                false => suffixes.priority(relative_path),
+            };
+            priority.unwrap_or(0)
+        });
+        files_to_copy = selection.selected_files;
+        if !selection.dropped_files.is_empty() {
+            args.output.paths("Over budget", &selection.dropped_files);
+            log::warn!(
+                target: catalog::OVER_BUDGET,
+                "{} files ({}) don't fit into max_total_size {} and won't be copied; \
+                 {} of selected files will be copied",
+                selection.dropped_files.len(),
+                Byte::from_bytes(selection.dropped_size as u128).get_appropriate_unit(true),
+                Byte::from_bytes(max_total_size as u128).get_appropriate_unit(true),
+                Byte::from_bytes(selection.selected_size as u128).get_appropriate_unit(true)
+            );
+        }
+    }
+
+    if let Some(Command::Estimate) = args.command {
+        report_estimate(&src_directory, &files_to_copy, args.output);
+        return Ok(ExitCode::Success);
+    }
+
+    args.output.paths("Will copy", &files_to_copy);
+    // States are recorded right after files are found to notice changes made before copying
+    let change_detection = args
+        .on_change
+        .map(|policy| ChangeDetection::new(policy, &files_to_copy));
+
+    if args.precheck_readable {
+        log::info!("Checking if files are readable");
+        let unreadable_files = find_unreadable_files(&files_to_copy);
+        for (file_path, err) in unreadable_files.iter() {
+            log::warn!(
+                target: catalog::UNREADABLE_FILE,
+                "Unreadable: {}; {err}",
+                file_path.to_string_lossy()
+            );
+        }
+        log::info!(
+            "{} of {} files are unreadable",
+            unreadable_files.len(),
+            files_to_copy.len()
+        );
     }
 
-    let needed_space = calculate_files_size(&files_to_copy);
-    let available_space = fs4::available_space(&args.dst_directory).expect(&format!(
-        "Failed to read available space at {}",
-        args.dst_directory.to_string_lossy()
-    ));
-    if needed_space > available_space {
+    if let Some(archive) = &args.archive {
+        if args.no_copy {
+            log::info!("Archiving skipped");
+            return Ok(ExitCode::Success);
+        }
+        log::info!("Archiving files");
+        let summary = archive_files(
+            &src_directory,
+            &files_to_copy,
+            archive,
+            args.compression,
+            args.volume_size,
+            args.encrypt.as_ref(),
+            args.symlinks,
+        )
+        .map_err(|err| RunError::new(ExitCode::Failure, err))?;
+        log::info!(
+            "Archived {} files ({}); failed to archive {} files",
+            summary.archived_files,
+            Byte::from_bytes(summary.archived_bytes as u128).get_appropriate_unit(true),
+            summary.failed_files.len()
+        );
+        return Ok(match summary.failed_files.is_empty() {
+            true => ExitCode::Success,
+            false => ExitCode::PartialCopyFailure,
+        });
+    }
+
+    let dst_directory = args
+        .dst_directory
+        .expect("dst_directory is validated by canonicalize_args");
+    let mut finished_files = HashMap::new();
+    if args.resume {
+        let resumed_run = read_state(&src_directory, &dst_directory, &fingerprint(&configuration))
+            .map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+        log::info!(
+            "Resuming interrupted run; {} of {} planned files are already finished",
+            resumed_run.finished_files.len(),
+            resumed_run.planned_files.len()
+        );
+        files_to_copy = resumed_run.planned_files;
+        finished_files = resumed_run.finished_files;
+    }
+    let layout = match (&args.dst_template, args.flatten) {
+        (Some(template), _) => DestinationLayout::Template(template.clone()),
+        (None, true) => DestinationLayout::Flat(flat_names(&src_directory, &files_to_copy)),
+        (None, false) => DestinationLayout::Tree,
+    };
+    let sanitized_names = args
+        .sanitize_names
+        .then(|| sanitized_names(&src_directory, &files_to_copy, &layout));
+    let pending_files = files_to_copy
+        .iter()
+        .filter(|path| !finished_files.contains_key(*path))
+        .cloned()
+        .collect::<Vec<_>>();
+    // Copies that are up to date won't be written again
+    let files_to_write = match args.update {
+        true => find_outdated_files(
+            &src_directory,
+            &dst_directory,
+            &pending_files,
+            &layout,
+            sanitized_names.as_ref(),
+        ),
+        false => pending_files.clone(),
+    };
+    let needed_space = calculate_files_size(&files_to_write, args.size_mode);
+    let available_space = read_usable_space(&dst_directory, args.reserve);
+    if needed_space > available_space && args.ignore_space_check {
+        log::warn!(
+            target: catalog::SPACE_CHECK_IGNORED,
+            "There's not enough space to copy all files; ignoring space check"
+        );
+    } else if needed_space > available_space {
+        report_advice(
+            &src_directory,
+            &files_to_write,
+            needed_space,
+            available_space,
+            args.output,
+        );
         let needed_space = Byte::from_bytes(needed_space as u128).get_appropriate_unit(true);
         let available_space = Byte::from_bytes(available_space as u128).get_appropriate_unit(true);
-        return Err(anyhow!(
-            "There's not enough space to copy all files! Needed space {}, available space {}",
-            needed_space,
-            available_space
+        let reserve = Byte::from_bytes(args.reserve as u128).get_appropriate_unit(true);
+        return Err(RunError::new(
+            ExitCode::NotEnoughSpace,
+            anyhow!(
+                "There's not enough space to copy all files! Needed space {}, available space {} ({} reserved)",
+                needed_space,
+                available_space,
+                reserve
+            ),
         ));
     }
 
+    if args.no_copy && args.simulate_io {
+        log::info!("Reading files without copying them");
+        let simulation = simulate_reads(&files_to_copy);
+        for (file_path, err) in simulation.unreadable_files.iter() {
+            log::warn!(
+                target: catalog::UNREADABLE_FILE,
+                "Unreadable: {}; {err}",
+                file_path.to_string_lossy()
+            );
+        }
+        let seconds = simulation.elapsed.as_secs_f64();
+        let throughput = if seconds > 0.0 {
+            simulation.read_bytes as f64 / seconds
+        } else {
+            0.0
+        };
+        log::info!(
+            "Read {} in {:.1}s ({}/s); {} of {} files are unreadable",
+            Byte::from_bytes(simulation.read_bytes as u128).get_appropriate_unit(true),
+            seconds,
+            Byte::from_bytes(throughput as u128).get_appropriate_unit(true),
+            simulation.unreadable_files.len(),
+            files_to_copy.len()
+        );
+        if is_interrupted() {
+            return Err(RunError::new(
+                ExitCode::Interrupted,
+                anyhow!("Interrupted while reading files"),
+            ));
+        }
+        if !simulation.unreadable_files.is_empty() {
+            return Ok(ExitCode::PartialCopyFailure);
+        }
+    }
+
+    let mut deletions = Vec::new();
+    if args.mirror {
+        let kept_files = files_to_copy
+            .iter()
+            .map(|path| {
+                destination_path(
+                    &src_directory,
+                    &dst_directory,
+                    path,
+                    &layout,
+                    sanitized_names.as_ref(),
+                )
+                .0
+            })
+            .collect();
+        deletions = plan_deletions(&dst_directory, &kept_files);
+        args.output.paths("Will delete", &deletions);
+    }
+
     if args.no_copy {
         log::info!("Copying skipped");
-        return Ok(());
+        return Ok(ExitCode::Success);
+    }
+
+    if !deletions.is_empty() && !args.yes && !confirm_deletions(deletions.len()) {
+        log::warn!(
+            target: catalog::MIRROR_NOT_CONFIRMED,
+            "Deletion of {} files wasn't confirmed; they won't be deleted",
+            deletions.len()
+        );
+        deletions.clear();
     }
 
     log::info!("Copying files");
-    copy_files(&args.src_directory, &args.dst_directory, &files_to_copy);
+    if args.link && !is_same_filesystem(&src_directory, &dst_directory) {
+        log::info!(
+            "src_directory is on different filesystem than dst_directory; \
+             files will be copied instead of linked"
+        );
+    }
+    if let Some(temp_dir) = &args.temp_dir {
+        if !is_same_filesystem(temp_dir, &dst_directory) {
+            log::warn!(
+                target: catalog::TEMP_DIR_ON_OTHER_FILESYSTEM,
+                "temp_dir '{}' is on different filesystem than dst_directory; \
+                 temporary files will be created next to copied files",
+                temp_dir.to_string_lossy()
+            );
+        }
+    }
+    if args.os_backup_integration {
+        if let Err(err) = os_backup::exclude_from_os_backup(&dst_directory) {
+            log::warn!(
+                target: catalog::OS_BACKUP_INTEGRATION_FAILED,
+                "Failed to exclude dst_directory from backups of the operating system; {err}"
+            );
+        }
+    }
+    let temperature_throttle = args.max_drive_temperature.and_then(|max_temperature| {
+        let throttle = TemperatureThrottle::new(&dst_directory, max_temperature);
+        if throttle.is_none() {
+            log::warn!(
+                target: catalog::TEMPERATURE_UNAVAILABLE,
+                "Temperature of destination drive can't be read; copying won't be throttled"
+            );
+        }
+        throttle
+    });
+    let append_only = match args.append_only.is_empty() {
+        true => None,
+        false => {
+            let mut globs = GlobSetBuilder::new();
+            for glob in &args.append_only {
+                globs.add(glob.clone());
+            }
+            let globs = globs
+                .build()
+                .map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+            Some(globs)
+        }
+    };
+    let state = match args.resume {
+        true => RunState::append(&src_directory, &dst_directory),
+        false => RunState::create(
+            &src_directory,
+            &dst_directory,
+            &fingerprint(&configuration),
+            &files_to_copy,
+        ),
+    };
+    let state = match state {
+        Ok(state) => Some(state),
+        Err(err) => {
+            log::warn!(
+                target: catalog::STATE_NOT_WRITTEN,
+                "Failed to write {STATE_FILE}; run can't be resumed; {err}"
+            );
+            None
+        }
+    };
+    let mut copy_options = CopyOptions {
+        symlinks: args.symlinks,
+        temp_directory: args.temp_dir,
+        preserve_timestamps: args.preserve,
+        consistent_databases: args.consistent_databases,
+        temperature_throttle,
+        rate_limiter: args.limit_rate.map(RateLimiter::new),
+        update: args.update,
+        compare: args.compare,
+        overwrite: args.overwrite,
+        on_collision: args.on_collision,
+        append_only,
+        change_detection,
+        preserve_permissions: args.preserve_permissions,
+        preserve_xattrs: args.preserve_xattrs,
+        preserve_acl: args.preserve_acl,
+        preserve_ads: args.preserve_ads,
+        preserve_metadata: args.preserve_metadata,
+        link: args.link,
+        reflink: args.reflink,
+        delta: args.delta,
+        layout,
+        order: args.order,
+        sanitize_names: sanitized_names,
+        backup_versions: args.backup_versions,
+        state,
+    };
+    let mut failed_files = Vec::new();
+    let mut up_to_date_files = 0;
+    let mut overwritten_files = 0;
+    let mut kept_files = 0;
+    let mut renamed_files = 0;
+    let mut appended_files = HashMap::new();
+    let mut changed_files = HashSet::new();
+    let mut original_paths = HashMap::new();
+    let mut copied_files = finished_files
+        .into_iter()
+        .filter_map(|(src_path, dst_path)| Some((src_path, dst_path?)))
+        .collect::<Vec<_>>();
+    let mut remaining_files = pending_files;
+    loop {
+        let summary = copy_files(
+            &src_directory,
+            &dst_directory,
+            &remaining_files,
+            &copy_options,
+        );
+        failed_files.extend(summary.failed_files);
+        up_to_date_files += summary.up_to_date_files;
+        overwritten_files += summary.overwritten_files;
+        kept_files += summary.kept_files;
+        renamed_files += summary.renamed_files;
+        appended_files.extend(summary.appended_files);
+        changed_files.extend(summary.changed_files);
+        copied_files.extend(summary.copied_files);
+        if !summary.changed_names.is_empty() {
+            log::warn!(
+                target: catalog::NAMES_SHORTENED,
+                "Changed names of {} files that were too long or invalid; original names are in {MANIFEST_FILE}",
+                summary.changed_names.len()
+            );
+        }
+        original_paths.extend(summary.changed_names);
+        if summary.out_of_space_files.is_empty() {
+            break;
+        }
+
+        remaining_files = summary.out_of_space_files;
+        let needed_space = calculate_files_size(&remaining_files, args.size_mode);
+        let available_space = read_usable_space(&dst_directory, args.reserve);
+        let missing_space = Byte::from_bytes(needed_space.saturating_sub(available_space) as u128)
+            .get_appropriate_unit(true);
+        let needed_space = Byte::from_bytes(needed_space as u128).get_appropriate_unit(true);
+        log::error!(
+            target: catalog::OUT_OF_SPACE,
+            "{} files ({}) are left to copy; {} more space is needed",
+            remaining_files.len(),
+            needed_space,
+            missing_space
+        );
+        if !wait_for_free_space() {
+            return Err(RunError::new(
+                ExitCode::NotEnoughSpace,
+                anyhow!(
+                    "dst_directory ran out of space; {} files were not copied",
+                    remaining_files.len()
+                ),
+            ));
+        }
+        log::info!("Copying remaining files");
+    }
 
-    Ok(())
+    if args.update {
+        log::info!(
+            "Copied {} files; skipped {} files that were up to date",
+            copied_files.len(),
+            up_to_date_files
+        );
+    }
+    if overwritten_files > 0 || kept_files > 0 {
+        log::info!(
+            "Overwrote {} existing files; kept {} existing files",
+            overwritten_files,
+            kept_files
+        );
+    }
+    if !appended_files.is_empty() {
+        log::info!(
+            "Copied only appended part of {} append-only files",
+            appended_files.len()
+        );
+    }
+    if !changed_files.is_empty() {
+        log::info!(
+            "{} files changed while they were copied; they're marked in {MANIFEST_FILE}",
+            changed_files.len()
+        );
+    }
+    if renamed_files > 0 {
+        log::info!(
+            "Copied {} files under new names because different files already existed",
+            renamed_files
+        );
+    }
+    // State is closed before it's removed
+    copy_options.state = None;
+    if is_interrupted() {
+        log::info!("Run can be continued with '--resume'");
+        return Err(RunError::new(
+            ExitCode::Interrupted,
+            anyhow!("Interrupted before all files were copied"),
+        ));
+    }
+    match failed_files.is_empty() {
+        true => {
+            if let Err(err) = remove_state(&dst_directory) {
+                log::warn!(
+                    target: catalog::STATE_NOT_WRITTEN,
+                    "Failed to remove {STATE_FILE} of finished run; {err}"
+                );
+            }
+        }
+        false => log::info!("Failed files can be copied again with '--resume'"),
+    }
+    if !deletions.is_empty() {
+        let deleted_files = delete_files(&dst_directory, &deletions, args.use_trash);
+        log::info!(
+            "Deleted {} of {} files that are no longer selected",
+            deleted_files,
+            deletions.len()
+        );
+    }
+    let mut linked_files = HashMap::new();
+    if args.dedup_dst {
+        log::info!("Looking for copies with identical content");
+        let copies = copied_files
+            .iter()
+            .map(|(_, dst_path)| dst_path.clone())
+            .collect::<Vec<_>>();
+        let clusters = find_duplicates(&copies);
+        linked_files = link_duplicates(&clusters);
+        if !linked_files.is_empty() {
+            // Duplicates with different metadata aren't linked
+            let saved_bytes = clusters
+                .iter()
+                .flat_map(|cluster| {
+                    cluster.paths[1..]
+                        .iter()
+                        .filter(|path| linked_files.contains_key(*path))
+                        .map(|_| cluster.file_size)
+                })
+                .sum::<u64>();
+            log::info!(
+                "Replaced {} identical copies with hard links ({})",
+                linked_files.len(),
+                Byte::from_bytes(saved_bytes as u128).get_appropriate_unit(true)
+            );
+        }
+    }
+    let details = CopyDetails {
+        appended_files,
+        changed_files,
+        linked_files,
+        original_paths,
+    };
+    if let Err(err) = write_manifest(
+        &src_directory,
+        &dst_directory,
+        &configuration,
+        &copied_files,
+        &details,
+    ) {
+        log::error!(
+            target: catalog::MANIFEST_NOT_WRITTEN,
+            "Failed to write {MANIFEST_FILE}; {err}"
+        );
+    }
+    let different_files = args
+        .verify
+        .as_ref()
+        .map(|mode| verify_copied_files(&copied_files, mode.value))
+        .map(|different_files| {
+            recopy_different_files(
+                &copied_files,
+                different_files,
+                args.verify_retries,
+                &copy_options,
+            )
+        })
+        .unwrap_or_default();
+    if args.verify.is_some() && different_files.is_empty() {
+        log::info!("Copied files are the same as their sources");
+    }
+    // Copies written again while verifying change times of directories
+    if args.preserve_dir_times {
+        preserve_directory_times(
+            &src_directory,
+            &dst_directory,
+            &copied_files,
+            &copy_options.layout,
+        );
+    }
+    // Set last, because directories without write permission stop recopying into them
+    if args.preserve_directory_permissions {
+        preserve_directory_permissions(
+            &src_directory,
+            &dst_directory,
+            &copied_files,
+            &copy_options.layout,
+        );
+    }
+    if let Some(report_path) = &args.report_html {
+        let report = RunReport {
+            src_directory: &src_directory,
+            dst_directory: &dst_directory,
+            fingerprint: &fingerprint(&configuration),
+            selected_files: files_to_copy.len(),
+            copied_files: &copied_files,
+            failed_files: &failed_files,
+            up_to_date_files,
+            renamed_files: &details.original_paths,
+            different_files: &different_files,
+        };
+        match write_html_report(report_path, &report) {
+            Ok(()) => log::info!("Report written to {}", report_path.to_string_lossy()),
+            Err(err) => log::error!(
+                target: catalog::HTML_REPORT_NOT_WRITTEN,
+                "Failed to write report to {}; {err}",
+                report_path.to_string_lossy()
+            ),
+        }
+    }
+    if args.move_files {
+        let sources = copied_files
+            .iter()
+            .filter(|(_, dst_path)| {
+                !different_files.contains(dst_path) && !details.changed_files.contains(dst_path)
+            })
+            .map(|(src_path, _)| src_path.clone())
+            .collect::<Vec<_>>();
+        let removed_files = remove_sources(&src_directory, &sources);
+        log::info!(
+            "Moved {} of {} copied files",
+            removed_files,
+            copied_files.len()
+        );
+    }
+    if !different_files.is_empty() {
+        return Err(RunError::new(
+            ExitCode::VerificationFailure,
+            anyhow!(
+                "{} copied files differ from their sources",
+                different_files.len()
+            ),
+        ));
+    }
+    if !failed_files.is_empty() {
+        log::warn!(
+            target: catalog::PARTIAL_COPY,
+            "{} of {} files failed to copy",
+            failed_files.len(),
+            files_to_copy.len()
+        );
+        if args.seal {
+            log::warn!(
+                target: catalog::SEAL_FAILED,
+                "dst_directory is not sealed because some files failed to copy"
+            );
+        }
+        return Ok(ExitCode::PartialCopyFailure);
+    }
+
+    if args.seal {
+        log::info!("Sealing {}", dst_directory.to_string_lossy());
+        let failures = seal_directory(&dst_directory);
+        if failures > 0 {
+            log::warn!(
+                target: catalog::SEAL_FAILED,
+                "{failures} entries of dst_directory couldn't be sealed"
+            );
+            return Ok(ExitCode::PartialCopyFailure);
+        }
+    }
+
+    Ok(ExitCode::Success)
+}
+
+fn run_diff_config(
+    first: &Path,
+    second: &Path,
+    output: OutputFormat,
+) -> Result<ExitCode, RunError> {
+    let first =
+        read_configuration(first).map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+    let second =
+        read_configuration(second).map_err(|err| RunError::new(ExitCode::InvalidArguments, err))?;
+
+    let differences = diff_configurations(&first, &second);
+    report_configuration_differences(&first, &second, &differences, output);
+
+    match differences.is_empty() {
+        true => Ok(ExitCode::Success),
+        false => Ok(ExitCode::Failure),
+    }
+}
+
+fn run_explain_error(id: Option<&str>, output: OutputFormat) -> Result<ExitCode, RunError> {
+    let entries = match id {
+        Some(id) => match find_entry(id) {
+            Some(entry) => vec![entry],
+            None => {
+                return Err(RunError::new(
+                    ExitCode::InvalidArguments,
+                    anyhow!("unknown error identifier '{id}'"),
+                ))
+            }
+        },
+        None => CATALOG.iter().collect(),
+    };
+    report_catalog_entries(&entries, id.is_some(), output);
+
+    Ok(ExitCode::Success)
+}
+
+fn run_import_manifest(
+    args: &Args,
+    log_path: &Path,
+    format: Option<BackupLogFormat>,
+) -> Result<ExitCode, RunError> {
+    let dst_directory = match &args.dst_directory {
+        Some(dst_directory) if dst_directory.is_dir() => dst_directory,
+        _ => {
+            return Err(RunError::new(
+                ExitCode::InvalidArguments,
+                anyhow!("dst_directory containing the backup is required by this command"),
+            ))
+        }
+    };
+    let content = fs::read(log_path).map_err(|err| {
+        RunError::new(
+            ExitCode::InvalidArguments,
+            anyhow!("Failed to read log '{}'; {err}", log_path.to_string_lossy()),
+        )
+    })?;
+    let content = decode_log(&content);
+    let format = format
+        .or_else(|| detect_log_format(&content))
+        .ok_or_else(|| {
+            RunError::new(
+                ExitCode::InvalidArguments,
+                anyhow!("format of the log can't be detected; set it with '--format'"),
+            )
+        })?;
+
+    let imported = parse_log(&content, format);
+    let src_directory = match &args.src_directory {
+        Some(src_directory) => src_directory.clone(),
+        None => imported.src_directory.ok_or_else(|| {
+            RunError::new(
+                ExitCode::InvalidArguments,
+                anyhow!("src_directory is required because the log doesn't contain it"),
+            )
+        })?,
+    };
+    let mut imported_files = HashSet::new();
+    let mut copied_files = Vec::new();
+    for path in imported.files {
+        // Logs appended by several runs list the same files again
+        if !imported_files.insert(path.clone()) {
+            continue;
+        }
+        let dst_path = dst_directory.join(&path);
+        if fs::symlink_metadata(&dst_path).is_err() {
+            log::warn!(
+                target: catalog::IMPORTED_FILE_MISSING,
+                "File listed in the log is missing: {}",
+                dst_path.to_string_lossy()
+            );
+            continue;
+        }
+        copied_files.push((src_directory.join(&path), dst_path));
+    }
+
+    let configuration = json!({
+        "imported_from": value_name(format),
+        "log": log_path.to_string_lossy(),
+    });
+    write_manifest(
+        &src_directory,
+        dst_directory,
+        &configuration,
+        &copied_files,
+        &CopyDetails::default(),
+    )
+    .map_err(|err| RunError::new(ExitCode::Failure, err))?;
+    log::info!(
+        "Imported {} files to {MANIFEST_FILE} in {}",
+        copied_files.len(),
+        dst_directory.to_string_lossy()
+    );
+
+    Ok(ExitCode::Success)
+}
+
+///
+/// Ask user to free space in dst_directory and wait until
+/// they decide to continue.
+///
+/// Returns false when user stops the run or when standard
+/// input is not a terminal, so unattended runs don't hang.
+///
+fn wait_for_free_space() -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+
+    eprint!("Free some space in dst_directory and press Enter to continue or type 'q' to stop: ");
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => false,
+        Ok(_) => !answer.trim().eq_ignore_ascii_case("q"),
+    }
+}
+
+///
+/// Ask user to confirm deletion of files listed in mirror mode.
+///
+/// Returns false when user declines or when standard input
+/// is not a terminal, so unattended runs don't delete files
+/// nobody has seen.
+///
+fn confirm_deletions(count: usize) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+
+    eprint!("Delete {count} files listed above from dst_directory after copying? [y/N]: ");
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => false,
+        Ok(_) => answer.trim().eq_ignore_ascii_case("y"),
+    }
+}
+
+///
+/// Collect effective settings that decide which files are copied
+/// and how, so runs can be audited and compared.
+///
+/// Rules are stored instead of paths of files they were read from.
+///
+fn run_configuration(
+    args: &Args,
+    suffixes: &Suffixes,
+    exclusion_lines: &[String],
+    exclusions: &Exclusions,
+) -> Value {
+    let mut directory_names = exclusions
+        .directory_names
+        .iter()
+        .map(|name| name.to_string_lossy())
+        .collect::<Vec<_>>();
+    directory_names.sort();
+
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "suffixes": rules_to_string(&suffixes.rules),
+        "scoped_suffixes": suffixes
+            .scopes
+            .iter()
+            .map(|scope| json!({
+                "pattern": scope.pattern,
+                "suffixes": rules_to_string(&scope.rules),
+            }))
+            .collect::<Vec<_>>(),
+        "exclusions": exclusion_lines,
+        "excluded_directory_names": directory_names,
+        "include_paths_file": args.include_paths_file.as_ref().map(|path| path.to_string_lossy()),
+        "files_from": args.files_from.as_ref().map(|path| path.to_string_lossy()),
+        "max_depth": args.max_depth,
+        "git_tracked_only": args.git_tracked_only,
+        "git_modified_only": args.git_modified_only,
+        "case_insensitive": args.case_insensitive,
+        "skip_caches": args.skip_caches,
+        "marker_file": args.marker_file,
+        "one_file_system": args.one_file_system,
+        "skip_locked": args.skip_locked,
+        "skip_placeholders": args.skip_placeholders,
+        "backup_privileges": args.backup_privileges,
+        "accessed_within": args.accessed_within.map(|age| age.as_secs()),
+        "include_types": args
+            .include_types
+            .iter()
+            .map(|file_type| value_name(*file_type))
+            .collect::<Vec<_>>(),
+        "filter": args.filter.as_ref().map(|filter| &filter.text),
+        "owner": args.owner,
+        "group": args.group,
+        "symlinks": value_name(args.symlinks),
+        "reflink": value_name(args.reflink),
+        "delta": args.delta,
+        "flatten": args.flatten,
+        "sanitize_names": args.sanitize_names,
+        "dst_template": args.dst_template.as_ref().map(DstTemplate::to_string),
+        "archive": args.archive.as_ref().map(Archive::to_string),
+        "compression": args.compression,
+        "volume_size": args.volume_size,
+        "encrypt": args.encrypt.as_ref().map(Encryption::to_string),
+        "special_files": value_name(args.special_files),
+        "max_total_size": args.max_total_size,
+        "dedup_source": args.dedup_source,
+        "dedup_dst": args.dedup_dst,
+        "preserve": args.preserve,
+        "preserve_permissions": args.preserve_permissions,
+        "preserve_directory_permissions": args.preserve_directory_permissions,
+        "preserve_dir_times": args.preserve_dir_times,
+        "preserve_xattrs": args.preserve_xattrs,
+        "preserve_acl": args.preserve_acl,
+        "preserve_ads": args.preserve_ads,
+        "preserve_metadata": args.preserve_metadata,
+        "consistent_databases": args.consistent_databases,
+        "max_drive_temperature": args.max_drive_temperature,
+        "os_backup_integration": args.os_backup_integration,
+        "update": args.update,
+        "compare": value_name(args.compare),
+        "on_change": args.on_change.and_then(value_name),
+        "append_only": args.append_only.iter().map(|glob| glob.glob()).collect::<Vec<_>>(),
+        "overwrite": value_name(args.overwrite),
+        "on_collision": value_name(args.on_collision),
+        "mirror": args.mirror,
+        "use_trash": args.use_trash,
+        "backup_versions": args.backup_versions,
+        "move": args.move_files,
+        "verify": args.verify.as_ref().map(|mode| &mode.text),
+        "verify_retries": args.verify_retries,
+        "seal": args.seal,
+        "link": args.link,
+    })
+}
+
+fn value_name(value: impl ValueEnum) -> Option<String> {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_owned())
+}
+
+fn rules_to_string(rules: &[Rule]) -> String {
+    rules
+        .iter()
+        .map(|rule| rule.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn requires_dst_directory(command: &Option<Command>) -> bool {
+    match command {
+        None => true,
+        Some(Command::Report { kind }) => match kind {
+            ReportKind::Duplicates | ReportKind::Anomalies => false,
+            ReportKind::Advise => true,
+        },
+        Some(Command::DiffConfig { .. }) | Some(Command::ExplainError { .. }) => false,
+        Some(Command::ImportManifest { .. }) => true,
+        Some(Command::ExportFilters { .. }) | Some(Command::Estimate) => false,
+    }
+}
+
+fn read_available_space(dst_directory: &Path) -> u64 {
+    fs4::available_space(dst_directory).unwrap_or_else(|err| {
+        panic!(
+            "Failed to read available space at {}; {err}",
+            dst_directory.to_string_lossy()
+        )
+    })
+}
+
+///
+/// Read space available in dst_directory without the space
+/// that has to remain free.
+///
+fn read_usable_space(dst_directory: &Path, reserve: u64) -> u64 {
+    read_available_space(dst_directory).saturating_sub(reserve)
+}
+
+///
+/// Parse age like '90d' or '1y'.
+///
+fn parse_age(value: &str) -> Result<Duration> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|err| anyhow!("invalid age '{value}'; {err}"))?;
+    let unit_hours = match unit.trim() {
+        "h" => 1,
+        "d" => 24,
+        "w" => 7 * 24,
+        "mo" => 30 * 24,
+        "y" => 365 * 24,
+        unit => {
+            return Err(anyhow!(
+                "invalid age '{value}'; unknown unit '{unit}', use 'h', 'd', 'w', 'mo' or 'y'"
+            ))
+        }
+    };
+
+    number
+        .checked_mul(unit_hours * 3600)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow!("invalid age '{value}'; it's too long"))
+}
+
+fn parse_filter(value: &str) -> Result<ParsedArg<Filter>> {
+    Ok(ParsedArg {
+        text: value.to_owned(),
+        value: Filter::parse(value)?,
+    })
+}
+
+fn parse_verify(value: &str) -> Result<ParsedArg<VerifyMode>> {
+    Ok(ParsedArg {
+        text: value.to_owned(),
+        value: parse_verify_mode(value)?,
+    })
+}
+
+fn parse_size(value: &str) -> Result<u64> {
+    let size = Byte::from_str(value).map_err(|err| anyhow!("invalid size '{value}'; {err}"))?;
+    Ok(size.get_bytes() as u64)
 }
 
 fn canonicalize_args(mut args: Args) -> Result<Args> {
-    if !args.src_directory.is_dir() {
+    let Some(src_directory) = &args.src_directory else {
+        return Err(anyhow!("src_directory is required"));
+    };
+    if !src_directory.is_dir() {
         return Err(anyhow!(
             "src_directory '{}' is not a directory",
-            args.src_directory.to_string_lossy()
+            src_directory.to_string_lossy()
         ));
     }
+    args.src_directory = Some(src_directory.canonicalize().unwrap());
 
-    if !args.dst_directory.is_dir() {
-        return Err(anyhow!(
-            "dst_directory '{}' is not a directory",
-            args.dst_directory.to_string_lossy()
-        ));
+    if let Some(dst_directory) = &args.dst_directory {
+        if !dst_directory.is_dir() {
+            return Err(anyhow!(
+                "dst_directory '{}' is not a directory",
+                dst_directory.to_string_lossy()
+            ));
+        }
+        args.dst_directory = Some(dst_directory.canonicalize().unwrap());
+    } else if requires_dst_directory(&args.command) && args.archive.is_none() {
+        return Err(anyhow!("dst_directory is required by this command"));
+    }
+
+    if let Some((compression, archive)) = args.compression.zip(args.archive.as_ref()) {
+        match archive.compression_levels() {
+            Some(levels) if levels.contains(&compression) => {}
+            Some(levels) => {
+                return Err(anyhow!(
+                    "compression of '{archive}' has to be in [{}, {}] range",
+                    levels.start(),
+                    levels.end()
+                ))
+            }
+            None => return Err(anyhow!("'{archive}' is not compressed")),
+        }
+    }
+
+    if let Some((volume_size, archive)) = args.volume_size.zip(args.archive.as_ref()) {
+        if volume_size == 0 {
+            return Err(anyhow!("volume_size has to be greater than zero"));
+        }
+        if matches!(archive, Archive::Zip(_)) || archive.is_stdout() {
+            return Err(anyhow!("'{archive}' can't be split into volumes"));
+        }
+    }
+
+    if args.encrypt == Some(Encryption::Passphrase) {
+        encryption::passphrase()?;
     }
 
     if let Some(include_suffixes_file) = &args.include_suffixes_file {
@@ -142,8 +2140,47 @@ fn canonicalize_args(mut args: Args) -> Result<Args> {
         args.exclude_paths_file = Some(exclude_paths_file.canonicalize().unwrap());
     }
 
-    args.src_directory = args.src_directory.canonicalize().unwrap();
-    args.dst_directory = args.dst_directory.canonicalize().unwrap();
+    // Exclusions follow the same rules as lines of exclude_paths_file
+    for exclusion in args.excludes.iter_mut() {
+        if !is_directory_path(exclusion) {
+            continue;
+        }
+        if let Ok(path) = Path::new(exclusion).canonicalize() {
+            *exclusion = path.to_string_lossy().into_owned();
+        }
+    }
+
+    if let Some(files_from) = &args.files_from {
+        if files_from.as_os_str() != STDIN_PATH {
+            if !files_from.is_file() {
+                return Err(anyhow!(
+                    "files_from '{}' is not a file",
+                    files_from.to_string_lossy()
+                ));
+            }
+            args.files_from = Some(files_from.canonicalize().unwrap());
+        }
+    }
+
+    if let Some(include_paths_file) = &args.include_paths_file {
+        if !include_paths_file.is_file() {
+            return Err(anyhow!(
+                "include_paths_file '{}' is not a file",
+                include_paths_file.to_string_lossy()
+            ));
+        }
+        args.include_paths_file = Some(include_paths_file.canonicalize().unwrap());
+    }
+
+    if let Some(temp_dir) = &args.temp_dir {
+        if !temp_dir.is_dir() {
+            return Err(anyhow!(
+                "temp_dir '{}' is not a directory",
+                temp_dir.to_string_lossy()
+            ));
+        }
+        args.temp_dir = Some(temp_dir.canonicalize().unwrap());
+    }
 
     Ok(args)
 }
@@ -153,6 +2190,29 @@ mod test {
     use super::*;
     use tempfile::{NamedTempFile, TempDir};
 
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("10GiB").unwrap(), 10 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("1 KB").unwrap(), 1000);
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[test]
+    fn parse_age_units() {
+        assert_eq!(parse_age("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(
+            parse_age("2w").unwrap(),
+            Duration::from_secs(14 * 24 * 3600)
+        );
+        assert_eq!(
+            parse_age("1y").unwrap(),
+            Duration::from_secs(365 * 24 * 3600)
+        );
+        assert!(parse_age("y").is_err());
+        assert!(parse_age("5x").is_err());
+    }
+
     #[test]
     fn canonicalize_args_all_args_present() {
         let src_directory = TempDir::new().unwrap();
@@ -161,27 +2221,106 @@ mod test {
         let exclude_paths_file = NamedTempFile::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
             include_suffixes_file: Some(include_suffixes_file.path().to_path_buf()),
             exclude_paths_file: Some(exclude_paths_file.path().to_path_buf()),
-            no_copy: false,
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_ok());
     }
 
+    #[test]
+    fn parse_args_diff_config_without_src_directory() {
+        let args = Args::try_parse_from(["save-me-files", "diff-config", "old", "new"]).unwrap();
+
+        assert!(matches!(args.command, Some(Command::DiffConfig { .. })));
+        assert!(Args::try_parse_from(["save-me-files", "-d", "dst"]).is_err());
+    }
+
+    #[test]
+    fn parse_args_compare_requires_update_or_overwrite() {
+        let args = [
+            "save-me-files",
+            "-s",
+            "src",
+            "-d",
+            "dst",
+            "--compare",
+            "checksum",
+        ];
+
+        assert!(Args::try_parse_from(args).is_err());
+        assert!(Args::try_parse_from([&args[..], &["--update"]].concat()).is_ok());
+        assert!(
+            Args::try_parse_from([&args[..], &["--overwrite", "if-different"]].concat()).is_ok()
+        );
+        assert!(Args::try_parse_from(
+            [&args[..], &["--update", "--overwrite", "if-newer"]].concat()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn run_configuration_records_written_arguments() {
+        let args = Args::try_parse_from([
+            "save-me-files",
+            "-s",
+            "src",
+            "-d",
+            "dst",
+            "--filter",
+            "size<100MB && !name:*.tmp",
+            "--verify",
+            "sample:5%",
+        ])
+        .unwrap();
+
+        let configuration =
+            run_configuration(&args, &Suffixes::default(), &[], &Exclusions::default());
+
+        assert_eq!(configuration["filter"], "size<100MB && !name:*.tmp");
+        assert_eq!(configuration["verify"], "sample:5%");
+    }
+
+    #[test]
+    fn parse_args_dst_template_conflicts_with_mirror() {
+        let args = ["save-me-files", "-s", "src", "-d", "dst", "--mirror"];
+
+        assert!(Args::try_parse_from(args).is_ok());
+        assert!(Args::try_parse_from(
+            [&args[..], &["--dst-template", "{date}/{rel_path}"]].concat()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_args_archive_conflicts_with_copy_options() {
+        let args = ["save-me-files", "-s", "src", "--archive", "tar:backup.tar"];
+
+        assert!(Args::try_parse_from(args).is_ok());
+        for option in [
+            &["--preserve"][..],
+            &["--verify", "full"],
+            &["--mirror"],
+            &["--move"],
+            &["--report-html", "report.html"],
+            &["--resume"],
+        ] {
+            assert!(Args::try_parse_from([&args[..], option].concat()).is_err());
+        }
+    }
+
     #[test]
     fn canonicalize_args_no_optional_args() {
         let src_directory = TempDir::new().unwrap();
         let dst_directory = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
-            include_suffixes_file: None,
-            exclude_paths_file: None,
-            no_copy: false,
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_ok());
@@ -192,11 +2331,9 @@ mod test {
         let dst_directory = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: "save-me-files.test.noexistent.file".into(),
-            dst_directory: dst_directory.path().to_path_buf(),
-            include_suffixes_file: None,
-            exclude_paths_file: None,
-            no_copy: false,
+            src_directory: Some("save-me-files.test.noexistent.file".into()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -208,11 +2345,9 @@ mod test {
         let dst_directory = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
-            include_suffixes_file: None,
-            exclude_paths_file: None,
-            no_copy: false,
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -223,11 +2358,9 @@ mod test {
         let src_directory = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: "save-me-files.test.noexistent.file".into(),
-            include_suffixes_file: None,
-            exclude_paths_file: None,
-            no_copy: false,
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some("save-me-files.test.noexistent.file".into()),
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -239,11 +2372,64 @@ mod test {
         let dst_directory = NamedTempFile::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
-            include_suffixes_file: None,
-            exclude_paths_file: None,
-            no_copy: false,
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        assert!(canonicalize_args(args).is_err());
+    }
+
+    #[test]
+    fn canonicalize_args_dst_directory_missing() {
+        let src_directory = TempDir::new().unwrap();
+
+        let args = Args {
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: None,
+            ..Default::default()
+        };
+
+        assert!(canonicalize_args(args).is_err());
+    }
+
+    #[test]
+    fn canonicalize_args_dst_directory_missing_for_report() {
+        let src_directory = TempDir::new().unwrap();
+
+        let args = Args {
+            command: Some(Command::Report {
+                kind: ReportKind::Duplicates,
+            }),
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: None,
+            ..Default::default()
+        };
+
+        assert!(canonicalize_args(args).is_ok());
+    }
+
+    #[test]
+    fn canonicalize_args_dst_directory_missing_for_estimate() {
+        let src_directory = TempDir::new().unwrap();
+        let src = src_directory.path().to_str().unwrap();
+
+        let args = Args::try_parse_from(["save-me-files", "-s", src, "estimate"]).unwrap();
+
+        assert!(canonicalize_args(args).is_ok());
+    }
+
+    #[test]
+    fn canonicalize_args_dst_directory_missing_for_advise() {
+        let src_directory = TempDir::new().unwrap();
+
+        let args = Args {
+            command: Some(Command::Report {
+                kind: ReportKind::Advise,
+            }),
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: None,
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -255,11 +2441,10 @@ mod test {
         let dst_directory = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
             include_suffixes_file: Some("save-me-files.test.noexistent.file".into()),
-            exclude_paths_file: None,
-            no_copy: false,
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -272,11 +2457,10 @@ mod test {
         let include_suffixes_file = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
             include_suffixes_file: Some(include_suffixes_file.path().to_path_buf()),
-            exclude_paths_file: None,
-            no_copy: false,
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -288,11 +2472,10 @@ mod test {
         let dst_directory = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
-            include_suffixes_file: None,
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
             exclude_paths_file: Some("save-me-files.test.noexistent.file".into()),
-            no_copy: false,
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -305,11 +2488,102 @@ mod test {
         let exclude_paths_file = TempDir::new().unwrap();
 
         let args = Args {
-            src_directory: src_directory.path().to_path_buf(),
-            dst_directory: dst_directory.path().to_path_buf(),
-            include_suffixes_file: None,
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
             exclude_paths_file: Some(exclude_paths_file.path().to_path_buf()),
-            no_copy: false,
+            ..Default::default()
+        };
+
+        assert!(canonicalize_args(args).is_err());
+    }
+
+    #[test]
+    fn canonicalize_args_include_paths_file_is_directory() {
+        let src_directory = TempDir::new().unwrap();
+        let dst_directory = TempDir::new().unwrap();
+        let include_paths_file = TempDir::new().unwrap();
+
+        let args = Args {
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            include_paths_file: Some(include_paths_file.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        assert!(canonicalize_args(args).is_err());
+    }
+
+    #[test]
+    fn canonicalize_args_temp_dir_is_file() {
+        let src_directory = TempDir::new().unwrap();
+        let dst_directory = TempDir::new().unwrap();
+        let temp_dir = NamedTempFile::new().unwrap();
+
+        let args = Args {
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            temp_dir: Some(temp_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        assert!(canonicalize_args(args).is_err());
+    }
+
+    #[test]
+    fn canonicalize_args_excludes_follow_exclusion_rules() {
+        let src_directory = TempDir::new().unwrap();
+        let dst_directory = TempDir::new().unwrap();
+        fs::create_dir(src_directory.path().join("src")).unwrap();
+        let excluded_directory = src_directory.path().join("src").join("..");
+
+        let args = Args {
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            excludes: vec![
+                excluded_directory.to_string_lossy().into_owned(),
+                "src".to_string(),
+                "**/node_modules".to_string(),
+            ],
+            ..Default::default()
+        };
+
+        let args = canonicalize_args(args).unwrap();
+
+        assert_eq!(
+            Path::new(&args.excludes[0]),
+            src_directory.path().canonicalize().unwrap()
+        );
+        assert_eq!(args.excludes[1], "src");
+        assert_eq!(args.excludes[2], "**/node_modules");
+    }
+
+    #[test]
+    fn canonicalize_args_files_from_stdin() {
+        let src_directory = TempDir::new().unwrap();
+        let dst_directory = TempDir::new().unwrap();
+
+        let args = Args {
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            files_from: Some(STDIN_PATH.into()),
+            ..Default::default()
+        };
+
+        let args = canonicalize_args(args).unwrap();
+
+        assert_eq!(args.files_from, Some(STDIN_PATH.into()));
+    }
+
+    #[test]
+    fn canonicalize_args_files_from_not_exist() {
+        let src_directory = TempDir::new().unwrap();
+        let dst_directory = TempDir::new().unwrap();
+
+        let args = Args {
+            src_directory: Some(src_directory.path().to_path_buf()),
+            dst_directory: Some(dst_directory.path().to_path_buf()),
+            files_from: Some("save-me-files.test.noexistent.file".into()),
+            ..Default::default()
         };
 
         assert!(canonicalize_args(args).is_err());
@@ -326,16 +2600,20 @@ mod test {
         std::env::set_current_dir(&root).unwrap();
 
         let mut args = Args {
-            src_directory: src_directory
-                .path()
-                .strip_prefix(&root)
-                .unwrap()
-                .to_path_buf(),
-            dst_directory: dst_directory
-                .path()
-                .strip_prefix(&root)
-                .unwrap()
-                .to_path_buf(),
+            src_directory: Some(
+                src_directory
+                    .path()
+                    .strip_prefix(&root)
+                    .unwrap()
+                    .to_path_buf(),
+            ),
+            dst_directory: Some(
+                dst_directory
+                    .path()
+                    .strip_prefix(&root)
+                    .unwrap()
+                    .to_path_buf(),
+            ),
             include_suffixes_file: Some(
                 include_suffixes_file
                     .path()
@@ -350,13 +2628,13 @@ mod test {
                     .unwrap()
                     .to_path_buf(),
             ),
-            no_copy: false,
+            ..Default::default()
         };
 
         args = canonicalize_args(args).unwrap();
 
-        assert!(args.src_directory.is_absolute());
-        assert!(args.dst_directory.is_absolute());
+        assert!(args.src_directory.unwrap().is_absolute());
+        assert!(args.dst_directory.unwrap().is_absolute());
         assert!(args.include_suffixes_file.unwrap().is_absolute());
         assert!(args.exclude_paths_file.unwrap().is_absolute());
     }
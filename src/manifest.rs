@@ -0,0 +1,309 @@
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the file written to dst_directory that describes the run.
+pub const MANIFEST_FILE: &str = "save-me-files.manifest.json";
+
+///
+/// Details of copies recorded in manifest, keyed by paths of copies.
+///
+#[derive(Default)]
+pub struct CopyDetails {
+    /// Offsets from which only appended part of sources was copied.
+    pub appended_files: HashMap<PathBuf, u64>,
+    /// Copies of files that changed while they were copied.
+    pub changed_files: HashSet<PathBuf>,
+    /// Identical copies to which copies were hard linked.
+    pub linked_files: HashMap<PathBuf, PathBuf>,
+    /// Original paths relative to src_directory of copies
+    /// whose names were shortened or sanitized.
+    pub original_paths: HashMap<PathBuf, PathBuf>,
+}
+
+///
+/// Setting whose value differs between two configurations.
+///
+pub struct ConfigurationDifference {
+    pub key: String,
+    /// Value in the first configuration or null when it's missing.
+    pub first: Value,
+    /// Value in the second configuration or null when it's missing.
+    pub second: Value,
+}
+
+///
+/// Calculate fingerprint of configuration.
+///
+/// Keys of JSON objects are sorted so equal configurations
+/// always have the same fingerprint.
+///
+pub fn fingerprint(configuration: &Value) -> String {
+    blake3::hash(configuration.to_string().as_bytes())
+        .to_hex()
+        .to_string()
+}
+
+///
+/// Write manifest with configuration of the run, its fingerprint
+/// and copied files to dst_directory.
+///
+/// Files recorded in existing manifest by previous runs are kept,
+/// unless they were copied again or their copies don't exist anymore.
+/// Files to which only appended part of their sources was copied
+/// have offset from which it was appended recorded. Files that changed
+/// while they were copied are marked, because their copies may be
+/// inconsistent. Copies replaced with hard links to identical copies
+/// have path of the linked copy recorded. Copies whose names were shortened
+/// or sanitized have original path relative to src_directory recorded,
+/// so they can be renamed back.
+///
+/// #### Errors
+/// This function returns error when manifest can't be written.
+///
+pub fn write_manifest(
+    src_directory: &Path,
+    dst_directory: &Path,
+    configuration: &Value,
+    copied_files: &[(PathBuf, PathBuf)],
+    details: &CopyDetails,
+) -> Result<()> {
+    let files = copied_files
+        .iter()
+        .map(|(src_path, dst_path)| {
+            let path = dst_path.strip_prefix(dst_directory).unwrap_or(dst_path);
+            let size = fs::symlink_metadata(dst_path).map_or(0, |metadata| metadata.len());
+            let mut file = json!({
+                "source": src_path.to_string_lossy(),
+                "path": path.to_string_lossy(),
+                "size": size,
+            });
+            if let Some(offset) = details.appended_files.get(dst_path) {
+                file["appended_from"] = json!(offset);
+            }
+            if details.changed_files.contains(dst_path) {
+                file["changed_while_copying"] = json!(true);
+            }
+            if let Some(original) = details.linked_files.get(dst_path) {
+                let original = original.strip_prefix(dst_directory).unwrap_or(original);
+                file["linked_to"] = json!(original.to_string_lossy());
+            }
+            if let Some(original_path) = details.original_paths.get(dst_path) {
+                file["original_path"] = json!(original_path.to_string_lossy());
+            }
+            file
+        })
+        .collect::<Vec<_>>();
+    let copied_paths = files
+        .iter()
+        .filter_map(|file| file["path"].as_str())
+        .collect::<HashSet<_>>();
+    let mut previous_files = previous_files(dst_directory)
+        .into_iter()
+        .filter(|file| {
+            file["path"].as_str().is_some_and(|path| {
+                !copied_paths.contains(path) && dst_directory.join(path).symlink_metadata().is_ok()
+            })
+        })
+        .collect::<Vec<_>>();
+    previous_files.extend(files);
+    let files = previous_files;
+    let manifest = json!({
+        "src_directory": src_directory.to_string_lossy(),
+        "fingerprint": fingerprint(configuration),
+        "configuration": configuration,
+        "files": files,
+    });
+
+    fs::write(
+        dst_directory.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    Ok(())
+}
+
+///
+/// Files recorded in manifest in dst_directory. Missing
+/// or invalid manifest has no files.
+///
+fn previous_files(dst_directory: &Path) -> Vec<Value> {
+    fs::read(dst_directory.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_slice::<Value>(&content).ok())
+        .and_then(|mut manifest| match manifest["files"].take() {
+            Value::Array(files) => Some(files),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+///
+/// Read configuration from manifest at path or from [MANIFEST_FILE]
+/// when path is a directory.
+///
+/// #### Errors
+/// This function returns error when manifest can't be read
+/// or doesn't contain configuration.
+///
+pub fn read_configuration(path: &Path) -> Result<Value> {
+    let path = match path.is_dir() {
+        true => path.join(MANIFEST_FILE),
+        false => path.to_path_buf(),
+    };
+    let manifest: Value = serde_json::from_slice(&fs::read(&path)?).map_err(|err| {
+        anyhow!(
+            "'{}' is not a valid manifest; {err}",
+            path.to_string_lossy()
+        )
+    })?;
+
+    match manifest.get("configuration") {
+        Some(configuration) if configuration.is_object() => Ok(configuration.clone()),
+        _ => Err(anyhow!(
+            "manifest '{}' doesn't contain configuration",
+            path.to_string_lossy()
+        )),
+    }
+}
+
+///
+/// Find settings whose values differ between configurations.
+///
+/// Differences are sorted by key.
+///
+pub fn diff_configurations(first: &Value, second: &Value) -> Vec<ConfigurationDifference> {
+    let empty = serde_json::Map::new();
+    let first = first.as_object().unwrap_or(&empty);
+    let second = second.as_object().unwrap_or(&empty);
+
+    let mut keys = first.keys().chain(second.keys()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let first = first.get(key).cloned().unwrap_or_default();
+            let second = second.get(key).cloned().unwrap_or_default();
+            (first != second).then(|| ConfigurationDifference {
+                key: key.clone(),
+                first,
+                second,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn write_manifest_and_read_configuration() {
+        let src_directory = TempDir::new().unwrap();
+        let dst_directory = TempDir::new().unwrap();
+        let src_path = src_directory.path().join("a.txt");
+        let dst_path = dst_directory.path().join("a.txt");
+        fs::write(&src_path, "text").unwrap();
+        fs::write(&dst_path, "text").unwrap();
+        let configuration = json!({"suffixes": ".txt", "seal": false});
+
+        write_manifest(
+            src_directory.path(),
+            dst_directory.path(),
+            &configuration,
+            &[(src_path, dst_path.clone())],
+            &CopyDetails {
+                appended_files: HashMap::from([(dst_path.clone(), 2)]),
+                changed_files: HashSet::from([dst_path.clone()]),
+                linked_files: HashMap::from([(
+                    dst_path.clone(),
+                    dst_directory.path().join("b.txt"),
+                )]),
+                original_paths: HashMap::from([(dst_path, PathBuf::from("a?.txt"))]),
+            },
+        )
+        .unwrap();
+
+        let manifest: Value =
+            serde_json::from_slice(&fs::read(dst_directory.path().join(MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(manifest["fingerprint"], fingerprint(&configuration));
+        assert_eq!(manifest["files"][0]["path"], "a.txt");
+        assert_eq!(manifest["files"][0]["size"], 4);
+        assert_eq!(manifest["files"][0]["appended_from"], 2);
+        assert_eq!(manifest["files"][0]["changed_while_copying"], true);
+        assert_eq!(manifest["files"][0]["linked_to"], "b.txt");
+        assert_eq!(manifest["files"][0]["original_path"], "a?.txt");
+        assert_eq!(
+            read_configuration(dst_directory.path()).unwrap(),
+            configuration
+        );
+    }
+
+    #[test]
+    fn write_manifest_keeps_files_of_previous_runs() {
+        let src_directory = TempDir::new().unwrap();
+        let dst_directory = TempDir::new().unwrap();
+        let names = ["a.txt", "b.txt", "deleted.txt"];
+        let copied_files = names.map(|name| {
+            let dst_path = dst_directory.path().join(name);
+            fs::write(&dst_path, name).unwrap();
+            (src_directory.path().join(name), dst_path)
+        });
+        let write = |copied_files: &[(PathBuf, PathBuf)]| {
+            write_manifest(
+                src_directory.path(),
+                dst_directory.path(),
+                &json!({}),
+                copied_files,
+                &CopyDetails::default(),
+            )
+            .unwrap();
+        };
+
+        write(&copied_files);
+        fs::remove_file(&copied_files[2].1).unwrap();
+        write(&copied_files[1..2]);
+
+        let manifest: Value =
+            serde_json::from_slice(&fs::read(dst_directory.path().join(MANIFEST_FILE)).unwrap())
+                .unwrap();
+        let paths = manifest["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| file["path"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn fingerprint_ignores_key_order() {
+        let first = json!({"a": 1, "b": [".txt"]});
+        let second = json!({"b": [".txt"], "a": 1});
+
+        assert_eq!(fingerprint(&first), fingerprint(&second));
+        assert_ne!(fingerprint(&first), fingerprint(&json!({"a": 2})));
+    }
+
+    #[test]
+    fn diff_configurations_finds_changed_and_missing_keys() {
+        let first = json!({"seal": false, "suffixes": ".txt", "verify": null});
+        let second = json!({"seal": true, "suffixes": ".txt", "max_depth": 2});
+
+        let differences = diff_configurations(&first, &second);
+
+        let keys = differences
+            .iter()
+            .map(|difference| difference.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["max_depth", "seal"]);
+        assert_eq!(differences[1].first, json!(false));
+        assert_eq!(differences[1].second, json!(true));
+    }
+}
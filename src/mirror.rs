@@ -0,0 +1,136 @@
+use crate::{
+    catalog, manifest::MANIFEST_FILE, os_backup::SPOTLIGHT_NEVER_INDEX_FILE, state::STATE_FILE,
+    versions::versioned_file,
+};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Files written to dst_directory by the application itself.
+const APPLICATION_FILES: [&str; 3] = [MANIFEST_FILE, SPOTLIGHT_NEVER_INDEX_FILE, STATE_FILE];
+
+///
+/// Find files in dst_directory that aren't copies of selected files
+/// and have to be deleted to make dst_directory a mirror of them.
+///
+/// Files written by the application to dst_directory are never deleted.
+/// Previous versions of files are deleted only with the files.
+/// Returned paths are sorted.
+///
+pub fn plan_deletions(dst_directory: &Path, kept_files: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let application_files = APPLICATION_FILES
+        .iter()
+        .map(|name| dst_directory.join(name))
+        .collect::<Vec<_>>();
+    let mut deletions = WalkDir::new(dst_directory)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+                None
+            }
+        })
+        .filter(|entry| !entry.file_type().is_dir())
+        .map(|entry| entry.into_path())
+        .filter(|path| !kept_files.contains(path) && !application_files.contains(path))
+        .filter(|path| versioned_file(path).is_none_or(|file| !kept_files.contains(&file)))
+        .collect::<Vec<_>>();
+    deletions.sort();
+
+    deletions
+}
+
+///
+/// Delete planned files (or move them to trash of the system when
+/// use_trash is set) and directories of dst_directory that became empty.
+///
+/// Files that can't be deleted are logged.
+/// Returns number of deleted files.
+///
+pub fn delete_files(dst_directory: &Path, deletions: &[PathBuf], use_trash: bool) -> usize {
+    let mut deleted_files = 0;
+    let mut directories = BTreeSet::new();
+    for path in deletions {
+        let result = match use_trash {
+            true => trash::delete(path).map_err(|err| err.to_string()),
+            false => fs::remove_file(path).map_err(|err| err.to_string()),
+        };
+        match result {
+            Ok(()) => {
+                match use_trash {
+                    true => log::info!("Moved {} to trash", path.to_string_lossy()),
+                    false => log::info!("Deleted {}", path.to_string_lossy()),
+                }
+                deleted_files += 1;
+                directories.extend(
+                    path.ancestors()
+                        .skip(1)
+                        .take_while(|directory| *directory != dst_directory),
+                );
+            }
+            Err(err) => log::warn!(
+                target: catalog::MIRROR_DELETE_FAILED,
+                "Failed to delete {}; {err}",
+                path.to_string_lossy()
+            ),
+        }
+    }
+
+    // Children are sorted after their parents, so they're removed first
+    for directory in directories.iter().rev() {
+        // Directories that aren't empty are kept
+        let _ = fs::remove_dir(directory);
+    }
+
+    deleted_files
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn plan_deletions_skips_kept_and_application_files() {
+        let dst_dir = TempDir::new().unwrap();
+        let kept = dst_dir.path().join("kept.txt");
+        let extra = dst_dir.path().join("old").join("extra.txt");
+        fs::create_dir(dst_dir.path().join("old")).unwrap();
+        fs::write(&kept, "kept").unwrap();
+        fs::write(&extra, "extra").unwrap();
+        fs::write(dst_dir.path().join(MANIFEST_FILE), "{}").unwrap();
+
+        fs::write(dst_dir.path().join("kept.txt.~1~"), "previous").unwrap();
+        fs::write(dst_dir.path().join("old").join("extra.txt.~1~"), "previous").unwrap();
+
+        let deletions = plan_deletions(dst_dir.path(), &HashSet::from([kept]));
+
+        assert_eq!(
+            deletions,
+            vec![
+                extra.clone(),
+                dst_dir.path().join("old").join("extra.txt.~1~")
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_files_removes_empty_directories() {
+        let dst_dir = TempDir::new().unwrap();
+        let nested = dst_dir.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("extra.txt"), "extra").unwrap();
+        fs::write(dst_dir.path().join("a").join("kept.txt"), "kept").unwrap();
+
+        let deleted = delete_files(dst_dir.path(), &[nested.join("extra.txt")], false);
+
+        assert_eq!(deleted, 1);
+        assert!(!nested.exists());
+        assert!(dst_dir.path().join("a").join("kept.txt").exists());
+    }
+}
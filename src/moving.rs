@@ -0,0 +1,74 @@
+use crate::catalog;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+///
+/// Remove sources of moved files and directories of src_directory
+/// that became empty.
+///
+/// Files that can't be removed are logged with WARN level.
+/// Returns number of removed files.
+///
+pub fn remove_sources(src_directory: &Path, sources: &[PathBuf]) -> usize {
+    let mut removed_files = 0;
+    let mut directories = BTreeSet::new();
+    for path in sources {
+        match fs::remove_file(path) {
+            Ok(()) => {
+                log::debug!("Removed {}", path.to_string_lossy());
+                removed_files += 1;
+                directories.extend(path.ancestors().skip(1).take_while(|directory| {
+                    directory.starts_with(src_directory) && *directory != src_directory
+                }));
+            }
+            Err(err) => log::warn!(
+                target: catalog::SOURCE_NOT_REMOVED,
+                "Failed to remove moved file {}; {err}",
+                path.to_string_lossy()
+            ),
+        }
+    }
+
+    // Children are sorted after their parents, so they're removed first
+    for directory in directories.iter().rev() {
+        // Directories that aren't empty are kept
+        if fs::remove_dir(directory).is_ok() {
+            log::debug!("Removed empty directory {}", directory.to_string_lossy());
+        }
+    }
+
+    removed_files
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn remove_sources_removes_empty_directories() {
+        let src_dir = TempDir::new().unwrap();
+        let project = src_dir.path().join("project");
+        let assets = project.join("assets");
+        fs::create_dir_all(&assets).unwrap();
+        fs::create_dir(src_dir.path().join("empty")).unwrap();
+        fs::write(assets.join("logo.png"), "logo").unwrap();
+        fs::write(project.join("notes.txt"), "notes").unwrap();
+        fs::write(project.join("kept.txt"), "kept").unwrap();
+
+        let removed = remove_sources(
+            src_dir.path(),
+            &[assets.join("logo.png"), project.join("notes.txt")],
+        );
+
+        assert_eq!(removed, 2);
+        assert!(!assets.exists());
+        assert!(project.join("kept.txt").exists());
+        // Directories that were empty before aren't removed
+        assert!(src_dir.path().join("empty").exists());
+        assert!(src_dir.path().exists());
+    }
+}
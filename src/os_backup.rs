@@ -0,0 +1,114 @@
+use crate::exit_code::ExitCode;
+use anyhow::Result;
+use std::path::Path;
+
+/// Name under which runs are reported to the operating system.
+#[cfg_attr(not(windows), allow(dead_code))]
+const EVENT_SOURCE: &str = "save-me-files";
+
+/// File that stops Spotlight from indexing volume or directory containing it.
+pub const SPOTLIGHT_NEVER_INDEX_FILE: &str = ".metadata_never_index";
+
+///
+/// Keep dst_directory from being backed up again or indexed
+/// by backup and search facilities of the operating system.
+///
+/// On macOS dst_directory is excluded from Time Machine
+/// (the same way 'tmutil addexclusion' does it, with extended
+/// attribute that stays when directory is moved) and from Spotlight.
+/// Other platforms have nothing to do.
+///
+/// #### Errors
+/// This function returns error when exclusion can't be set.
+///
+#[cfg(target_os = "macos")]
+pub fn exclude_from_os_backup(dst_directory: &Path) -> Result<()> {
+    use anyhow::anyhow;
+    use std::{fs, process::Command};
+
+    let output = Command::new("tmutil")
+        .arg("addexclusion")
+        .arg(dst_directory)
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "tmutil failed; {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    fs::write(dst_directory.join(SPOTLIGHT_NEVER_INDEX_FILE), "")?;
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn exclude_from_os_backup(_dst_directory: &Path) -> Result<()> {
+    Ok(())
+}
+
+///
+/// Report outcome of the run to the operating system.
+///
+/// On Windows event is written to Application event log
+/// with source [EVENT_SOURCE] and exit code as event id,
+/// so backup monitoring tools can pick it up.
+/// Other platforms have nothing to do.
+///
+/// #### Errors
+/// This function returns error when event can't be written.
+///
+#[cfg(windows)]
+pub fn report_run_to_os(code: ExitCode, message: &str) -> Result<()> {
+    use std::{io, iter, ptr};
+    use windows_sys::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+        EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+    };
+
+    let event_type = match code {
+        ExitCode::Success => EVENTLOG_INFORMATION_TYPE,
+        ExitCode::PartialCopyFailure | ExitCode::Interrupted => EVENTLOG_WARNING_TYPE,
+        _ => EVENTLOG_ERROR_TYPE,
+    };
+    let source = EVENT_SOURCE
+        .encode_utf16()
+        .chain(iter::once(0))
+        .collect::<Vec<_>>();
+    let message = message
+        .encode_utf16()
+        .chain(iter::once(0))
+        .collect::<Vec<_>>();
+    let strings = [message.as_ptr()];
+
+    // SAFETY: source and message are NUL terminated wide strings
+    // that outlive the calls and handle is checked before use
+    unsafe {
+        let handle = RegisterEventSourceW(ptr::null(), source.as_ptr());
+        if handle.is_null() {
+            return Err(io::Error::last_os_error().into());
+        }
+        let reported = ReportEventW(
+            handle,
+            event_type,
+            0,
+            code as u32,
+            ptr::null_mut(),
+            strings.len() as u16,
+            0,
+            strings.as_ptr(),
+            ptr::null(),
+        );
+        let error = io::Error::last_os_error();
+        DeregisterEventSource(handle);
+        if reported == 0 {
+            return Err(error.into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn report_run_to_os(_code: ExitCode, _message: &str) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use clap::ValueEnum;
+use serde_json::Value;
+use std::{
+    fmt::Arguments,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+///
+/// Format of the data produced by the application
+/// (list of files to copy, reports).
+///
+/// Data is written to standard output while logs go to the
+/// log target, so data can be piped to other programs.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Data is logged together with everything else.
+    #[default]
+    Log,
+    /// Data is written as plain text lines.
+    Text,
+    /// Data is written as JSON.
+    Json,
+}
+
+impl OutputFormat {
+    ///
+    /// Write human readable line of data.
+    ///
+    /// Line is logged with INFO level in [OutputFormat::Log],
+    /// written to standard output in [OutputFormat::Text]
+    /// and ignored in [OutputFormat::Json].
+    ///
+    pub fn line(self, line: Arguments) {
+        match self {
+            OutputFormat::Log => log::info!("{line}"),
+            OutputFormat::Text => write_stdout(line),
+            OutputFormat::Json => {}
+        }
+    }
+
+    ///
+    /// Write data as JSON to standard output.
+    ///
+    /// Ignored unless format is [OutputFormat::Json].
+    ///
+    pub fn json(self, value: &Value) {
+        if self == OutputFormat::Json {
+            write_stdout(format_args!("{value}"));
+        }
+    }
+
+    ///
+    /// Write list of paths.
+    ///
+    /// Every path is logged with description in [OutputFormat::Log],
+    /// written in separate line in [OutputFormat::Text]
+    /// and written as JSON array in [OutputFormat::Json].
+    ///
+    pub fn paths(self, description: &str, paths: &[PathBuf]) {
+        match self {
+            OutputFormat::Log => {
+                for path in paths.iter() {
+                    log::info!("{description}: {}", path.to_string_lossy());
+                }
+            }
+            OutputFormat::Text => {
+                for path in paths.iter() {
+                    write_stdout(format_args!("{}", path.to_string_lossy()));
+                }
+            }
+            OutputFormat::Json => {
+                let paths = paths
+                    .iter()
+                    .map(|path| path.to_string_lossy())
+                    .collect::<Vec<_>>();
+                self.json(&Value::from(paths));
+            }
+        }
+    }
+}
+
+fn write_stdout(line: Arguments) {
+    // Errors are ignored so closing the pipe early (e.g. by 'head')
+    // doesn't crash the application
+    let _ = writeln!(io::stdout().lock(), "{line}");
+}
@@ -0,0 +1,217 @@
+use crate::{
+    catalog,
+    suffixes::{parse_suffix, rules_to_lowercase, Rule},
+    COMMENT_LINE_PREFIX,
+};
+use anyhow::{anyhow, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Name of the file with rules that apply to directory containing it.
+pub const OVERRIDES_FILE_NAME: &str = ".savemefiles";
+
+/// Lines starting with this prefix are exclusion globs instead of suffixes.
+const EXCLUDE_LINE_PREFIX: &str = "exclude:";
+
+///
+/// Rules read from [OVERRIDES_FILE_NAME] of a single directory.
+///
+struct DirectoryOverrides {
+    directory: PathBuf,
+    /// Suffix rules checked after rules of parent directories.
+    rules: Vec<Rule>,
+    /// Exclusion globs matched against paths relative to directory.
+    globs: GlobSet,
+}
+
+///
+/// Rules from [OVERRIDES_FILE_NAME] files of directories
+/// that contain currently traversed entry.
+///
+/// Entries need to be passed to [Overrides::enter] in traversal
+/// order (directory before its content) so rules of every directory
+/// apply only to its subtree.
+///
+#[derive(Default)]
+pub struct Overrides {
+    case_insensitive: bool,
+    stack: Vec<DirectoryOverrides>,
+}
+
+impl Overrides {
+    pub fn new(case_insensitive: bool) -> Self {
+        Self {
+            case_insensitive,
+            stack: Vec::new(),
+        }
+    }
+
+    ///
+    /// Forget rules of directories that don't contain entry at path.
+    ///
+    pub fn enter(&mut self, path: &Path) {
+        while self
+            .stack
+            .last()
+            .is_some_and(|overrides| !path.starts_with(&overrides.directory))
+        {
+            self.stack.pop();
+        }
+    }
+
+    ///
+    /// Read rules of the directory when it has [OVERRIDES_FILE_NAME].
+    ///
+    /// Invalid files are logged with WARN level and ignored.
+    ///
+    pub fn push_directory(&mut self, directory: &Path) {
+        let path = directory.join(OVERRIDES_FILE_NAME);
+        if !path.is_file() {
+            return;
+        }
+
+        match read_overrides(&path, self.case_insensitive) {
+            Ok((rules, globs)) => self.stack.push(DirectoryOverrides {
+                directory: directory.to_path_buf(),
+                rules,
+                globs,
+            }),
+            Err(err) => {
+                log::warn!(
+                    target: catalog::INVALID_OVERRIDES,
+                    "Ignoring {}; {err}",
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+
+    ///
+    /// Check whether entry at path is excluded by any of the directories.
+    ///
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.stack.iter().any(|overrides| {
+            path.strip_prefix(&overrides.directory)
+                .is_ok_and(|relative| {
+                    !relative.as_os_str().is_empty() && overrides.globs.is_match(relative)
+                })
+        })
+    }
+
+    ///
+    /// Check whether file at path should be copied when global
+    /// suffixes decided it with `matches`.
+    ///
+    /// Rules of inner directories are checked after rules of outer
+    /// directories so the last matching rule wins.
+    ///
+    pub fn matches(&self, path: &Path, matches: bool) -> bool {
+        let Some(filename) = path.file_name() else {
+            return matches;
+        };
+        let filename = match self.case_insensitive {
+            true => filename.to_string_lossy().to_lowercase(),
+            false => filename.to_string_lossy().into_owned(),
+        };
+
+        self.stack
+            .iter()
+            .flat_map(|overrides| overrides.rules.iter())
+            .rev()
+            .find(|rule| rule.matcher.matches(&filename))
+            .map_or(matches, |rule| !rule.negated)
+    }
+}
+
+///
+/// Read suffix rules and exclusion globs from overrides file.
+///
+/// Every line is written the same way as line of suffixes file
+/// or starts with [EXCLUDE_LINE_PREFIX] followed by glob.
+///
+/// #### Errors
+/// This function returns error when file can't be read
+/// or when any line is invalid.
+///
+fn read_overrides(path: &Path, case_insensitive: bool) -> Result<(Vec<Rule>, GlobSet)> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let mut rules = Vec::new();
+    let mut globs = GlobSetBuilder::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(COMMENT_LINE_PREFIX) {
+            continue;
+        }
+
+        let invalid_line = |err| anyhow!("invalid line {}: {line}; {err}", index + 1);
+        match line.strip_prefix(EXCLUDE_LINE_PREFIX) {
+            Some(glob) => {
+                let glob = GlobBuilder::new(glob.trim())
+                    .literal_separator(true)
+                    .case_insensitive(case_insensitive)
+                    .build()
+                    .map_err(|err| invalid_line(err.into()))?;
+                globs.add(glob);
+            }
+            None => rules.push(parse_suffix(line).map_err(invalid_line)?),
+        }
+    }
+
+    if case_insensitive {
+        rules = rules_to_lowercase(rules);
+    }
+    Ok((rules, globs.build()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn overrides_apply_to_subtree() {
+        let root = TempDir::new().unwrap();
+        let child = root.path().join("child");
+        let sibling = root.path().join("sibling");
+        fs::create_dir(&child).unwrap();
+        fs::create_dir(&sibling).unwrap();
+        fs::write(
+            child.join(OVERRIDES_FILE_NAME),
+            "// local rules\n.log\n!.txt\nexclude: cache\n",
+        )
+        .unwrap();
+        let mut overrides = Overrides::new(false);
+
+        overrides.enter(&child);
+        overrides.push_directory(&child);
+
+        assert!(overrides.matches(&child.join("app.log"), false));
+        assert!(!overrides.matches(&child.join("notes.txt"), true));
+        assert!(overrides.matches(&child.join("photo.jpg"), true));
+        assert!(overrides.is_excluded(&child.join("cache")));
+        assert!(!overrides.is_excluded(&child));
+
+        overrides.enter(&sibling.join("app.log"));
+
+        assert!(!overrides.matches(&sibling.join("app.log"), false));
+        assert!(!overrides.is_excluded(&sibling.join("cache")));
+    }
+
+    #[test]
+    fn overrides_invalid_file_is_ignored() {
+        let root = TempDir::new().unwrap();
+        fs::write(root.path().join(OVERRIDES_FILE_NAME), "re:[invalid\n").unwrap();
+        let mut overrides = Overrides::new(false);
+
+        overrides.push_directory(root.path());
+
+        assert!(!overrides.matches(&root.path().join("a.txt"), false));
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Result};
+
+///
+/// Resolve user name or numeric uid to uid.
+///
+/// #### Errors
+/// This function returns error when user doesn't exist
+/// or when platform is not Unix.
+///
+#[cfg(unix)]
+pub fn parse_user(value: &str) -> Result<u32> {
+    use nix::unistd::User;
+
+    if let Ok(uid) = value.parse() {
+        return Ok(uid);
+    }
+    match User::from_name(value)? {
+        Some(user) => Ok(user.uid.as_raw()),
+        None => Err(anyhow!("user '{value}' not exist")),
+    }
+}
+
+///
+/// Resolve group name or numeric gid to gid.
+///
+/// #### Errors
+/// This function returns error when group doesn't exist
+/// or when platform is not Unix.
+///
+#[cfg(unix)]
+pub fn parse_group(value: &str) -> Result<u32> {
+    use nix::unistd::Group;
+
+    if let Ok(gid) = value.parse() {
+        return Ok(gid);
+    }
+    match Group::from_name(value)? {
+        Some(group) => Ok(group.gid.as_raw()),
+        None => Err(anyhow!("group '{value}' not exist")),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn parse_user(_value: &str) -> Result<u32> {
+    Err(anyhow!("filtering by owner is supported only on Unix"))
+}
+
+#[cfg(not(unix))]
+pub fn parse_group(_value: &str) -> Result<u32> {
+    Err(anyhow!("filtering by group is supported only on Unix"))
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_user_numeric_and_name() {
+        assert_eq!(parse_user("1234").unwrap(), 1234);
+        assert_eq!(parse_user("root").unwrap(), 0);
+        assert!(parse_user("save-me-files.test.noexistent.user").is_err());
+    }
+
+    #[test]
+    fn parse_group_numeric_and_name() {
+        assert_eq!(parse_group("1234").unwrap(), 1234);
+        assert!(parse_group("save-me-files.test.noexistent.group").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static BACKUP_PRIVILEGES_ENABLED: AtomicBool = AtomicBool::new(false);
+
+///
+/// Let the process read files regardless of their permissions.
+///
+/// On Windows SeBackupPrivilege and SeRestorePrivilege are enabled,
+/// which requires elevated process, and files are opened with backup
+/// semantics. On Linux the process needs CAP_DAC_READ_SEARCH capability
+/// (it's run as root or the capability is granted with 'setcap').
+///
+/// #### Errors
+/// This function returns error when privileges can't be enabled
+/// or the platform doesn't support them.
+///
+pub fn enable_backup_privileges() -> Result<()> {
+    enable_platform_privileges()?;
+    BACKUP_PRIVILEGES_ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+///
+/// Check whether backup privileges were enabled.
+///
+pub fn backup_privileges_enabled() -> bool {
+    BACKUP_PRIVILEGES_ENABLED.load(Ordering::Relaxed)
+}
+
+///
+/// Open file for reading, with backup semantics when backup
+/// privileges are enabled on Windows.
+///
+/// #### Errors
+/// This function returns error when file can't be opened.
+///
+#[cfg(windows)]
+pub fn open_for_reading(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_BACKUP_SEMANTICS;
+
+    if !backup_privileges_enabled() {
+        return File::open(path);
+    }
+    File::options()
+        .read(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+pub fn open_for_reading(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+#[cfg(windows)]
+fn enable_platform_privileges() -> Result<()> {
+    use anyhow::anyhow;
+    use std::ptr;
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, GetLastError, ERROR_NOT_ALL_ASSIGNED, LUID},
+        Security::{
+            AdjustTokenPrivileges, LookupPrivilegeValueW, LUID_AND_ATTRIBUTES, SE_BACKUP_NAME,
+            SE_PRIVILEGE_ENABLED, SE_RESTORE_NAME, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
+        },
+        System::Threading::{GetCurrentProcess, OpenProcessToken},
+    };
+
+    let mut token = ptr::null_mut();
+    // SAFETY: token is valid pointer to store handle in
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES, &mut token) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let result = [SE_BACKUP_NAME, SE_RESTORE_NAME]
+        .into_iter()
+        .try_for_each(|name| {
+            let mut luid = LUID {
+                LowPart: 0,
+                HighPart: 0,
+            };
+            // SAFETY: name is NUL terminated wide string and luid is valid pointer
+            if unsafe { LookupPrivilegeValueW(ptr::null(), name, &mut luid) } == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let privileges = TOKEN_PRIVILEGES {
+                PrivilegeCount: 1,
+                Privileges: [LUID_AND_ATTRIBUTES {
+                    Luid: luid,
+                    Attributes: SE_PRIVILEGE_ENABLED,
+                }],
+            };
+            // SAFETY: token is open with TOKEN_ADJUST_PRIVILEGES access and
+            // previous state isn't requested
+            let adjusted = unsafe {
+                AdjustTokenPrivileges(token, 0, &privileges, 0, ptr::null_mut(), ptr::null_mut())
+            };
+            // AdjustTokenPrivileges succeeds even when process doesn't hold the privilege
+            match (adjusted, unsafe { GetLastError() }) {
+                (0, _) => Err(io::Error::last_os_error().into()),
+                (_, ERROR_NOT_ALL_ASSIGNED) => Err(anyhow!(
+                    "process doesn't hold backup privileges; run it as administrator"
+                )),
+                _ => Ok(()),
+            }
+        });
+    // SAFETY: token is open handle that's not used anymore
+    unsafe { CloseHandle(token) };
+
+    result
+}
+
+#[cfg(target_os = "linux")]
+fn enable_platform_privileges() -> Result<()> {
+    use anyhow::anyhow;
+    use std::fs;
+
+    let status = fs::read_to_string("/proc/self/status")?;
+    if !has_effective_capability(&status, CAP_DAC_READ_SEARCH) {
+        return Err(anyhow!(
+            "process doesn't have CAP_DAC_READ_SEARCH capability; run it as root \
+             or grant it with 'setcap cap_dac_read_search+ep'"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn enable_platform_privileges() -> Result<()> {
+    Err(anyhow::anyhow!(
+        "backup privileges are supported only on Windows and Linux"
+    ))
+}
+
+/// Capability that bypasses read permission checks of files and directories.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const CAP_DAC_READ_SEARCH: u32 = 2;
+
+///
+/// Check whether capability is in effective set listed
+/// in content of '/proc/self/status'.
+///
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn has_effective_capability(status: &str, capability: u32) -> bool {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+        .is_some_and(|mask| mask & (1 << capability) != 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn has_effective_capability_reads_mask() {
+        let status = "Name:\tsave-me-files\nCapPrm:\t0000000000000004\nCapEff:\t0000000000000004\n";
+
+        assert!(has_effective_capability(status, CAP_DAC_READ_SEARCH));
+        assert!(!has_effective_capability(status, 1));
+        assert!(!has_effective_capability("Name:\tsave-me-files\n", 2));
+    }
+}
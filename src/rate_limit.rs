@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use byte_unit::Byte;
+use std::{
+    io::{self, Read, Write},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Size of chunks in which content is copied when rate is limited.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Suffix of the rate, e.g. '50MB/s'.
+const PER_SECOND_SUFFIX: &str = "/s";
+
+///
+/// Limits rate at which all threads together copy content
+/// (token bucket refilled with given rate and holding at most
+/// one second of tokens).
+///
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Negative when threads took more bytes than were available
+    /// and they're waiting until the debt is paid off.
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_second as f64,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    ///
+    /// Take tokens for bytes and block until they're available.
+    ///
+    pub fn acquire(&self, bytes: u64) {
+        let rate = self.bytes_per_second as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refilled = now.duration_since(bucket.updated).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refilled).min(rate) - bytes as f64;
+            bucket.updated = now;
+            match bucket.tokens < 0.0 {
+                true => Duration::from_secs_f64(-bucket.tokens / rate),
+                false => Duration::ZERO,
+            }
+        };
+        // Other threads can take tokens while this one waits,
+        // they'll wait until its debt is paid off too
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+
+    ///
+    /// Copy reader to writer in chunks not faster than the limit.
+    ///
+    /// #### Errors
+    /// This function returns error when reading or writing fails.
+    ///
+    pub fn copy(&self, reader: &mut impl Read, writer: &mut impl Write) -> io::Result<u64> {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut copied_bytes = 0;
+        loop {
+            let read_bytes = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(copied_bytes),
+                Ok(read_bytes) => read_bytes,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            self.acquire(read_bytes as u64);
+            writer.write_all(&buffer[..read_bytes])?;
+            copied_bytes += read_bytes as u64;
+        }
+    }
+}
+
+///
+/// Parse rate written as size per second (e.g. '50MB/s' or '1 GiB').
+///
+/// #### Errors
+/// This function returns error when rate is not valid size or it's zero.
+///
+pub fn parse_rate(value: &str) -> Result<u64> {
+    let size = value.strip_suffix(PER_SECOND_SUFFIX).unwrap_or(value);
+    let bytes_per_second = Byte::from_str(size)
+        .map_err(|err| anyhow!("invalid rate '{value}'; {err}"))?
+        .get_bytes() as u64;
+    if bytes_per_second == 0 {
+        return Err(anyhow!("rate '{value}' has to be greater than zero"));
+    }
+
+    Ok(bytes_per_second)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_rate_units() {
+        assert_eq!(parse_rate("50MB/s").unwrap(), 50_000_000);
+        assert_eq!(parse_rate("1 KiB").unwrap(), 1024);
+        assert!(parse_rate("0/s").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn copy_is_limited() {
+        let limiter = RateLimiter::new(100_000);
+        let content = vec![7; 150_000];
+        let mut copy = Vec::new();
+
+        let started = Instant::now();
+        let copied_bytes = limiter.copy(&mut content.as_slice(), &mut copy).unwrap();
+
+        // The first second of tokens is available at once
+        assert!(started.elapsed() >= Duration::from_millis(450));
+        assert_eq!(copied_bytes, 150_000);
+        assert_eq!(copy, content);
+    }
+}
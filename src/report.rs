@@ -0,0 +1,394 @@
+use crate::{
+    advice::advise,
+    anomalies::{find_anomalies, Anomaly},
+    catalog::CatalogEntry,
+    duplicates::{find_duplicates, DuplicateCluster},
+    files::{calculate_files_size, SizeMode},
+    manifest::{fingerprint, ConfigurationDifference},
+    output::OutputFormat,
+    statistics::extension_statistics,
+};
+use byte_unit::Byte;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Maximum number of paths written as text for single suggestion.
+const MAX_LISTED_PATHS: usize = 20;
+
+///
+/// Report number and size of files that would be copied, in total
+/// and for every entry directly inside src_directory.
+///
+/// Entries are sorted by size in descending order.
+///
+pub fn report_estimate(src_directory: &Path, files_paths: &[PathBuf], output: OutputFormat) {
+    let mut by_entry: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for path in files_paths {
+        let entry = path
+            .strip_prefix(src_directory)
+            .ok()
+            .and_then(|relative| relative.components().next())
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .unwrap_or_default();
+        by_entry.entry(entry).or_default().push(path.clone());
+    }
+    let mut entries = by_entry
+        .into_iter()
+        .map(|(entry, paths)| {
+            (
+                entry,
+                paths.len(),
+                calculate_files_size(&paths, SizeMode::Apparent),
+            )
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    let total_bytes = entries.iter().map(|(_, _, bytes)| bytes).sum::<u64>();
+
+    output.json(&json!({
+        "files": files_paths.len(),
+        "bytes": total_bytes,
+        "entries": entries
+            .iter()
+            .map(|(entry, files, bytes)| json!({
+                "entry": entry,
+                "files": files,
+                "bytes": bytes,
+            }))
+            .collect::<Vec<_>>(),
+    }));
+
+    for (entry, files, bytes) in entries.iter() {
+        let bytes = Byte::from_bytes(*bytes as u128).get_appropriate_unit(true);
+        output.line(format_args!("    {entry}: {files} files, {bytes}"));
+    }
+    output.line(format_args!(
+        "Estimated {} files, {}",
+        files_paths.len(),
+        Byte::from_bytes(total_bytes as u128).get_appropriate_unit(true)
+    ));
+}
+
+///
+/// Report clusters of files with identical content together
+/// with the number of bytes wasted by each cluster.
+///
+pub fn report_duplicates(files_paths: &[PathBuf], output: OutputFormat) {
+    let clusters = find_duplicates(files_paths);
+    output.json(&Value::from(
+        clusters
+            .iter()
+            .map(|cluster| {
+                json!({
+                    "file_size": cluster.file_size,
+                    "wasted_bytes": cluster.wasted_bytes(),
+                    "paths": paths_to_json(&cluster.paths),
+                })
+            })
+            .collect::<Vec<_>>(),
+    ));
+
+    if clusters.is_empty() {
+        output.line(format_args!("No duplicates found"));
+        return;
+    }
+
+    for cluster in clusters.iter() {
+        let wasted = Byte::from_bytes(cluster.wasted_bytes() as u128).get_appropriate_unit(true);
+        output.line(format_args!(
+            "Duplicate cluster of {} files wasting {}:",
+            cluster.paths.len(),
+            wasted
+        ));
+        for path in cluster.paths.iter() {
+            output.line(format_args!("    {}", path.to_string_lossy()));
+        }
+    }
+
+    let duplicated_files = clusters
+        .iter()
+        .map(|cluster| cluster.paths.len() - 1)
+        .sum::<usize>();
+    let wasted_bytes = clusters
+        .iter()
+        .map(|cluster| cluster.wasted_bytes())
+        .sum::<u64>();
+    let wasted = Byte::from_bytes(wasted_bytes as u128).get_appropriate_unit(true);
+    output.line(format_args!(
+        "Found {} clusters with {} redundant files wasting {}",
+        clusters.len(),
+        duplicated_files,
+        wasted
+    ));
+}
+
+///
+/// Report files left out because their content is identical
+/// to the file of the same cluster that is copied.
+///
+pub fn report_skipped_duplicates(clusters: &[DuplicateCluster], output: OutputFormat) {
+    output.json(&Value::from(
+        clusters
+            .iter()
+            .map(|cluster| {
+                json!({
+                    "file_size": cluster.file_size,
+                    "copied": cluster.paths[0].to_string_lossy(),
+                    "skipped": paths_to_json(&cluster.paths[1..]),
+                })
+            })
+            .collect::<Vec<_>>(),
+    ));
+
+    for cluster in clusters.iter() {
+        output.line(format_args!(
+            "Copying {} instead of {} identical files:",
+            cluster.paths[0].to_string_lossy(),
+            cluster.paths.len() - 1
+        ));
+        for path in cluster.paths[1..].iter() {
+            output.line(format_args!("    {}", path.to_string_lossy()));
+        }
+    }
+}
+
+///
+/// Report settings that differ between configurations
+/// of two runs together with their fingerprints.
+///
+pub fn report_configuration_differences(
+    first: &Value,
+    second: &Value,
+    differences: &[ConfigurationDifference],
+    output: OutputFormat,
+) {
+    output.json(&json!({
+        "first_fingerprint": fingerprint(first),
+        "second_fingerprint": fingerprint(second),
+        "differences": differences
+            .iter()
+            .map(|difference| {
+                json!({
+                    "key": difference.key,
+                    "first": difference.first,
+                    "second": difference.second,
+                })
+            })
+            .collect::<Vec<_>>(),
+    }));
+
+    output.line(format_args!(
+        "Fingerprints: {} and {}",
+        fingerprint(first),
+        fingerprint(second)
+    ));
+    if differences.is_empty() {
+        output.line(format_args!("Configurations are the same"));
+        return;
+    }
+    for difference in differences.iter() {
+        output.line(format_args!(
+            "{}: {} -> {}",
+            difference.key, difference.first, difference.second
+        ));
+    }
+}
+
+///
+/// Report identifiers of warnings and errors with their summaries
+/// and, when details are requested, their causes and remediations.
+///
+pub fn report_catalog_entries(entries: &[&CatalogEntry], details: bool, output: OutputFormat) {
+    output.json(&Value::from(
+        entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "id": entry.id,
+                    "summary": entry.summary,
+                    "cause": entry.cause,
+                    "remediation": entry.remediation,
+                })
+            })
+            .collect::<Vec<_>>(),
+    ));
+
+    for entry in entries.iter() {
+        output.line(format_args!("{}: {}", entry.id, entry.summary));
+        if details {
+            output.line(format_args!("Cause: {}", entry.cause));
+            output.line(format_args!("Remediation: {}", entry.remediation));
+        }
+    }
+}
+
+///
+/// Report suggestions which directories or files could be left out
+/// so that files fit into available space.
+///
+pub fn report_advice(
+    src_directory: &Path,
+    files_paths: &[PathBuf],
+    needed_space: u64,
+    available_space: u64,
+    output: OutputFormat,
+) {
+    let missing_space = needed_space.saturating_sub(available_space);
+    let advice = advise(src_directory, files_paths, missing_space);
+    output.json(&json!({
+        "needed_bytes": needed_space,
+        "available_bytes": available_space,
+        "missing_bytes": missing_space,
+        "directories": sized_paths_to_json(&advice.directories),
+        "largest_files": sized_paths_to_json(&advice.largest_files),
+        "oldest_files": sized_paths_to_json(&advice.oldest_files),
+    }));
+
+    if missing_space == 0 {
+        output.line(format_args!("All files fit into available space"));
+        return;
+    }
+
+    let missing = Byte::from_bytes(missing_space as u128).get_appropriate_unit(true);
+    output.line(format_args!("{} more space is needed", missing));
+
+    if advice.largest_files.is_empty() {
+        output.line(format_args!(
+            "There's no way to fit files by leaving some of them out"
+        ));
+        return;
+    }
+
+    if advice.directories.is_empty() {
+        output.line(format_args!(
+            "Excluding directories is not enough to fit files"
+        ));
+    } else {
+        write_suggestion(
+            &format!("exclude {} directories", advice.directories.len()),
+            &advice.directories,
+            output,
+        );
+    }
+    write_suggestion(
+        &format!("skip {} largest files", advice.largest_files.len()),
+        &advice.largest_files,
+        output,
+    );
+    write_suggestion(
+        &format!("skip {} oldest files", advice.oldest_files.len()),
+        &advice.oldest_files,
+        output,
+    );
+}
+
+fn write_suggestion(description: &str, paths: &[(PathBuf, u64)], output: OutputFormat) {
+    output.line(format_args!("To fit files {}:", description));
+    for (path, size) in paths.iter().take(MAX_LISTED_PATHS) {
+        let size = Byte::from_bytes(*size as u128).get_appropriate_unit(true);
+        output.line(format_args!("    {} ({})", path.to_string_lossy(), size));
+    }
+    if paths.len() > MAX_LISTED_PATHS {
+        output.line(format_args!(
+            "    ... and {} more",
+            paths.len() - MAX_LISTED_PATHS
+        ));
+    }
+}
+
+///
+/// Report number and size of files per extension together
+/// with suspicious anomalies found among files.
+///
+/// Empty files are reported only when check_empty_files is set.
+///
+pub fn report_anomalies(files_paths: &[PathBuf], check_empty_files: bool, output: OutputFormat) {
+    let statistics = extension_statistics(files_paths);
+    let anomalies = find_anomalies(files_paths, check_empty_files);
+    output.json(&json!({
+        "extensions": statistics
+            .iter()
+            .map(|statistics| json!({
+                "extension": statistics.extension,
+                "files": statistics.files,
+                "bytes": statistics.bytes,
+            }))
+            .collect::<Vec<_>>(),
+        "anomalies": anomalies.iter().map(anomaly_to_json).collect::<Vec<_>>(),
+    }));
+
+    output.line(format_args!("Files by extension:"));
+    for statistics in statistics.iter() {
+        let bytes = Byte::from_bytes(statistics.bytes as u128).get_appropriate_unit(true);
+        output.line(format_args!(
+            "    {}: {} files, {}",
+            statistics.extension, statistics.files, bytes
+        ));
+    }
+
+    for anomaly in anomalies.iter() {
+        match anomaly {
+            Anomaly::ExtensionMismatch {
+                path,
+                detected_extension,
+            } => output.line(format_args!(
+                "Extension does not match content ({}): {}",
+                detected_extension,
+                path.to_string_lossy()
+            )),
+            Anomaly::EmptyFile { path } => {
+                output.line(format_args!("Empty file: {}", path.to_string_lossy()))
+            }
+            Anomaly::SharedModificationTime { modified, files } => output.line(format_args!(
+                "{} recently modified files share the same modification time ({} seconds since UNIX epoch); \
+                 they might have been modified by ransomware",
+                files,
+                modified
+            )),
+        }
+    }
+    output.line(format_args!("Found {} anomalies", anomalies.len()));
+}
+
+fn anomaly_to_json(anomaly: &Anomaly) -> Value {
+    match anomaly {
+        Anomaly::ExtensionMismatch {
+            path,
+            detected_extension,
+        } => json!({
+            "kind": "extension_mismatch",
+            "path": path.to_string_lossy(),
+            "detected_extension": detected_extension,
+        }),
+        Anomaly::EmptyFile { path } => json!({
+            "kind": "empty_file",
+            "path": path.to_string_lossy(),
+        }),
+        Anomaly::SharedModificationTime { modified, files } => json!({
+            "kind": "shared_modification_time",
+            "modified": modified,
+            "files": files,
+        }),
+    }
+}
+
+fn paths_to_json(paths: &[PathBuf]) -> Value {
+    Value::from(
+        paths
+            .iter()
+            .map(|path| path.to_string_lossy())
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn sized_paths_to_json(paths: &[(PathBuf, u64)]) -> Value {
+    Value::from(
+        paths
+            .iter()
+            .map(|(path, bytes)| json!({ "path": path.to_string_lossy(), "bytes": bytes }))
+            .collect::<Vec<_>>(),
+    )
+}
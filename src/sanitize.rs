@@ -0,0 +1,225 @@
+use crate::files::numbered_path;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+/// Character that replaces characters not allowed in names.
+const REPLACEMENT: char = '_';
+
+/// Characters that FAT, exFAT and NTFS don't allow in names.
+const INVALID_CHARACTERS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Names reserved by Windows, also with any extension (e.g. 'con.txt').
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+///
+/// Sanitized paths of copies relative to dst_directory.
+///
+/// Different names can be sanitized to the same name (e.g. 'a:b.txt'
+/// and 'a?b.txt' both become 'a_b.txt'), so later of such paths
+/// get ' (N)' added, like with [crate::flatten::flat_names].
+///
+#[derive(Clone, Default)]
+pub struct SanitizedNames {
+    paths: HashMap<PathBuf, PathBuf>,
+}
+
+impl SanitizedNames {
+    ///
+    /// Sanitize paths of copies relative to dst_directory.
+    ///
+    /// Paths are compared ignoring case, because dst_directory
+    /// is usually on case-insensitive filesystem. Sanitized paths
+    /// don't take paths that don't need sanitizing and depend only
+    /// on paths, so the same files get the same paths in every run.
+    ///
+    pub fn new<'a>(relative_paths: impl IntoIterator<Item = &'a Path>) -> SanitizedNames {
+        let mut sorted_paths = relative_paths.into_iter().collect::<Vec<_>>();
+        sorted_paths.sort();
+        sorted_paths.dedup();
+
+        let mut sanitized_paths = Vec::new();
+        let mut taken_paths = HashSet::new();
+        for path in sorted_paths {
+            match sanitize_path(path) {
+                Some(sanitized) => sanitized_paths.push((path, sanitized)),
+                None => {
+                    taken_paths.insert(path_key(path));
+                }
+            }
+        }
+
+        let mut paths = HashMap::with_capacity(sanitized_paths.len());
+        for (path, sanitized) in sanitized_paths {
+            let mut candidate = sanitized.clone();
+            let mut number = 0;
+            while !taken_paths.insert(path_key(&candidate)) {
+                number += 1;
+                candidate = numbered_path(&sanitized, number);
+            }
+            paths.insert(path.to_path_buf(), candidate);
+        }
+
+        SanitizedNames { paths }
+    }
+
+    ///
+    /// Sanitized relative path or None when it doesn't need sanitizing.
+    ///
+    /// Paths that weren't known when names were sanitized
+    /// are sanitized without checking for collisions.
+    ///
+    pub fn sanitize(&self, relative_path: &Path) -> Option<PathBuf> {
+        match self.paths.get(relative_path) {
+            Some(sanitized) => Some(sanitized.clone()),
+            None => sanitize_path(relative_path),
+        }
+    }
+}
+
+fn path_key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+///
+/// Sanitize every component of relative path, so it can be created
+/// on FAT32, exFAT and NTFS (e.g. on USB sticks).
+///
+/// Returns None when no component needs to be sanitized.
+///
+pub fn sanitize_path(relative_path: &Path) -> Option<PathBuf> {
+    let mut is_sanitized = false;
+    let sanitized_path = relative_path
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => match sanitize_name(name) {
+                Some(sanitized) => {
+                    is_sanitized = true;
+                    sanitized
+                }
+                None => name.to_os_string(),
+            },
+            component => component.as_os_str().to_os_string(),
+        })
+        .collect::<PathBuf>();
+
+    is_sanitized.then_some(sanitized_path)
+}
+
+///
+/// Replace with [REPLACEMENT] characters that aren't allowed in names,
+/// control characters and trailing dots and spaces (which are dropped
+/// by Windows). [REPLACEMENT] is appended to reserved names.
+/// Bytes that aren't valid UTF-8 are kept as they are.
+///
+/// Returns None when name is valid.
+///
+fn sanitize_name(name: &OsStr) -> Option<OsString> {
+    let name = name.as_encoded_bytes();
+    let trimmed_length = name
+        .iter()
+        .rposition(|byte| !matches!(byte, b'.' | b' '))
+        .map_or(0, |position| position + 1);
+    let mut sanitized = Vec::with_capacity(name.len() + 1);
+    for chunk in name[..trimmed_length].utf8_chunks() {
+        for character in chunk.valid().chars() {
+            match is_invalid(character) {
+                true => sanitized.push(REPLACEMENT as u8),
+                false => {
+                    let mut buffer = [0; 4];
+                    sanitized.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+                }
+            }
+        }
+        sanitized.extend_from_slice(chunk.invalid());
+    }
+    sanitized.extend(std::iter::repeat_n(
+        REPLACEMENT as u8,
+        name.len() - trimmed_length,
+    ));
+
+    let stem_length = sanitized
+        .iter()
+        .position(|byte| *byte == b'.')
+        .unwrap_or(sanitized.len());
+    if RESERVED_NAMES.iter().any(|reserved| {
+        reserved
+            .as_bytes()
+            .eq_ignore_ascii_case(&sanitized[..stem_length])
+    }) {
+        sanitized.insert(stem_length, REPLACEMENT as u8);
+    }
+
+    // SAFETY: only whole valid characters were replaced with ASCII
+    // and other bytes were copied as they were in name
+    (sanitized != name).then(|| unsafe { OsString::from_encoded_bytes_unchecked(sanitized) })
+}
+
+fn is_invalid(character: char) -> bool {
+    INVALID_CHARACTERS.contains(&character) || character.is_control()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sanitize_path_valid_names() {
+        assert_eq!(sanitize_path(Path::new("docs/report 2024.pdf")), None);
+        assert_eq!(sanitize_path(Path::new("console.log")), None);
+    }
+
+    #[test]
+    fn sanitize_path_invalid_names() {
+        assert_eq!(
+            sanitize_path(Path::new("notes: draft?/a*b|c.txt")),
+            Some(PathBuf::from("notes_ draft_/a_b_c.txt"))
+        );
+        assert_eq!(
+            sanitize_path(Path::new("dir./file. ")),
+            Some(PathBuf::from("dir_/file__"))
+        );
+        assert_eq!(
+            sanitize_path(Path::new("con/aux.txt")),
+            Some(PathBuf::from("con_/aux_.txt"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sanitize_path_keeps_invalid_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9: menu.txt"));
+
+        assert_eq!(
+            sanitize_path(path),
+            Some(PathBuf::from(OsStr::from_bytes(b"caf\xe9_ menu.txt")))
+        );
+    }
+
+    #[test]
+    fn sanitized_names_numbers_colliding_paths() {
+        let paths = [
+            Path::new("a?b.txt"),
+            Path::new("a:b.txt"),
+            Path::new("A_B (1).txt"),
+            Path::new("c.txt"),
+        ];
+
+        let names = SanitizedNames::new(paths);
+
+        assert_eq!(names.sanitize(paths[1]), Some(PathBuf::from("a_b.txt")));
+        assert_eq!(names.sanitize(paths[0]), Some(PathBuf::from("a_b (2).txt")));
+        assert_eq!(names.sanitize(paths[3]), None);
+        assert_eq!(
+            names.sanitize(Path::new("d|e.txt")),
+            Some(PathBuf::from("d_e.txt"))
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use crate::catalog;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// File in dst_directory where plan of the run and its progress are recorded.
+pub const STATE_FILE: &str = "save-me-files.state.jsonl";
+
+///
+/// Plan of the run and its progress recorded in [STATE_FILE].
+///
+/// The first line contains fingerprint of configuration and planned
+/// files, every next line one finished file. Lines are written
+/// as soon as files are finished, so the state survives crashes
+/// and interruptions.
+///
+pub struct RunState {
+    src_directory: PathBuf,
+    dst_directory: PathBuf,
+    file: Mutex<File>,
+    /// Failure to write is logged only once.
+    failed: AtomicBool,
+}
+
+///
+/// Run read from [STATE_FILE] that can be continued.
+///
+#[derive(Debug, Default, PartialEq)]
+pub struct ResumedRun {
+    /// Absolute paths of all planned files.
+    pub planned_files: Vec<PathBuf>,
+    /// Absolute paths of finished files and their copies. Files that
+    /// didn't have to be copied (e.g. they were up to date) have no copy.
+    pub finished_files: HashMap<PathBuf, Option<PathBuf>>,
+}
+
+impl RunState {
+    ///
+    /// Record plan of the new run, replacing state of previous run.
+    ///
+    /// #### Errors
+    /// This function returns error when state can't be written.
+    ///
+    pub fn create(
+        src_directory: &Path,
+        dst_directory: &Path,
+        fingerprint: &str,
+        planned_files: &[PathBuf],
+    ) -> Result<Self> {
+        let files = planned_files
+            .iter()
+            .map(|path| relative(path, src_directory))
+            .collect::<Vec<_>>();
+        let plan = json!({
+            "src_directory": src_directory.to_string_lossy(),
+            "fingerprint": fingerprint,
+            "files": files,
+        });
+        let mut file = File::create(dst_directory.join(STATE_FILE))?;
+        writeln!(file, "{plan}")?;
+        file.sync_data()?;
+
+        Ok(Self::new(src_directory, dst_directory, file))
+    }
+
+    ///
+    /// Continue recording progress of resumed run.
+    ///
+    /// #### Errors
+    /// This function returns error when state can't be opened.
+    ///
+    pub fn append(src_directory: &Path, dst_directory: &Path) -> Result<Self> {
+        let path = dst_directory.join(STATE_FILE);
+        let is_line_incomplete = fs::read(&path)?.last().is_some_and(|byte| *byte != b'\n');
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        // Line written when the run crashed is left on its own
+        if is_line_incomplete {
+            writeln!(file)?;
+        }
+
+        Ok(Self::new(src_directory, dst_directory, file))
+    }
+
+    fn new(src_directory: &Path, dst_directory: &Path, file: File) -> Self {
+        Self {
+            src_directory: src_directory.to_path_buf(),
+            dst_directory: dst_directory.to_path_buf(),
+            file: Mutex::new(file),
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    ///
+    /// Record that file is finished and where it was copied
+    /// (None when it didn't have to be copied).
+    ///
+    /// Failure is logged with WARN level.
+    ///
+    pub fn record_finished(&self, src_path: &Path, dst_path: Option<&Path>) {
+        let line = json!({
+            "finished": relative(src_path, &self.src_directory),
+            "copy": dst_path.map(|dst_path| relative(dst_path, &self.dst_directory)),
+        });
+        let result = writeln!(self.file.lock().unwrap(), "{line}");
+        if let Err(err) = result {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                log::warn!(
+                    target: catalog::STATE_NOT_WRITTEN,
+                    "Failed to record progress in {STATE_FILE}; run can't be resumed; {err}"
+                );
+            }
+        }
+    }
+}
+
+///
+/// Read run recorded in [STATE_FILE] of dst_directory.
+///
+/// Incomplete last line (written when the run crashed) is ignored.
+///
+/// #### Errors
+/// This function returns error when there's no state, it can't be read
+/// or it was recorded for other src_directory or configuration.
+///
+pub fn read_state(
+    src_directory: &Path,
+    dst_directory: &Path,
+    fingerprint: &str,
+) -> Result<ResumedRun> {
+    let path = dst_directory.join(STATE_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(anyhow!(
+                "there's no interrupted run to resume in '{}'",
+                dst_directory.to_string_lossy()
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let mut lines = content.lines();
+    let plan = lines
+        .next()
+        .and_then(|line| serde_json::from_str::<Value>(line).ok())
+        .ok_or_else(|| anyhow!("'{}' is not a valid state", path.to_string_lossy()))?;
+
+    if plan["src_directory"].as_str() != Some(&*src_directory.to_string_lossy()) {
+        return Err(anyhow!(
+            "interrupted run copied files from other src_directory ({})",
+            plan["src_directory"]
+        ));
+    }
+    if plan["fingerprint"].as_str() != Some(fingerprint) {
+        return Err(anyhow!(
+            "configuration differs from configuration of interrupted run; \
+             start new run instead"
+        ));
+    }
+
+    let planned_files = plan["files"]
+        .as_array()
+        .ok_or_else(|| anyhow!("'{}' is not a valid state", path.to_string_lossy()))?
+        .iter()
+        .filter_map(Value::as_str)
+        .map(|path| src_directory.join(path))
+        .collect();
+    let finished_files = lines
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter_map(|line| {
+            let src_path = src_directory.join(line["finished"].as_str()?);
+            let dst_path = line["copy"].as_str().map(|path| dst_directory.join(path));
+            Some((src_path, dst_path))
+        })
+        .collect();
+
+    Ok(ResumedRun {
+        planned_files,
+        finished_files,
+    })
+}
+
+///
+/// Remove state of finished run.
+///
+/// #### Errors
+/// This function returns error when existing state can't be removed.
+///
+pub fn remove_state(dst_directory: &Path) -> io::Result<()> {
+    match fs::remove_file(dst_directory.join(STATE_FILE)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn relative(path: &Path, directory: &Path) -> String {
+    path.strip_prefix(directory)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn read_state_returns_plan_and_finished_files() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        let planned_files = [
+            src_dir.path().join("a.txt"),
+            src_dir.path().join("b.txt"),
+            src_dir.path().join("c.txt"),
+        ];
+
+        let state =
+            RunState::create(src_dir.path(), dst_dir.path(), "abc", &planned_files).unwrap();
+        state.record_finished(&planned_files[0], Some(&dst_dir.path().join("a.txt")));
+        // Line written when the run crashed
+        write!(state.file.lock().unwrap(), "{{\"finished\": \"c.t").unwrap();
+        drop(state);
+        let state = RunState::append(src_dir.path(), dst_dir.path()).unwrap();
+        state.record_finished(&planned_files[1], None);
+        drop(state);
+
+        let resumed = read_state(src_dir.path(), dst_dir.path(), "abc").unwrap();
+
+        assert_eq!(resumed.planned_files, planned_files.to_vec());
+        assert_eq!(
+            resumed.finished_files,
+            HashMap::from([
+                (planned_files[0].clone(), Some(dst_dir.path().join("a.txt"))),
+                (planned_files[1].clone(), None),
+            ])
+        );
+    }
+
+    #[test]
+    fn read_state_rejects_other_configuration() {
+        let src_dir = TempDir::new().unwrap();
+        let dst_dir = TempDir::new().unwrap();
+        RunState::create(src_dir.path(), dst_dir.path(), "abc", &[]).unwrap();
+
+        assert!(read_state(src_dir.path(), dst_dir.path(), "abd").is_err());
+        assert!(read_state(dst_dir.path(), dst_dir.path(), "abc").is_err());
+
+        remove_state(dst_dir.path()).unwrap();
+        assert!(read_state(src_dir.path(), dst_dir.path(), "abc").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::catalog;
+use std::{collections::HashMap, fs, path::PathBuf};
+
+/// Name used for files without an extension.
+pub const NO_EXTENSION: &str = "<none>";
+
+///
+/// Number and size of files sharing the same extension.
+///
+pub struct ExtensionStatistics {
+    /// Lowercase extension without leading dot.
+    pub extension: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+///
+/// Group files by their lowercase extension and count
+/// their number and size.
+///
+/// Statistics are sorted by size in descending order.
+///
+/// When there's problem with reading file metadata, error is logged
+/// and file is ignored.
+///
+pub fn extension_statistics(files_paths: &[PathBuf]) -> Vec<ExtensionStatistics> {
+    let mut by_extension: HashMap<String, (usize, u64)> = HashMap::new();
+    for file_path in files_paths {
+        let size = match fs::metadata(file_path) {
+            Ok(metadata) => metadata.len(),
+            Err(err) => {
+                log::warn!(target: catalog::UNREADABLE_ENTRY, "{err}");
+                continue;
+            }
+        };
+        let extension = file_path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_else(|| NO_EXTENSION.to_string());
+
+        let entry = by_extension.entry(extension).or_default();
+        entry.0 += 1;
+        entry.1 += size;
+    }
+
+    let mut statistics = by_extension
+        .into_iter()
+        .map(|(extension, (files, bytes))| ExtensionStatistics {
+            extension,
+            files,
+            bytes,
+        })
+        .collect::<Vec<_>>();
+    statistics.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.extension.cmp(&b.extension))
+    });
+
+    statistics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn extension_statistics_grouped_by_extension() {
+        let root = TempDir::new().unwrap();
+        let files = [
+            ("a.txt", 10),
+            ("b.TXT", 5),
+            ("c.png", 100),
+            ("no_extension", 1),
+        ];
+        let paths = files
+            .iter()
+            .map(|(name, size)| {
+                let path = root.path().join(name);
+                fs::write(&path, vec![0u8; *size]).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        let statistics = extension_statistics(&paths);
+
+        let summary = statistics
+            .iter()
+            .map(|stats| (stats.extension.as_str(), stats.files, stats.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![("png", 1, 100), ("txt", 2, 15), (NO_EXTENSION, 1, 1)]
+        );
+    }
+}
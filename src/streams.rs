@@ -0,0 +1,218 @@
+use crate::catalog;
+use std::{io, path::Path};
+
+///
+/// List names of NTFS alternate data streams of the file
+/// (e.g. ':Zone.Identifier:$DATA'). Default data stream is not listed.
+///
+/// #### Errors
+/// This function returns error when streams can't be listed.
+///
+#[cfg(windows)]
+pub fn alternate_streams(path: &Path) -> io::Result<Vec<String>> {
+    use std::{iter, os::windows::ffi::OsStrExt, ptr};
+    use windows_sys::Win32::{
+        Foundation::{ERROR_HANDLE_EOF, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::{
+            FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+            WIN32_FIND_STREAM_DATA,
+        },
+    };
+
+    /// Name of the stream that holds file content.
+    const DEFAULT_STREAM_NAME: &str = "::$DATA";
+
+    let path = crate::long_names::extended_length_path(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(iter::once(0))
+        .collect::<Vec<_>>();
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+    // SAFETY: path is NUL terminated wide string and data lives until the call ends
+    let handle = unsafe {
+        FindFirstStreamW(
+            path.as_ptr(),
+            FindStreamInfoStandard,
+            ptr::from_mut(&mut data).cast(),
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+            return Ok(Vec::new());
+        }
+        return Err(err);
+    }
+
+    let mut streams = Vec::new();
+    loop {
+        let name_length = data
+            .cStreamName
+            .iter()
+            .position(|&character| character == 0)
+            .unwrap_or(data.cStreamName.len());
+        let name = String::from_utf16_lossy(&data.cStreamName[..name_length]);
+        if name != DEFAULT_STREAM_NAME {
+            streams.push(name);
+        }
+
+        // SAFETY: handle is valid until FindClose and data lives until the call ends
+        if unsafe { FindNextStreamW(handle, ptr::from_mut(&mut data).cast()) } == 0 {
+            break;
+        }
+    }
+    // SAFETY: handle was returned by FindFirstStreamW and is not used anymore
+    unsafe { FindClose(handle) };
+
+    Ok(streams)
+}
+
+#[cfg(not(windows))]
+pub fn alternate_streams(_path: &Path) -> io::Result<Vec<String>> {
+    Ok(Vec::new())
+}
+
+///
+/// Copy alternate data streams of src that are missing at dst
+/// (e.g. when content was copied in chunks or cloned), when
+/// both files are on filesystems that support them (NTFS).
+/// Files copied with 'fs::copy' have them already, because
+/// CopyFileEx copies all streams.
+///
+/// Failure is logged with WARN level.
+/// Other platforms have no alternate data streams.
+///
+#[cfg(windows)]
+pub fn copy_streams(src: &Path, dst: &Path) {
+    use crate::long_names::extended_length_path;
+    use std::fs::File;
+
+    if !supports_streams(src) || !supports_streams(dst) {
+        return;
+    }
+    let result = alternate_streams(src).and_then(|src_streams| {
+        let dst_streams = alternate_streams(dst)?;
+        let (src, dst) = (extended_length_path(src), extended_length_path(dst));
+        for stream in src_streams
+            .iter()
+            .filter(|stream| !dst_streams.contains(stream))
+        {
+            // Stream is opened as 'path:name:$DATA'
+            let mut src_stream = src.as_os_str().to_os_string();
+            src_stream.push(stream);
+            let mut dst_stream = dst.as_os_str().to_os_string();
+            dst_stream.push(stream);
+            io::copy(&mut File::open(src_stream)?, &mut File::create(dst_stream)?)?;
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        log::warn!(
+            target: catalog::STREAMS_NOT_COPIED,
+            "Failed to copy alternate data streams of {}; {err}",
+            src.to_string_lossy()
+        );
+    }
+}
+
+#[cfg(not(windows))]
+pub fn copy_streams(_src: &Path, _dst: &Path) {}
+
+///
+/// Check whether filesystem of the file supports named streams.
+///
+#[cfg(windows)]
+fn supports_streams(path: &Path) -> bool {
+    use std::{fs::File, os::windows::io::AsRawHandle, ptr};
+    use windows_sys::Win32::Storage::FileSystem::GetVolumeInformationByHandleW;
+
+    /// Flag of filesystems that support named streams.
+    const FILE_NAMED_STREAMS: u32 = 0x0004_0000;
+
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut flags = 0;
+    // SAFETY: handle is valid until file is dropped and flags live until the call ends
+    let result = unsafe {
+        GetVolumeInformationByHandleW(
+            file.as_raw_handle(),
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut flags,
+            ptr::null_mut(),
+            0,
+        )
+    };
+
+    result != 0 && flags & FILE_NAMED_STREAMS != 0
+}
+
+///
+/// Log with WARN level alternate data streams of src
+/// that are missing at dst after copying.
+///
+/// Streams are copied together with the file on NTFS but
+/// they are lost when dst is on filesystem that doesn't support them.
+///
+pub fn warn_about_lost_streams(src: &Path, dst: &Path) {
+    let src_streams = match alternate_streams(src) {
+        Ok(streams) if !streams.is_empty() => streams,
+        Ok(_) => return,
+        Err(err) => {
+            log::warn!(
+                target: catalog::STREAMS_NOT_COPIED,
+                "Failed to list alternate data streams of {}; {err}",
+                src.to_string_lossy()
+            );
+            return;
+        }
+    };
+
+    let dst_streams = alternate_streams(dst).unwrap_or_default();
+    let lost_streams = src_streams
+        .into_iter()
+        .filter(|stream| !dst_streams.contains(stream))
+        .collect::<Vec<_>>();
+    if !lost_streams.is_empty() {
+        log::warn!(
+            target: catalog::STREAMS_NOT_COPIED,
+            "Alternate data streams of {} were not copied: {}",
+            src.to_string_lossy(),
+            lost_streams.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn alternate_streams_plain_file() {
+        let file = NamedTempFile::new().unwrap();
+
+        assert!(alternate_streams(file.path()).unwrap().is_empty());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn copy_streams_copies_missing_streams() {
+        let src = NamedTempFile::new().unwrap();
+        let dst = NamedTempFile::new().unwrap();
+        let mut stream_path = src.path().as_os_str().to_os_string();
+        stream_path.push(":Zone.Identifier");
+        std::fs::write(&stream_path, "[ZoneTransfer]").unwrap();
+
+        copy_streams(src.path(), dst.path());
+
+        assert_eq!(
+            alternate_streams(dst.path()).unwrap(),
+            vec![":Zone.Identifier:$DATA".to_string()]
+        );
+    }
+}
@@ -1,7 +1,9 @@
-use crate::COMMENT_LINE_PREFIX;
-use anyhow::Result;
-use regex::Regex;
+use crate::{catalog, COMMENT_LINE_PREFIX};
+use anyhow::{anyhow, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
 use std::{
+    fmt::{self, Display},
     fs::File,
     io::{BufRead, BufReader},
     path::Path,
@@ -9,47 +11,482 @@ use std::{
 
 const VALID_SUFFIX_REGEX: &str = r"^[a-zA-Z0-9_.\-\s]+$";
 
+/// Prefix of the line that subtracts suffix from matched suffixes.
+const NEGATION_PREFIX: char = '!';
+
+/// Prefix of the line that matches beginning of the filename.
+const PREFIX_LINE_PREFIX: &str = "prefix:";
+
+/// Prefix of the line that matches whole filename against regex.
+const REGEX_LINE_PREFIX: &str = "re:";
+
+/// Separates scope glob from its rules.
+const SCOPE_SEPARATOR: char = ':';
+
 ///
-/// Read suffixes from file to the vector.
+/// Part of the filename that rule compares.
+///
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    /// Filename ends with value.
+    Suffix(String),
+    /// Filename starts with value.
+    Prefix(String),
+    /// Filename matches regex.
+    Regex(Regex),
+}
+
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Matcher::Suffix(suffix), Matcher::Suffix(other)) => suffix == other,
+            (Matcher::Prefix(prefix), Matcher::Prefix(other)) => prefix == other,
+            (Matcher::Regex(regex), Matcher::Regex(other)) => regex.as_str() == other.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Matcher {}
+
+impl Matcher {
+    ///
+    /// Check whether filename is matched.
+    ///
+    pub fn matches(&self, filename: &str) -> bool {
+        match self {
+            Matcher::Suffix(suffix) => filename.ends_with(suffix.as_str()),
+            Matcher::Prefix(prefix) => filename.starts_with(prefix.as_str()),
+            Matcher::Regex(regex) => regex.is_match(filename),
+        }
+    }
+
+    ///
+    /// Check whether every filename matched by other
+    /// is matched by self too.
+    ///
+    fn covers(&self, other: &Matcher) -> bool {
+        match (self, other) {
+            (Matcher::Suffix(value), _) | (Matcher::Prefix(value), _) if value.is_empty() => true,
+            (Matcher::Suffix(suffix), Matcher::Suffix(other)) => other.ends_with(suffix.as_str()),
+            (Matcher::Prefix(prefix), Matcher::Prefix(other)) => other.starts_with(prefix.as_str()),
+            (Matcher::Regex(_), Matcher::Regex(_)) => self == other,
+            _ => false,
+        }
+    }
+
+    ///
+    /// Check whether there's no filename matched by both matchers.
+    ///
+    fn is_disjoint(&self, other: &Matcher) -> bool {
+        match (self, other) {
+            (Matcher::Suffix(_), Matcher::Suffix(_)) | (Matcher::Prefix(_), Matcher::Prefix(_)) => {
+                !self.covers(other) && !other.covers(self)
+            }
+            _ => false,
+        }
+    }
+
+    fn to_lowercase(&self) -> Matcher {
+        match self {
+            Matcher::Suffix(suffix) => Matcher::Suffix(suffix.to_lowercase()),
+            Matcher::Prefix(prefix) => Matcher::Prefix(prefix.to_lowercase()),
+            // Lowercasing the pattern would change escapes like '\D'
+            Matcher::Regex(regex) => Matcher::Regex(
+                RegexBuilder::new(regex.as_str())
+                    .case_insensitive(true)
+                    .build()
+                    .expect("regex is already validated"),
+            ),
+        }
+    }
+}
+
+impl Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Matcher::Suffix(suffix) => write!(f, "{suffix}"),
+            Matcher::Prefix(prefix) => write!(f, "{PREFIX_LINE_PREFIX}{prefix}"),
+            Matcher::Regex(regex) => write!(f, "{REGEX_LINE_PREFIX}{regex}"),
+        }
+    }
+}
+
+///
+/// Single line of suffixes file.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub matcher: Matcher,
+    /// Files matched by matcher are not copied.
+    pub negated: bool,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            write!(f, "{NEGATION_PREFIX}")?;
+        }
+        write!(f, "{}", self.matcher)
+    }
+}
+
+///
+/// Rules that replace top level rules for files
+/// in part of the source tree.
+///
+#[derive(Clone, Debug)]
+pub struct Scope {
+    /// Glob matched against paths relative to src_directory.
+    pub pattern: String,
+    glob: GlobMatcher,
+    pub rules: Vec<Rule>,
+}
+
+impl Scope {
+    ///
+    /// Create scope for files matching pattern.
+    ///
+    /// #### Errors
+    /// This function returns error when pattern is not a valid glob.
+    ///
+    pub fn new(pattern: &str, rules: Vec<Rule>) -> Result<Self> {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            glob,
+            rules,
+        })
+    }
+}
+
+impl PartialEq for Scope {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.rules == other.rules
+    }
+}
+
+impl Eq for Scope {}
+
+///
+/// Ordered rules that decide which files are copied.
+///
+/// Rules are evaluated in order and the last rule matching
+/// the filename decides whether file is copied, so
+/// `.png` followed by `!.thumb.png` copies every PNG file
+/// except thumbnails.
+/// Files inside scope use rules of the last scope
+/// they match instead of top level rules.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Suffixes {
+    pub rules: Vec<Rule>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Suffixes {
+    ///
+    /// Create suffixes matching every filename.
+    ///
+    pub fn any() -> Self {
+        Self::from_iter(["".to_string()])
+    }
+
+    ///
+    /// Check whether file at path relative to src_directory
+    /// should be copied.
+    ///
+    pub fn matches(&self, relative_path: impl AsRef<Path>) -> bool {
+        self.matching_rule(relative_path.as_ref())
+            .is_some_and(|(_, rule)| !rule.negated)
+    }
+
+    ///
+    /// Priority of the file at path relative to src_directory.
+    ///
+    /// Priority is the position of the rule that includes the file,
+    /// so files matching earlier rules have lower numbers and are
+    /// more important. Returns None when file is not included.
+    ///
+    pub fn priority(&self, relative_path: impl AsRef<Path>) -> Option<usize> {
+        self.matching_rule(relative_path.as_ref())
+            .filter(|(_, rule)| !rule.negated)
+            .map(|(index, _)| index)
+    }
+
+    fn matching_rule(&self, relative_path: &Path) -> Option<(usize, &Rule)> {
+        let filename = relative_path.file_name()?.to_string_lossy();
+
+        let rules = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.glob.is_match(relative_path))
+            .map_or(&self.rules, |scope| &scope.rules);
+        rules
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, rule)| rule.matcher.matches(&filename))
+    }
+}
+
+impl FromIterator<String> for Suffixes {
+    ///
+    /// Create suffixes where every suffix includes files.
+    ///
+    fn from_iter<T: IntoIterator<Item = String>>(iter: T) -> Self {
+        let rules = iter
+            .into_iter()
+            .map(|suffix| Rule {
+                matcher: Matcher::Suffix(suffix),
+                negated: false,
+            })
+            .collect();
+
+        Self {
+            rules,
+            scopes: Vec::new(),
+        }
+    }
+}
+
+///
+/// Read suffixes from file.
 ///
 /// Suffixes are trimmed so they don't contain leading and following
-/// whitespaces.
-/// Suffix is valid when it matches against regex: [VALID_SUFFIX_REGEX].
-/// Every invalid suffix is logged with WARN level unless it starts
+/// whitespaces. Lines starting with [PREFIX_LINE_PREFIX] match
+/// beginning of the filename instead of its end and lines starting
+/// with [REGEX_LINE_PREFIX] match whole filename against regex.
+/// Lines starting with [NEGATION_PREFIX] exclude files matched
+/// by previous lines.
+/// Lines like `Projects/** : .rs .toml` create [Scope] with
+/// whitespace separated rules. Scope and its rules are separated
+/// with [SCOPE_SEPARATOR] followed by whitespace.
+/// Suffix or prefix is valid when it matches against regex: [VALID_SUFFIX_REGEX].
+/// Every invalid line is logged with WARN level unless it starts
 /// with [COMMENT_LINE_PREFIX].
 ///
 /// #### Errors
 /// This function returns error when there's a problem with
-/// opening the file.
+/// opening the file or when any regex is invalid.
 ///
 /// #### Panics
 /// This function panics when input file contains not valid
 /// UTF-8 characters.
 ///
-pub fn read_suffixes(path: impl AsRef<Path>) -> Result<Vec<String>> {
+pub fn read_suffixes(path: impl AsRef<Path>) -> Result<Suffixes> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     let valid_filename_regex = Regex::new(VALID_SUFFIX_REGEX).unwrap();
 
-    let suffixes = reader
+    let mut suffixes = Suffixes::default();
+    let lines = reader
         .lines()
         .map(|line| line.unwrap().trim().to_owned())
-        .filter(|line| {
-            if line.is_empty() || line.starts_with(COMMENT_LINE_PREFIX) {
-                return false;
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with(COMMENT_LINE_PREFIX));
+    for (index, line) in lines {
+        let parse_rule = |rule| {
+            parse_rule(rule, &valid_filename_regex)
+                .map_err(|err| anyhow!("Invalid suffixes line {}: {line}\n{err}", index + 1))
+        };
+
+        let Some((pattern, rules)) = split_scope(&line) else {
+            if let Some(rule) = parse_rule(&line)? {
+                suffixes.rules.push(rule);
             }
+            continue;
+        };
 
-            let is_valid = valid_filename_regex.is_match(&line);
-            if !is_valid {
-                log::warn!("Invalid suffix: {line}");
+        let mut scope_rules = Vec::new();
+        for rule in rules.split_whitespace() {
+            scope_rules.extend(parse_rule(rule)?);
+        }
+        match Scope::new(pattern, scope_rules) {
+            Ok(scope) => suffixes.scopes.push(scope),
+            Err(err) => {
+                log::warn!(target: catalog::INVALID_SUFFIX, "Invalid suffixes scope: {line}; {err}")
             }
+        }
+    }
+
+    Ok(suffixes)
+}
+
+fn split_scope(line: &str) -> Option<(&str, &str)> {
+    let rule = line
+        .strip_prefix(NEGATION_PREFIX)
+        .unwrap_or(line)
+        .trim_start();
+    if rule.starts_with(REGEX_LINE_PREFIX) {
+        return None;
+    }
+
+    let separator = line.match_indices(SCOPE_SEPARATOR).find(|(index, _)| {
+        line[index + SCOPE_SEPARATOR.len_utf8()..]
+            .chars()
+            .next()
+            .is_some_and(char::is_whitespace)
+    })?;
+    let (pattern, rules) = line.split_at(separator.0);
+
+    Some((pattern.trim_end(), &rules[SCOPE_SEPARATOR.len_utf8()..]))
+}
+
+///
+/// Parse single rule written the same way as line of suffixes file.
+///
+/// #### Errors
+/// This function returns error when rule is not valid.
+///
+pub fn parse_suffix(value: &str) -> Result<Rule> {
+    let valid_filename_regex = Regex::new(VALID_SUFFIX_REGEX).unwrap();
+    parse_rule(value.trim(), &valid_filename_regex)?
+        .ok_or_else(|| anyhow!("invalid suffix '{value}'"))
+}
+
+fn parse_rule(line: &str, valid_filename_regex: &Regex) -> Result<Option<Rule>> {
+    let (value, negated) = match line.strip_prefix(NEGATION_PREFIX) {
+        Some(value) => (value.trim_start(), true),
+        None => (line, false),
+    };
+
+    if let Some(pattern) = value.strip_prefix(REGEX_LINE_PREFIX) {
+        let matcher = Matcher::Regex(Regex::new(pattern)?);
+        return Ok(Some(Rule { matcher, negated }));
+    }
 
-            is_valid
+    let (value, is_prefix) = match value.strip_prefix(PREFIX_LINE_PREFIX) {
+        Some(prefix) => (prefix, true),
+        None => (value, false),
+    };
+    if !valid_filename_regex.is_match(value) {
+        log::warn!(target: catalog::INVALID_SUFFIX, "Invalid suffix: {line}");
+        return Ok(None);
+    }
+
+    let matcher = match is_prefix {
+        true => Matcher::Prefix(value.to_string()),
+        false => Matcher::Suffix(value.to_string()),
+    };
+    Ok(Some(Rule { matcher, negated }))
+}
+
+///
+/// Convert suffixes and scopes to lowercase so they can be compared
+/// with lowercase paths.
+///
+pub fn to_lowercase(suffixes: Suffixes) -> Suffixes {
+    let scopes = suffixes
+        .scopes
+        .into_iter()
+        .map(|scope| {
+            Scope::new(
+                &scope.pattern.to_lowercase(),
+                rules_to_lowercase(scope.rules),
+            )
+            .expect("lowercase pattern is a valid glob")
         })
         .collect();
 
-    Ok(suffixes)
+    Suffixes {
+        rules: rules_to_lowercase(suffixes.rules),
+        scopes,
+    }
+}
+
+///
+/// Convert rules to lowercase so they can be compared
+/// with lowercase filenames.
+///
+pub fn rules_to_lowercase(rules: Vec<Rule>) -> Vec<Rule> {
+    rules
+        .into_iter()
+        .map(|rule| Rule {
+            matcher: rule.matcher.to_lowercase(),
+            ..rule
+        })
+        .collect()
+}
+
+///
+/// Remove rules that never change whether file is copied.
+///
+/// Rule is redundant when it's duplicated, when later rule matches
+/// every filename it matches (e.g. `.tar.gz` followed by `.gz`)
+/// or when earlier rule already gives the same result
+/// (e.g. `.gz` followed by `.tar.gz`). Every removed rule
+/// is logged with WARN level.
+///
+pub fn remove_redundant_rules(suffixes: Suffixes) -> Suffixes {
+    let remove_from_rules = |mut rules: Vec<Rule>| {
+        while let Some((index, reason)) = find_redundant_rule(&rules) {
+            log::warn!(
+                target: catalog::REDUNDANT_SUFFIX,
+                "Suffix '{}' has no effect; {reason}",
+                rules[index]
+            );
+            rules.remove(index);
+        }
+        rules
+    };
+
+    let scopes = suffixes
+        .scopes
+        .into_iter()
+        .map(|scope| Scope {
+            rules: remove_from_rules(scope.rules),
+            ..scope
+        })
+        .collect();
+
+    Suffixes {
+        rules: remove_from_rules(suffixes.rules),
+        scopes,
+    }
+}
+
+fn find_redundant_rule(rules: &[Rule]) -> Option<(usize, String)> {
+    for (index, rule) in rules.iter().enumerate() {
+        let covers = |other: &Rule| other.matcher.covers(&rule.matcher);
+
+        if let Some(later) = rules[index + 1..].iter().find(|later| covers(later)) {
+            let reason = if later == rule {
+                "it's duplicated".to_string()
+            } else {
+                format!("it's shadowed by later '{later}'")
+            };
+            return Some((index, reason));
+        }
+
+        // Without the rule files it matches are decided by the closest
+        // earlier rule covering it, unless rule with different
+        // result in between matches some of them.
+        let earlier_rules = &rules[..index];
+        let earlier = earlier_rules.iter().rposition(covers);
+        let earlier_negated = earlier.is_none_or(|earlier| earlier_rules[earlier].negated);
+        let is_overridden_between = earlier_rules[earlier.map_or(0, |earlier| earlier + 1)..]
+            .iter()
+            .any(|between| {
+                !between.matcher.is_disjoint(&rule.matcher) && between.negated != rule.negated
+            });
+        if earlier_negated == rule.negated && !is_overridden_between {
+            let reason = match earlier {
+                Some(earlier) if earlier_rules[earlier] == *rule => "it's duplicated".to_string(),
+                Some(earlier) => format!("'{}' already matches it", earlier_rules[earlier]),
+                None => "no earlier suffix matches it".to_string(),
+            };
+            return Some((index, reason));
+        }
+    }
+
+    None
 }
 
 #[cfg(test)]
@@ -73,7 +510,7 @@ mod test {
 
         suffixes
             .into_iter()
-            .for_each(|suffix| assert!(read_suffixes.contains(&suffix.to_string())));
+            .for_each(|suffix| assert!(read_suffixes.matches(suffix)));
     }
 
     #[test]
@@ -94,7 +531,7 @@ mod test {
 
         suffixes
             .into_iter()
-            .for_each(|suffix| assert!(read_suffixes.contains(&suffix.to_string())));
+            .for_each(|suffix| assert!(read_suffixes.matches(suffix)));
     }
 
     #[test]
@@ -106,6 +543,249 @@ mod test {
 
         let read_suffixes = read_suffixes(file.path()).unwrap();
 
-        assert!(read_suffixes.is_empty());
+        assert!(read_suffixes.rules.is_empty());
+    }
+
+    #[test]
+    fn read_suffixes_negated() {
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(
+            file.path(),
+            ".png\n! .thumb.png\n.keep.thumb.png\n!invalid:suffix\n",
+        )
+        .unwrap();
+
+        let read_suffixes = read_suffixes(file.path()).unwrap();
+
+        assert_eq!(read_suffixes.rules.len(), 3);
+        assert!(read_suffixes.matches("photo.png"));
+        assert!(!read_suffixes.matches("photo.thumb.png"));
+        assert!(read_suffixes.matches("photo.keep.thumb.png"));
+        assert!(!read_suffixes.matches("notes.txt"));
+    }
+
+    #[test]
+    fn remove_redundant_rules_duplicated_and_shadowed() {
+        let suffixes = Suffixes {
+            rules: vec![
+                rule(".txt", false),
+                rule(".gz", false),
+                rule(".tar.gz", false),
+                rule(".txt", false),
+                rule(".old.png", false),
+                rule(".png", false),
+                rule(".thumb.png", true),
+                rule(".raw", true),
+            ],
+            ..Default::default()
+        };
+
+        let suffixes = remove_redundant_rules(suffixes);
+
+        assert_eq!(
+            suffixes.rules,
+            vec![
+                rule(".gz", false),
+                rule(".txt", false),
+                rule(".png", false),
+                rule(".thumb.png", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_redundant_rules_keeps_overriding_rules() {
+        let rules = vec![
+            rule(".png", false),
+            rule(".thumb.png", true),
+            rule(".keep.thumb.png", false),
+        ];
+        let suffixes = Suffixes {
+            rules: rules.clone(),
+            ..Default::default()
+        };
+
+        let suffixes = remove_redundant_rules(suffixes);
+
+        assert_eq!(suffixes.rules, rules);
+    }
+
+    fn rule(suffix: &str, negated: bool) -> Rule {
+        Rule {
+            matcher: Matcher::Suffix(suffix.to_string()),
+            negated,
+        }
+    }
+
+    #[test]
+    fn read_suffixes_prefixes() {
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(
+            file.path(),
+            "prefix:IMG_\n!prefix:IMG_tmp\nprefix:invalid:prefix\n",
+        )
+        .unwrap();
+
+        let read_suffixes = read_suffixes(file.path()).unwrap();
+
+        assert_eq!(
+            read_suffixes.rules,
+            vec![
+                Rule {
+                    matcher: Matcher::Prefix("IMG_".to_string()),
+                    negated: false,
+                },
+                Rule {
+                    matcher: Matcher::Prefix("IMG_tmp".to_string()),
+                    negated: true,
+                },
+            ]
+        );
+        assert!(read_suffixes.matches("IMG_0001.jpg"));
+        assert!(!read_suffixes.matches("IMG_tmp.jpg"));
+        assert!(!read_suffixes.matches("photo_IMG_.jpg"));
+    }
+
+    #[test]
+    fn read_suffixes_regex() {
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(
+            file.path(),
+            "re:^invoice-\\d{4}\\.pdf$\n!re:^invoice-0000\n",
+        )
+        .unwrap();
+
+        let read_suffixes = read_suffixes(file.path()).unwrap();
+
+        assert_eq!(read_suffixes.rules.len(), 2);
+        assert!(read_suffixes.matches("invoice-2024.pdf"));
+        assert!(!read_suffixes.matches("invoice-0000.pdf"));
+        assert!(!read_suffixes.matches("old-invoice-2024.pdf"));
+        assert!(!read_suffixes.matches("invoice-2024.pdf.bak"));
+    }
+
+    #[test]
+    fn read_suffixes_invalid_regex() {
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(file.path(), ".txt\nre:^invoice-(\\d{4}\n").unwrap();
+
+        let err = read_suffixes(file.path()).unwrap_err();
+
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn to_lowercase_regex() {
+        let suffixes = Suffixes {
+            rules: vec![Rule {
+                matcher: Matcher::Regex(Regex::new(r"^IMG_\D+$").unwrap()),
+                negated: false,
+            }],
+            ..Default::default()
+        };
+
+        let lowercase = to_lowercase(suffixes);
+
+        assert!(lowercase.matches("img_photo"));
+        assert!(!lowercase.matches("img_1234"));
+    }
+
+    #[test]
+    fn parse_suffix_valid_and_invalid() {
+        assert_eq!(parse_suffix(".txt").unwrap(), rule(".txt", false));
+        assert_eq!(parse_suffix("!.tmp").unwrap(), rule(".tmp", true));
+        assert!(parse_suffix("invalid:suffix").is_err());
+        assert!(parse_suffix("re:(").is_err());
+    }
+
+    #[test]
+    fn read_suffixes_scopes() {
+        let file = NamedTempFile::new().unwrap();
+
+        fs::write(
+            file.path(),
+            ".txt\nProjects/** : .rs .toml\nPhotos/**:\t.jpg !prefix:tmp\n[invalid : .txt\n",
+        )
+        .unwrap();
+
+        let read_suffixes = read_suffixes(file.path()).unwrap();
+
+        assert_eq!(read_suffixes.rules.len(), 1);
+        assert_eq!(read_suffixes.scopes.len(), 2);
+        assert_eq!(read_suffixes.scopes[0].pattern, "Projects/**");
+        assert_eq!(read_suffixes.scopes[1].rules.len(), 2);
+        assert!(read_suffixes.matches("notes.txt"));
+        assert!(!read_suffixes.matches("main.rs"));
+        assert!(read_suffixes.matches("Projects/app/src/main.rs"));
+        assert!(!read_suffixes.matches("Projects/notes.txt"));
+        assert!(read_suffixes.matches("Photos/2024/beach.jpg"));
+        assert!(!read_suffixes.matches("Photos/tmp.jpg"));
+    }
+
+    #[test]
+    fn to_lowercase_scopes() {
+        let suffixes = Suffixes {
+            scopes: vec![Scope::new("Projects/**", vec![rule(".RS", false)]).unwrap()],
+            ..Default::default()
+        };
+
+        let lowercase = to_lowercase(suffixes);
+
+        assert!(lowercase.matches("projects/main.rs"));
+    }
+
+    #[test]
+    fn remove_redundant_rules_prefixes_and_suffixes() {
+        let rules = vec![
+            Rule {
+                matcher: Matcher::Prefix("IMG_".to_string()),
+                negated: false,
+            },
+            rule(".tmp", true),
+            Rule {
+                matcher: Matcher::Prefix("IMG_2024".to_string()),
+                negated: false,
+            },
+        ];
+        let suffixes = Suffixes {
+            rules: rules.clone(),
+            ..Default::default()
+        };
+
+        let suffixes = remove_redundant_rules(suffixes);
+
+        // 'IMG_2024' brings back temporary files excluded by '.tmp'
+        assert_eq!(suffixes.rules, rules);
+    }
+
+    #[test]
+    fn to_lowercase_all_suffixes() {
+        let suffixes = [".TXT", ".Png", "_backup"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Suffixes>();
+
+        let lowercase = to_lowercase(suffixes);
+
+        assert_eq!(
+            lowercase,
+            [".txt", ".png", "_backup"]
+                .into_iter()
+                .map(String::from)
+                .collect::<Suffixes>()
+        );
+    }
+
+    #[test]
+    fn suffixes_priority_is_rule_position() {
+        let suffixes = Suffixes::from_iter([".docx".to_string(), ".jpg".to_string()]);
+
+        assert_eq!(suffixes.priority("a/report.docx"), Some(0));
+        assert_eq!(suffixes.priority("a/photo.jpg"), Some(1));
+        assert_eq!(suffixes.priority("a/movie.mp4"), None);
     }
 }
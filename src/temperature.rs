@@ -0,0 +1,163 @@
+use crate::{catalog, exit_code::is_interrupted};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often temperature of the drive is read while copying.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often temperature is read while copying is paused.
+const COOL_DOWN_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Copying resumes when drive is this many degrees below the limit.
+const COOL_DOWN_MARGIN: f64 = 5.0;
+
+///
+/// Pauses copying when drive gets hotter than allowed.
+///
+/// Temperature is read from sensor exposed by the operating system,
+/// which is currently supported only on Linux (hwmon sensors of SATA
+/// drives with 'drivetemp' module and NVMe drives).
+///
+pub struct TemperatureThrottle {
+    sensor: PathBuf,
+    max_temperature: f64,
+    last_check: Mutex<Option<Instant>>,
+}
+
+impl TemperatureThrottle {
+    ///
+    /// Create throttle for the drive that stores path.
+    ///
+    /// Returns None when temperature of the drive can't be read.
+    ///
+    pub fn new(path: &Path, max_temperature: f64) -> Option<Self> {
+        let sensor = find_sensor(path)?;
+        read_temperature(&sensor)?;
+        Some(Self::with_sensor(sensor, max_temperature))
+    }
+
+    fn with_sensor(sensor: PathBuf, max_temperature: f64) -> Self {
+        Self {
+            sensor,
+            max_temperature,
+            last_check: Mutex::new(None),
+        }
+    }
+
+    ///
+    /// Read temperature when it wasn't read recently and block
+    /// until drive cools down when it's too hot.
+    ///
+    /// All threads calling this function wait while drive cools down.
+    ///
+    pub fn wait_until_cool(&self) {
+        let mut last_check = self.last_check.lock().unwrap();
+        if last_check.is_some_and(|checked| checked.elapsed() < CHECK_INTERVAL) {
+            return;
+        }
+        *last_check = Some(Instant::now());
+
+        let Some(temperature) = read_temperature(&self.sensor) else {
+            return;
+        };
+        if temperature <= self.max_temperature {
+            return;
+        }
+
+        let resume_temperature = self.max_temperature - COOL_DOWN_MARGIN;
+        log::warn!(
+            target: catalog::DRIVE_TOO_HOT,
+            "Destination drive has {temperature}°C; \
+             pausing copying until it cools down to {resume_temperature}°C"
+        );
+        let mut paused = Instant::now();
+        while !is_interrupted() {
+            thread::sleep(Duration::from_secs(1));
+            if paused.elapsed() < COOL_DOWN_CHECK_INTERVAL {
+                continue;
+            }
+            paused = Instant::now();
+            match read_temperature(&self.sensor) {
+                Some(temperature) if temperature > resume_temperature => continue,
+                _ => break,
+            }
+        }
+        log::info!("Resuming copying");
+        *last_check = Some(Instant::now());
+    }
+}
+
+///
+/// Read temperature in degrees Celsius from hwmon sensor
+/// which stores it in millidegrees.
+///
+fn read_temperature(sensor: &Path) -> Option<f64> {
+    let value = fs::read_to_string(sensor).ok()?;
+    let millidegrees = value.trim().parse::<i64>().ok()?;
+    Some(millidegrees as f64 / 1000.0)
+}
+
+///
+/// Find temperature sensor of the drive that stores path.
+///
+#[cfg(target_os = "linux")]
+fn find_sensor(path: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = fs::metadata(path).ok()?.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let mut block_device = Path::new("/sys/dev/block")
+        .join(format!("{major}:{minor}"))
+        .canonicalize()
+        .ok()?;
+    if block_device.join("partition").exists() {
+        block_device.pop();
+    }
+
+    // SATA drives have sensors below 'device/hwmon', NVMe controllers directly in 'device'
+    let device = block_device.join("device");
+    [device.join("hwmon"), device]
+        .iter()
+        .filter_map(|directory| fs::read_dir(directory).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("hwmon"))
+        .map(|entry| entry.path().join("temp1_input"))
+        .find(|sensor| sensor.is_file())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn find_sensor(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn read_temperature_millidegrees() {
+        let sensor = NamedTempFile::new().unwrap();
+        fs::write(&sensor, "41500\n").unwrap();
+
+        assert_eq!(read_temperature(sensor.path()), Some(41.5));
+    }
+
+    #[test]
+    fn wait_until_cool_returns_when_drive_is_cool() {
+        let sensor = NamedTempFile::new().unwrap();
+        fs::write(&sensor, "30000").unwrap();
+        let throttle = TemperatureThrottle::with_sensor(sensor.path().to_path_buf(), 50.0);
+
+        throttle.wait_until_cool();
+
+        assert!(throttle.last_check.lock().unwrap().is_some());
+    }
+}
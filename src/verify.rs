@@ -0,0 +1,255 @@
+use crate::{
+    catalog,
+    files::{recopy_file, CopyOptions},
+    hash::{hash_file, hash_file_uncached},
+};
+use anyhow::{anyhow, Result};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Prefix of the verification mode that checks only part of the files.
+const SAMPLE_PREFIX: &str = "sample:";
+
+///
+/// Which copied files are compared with their sources.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerifyMode {
+    /// Every copied file.
+    Full,
+    /// Random sample with given percent of copied files.
+    Sample(f64),
+}
+
+///
+/// Parse verification mode written as 'full' or 'sample:N%'.
+///
+/// #### Errors
+/// This function returns error when mode is not valid
+/// or when percent is not in (0, 100] range.
+///
+pub fn parse_verify_mode(value: &str) -> Result<VerifyMode> {
+    if value == "full" {
+        return Ok(VerifyMode::Full);
+    }
+
+    let percent = value
+        .strip_prefix(SAMPLE_PREFIX)
+        .and_then(|percent| percent.strip_suffix('%'))
+        .and_then(|percent| percent.trim().parse::<f64>().ok())
+        .ok_or_else(|| anyhow!("invalid verify mode '{value}'; use 'full' or 'sample:N%'"))?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(anyhow!(
+            "sample percent '{percent}' is not in (0, 100] range"
+        ));
+    }
+
+    Ok(VerifyMode::Sample(percent))
+}
+
+///
+/// Compare content of copied files with their sources.
+///
+/// When any file of the sample differs, all copied files are verified.
+/// Copies are read from the device, not from memory where they were
+/// just written, see [hash_file_uncached].
+/// Every file that differs or can't be read is logged with WARN level.
+/// Returns paths of copies that differ from their sources.
+///
+pub fn verify_copied_files(copied_files: &[(PathBuf, PathBuf)], mode: VerifyMode) -> Vec<PathBuf> {
+    let VerifyMode::Sample(percent) = mode else {
+        log::info!("Verifying {} copied files", copied_files.len());
+        return find_different_files(copied_files);
+    };
+
+    let sample = random_sample(copied_files, percent);
+    log::info!(
+        "Verifying random sample of {} of {} copied files",
+        sample.len(),
+        copied_files.len()
+    );
+    let different_files = find_different_files(&sample);
+    if different_files.is_empty() {
+        return different_files;
+    }
+
+    log::warn!(
+        target: catalog::COPY_DIFFERS,
+        "{} of sampled files differ from their sources; verifying all copied files",
+        different_files.len()
+    );
+    find_different_files(copied_files)
+}
+
+///
+/// Copy again files whose copies differ from their sources
+/// and compare them again, at most `retries` times.
+///
+/// Files that can't be copied are logged with WARN level.
+/// Returns paths of copies that still differ from their sources.
+///
+pub fn recopy_different_files(
+    copied_files: &[(PathBuf, PathBuf)],
+    mut different_files: Vec<PathBuf>,
+    retries: usize,
+    options: &CopyOptions,
+) -> Vec<PathBuf> {
+    for attempt in 1..=retries {
+        if different_files.is_empty() {
+            break;
+        }
+        let different_paths = different_files.iter().collect::<HashSet<_>>();
+        let files_to_recopy = copied_files
+            .iter()
+            .filter(|(_, dst_path)| different_paths.contains(dst_path))
+            .cloned()
+            .collect::<Vec<_>>();
+        log::info!(
+            "Copying again {} files that differ from their sources (attempt {attempt} of {retries})",
+            files_to_recopy.len()
+        );
+        for (src_path, dst_path) in &files_to_recopy {
+            if let Err(err) = recopy_file(src_path, dst_path, options) {
+                log::warn!(
+                    target: catalog::COPY_FAILED,
+                    "Failed to copy {} again; {err}",
+                    src_path.to_string_lossy()
+                );
+            }
+        }
+        different_files = find_different_files(&files_to_recopy);
+    }
+
+    different_files
+}
+
+fn find_different_files(copied_files: &[(PathBuf, PathBuf)]) -> Vec<PathBuf> {
+    copied_files
+        .par_iter()
+        .filter(|(src_path, _)| {
+            // Copied links point to the same files so there's nothing to compare
+            !src_path
+                .symlink_metadata()
+                .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        })
+        .filter_map(|(src_path, dst_path)| {
+            match (hash_file(src_path), hash_file_uncached(dst_path)) {
+                (Ok(src_hash), Ok(dst_hash)) if src_hash == dst_hash => None,
+                (Ok(_), Ok(_)) => {
+                    log::warn!(
+                        target: catalog::COPY_DIFFERS,
+                        "Copy differs from source: {}",
+                        dst_path.to_string_lossy()
+                    );
+                    Some(dst_path.clone())
+                }
+                (Err(err), _) | (_, Err(err)) => {
+                    log::warn!(
+                        target: catalog::COPY_DIFFERS,
+                        "Failed to verify {}; {err}",
+                        dst_path.to_string_lossy()
+                    );
+                    Some(dst_path.clone())
+                }
+            }
+        })
+        .collect()
+}
+
+///
+/// Choose percent of files at random. At least one file is chosen
+/// when there are any files.
+///
+fn random_sample(files: &[(PathBuf, PathBuf)], percent: f64) -> Vec<(PathBuf, PathBuf)> {
+    let sample_size = ((files.len() as f64 * percent / 100.0).ceil() as usize).min(files.len());
+
+    // Partial Fisher-Yates shuffle with xorshift generator is random enough
+    // to spread checks over many runs
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64);
+    let mut state = (time ^ (u64::from(process::id()) << 32)) | 1;
+    let mut files = files.to_vec();
+    for index in 0..sample_size {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let chosen = index + (state % (files.len() - index) as u64) as usize;
+        files.swap(index, chosen);
+    }
+    files.truncate(sample_size);
+
+    files
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_verify_mode_values() {
+        assert_eq!(parse_verify_mode("full").unwrap(), VerifyMode::Full);
+        assert_eq!(
+            parse_verify_mode("sample:5%").unwrap(),
+            VerifyMode::Sample(5.0)
+        );
+        assert!(parse_verify_mode("sample:5").is_err());
+        assert!(parse_verify_mode("sample:0%").is_err());
+        assert!(parse_verify_mode("sample:101%").is_err());
+        assert!(parse_verify_mode("partial").is_err());
+    }
+
+    #[test]
+    fn random_sample_size() {
+        let files = (0..10)
+            .map(|index| (PathBuf::from(index.to_string()), PathBuf::new()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(random_sample(&files, 5.0).len(), 1);
+        assert_eq!(random_sample(&files, 50.0).len(), 5);
+        assert_eq!(random_sample(&files, 100.0).len(), 10);
+        assert!(random_sample(&[], 50.0).is_empty());
+    }
+
+    #[test]
+    fn verify_copied_files_escalates_to_full() {
+        let dir = TempDir::new().unwrap();
+        let copied_files = (0..4)
+            .map(|index| {
+                let src = dir.path().join(format!("src{index}"));
+                let dst = dir.path().join(format!("dst{index}"));
+                fs::write(&src, "same text").unwrap();
+                fs::write(&dst, "different text").unwrap();
+                (src, dst)
+            })
+            .collect::<Vec<_>>();
+
+        let different_files = verify_copied_files(&copied_files, VerifyMode::Sample(1.0));
+
+        assert_eq!(different_files.len(), 4);
+    }
+
+    #[test]
+    fn recopy_different_files_fixes_copies() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::write(&src, "original text").unwrap();
+        fs::write(&dst, "flipped text!").unwrap();
+        let copied_files = [(src, dst.clone())];
+        let different_files = verify_copied_files(&copied_files, VerifyMode::Full);
+
+        let different_files =
+            recopy_different_files(&copied_files, different_files, 1, &CopyOptions::default());
+
+        assert!(different_files.is_empty());
+        assert_eq!(fs::read_to_string(dst).unwrap(), "original text");
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+///
+/// Path of previous version of the file, e.g. 'name.ext.~1~'
+/// for the most recent one.
+///
+pub fn version_path(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".~{number}~"));
+
+    path.with_file_name(name)
+}
+
+///
+/// Path of the file whose previous version is at path
+/// or None when path is not a previous version.
+///
+pub fn versioned_file(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let (name, number) = name.strip_suffix('~')?.rsplit_once(".~")?;
+    let is_number = !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit());
+    (is_number && !name.is_empty()).then(|| path.with_file_name(OsString::from(name)))
+}
+
+///
+/// Move file at path to its most recent previous version, shifting
+/// older versions by one and removing the oldest when there are
+/// already max_versions of them.
+///
+/// #### Errors
+/// This function returns error when any version can't be moved.
+///
+pub fn keep_previous_version(path: &Path, max_versions: usize) -> io::Result<()> {
+    let oldest = version_path(path, max_versions);
+    match fs::remove_file(&oldest) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    for number in (1..max_versions).rev() {
+        match fs::rename(version_path(path, number), version_path(path, number + 1)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    fs::rename(path, version_path(path, 1))?;
+    log::info!(
+        "Kept previous version of {} as {}",
+        path.to_string_lossy(),
+        version_path(path, 1).to_string_lossy()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn keep_previous_version_rotates_versions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notes.txt");
+
+        for content in ["1", "2", "3", "4"] {
+            fs::write(&path, content).unwrap();
+            keep_previous_version(&path, 2).unwrap();
+        }
+
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(version_path(&path, 1)).unwrap(), "4");
+        assert_eq!(fs::read_to_string(version_path(&path, 2)).unwrap(), "3");
+        assert!(!version_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn versioned_file_parses_version_paths() {
+        let path = Path::new("/dst/notes.txt");
+
+        assert_eq!(
+            versioned_file(&version_path(path, 12)),
+            Some(path.to_path_buf())
+        );
+        assert_eq!(versioned_file(path), None);
+        assert_eq!(versioned_file(Path::new("/dst/notes.txt.~a~")), None);
+        assert_eq!(versioned_file(Path::new("/dst/.~1~")), None);
+    }
+}
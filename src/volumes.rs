@@ -0,0 +1,251 @@
+use crate::files::temp_file_name;
+use serde_json::json;
+use std::{
+    cell::Cell,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// Suffix of the manifest written next to archive split into volumes.
+const VOLUMES_MANIFEST_SUFFIX: &str = ".volumes.json";
+
+///
+/// Writer that splits archive into volumes of fixed size named
+/// after the archive with sequential numbers ('backup.tar.001',
+/// 'backup.tar.002'...). Joined volumes are the whole archive.
+///
+/// Volumes are written to temporary files and renamed when the archive
+/// is complete. Temporary files of unfinished archive are removed.
+///
+pub struct VolumeWriter {
+    path: PathBuf,
+    volume_size: u64,
+    volume: Option<BufWriter<File>>,
+    temp_paths: Vec<PathBuf>,
+    position: Rc<Cell<u64>>,
+    finished: bool,
+}
+
+impl VolumeWriter {
+    pub fn new(path: &Path, volume_size: u64) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            volume_size,
+            volume: None,
+            temp_paths: Vec::new(),
+            position: Rc::new(Cell::new(0)),
+            finished: false,
+        }
+    }
+
+    ///
+    /// Number of bytes written to all volumes, which can be read
+    /// while the writer is owned by encoders writing into it.
+    ///
+    pub fn position(&self) -> Rc<Cell<u64>> {
+        Rc::clone(&self.position)
+    }
+
+    ///
+    /// Rename volumes to their final names and return their paths.
+    ///
+    /// Volumes with higher numbers left by earlier run that wrote
+    /// bigger archive are removed, so joined volumes are the new archive.
+    ///
+    /// #### Errors
+    /// This function returns error when volume can't be written or renamed.
+    ///
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        if let Some(mut volume) = self.volume.take() {
+            volume.flush()?;
+        }
+        let mut volumes_paths = Vec::with_capacity(self.temp_paths.len());
+        for (index, temp_path) in self.temp_paths.iter().enumerate() {
+            let volume_path = volume_path(&self.path, index + 1);
+            fs::rename(temp_path, &volume_path)?;
+            volumes_paths.push(volume_path);
+        }
+        self.finished = true;
+        let mut number = volumes_paths.len() + 1;
+        loop {
+            match fs::remove_file(volume_path(&self.path, number)) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => break,
+                result => result?,
+            }
+            number += 1;
+        }
+
+        Ok(volumes_paths)
+    }
+
+    fn next_volume(&mut self) -> io::Result<()> {
+        if let Some(mut volume) = self.volume.take() {
+            volume.flush()?;
+        }
+        let volume_path = volume_path(&self.path, self.temp_paths.len() + 1);
+        let temp_path = volume_path.with_file_name(temp_file_name());
+        let file = File::create(&temp_path)?;
+        self.temp_paths.push(temp_path);
+        self.volume = Some(BufWriter::new(file));
+
+        Ok(())
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let position = self.position.get();
+        if position / self.volume_size == self.temp_paths.len() as u64 {
+            self.next_volume()?;
+        }
+        let remaining = self.volume_size - position % self.volume_size;
+        let length = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let written = self.volume.as_mut().unwrap().write(&buf[..length])?;
+        self.position.set(position + written as u64);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.volume.as_mut() {
+            Some(volume) => volume.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for VolumeWriter {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.volume = None;
+        for temp_path in &self.temp_paths {
+            let _ = fs::remove_file(temp_path);
+        }
+    }
+}
+
+///
+/// Path of volume with given number (starting from 1).
+///
+pub fn volume_path(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{number:03}"));
+    path.with_file_name(name)
+}
+
+///
+/// Write manifest describing in which volumes archived files are,
+/// next to the archive (e.g. 'backup.tar.volumes.json').
+///
+/// files_ranges contain relative paths of files and byte ranges
+/// of their entries in the whole archive.
+///
+/// #### Errors
+/// This function returns error when manifest can't be written.
+///
+pub fn write_volumes_manifest(
+    path: &Path,
+    volume_size: u64,
+    volumes_paths: &[PathBuf],
+    files_ranges: &[(PathBuf, u64, u64)],
+) -> io::Result<PathBuf> {
+    let volumes = volumes_paths
+        .iter()
+        .map(|volume_path| {
+            volume_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        })
+        .collect::<Vec<_>>();
+    let files = files_ranges
+        .iter()
+        .map(|(file_path, start, end)| {
+            json!({
+                "path": file_path.to_string_lossy(),
+                "first_volume": start / volume_size + 1,
+                "last_volume": end.saturating_sub(1).max(*start) / volume_size + 1,
+            })
+        })
+        .collect::<Vec<_>>();
+    let manifest = json!({
+        "volume_size": volume_size,
+        "volumes": volumes,
+        "files": files,
+    });
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(VOLUMES_MANIFEST_SUFFIX);
+    let manifest_path = path.with_file_name(name);
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+
+    Ok(manifest_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn volume_writer_splits_content() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("backup.tar");
+        let content = (0..250).map(|byte| byte as u8).collect::<Vec<_>>();
+
+        let mut writer = VolumeWriter::new(&path, 100);
+        writer.write_all(&content).unwrap();
+        let volumes_paths = writer.finish().unwrap();
+
+        assert_eq!(
+            volumes_paths,
+            vec![
+                dir.path().join("backup.tar.001"),
+                dir.path().join("backup.tar.002"),
+                dir.path().join("backup.tar.003"),
+            ]
+        );
+        let joined = volumes_paths
+            .iter()
+            .flat_map(|volume_path| fs::read(volume_path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(joined, content);
+        assert_eq!(fs::read(&volumes_paths[2]).unwrap().len(), 50);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn volume_writer_removes_stale_volumes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("backup.tar");
+        for number in 1..=5 {
+            fs::write(volume_path(&path, number), [0; 10]).unwrap();
+        }
+
+        let mut writer = VolumeWriter::new(&path, 10);
+        writer.write_all(&[1; 15]).unwrap();
+        let volumes_paths = writer.finish().unwrap();
+
+        assert_eq!(volumes_paths.len(), 2);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        assert_eq!(fs::read(&volumes_paths[1]).unwrap(), [1; 5]);
+    }
+
+    #[test]
+    fn volume_writer_removes_unfinished_volumes() {
+        let dir = TempDir::new().unwrap();
+        let mut writer = VolumeWriter::new(&dir.path().join("backup.tar"), 10);
+        writer.write_all(&[1; 25]).unwrap();
+
+        drop(writer);
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
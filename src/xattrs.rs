@@ -0,0 +1,97 @@
+use crate::catalog;
+use std::path::Path;
+#[cfg(unix)]
+use std::{collections::BTreeMap, ffi::OsString, io};
+
+///
+/// Copy extended attributes (e.g. tags and comments) of src to dst.
+///
+/// Every attribute is copied separately, so attribute that can't be
+/// copied doesn't stop others. Failures of user attributes are logged
+/// with WARN level. Attributes of other namespaces (e.g. SELinux
+/// contexts) are copied only where the process is permitted to set
+/// them and their failures are logged with DEBUG level.
+/// Windows has no extended attributes to copy (alternate data streams
+/// are copied with file content).
+///
+#[cfg(unix)]
+pub fn copy_xattrs(src: &Path, dst: &Path) {
+    let names = match xattr::list(src) {
+        Ok(names) => names,
+        Err(err) => {
+            log::warn!(
+                target: catalog::XATTRS_NOT_PRESERVED,
+                "Failed to read extended attributes of {}; {err}",
+                src.to_string_lossy()
+            );
+            return;
+        }
+    };
+
+    for name in names {
+        let result = xattr::get(src, &name).and_then(|value| match value {
+            Some(value) => xattr::set(dst, &name, &value),
+            // Attribute was removed after it was listed
+            None => Ok(()),
+        });
+        let Err(err) = result else {
+            continue;
+        };
+        match is_user_attribute(&name) {
+            true => log::warn!(
+                target: catalog::XATTRS_NOT_PRESERVED,
+                "Failed to preserve extended attribute {} of {}; {err}",
+                name.to_string_lossy(),
+                dst.to_string_lossy()
+            ),
+            false => log::debug!(
+                "Extended attribute {} of {} isn't preserved; {err}",
+                name.to_string_lossy(),
+                dst.to_string_lossy()
+            ),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn copy_xattrs(_src: &Path, _dst: &Path) {}
+
+///
+/// Check whether files have the same extended attributes.
+///
+/// Files whose attributes can't be read are treated as different.
+///
+#[cfg(unix)]
+pub fn have_same_xattrs(first: &Path, second: &Path) -> bool {
+    match (read_xattrs(first), read_xattrs(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn have_same_xattrs(_first: &Path, _second: &Path) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> io::Result<BTreeMap<OsString, Option<Vec<u8>>>> {
+    xattr::list(path)?
+        .map(|name| {
+            let value = xattr::get(path, &name)?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+///
+/// Check whether attribute can be set by owner of the file.
+///
+/// macOS has no namespaces, so all its attributes are user attributes.
+///
+#[cfg(unix)]
+fn is_user_attribute(name: &std::ffi::OsStr) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    cfg!(target_os = "macos") || name.as_bytes().starts_with(b"user.")
+}